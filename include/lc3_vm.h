@@ -0,0 +1,59 @@
+/* C interface to the lc3-vm crate, built with the `ffi` feature:
+ *
+ *     cargo rustc --lib --crate-type cdylib --release --features ffi
+ *
+ * and linked against target/release/liblc3_vm.so (.dylib, .dll). See
+ * src/ffi.rs for the details of each call. A VM must only be used from one
+ * thread at a time.
+ */
+
+#ifndef LC3_VM_H
+#define LC3_VM_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct lc3_vm lc3_vm;
+
+/* lc3_vm_step results */
+#define LC3_CONTINUED 0
+#define LC3_TRAPPED 1
+#define LC3_HALTED 2
+#define LC3_FAULTED 3
+#define LC3_WAITING_FOR_INPUT 4
+
+/* create a VM with empty memory; free it with lc3_vm_free */
+lc3_vm *lc3_vm_new(void);
+void lc3_vm_free(lc3_vm *vm);
+
+/* load an object image (origin word, then the program); 0 on success, -1 if malformed */
+int32_t lc3_vm_load(lc3_vm *vm, const uint8_t *bytes, size_t len);
+
+/* execute one instruction; returns an LC3_* step result */
+int32_t lc3_vm_step(lc3_vm *vm);
+/* execute up to budget instructions, stopping at HALT, a fault or GETC/IN without input */
+uint64_t lc3_vm_run(lc3_vm *vm, uint64_t budget);
+void lc3_vm_reset(lc3_vm *vm);
+int32_t lc3_vm_halted(const lc3_vm *vm);
+
+uint16_t lc3_vm_read_mem(const lc3_vm *vm, uint16_t address);
+void lc3_vm_write_mem(lc3_vm *vm, uint16_t address, uint16_t value);
+/* index 0-7: R0-R7, 8: PC, 9: COND */
+uint16_t lc3_vm_read_reg(const lc3_vm *vm, uint32_t index);
+void lc3_vm_write_reg(lc3_vm *vm, uint32_t index, uint16_t value);
+uint16_t lc3_vm_pc(const lc3_vm *vm);
+
+/* queue a key for the keyboard */
+void lc3_vm_push_input(lc3_vm *vm, uint8_t key);
+/* move up to capacity bytes of output into buffer (not NUL-terminated); returns the count */
+size_t lc3_vm_take_output(lc3_vm *vm, uint8_t *buffer, size_t capacity);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
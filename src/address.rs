@@ -0,0 +1,24 @@
+use core::ops::RangeInclusive;
+
+/// Parse an address written as `0x3000`, `x3000` or decimal.
+pub fn parse_address(text: &str) -> Option<u16> {
+    let text = text.trim();
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix('x')) {
+        u16::from_str_radix(hex, 16).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+/// Parse `START..END` (exclusive) or `START..=END` (inclusive) into an inclusive range.
+pub fn parse_range(text: &str) -> Option<RangeInclusive<u16>> {
+    if let Some((start, end)) = text.split_once("..=") {
+        return Some(parse_address(start)?..=parse_address(end)?);
+    }
+    let (start, end) = text.split_once("..")?;
+    let (start, end) = (parse_address(start)?, parse_address(end)?);
+    if end <= start {
+        return None;
+    }
+    Some(start..=end - 1)
+}
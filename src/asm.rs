@@ -0,0 +1,393 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::program::Program;
+
+/// An error found while assembling, tagged with its 1-based source line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/* a source line reduced to its label, mnemonic and operand text */
+struct Statement<'a> {
+    line: usize,
+    address: u16,
+    op: String,
+    operands: Vec<&'a str>,
+    raw_operands: &'a str,
+}
+
+const OPCODES: &[&str] = &[
+    "ADD", "AND", "NOT", "BR", "BRN", "BRZ", "BRP", "BRNZ", "BRNP", "BRZP", "BRNZP", "JMP", "RET",
+    "JSR", "JSRR", "LD", "LDI", "LDR", "LEA", "ST", "STI", "STR", "TRAP", "RTI", "GETC", "OUT",
+    "PUTS", "IN", "PUTSP", "HALT", ".ORIG", ".FILL", ".BLKW", ".STRINGZ", ".END",
+];
+
+fn error(line: usize, message: impl Into<String>) -> AsmError {
+    AsmError { line, message: message.into() }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn is_opcode(token: &str) -> bool {
+    OPCODES.contains(&token.to_ascii_uppercase().as_str())
+}
+
+/// Parse a numeric literal in any of the usual LC-3 spellings:
+/// `#-5`, `5`, `x1F`, `0x1F`, `b101`.
+pub fn parse_number(token: &str) -> Option<i32> {
+    let (digits, radix) = if let Some(rest) = token.strip_prefix('#') {
+        (rest, 10)
+    } else if let Some(rest) = token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")) {
+        (rest, 16)
+    } else if let Some(rest) = token.strip_prefix('x').or_else(|| token.strip_prefix('X')) {
+        (rest, 16)
+    } else if let Some(rest) = token.strip_prefix('b').or_else(|| token.strip_prefix('B')) {
+        (rest, 2)
+    } else {
+        (token, 10)
+    };
+    let (negative, digits) = match digits.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, digits),
+    };
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    let value = i32::from_str_radix(digits, radix).ok()?;
+    Some(if negative { -value } else { value })
+}
+
+fn parse_register(line: usize, token: &str) -> Result<u16, AsmError> {
+    let digits = token.strip_prefix('R').or_else(|| token.strip_prefix('r'));
+    match digits.and_then(|d| d.parse::<u16>().ok()) {
+        Some(reg) if reg < 8 => Ok(reg),
+        _ => Err(error(line, format!("expected a register R0-R7, found `{}`", token))),
+    }
+}
+
+fn parse_string(line: usize, raw: &str) -> Result<Vec<u16>, AsmError> {
+    let raw = raw.trim();
+    let inner = raw
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or_else(|| error(line, "expected a double-quoted string"))?;
+    let mut words = Vec::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        let c = if c == '\\' {
+            match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('r') => '\r',
+                Some('0') => '\0',
+                Some('e') => '\x1b',
+                Some(other) => other,
+                None => return Err(error(line, "dangling escape in string")),
+            }
+        } else {
+            c
+        };
+        words.push(c as u16);
+    }
+    words.push(0);
+    Ok(words)
+}
+
+fn check_range(line: usize, value: i32, bits: u32, what: &str) -> Result<u16, AsmError> {
+    let min = -(1 << (bits - 1));
+    let max = (1 << (bits - 1)) - 1;
+    if value < min || value > max {
+        return Err(error(line, format!("{} {} does not fit in {} bits", what, value, bits)));
+    }
+    Ok((value as u16) & ((1 << bits) - 1))
+}
+
+fn expect_operands(statement: &Statement, count: usize) -> Result<(), AsmError> {
+    if statement.operands.len() != count {
+        return Err(error(
+            statement.line,
+            format!("{} expects {} operand(s), found {}", statement.op, count, statement.operands.len()),
+        ));
+    }
+    Ok(())
+}
+
+struct Assembler<'a> {
+    symbols: &'a HashMap<String, u16>,
+}
+
+impl Assembler<'_> {
+    fn value(&self, line: usize, token: &str) -> Result<i32, AsmError> {
+        if let Some(value) = parse_number(token) {
+            return Ok(value);
+        }
+        self.symbols
+            .get(token)
+            .map(|&address| address as i32)
+            .ok_or_else(|| error(line, format!("undefined label `{}`", token)))
+    }
+
+    fn pc_offset(&self, statement: &Statement, token: &str, bits: u32) -> Result<u16, AsmError> {
+        let offset = match parse_number(token) {
+            Some(value) => value,
+            None => {
+                let target = self.value(statement.line, token)?;
+                target - (statement.address as i32 + 1)
+            }
+        };
+        check_range(statement.line, offset, bits, "offset")
+    }
+
+    fn encode(&self, statement: &Statement) -> Result<Vec<u16>, AsmError> {
+        let line = statement.line;
+        let ops = &statement.operands;
+        let word = match statement.op.as_str() {
+            "ADD" | "AND" => {
+                expect_operands(statement, 3)?;
+                let opcode: u16 = if statement.op == "ADD" { 0b0001 } else { 0b0101 };
+                let dr = parse_register(line, ops[0])?;
+                let sr1 = parse_register(line, ops[1])?;
+                let last = match parse_register(line, ops[2]) {
+                    Ok(sr2) => sr2,
+                    Err(_) => {
+                        let imm = self.value(line, ops[2])?;
+                        (1 << 5) | check_range(line, imm, 5, "immediate")?
+                    }
+                };
+                (opcode << 12) | (dr << 9) | (sr1 << 6) | last
+            }
+            "NOT" => {
+                expect_operands(statement, 2)?;
+                let dr = parse_register(line, ops[0])?;
+                let sr = parse_register(line, ops[1])?;
+                (0b1001 << 12) | (dr << 9) | (sr << 6) | 0x3F
+            }
+            op if op.starts_with("BR") => {
+                expect_operands(statement, 1)?;
+                let flags = &op[2..];
+                let mut nzp = 0;
+                if flags.contains('N') { nzp |= 0b100; }
+                if flags.contains('Z') { nzp |= 0b010; }
+                if flags.contains('P') { nzp |= 0b001; }
+                if nzp == 0 { nzp = 0b111; }
+                (nzp << 9) | self.pc_offset(statement, ops[0], 9)?
+            }
+            "JMP" | "JSRR" => {
+                expect_operands(statement, 1)?;
+                let opcode: u16 = if statement.op == "JMP" { 0b1100 } else { 0b0100 };
+                (opcode << 12) | (parse_register(line, ops[0])? << 6)
+            }
+            "RET" => {
+                expect_operands(statement, 0)?;
+                0xC1C0
+            }
+            "JSR" => {
+                expect_operands(statement, 1)?;
+                (0b0100 << 12) | (1 << 11) | self.pc_offset(statement, ops[0], 11)?
+            }
+            "LD" | "LDI" | "LEA" | "ST" | "STI" => {
+                expect_operands(statement, 2)?;
+                let opcode: u16 = match statement.op.as_str() {
+                    "LD" => 0b0010,
+                    "LDI" => 0b1010,
+                    "LEA" => 0b1110,
+                    "ST" => 0b0011,
+                    _ => 0b1011,
+                };
+                let reg = parse_register(line, ops[0])?;
+                (opcode << 12) | (reg << 9) | self.pc_offset(statement, ops[1], 9)?
+            }
+            "LDR" | "STR" => {
+                expect_operands(statement, 3)?;
+                let opcode: u16 = if statement.op == "LDR" { 0b0110 } else { 0b0111 };
+                let reg = parse_register(line, ops[0])?;
+                let base = parse_register(line, ops[1])?;
+                let offset = self.value(line, ops[2])?;
+                (opcode << 12) | (reg << 9) | (base << 6) | check_range(line, offset, 6, "offset")?
+            }
+            "TRAP" => {
+                expect_operands(statement, 1)?;
+                let vector = self.value(line, ops[0])?;
+                if !(0..=0xFF).contains(&vector) {
+                    return Err(error(line, format!("trap vector {} does not fit in 8 bits", vector)));
+                }
+                0xF000 | vector as u16
+            }
+            "RTI" => 0x8000,
+            "GETC" => 0xF020,
+            "OUT" => 0xF021,
+            "PUTS" => 0xF022,
+            "IN" => 0xF023,
+            "PUTSP" => 0xF024,
+            "HALT" => 0xF025,
+            ".FILL" => {
+                expect_operands(statement, 1)?;
+                let value = self.value(line, ops[0])?;
+                if !(-0x8000..=0xFFFF).contains(&value) {
+                    return Err(error(line, format!(".FILL value {} does not fit in 16 bits", value)));
+                }
+                value as u16
+            }
+            ".BLKW" => {
+                let count = self.value(line, ops[0])?;
+                let fill = match ops.get(1) {
+                    Some(token) => self.value(line, token)? as u16,
+                    None => 0,
+                };
+                return Ok(vec![fill; count as usize]);
+            }
+            ".STRINGZ" => return parse_string(line, statement.raw_operands),
+            other => return Err(error(line, format!("unknown instruction `{}`", other))),
+        };
+        Ok(vec![word])
+    }
+}
+
+fn statement_size(statement: &Statement) -> Result<u16, AsmError> {
+    match statement.op.as_str() {
+        ".BLKW" => {
+            let count = statement
+                .operands
+                .first()
+                .and_then(|token| parse_number(token))
+                .ok_or_else(|| error(statement.line, ".BLKW expects a numeric count"))?;
+            if !(0..=0xFFFF).contains(&count) {
+                return Err(error(statement.line, format!(".BLKW count {} is out of range", count)));
+            }
+            Ok(count as u16)
+        }
+        ".STRINGZ" => Ok(parse_string(statement.line, statement.raw_operands)?.len() as u16),
+        _ => Ok(1),
+    }
+}
+
+/// Assemble LC-3 source text into a [`Program`].
+///
+/// Supports the standard mnemonics, the trap aliases, and the `.ORIG`,
+/// `.FILL`, `.BLKW`, `.STRINGZ` and `.END` directives. Labels end up in
+/// [`Program::symbols`].
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    assemble_with_lines(source).map(|(program, _)| program)
+}
+
+/// Like [`assemble`], also returning the line number (from 1) and address
+/// of every instruction and directive that takes up memory, in source
+/// order, e.g. for a debugger to place breakpoints by line.
+pub fn assemble_with_lines(source: &str) -> Result<(Program, Vec<(usize, u16)>), AsmError> {
+    let mut symbols: HashMap<String, u16> = HashMap::new();
+    let mut statements: Vec<Statement> = Vec::new();
+    let mut origin: Option<u16> = None;
+    let mut address: u16 = 0;
+
+    /* pass 1: lay out addresses and collect labels */
+    for (index, raw_line) in source.lines().enumerate() {
+        let line = index + 1;
+        let text = strip_comment(raw_line).trim();
+        if text.is_empty() {
+            continue;
+        }
+
+        let (first, rest) = split_first_token(text);
+        let (label, op, raw_operands) = if is_opcode(first) {
+            (None, first, rest)
+        } else {
+            let (op, rest) = split_first_token(rest);
+            (Some(first.trim_end_matches(':')), op, rest)
+        };
+
+        if let Some(label) = label {
+            if origin.is_none() {
+                return Err(error(line, "label before .ORIG"));
+            }
+            if symbols.insert(label.to_string(), address).is_some() {
+                return Err(error(line, format!("duplicate label `{}`", label)));
+            }
+        }
+        if op.is_empty() {
+            continue;
+        }
+
+        let op = op.to_ascii_uppercase();
+        if !is_opcode(&op) {
+            return Err(error(line, format!("unknown instruction `{}`", op)));
+        }
+        let operands: Vec<&str> = raw_operands
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|token| !token.is_empty())
+            .collect();
+
+        match op.as_str() {
+            ".ORIG" => {
+                if origin.is_some() {
+                    return Err(error(line, "multiple .ORIG directives"));
+                }
+                let value = operands
+                    .first()
+                    .and_then(|token| parse_number(token))
+                    .filter(|value| (0..=0xFFFF).contains(value))
+                    .ok_or_else(|| error(line, ".ORIG expects an address"))?;
+                origin = Some(value as u16);
+                address = value as u16;
+                continue;
+            }
+            ".END" => break,
+            _ => {}
+        }
+        if origin.is_none() {
+            return Err(error(line, "instruction before .ORIG"));
+        }
+
+        let statement = Statement { line, address, op, operands, raw_operands };
+        address = address.wrapping_add(statement_size(&statement)?);
+        statements.push(statement);
+    }
+
+    let origin = origin.ok_or_else(|| error(1, "missing .ORIG"))?;
+
+    /* pass 2: encode */
+    let assembler = Assembler { symbols: &symbols };
+    let mut words = Vec::new();
+    for statement in &statements {
+        words.extend(assembler.encode(statement)?);
+    }
+
+    let mut program = Program::from_words(origin, &words);
+    program.symbols = symbols;
+    let lines = statements.iter().map(|statement| (statement.line, statement.address)).collect();
+    Ok((program, lines))
+}
+
+fn split_first_token(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    match text.find(|c: char| c.is_whitespace()) {
+        Some(i) => (&text[..i], text[i..].trim_start()),
+        None => (text, ""),
+    }
+}
@@ -0,0 +1,136 @@
+use std::mem;
+
+use rayon::prelude::*;
+
+use crate::console::CapturedConsole;
+use crate::json;
+use crate::program::Program;
+use crate::vm::{REGISTER, Vm};
+
+/// One independent run: an image, the keyboard input it gets, and how many
+/// instructions it may execute before it is stopped.
+#[derive(Debug, Clone)]
+pub struct BatchJob {
+    pub name: String,
+    pub program: Program,
+    pub input: Vec<u8>,
+    pub max_instructions: u64,
+}
+
+/// How one [`BatchJob`] ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchResult {
+    pub name: String,
+    /// Everything the program printed.
+    pub output: Vec<u8>,
+    /// False if the run hit its instruction limit.
+    pub halted: bool,
+    pub instructions: u64,
+    /// R0-R7, PC and COND at the end of the run.
+    pub registers: [u16; REGISTER::COUNT as usize],
+}
+
+impl BatchResult {
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"kind\":\"batch-result\",\"name\":{},\"halted\":{},\"instructions\":{},\"registers\":{},\"output\":{}}}",
+            json::string(&self.name),
+            self.halted,
+            self.instructions,
+            json::array(self.registers.iter().take(8).map(u16::to_string)),
+            json::string(&String::from_utf8_lossy(&self.output))
+        )
+    }
+}
+
+/// Run a single job on a fresh VM with a captured console.
+pub fn run_job(job: &BatchJob) -> BatchResult {
+    let mut vm = Vm::new();
+    vm.console = Box::new(CapturedConsole::new(job.input.clone()));
+    vm.load_program(&job.program);
+    while !vm.halted && vm.instruction_count < job.max_instructions {
+        vm.step();
+    }
+    BatchResult {
+        name: job.name.clone(),
+        output: vm.console.downcast_mut::<CapturedConsole>().map(|console| mem::take(&mut console.output)).unwrap_or_default(),
+        halted: vm.halted,
+        instructions: vm.instruction_count,
+        registers: vm.registers,
+    }
+}
+
+/// Run every job on its own VM, spread across all host cores. Each run has
+/// its own console, and the results come back in the order of `jobs`
+/// regardless of which finished first.
+pub fn run_batch(jobs: &[BatchJob]) -> Vec<BatchResult> {
+    jobs.par_iter().map(run_job).collect()
+}
+
+#[cfg(all(test, feature = "assembler"))]
+mod tests {
+    use super::*;
+
+    /* print the key read and halt with it in R1 */
+    const ECHO_ONE: &str = "
+                .ORIG x3000
+                GETC
+                OUT
+                ADD R1, R0, #0
+                HALT
+                .END
+    ";
+
+    const SPIN: &str = "
+                .ORIG x3000
+        LOOP    BR LOOP
+                .END
+    ";
+
+    fn job(name: &str, source: &str, input: &[u8]) -> BatchJob {
+        BatchJob {
+            name: name.to_string(),
+            program: Program::from_assembly(source).unwrap(),
+            input: input.to_vec(),
+            max_instructions: 10_000,
+        }
+    }
+
+    #[test]
+    fn results_come_back_in_job_order_with_their_own_output() {
+        let jobs: Vec<BatchJob> = (b'a'..=b'z').map(|key| job(&(key as char).to_string(), ECHO_ONE, &[key])).collect();
+        let results = run_batch(&jobs);
+        assert_eq!(results.len(), jobs.len());
+        for (key, result) in (b'a'..=b'z').zip(&results) {
+            assert_eq!(result.name, (key as char).to_string());
+            assert!(result.halted);
+            assert_eq!(result.output, [key, b'H', b'A', b'L', b'T']);
+            assert_eq!(result.registers[1], key as u16);
+            assert_eq!(result.instructions, 4);
+        }
+    }
+
+    #[test]
+    fn a_run_stops_at_its_instruction_limit() {
+        let results = run_batch(&[job("spin", SPIN, b""), job("echo", ECHO_ONE, b"!")]);
+        assert!(!results[0].halted);
+        assert_eq!(results[0].instructions, 10_000);
+        assert!(results[1].halted);
+        assert_eq!(results[1].output, b"!HALT");
+    }
+
+    #[test]
+    fn a_run_without_input_halts_at_its_first_read() {
+        let results = run_batch(&[job("empty", ECHO_ONE, b"")]);
+        assert!(results[0].halted);
+        assert!(results[0].output.is_empty());
+        assert_eq!(results[0].instructions, 0);
+    }
+
+    #[test]
+    fn results_serialize_as_json() {
+        let json = run_batch(&[job("one", ECHO_ONE, b"x")])[0].to_json();
+        assert!(json.contains("\"name\":\"one\""), "{}", json);
+        assert!(json.contains("\"halted\":true"), "{}", json);
+    }
+}
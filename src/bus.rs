@@ -0,0 +1,182 @@
+//! Memory-mapped peripherals. A [`Device`] claims a range of addresses on
+//! the VM's [`Bus`]; guest loads and stores there go to the device instead
+//! of memory, so new peripherals plug in without touching the interpreter.
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+
+use tracing::debug;
+
+use crate::vm::Interrupt;
+
+/// A peripheral answering to a fixed range of addresses.
+pub trait Device: Any {
+    /// Short name used to list and detach the device, e.g. `disk`.
+    fn name(&self) -> &str;
+
+    /// The addresses the device answers to; read once, when it is attached.
+    fn range(&self) -> RangeInclusive<u16>;
+
+    /// A guest load from `address`, which lies within [`Device::range`].
+    fn read(&mut self, address: u16) -> u16;
+
+    /// A guest store to `address`, which lies within [`Device::range`].
+    fn write(&mut self, address: u16, value: u16);
+
+    /// Called after every instruction, with access to memory for devices
+    /// that move data on their own (DMA, disk transfers). Returns an
+    /// interrupt to request.
+    fn tick(&mut self, _memory: &mut [u16]) -> Option<Interrupt> {
+        None
+    }
+
+    /// Return to the power-on state; connections and backing files stay open.
+    fn reset(&mut self) {}
+
+    /// Complete any work in progress; called before the device is detached.
+    fn finish(&mut self, _memory: &mut [u16]) {}
+
+    /// The device's registers for a [`VmState`](crate::state::VmState).
+    /// Devices whose state lives outside the VM (connections, host audio)
+    /// keep the default, which saves nothing.
+    fn save(&self) -> Vec<u16> {
+        Vec::new()
+    }
+
+    /// Load registers written by [`Device::save`].
+    fn restore(&mut self, _state: &[u16]) {}
+
+    /// A copy of the device for a clone of the VM, or `None` (the default)
+    /// if it is bound to something that cannot be duplicated, such as a
+    /// file or connection; the clone then goes without it.
+    fn fork(&self) -> Option<Box<dyn Device>> {
+        None
+    }
+}
+
+/// The devices attached to a VM, each with the address range it claimed.
+#[derive(Default)]
+pub struct Bus {
+    devices: Vec<(RangeInclusive<u16>, Box<dyn Device>)>,
+}
+
+impl Bus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attach a device. Fails if its range overlaps an attached device or
+    /// another device already has its name.
+    pub fn attach(&mut self, device: Box<dyn Device>) -> Result<(), String> {
+        let range = device.range();
+        if self.devices.iter().any(|(_, other)| other.name() == device.name()) {
+            return Err(format!("{} is already attached", device.name()));
+        }
+        if let Some((_, other)) = self
+            .devices
+            .iter()
+            .find(|(taken, _)| taken.start() <= range.end() && range.start() <= taken.end())
+        {
+            return Err(format!("{} overlaps {} at x{:04X}", device.name(), other.name(), range.start()));
+        }
+        debug!(name = device.name(), start = range.start(), end = range.end(), "device attached");
+        self.devices.push((range, device));
+        Ok(())
+    }
+
+    /// Detach a device by name once it has finished its work in progress;
+    /// its addresses go back to plain memory.
+    pub fn detach(&mut self, name: &str, memory: &mut [u16]) -> Option<Box<dyn Device>> {
+        let index = self.devices.iter().position(|(_, device)| device.name() == name)?;
+        let (_, mut device) = self.devices.remove(index);
+        device.finish(memory);
+        debug!(name, "device detached");
+        Some(device)
+    }
+
+    /// Names of the attached devices, in the order they were attached.
+    pub fn names(&self) -> Vec<&str> {
+        self.devices.iter().map(|(_, device)| device.name()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    /// The attached device of type `T`, e.g. to drive its host-side controls.
+    pub fn get<T: Device>(&self) -> Option<&T> {
+        self.devices.iter().find_map(|(_, device)| (device.as_ref() as &dyn Any).downcast_ref())
+    }
+
+    pub fn get_mut<T: Device>(&mut self) -> Option<&mut T> {
+        self.devices.iter_mut().find_map(|(_, device)| (device.as_mut() as &mut dyn Any).downcast_mut())
+    }
+
+    /// The value of a device register, or `None` if no device claims `address`.
+    pub fn read(&mut self, address: u16) -> Option<u16> {
+        let (_, device) = self.devices.iter_mut().find(|(range, _)| range.contains(&address))?;
+        Some(device.read(address))
+    }
+
+    /// Deliver a store to the device that claims `address`; false if none does.
+    pub fn write(&mut self, address: u16, value: u16) -> bool {
+        match self.devices.iter_mut().find(|(range, _)| range.contains(&address)) {
+            Some((_, device)) => {
+                device.write(address, value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Tick every device, passing each interrupt it requests to `raise`.
+    pub fn tick(&mut self, memory: &mut [u16], mut raise: impl FnMut(Interrupt)) {
+        for (_, device) in self.devices.iter_mut() {
+            if let Some(interrupt) = device.tick(memory) {
+                raise(interrupt);
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for (_, device) in self.devices.iter_mut() {
+            device.reset();
+        }
+    }
+
+    /// A bus for a clone of the VM, holding a [`Device::fork`] of every device that supports it.
+    pub fn fork(&self) -> Bus {
+        Bus {
+            devices: self
+                .devices
+                .iter()
+                .filter_map(|(range, device)| Some((range.clone(), device.fork()?)))
+                .collect(),
+        }
+    }
+
+    /// Saved registers of every device that has any, keyed by name.
+    pub fn save(&self) -> BTreeMap<String, Vec<u16>> {
+        self.devices
+            .iter()
+            .map(|(_, device)| (device.name().to_string(), device.save()))
+            .filter(|(_, state)| !state.is_empty())
+            .collect()
+    }
+
+    /// Restore each attached device named in `states`; the rest are left alone.
+    pub fn restore(&mut self, states: &BTreeMap<String, Vec<u16>>) {
+        for (_, device) in self.devices.iter_mut() {
+            if let Some(state) = states.get(device.name()) {
+                device.restore(state);
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for Bus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
@@ -0,0 +1,99 @@
+//! A best-effort call stack rebuilt from the instructions as they run: a
+//! JSR, JSRR or TRAP into guest code opens a frame, and a jump (usually
+//! RET) or RTI to a frame's return address closes it together with any
+//! opened inside it. That copes with tail calls, which leave their frame
+//! for the outer routine's RET to close, and a RET whose R7 was clobbered
+//! still closes the innermost frame.
+
+use std::fmt::Write as _;
+
+use crate::instruction::{Instruction, decode};
+use crate::symbols::SymbolTable;
+
+/* frames beyond this many (recursion that never returns) push out the oldest */
+const MAX_DEPTH: usize = 1024;
+
+/// A subroutine call that has not returned yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    /// The address of the JSR, JSRR or TRAP.
+    pub call_site: u16,
+    /// Where it went.
+    pub entry: u16,
+    /// Where a RET from it comes back to.
+    pub return_address: u16,
+}
+
+/// The frames of the calls in progress, outermost first, kept in
+/// [`Vm::call_stack`](crate::vm::Vm::call_stack).
+#[derive(Debug, Clone, Default)]
+pub struct CallStack {
+    frames: Vec<Frame>,
+}
+
+impl CallStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /* a stack of `frames`, outermost first, e.g. from a core file */
+    pub(crate) fn from_frames(frames: Vec<Frame>) -> Self {
+        CallStack { frames }
+    }
+
+    /// The calls in progress, outermost first.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /* follow the instruction at `pc`, which left the PC at `next` */
+    pub(crate) fn record(&mut self, pc: u16, instruction: u16, next: u16) {
+        match decode(instruction) {
+            /* a TRAP the VM serves itself comes straight back */
+            Instruction::Jsr { .. } | Instruction::Jsrr { .. } | Instruction::Trap { .. } if next != pc.wrapping_add(1) => {
+                if self.frames.len() == MAX_DEPTH {
+                    self.frames.remove(0);
+                }
+                self.frames.push(Frame { call_site: pc, entry: next, return_address: pc.wrapping_add(1) });
+            }
+            Instruction::Jmp { base } => {
+                match self.frames.iter().rposition(|frame| frame.return_address == next) {
+                    Some(index) => self.frames.truncate(index),
+                    /* R7 was changed under the routine: it is still returning */
+                    None if base == 7 => {
+                        self.frames.pop();
+                    }
+                    None => {}
+                }
+            }
+            Instruction::Rti => {
+                if let Some(index) = self.frames.iter().rposition(|frame| frame.return_address == next) {
+                    self.frames.truncate(index);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// A backtrace from `pc`, innermost first, e.g.
+    ///
+    /// ```text
+    /// #0  x3012 (PRINT+3)
+    /// #1  x3004 (MAIN+4) called PRINT
+    /// ```
+    pub fn render(&self, pc: u16, symbols: &SymbolTable) -> String {
+        let mut out = format!("#0  {}\n", symbols.describe(pc));
+        for (depth, frame) in self.frames.iter().rev().enumerate() {
+            writeln!(out, "#{:<2} {} called {}", depth + 1, symbols.describe(frame.call_site), symbols.describe(frame.entry)).unwrap();
+        }
+        out
+    }
+}
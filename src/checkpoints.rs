@@ -0,0 +1,73 @@
+//! A ring of full machine snapshots taken every so many instructions
+//! (`--checkpoint-every N`), so a debugger can travel back a long way at
+//! once: [`Vm::travel_to`](crate::vm::Vm::travel_to) restores the nearest
+//! checkpoint before the target and executes forward from there.
+
+use std::collections::VecDeque;
+
+use crate::state::VmState;
+
+/// Checkpoints kept unless [`Checkpoints::new`] is given another count.
+pub const DEFAULT_CAPACITY: usize = 16;
+
+/// The last [`Checkpoints::capacity`] snapshots, one every
+/// [`Checkpoints::interval`] instructions, oldest first.
+#[derive(Debug, Clone)]
+pub struct Checkpoints {
+    pub interval: u64,
+    capacity: usize,
+    ring: VecDeque<VmState>,
+}
+
+impl Checkpoints {
+    pub fn new(interval: u64, capacity: usize) -> Self {
+        Checkpoints {
+            interval: interval.max(1),
+            capacity: capacity.max(1),
+            ring: VecDeque::new(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    /// The checkpoints, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &VmState> {
+        self.ring.iter()
+    }
+
+    /// The latest checkpoint taken at or before `instruction_count`.
+    pub fn at_or_before(&self, instruction_count: u64) -> Option<&VmState> {
+        self.ring.iter().rev().find(|state| state.instruction_count <= instruction_count)
+    }
+
+    /* whether a checkpoint is due before executing instruction number `instruction_count` */
+    pub(crate) fn due(&self, instruction_count: u64) -> bool {
+        instruction_count.is_multiple_of(self.interval)
+            && self.ring.back().is_none_or(|state| state.instruction_count != instruction_count)
+    }
+
+    /* add a checkpoint; any taken later than it belong to a past that was travelled away from */
+    pub(crate) fn push(&mut self, state: VmState) {
+        while self.ring.back().is_some_and(|latest| latest.instruction_count >= state.instruction_count) {
+            self.ring.pop_back();
+        }
+        if self.ring.len() == self.capacity {
+            self.ring.pop_front();
+        }
+        self.ring.push_back(state);
+    }
+
+    pub fn clear(&mut self) {
+        self.ring.clear();
+    }
+}
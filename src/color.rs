@@ -0,0 +1,178 @@
+use std::io::IsTerminal;
+
+/// When to emit ANSI colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorMode {
+    /// Color when the stream is a terminal and `NO_COLOR` is unset.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "auto" => Some(ColorMode::Auto),
+            "always" => Some(ColorMode::Always),
+            "never" => Some(ColorMode::Never),
+            _ => None,
+        }
+    }
+
+    /// Resolve the mode for a stream, e.g. `ColorMode::Auto.enabled_for(&io::stdout())`.
+    pub fn enabled_for(self, stream: &impl IsTerminal) -> bool {
+        match self {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto => stream.is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+        }
+    }
+}
+
+/// The kinds of text that get their own color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Mnemonic,
+    Register,
+    Immediate,
+    Address,
+    Error,
+    Expected,
+    Actual,
+    /// The instruction at the PC in the debugger's disassembly.
+    Current,
+}
+
+/// SGR parameters (the part between `ESC[` and `m`) for each [`Role`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+    pub mnemonic: String,
+    pub register: String,
+    pub immediate: String,
+    pub address: String,
+    pub error: String,
+    pub expected: String,
+    pub actual: String,
+    pub current: String,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            mnemonic: "1;36".to_string(),
+            register: "33".to_string(),
+            immediate: "35".to_string(),
+            address: "34".to_string(),
+            error: "1;31".to_string(),
+            expected: "32".to_string(),
+            actual: "31".to_string(),
+            current: "7".to_string(),
+        }
+    }
+}
+
+impl Theme {
+    /// Parse a theme file of `role = SGR` lines, e.g. `mnemonic = 1;36`.
+    /// Roles that are not mentioned keep their default; `#` starts a comment.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut theme = Theme::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("theme line {}: expected `role = SGR`", index + 1))?;
+            let value = value.trim();
+            if !value.chars().all(|c| c.is_ascii_digit() || c == ';') {
+                return Err(format!("theme line {}: `{}` is not an SGR sequence", index + 1, value));
+            }
+            let slot = match key.trim() {
+                "mnemonic" => &mut theme.mnemonic,
+                "register" => &mut theme.register,
+                "immediate" => &mut theme.immediate,
+                "address" => &mut theme.address,
+                "error" => &mut theme.error,
+                "expected" => &mut theme.expected,
+                "actual" => &mut theme.actual,
+                "current" => &mut theme.current,
+                other => return Err(format!("theme line {}: unknown role `{}`", index + 1, other)),
+            };
+            *slot = value.to_string();
+        }
+        Ok(theme)
+    }
+
+    fn code(&self, role: Role) -> &str {
+        match role {
+            Role::Mnemonic => &self.mnemonic,
+            Role::Register => &self.register,
+            Role::Immediate => &self.immediate,
+            Role::Address => &self.address,
+            Role::Error => &self.error,
+            Role::Expected => &self.expected,
+            Role::Actual => &self.actual,
+            Role::Current => &self.current,
+        }
+    }
+}
+
+/// Applies a [`Theme`] to text, or passes it through when color is off.
+#[derive(Debug, Clone, Default)]
+pub struct Painter {
+    pub enabled: bool,
+    pub theme: Theme,
+}
+
+impl Painter {
+    pub fn new(enabled: bool, theme: Theme) -> Self {
+        Painter { enabled, theme }
+    }
+
+    pub fn plain() -> Self {
+        Painter::default()
+    }
+
+    pub fn paint(&self, role: Role, text: &str) -> String {
+        if !self.enabled || text.is_empty() {
+            return text.to_string();
+        }
+        format!("\x1b[{}m{}\x1b[0m", self.theme.code(role), text)
+    }
+
+    /// Color a line of disassembly as produced by [`crate::disasm::disassemble`].
+    pub fn disassembly(&self, text: &str) -> String {
+        if !self.enabled {
+            return text.to_string();
+        }
+        let (code, comment) = match text.split_once(';') {
+            Some((code, comment)) => (code, Some(comment)),
+            None => (text, None),
+        };
+        let mut out = String::new();
+        for (i, token) in code.split(' ').enumerate() {
+            if i > 0 {
+                out.push(' ');
+            }
+            let word = token.trim_end_matches(',');
+            let role = if i == 0 {
+                Role::Mnemonic
+            } else if word.starts_with('R') {
+                Role::Register
+            } else if word.starts_with('x') {
+                Role::Address
+            } else {
+                Role::Immediate
+            };
+            out.push_str(&self.paint(role, word));
+            out.push_str(&token[word.len()..]);
+        }
+        if let Some(comment) = comment {
+            out.push(';');
+            out.push_str(comment);
+        }
+        out
+    }
+}
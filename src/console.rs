@@ -0,0 +1,444 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::io::{self, BufWriter, Stdout, Write};
+
+use tracing::warn;
+
+#[cfg(feature = "terminal")]
+use crate::terminal::{check_key, get_char, read_stdin, stdin_closed};
+
+/// Where the guest's keyboard input comes from and its display output goes:
+/// the KBSR/KBDR and DDR registers and the console TRAPs all go through the
+/// [`Vm`](crate::vm::Vm)'s console.
+pub trait Console: Any {
+    /// A key typed since the last call, without blocking; `None` if there is none yet.
+    fn read_key(&mut self) -> Option<u8>;
+
+    /// Show text the guest printed. A failure, e.g. stdout piped to a reader
+    /// that has gone away, ends the run with [`VmError::Io`](crate::error::VmError::Io).
+    fn write(&mut self, text: &str) -> io::Result<()>;
+
+    /// Push buffered output to its destination, e.g. when the run ends.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// True once no more input will ever arrive, so a guest waiting for a
+    /// key is halted instead of blocking forever.
+    fn input_closed(&self) -> bool {
+        false
+    }
+
+    /// Whether GETC and IN wait inside the step for a key. Consoles for
+    /// hosts that cannot block (a browser's event loop) return false: the
+    /// step then ends with [`StepOutcome::WaitingForInput`](crate::vm::StepOutcome::WaitingForInput)
+    /// and the TRAP is retried on the next one.
+    fn blocking(&self) -> bool {
+        true
+    }
+
+    /// A console for a clone of the VM. Consoles that cannot be duplicated
+    /// keep the default: an empty [`CapturedConsole`], so the clone neither
+    /// blocks on input nor prints to the same place.
+    fn fork(&self) -> Box<dyn Console> {
+        Box::new(CapturedConsole::default())
+    }
+}
+
+impl dyn Console {
+    /// The console as its concrete type, e.g. to collect a [`CapturedConsole`]'s output.
+    pub fn downcast_ref<T: Console>(&self) -> Option<&T> {
+        (self as &dyn Any).downcast_ref()
+    }
+
+    pub fn downcast_mut<T: Console>(&mut self) -> Option<&mut T> {
+        (self as &mut dyn Any).downcast_mut()
+    }
+}
+
+/* print a report (a state dump, the watchdog's) on stderr, on lines of its own; the
+   terminal may be in raw mode for the guest, which needs explicit carriage returns */
+pub(crate) fn eprint_report(text: &str) {
+    eprint!("\r\n{}", text.replace('\n', "\r\n"));
+}
+
+/// The host terminal: keys are read from stdin as they arrive (with the
+/// `terminal` feature; otherwise the guest never sees a key) and output is
+/// written to stdout. Once stdin reaches end of file — input redirected
+/// from a file, or the image itself piped in — its input is closed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TerminalConsole;
+
+impl Console for TerminalConsole {
+    fn read_key(&mut self) -> Option<u8> {
+        #[cfg(feature = "terminal")]
+        if check_key() {
+            /* stdin at end of file polls as readable but never yields a key */
+            return get_char();
+        }
+        None
+    }
+
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(text.as_bytes())?;
+        stdout.flush()
+    }
+
+    fn input_closed(&self) -> bool {
+        #[cfg(feature = "terminal")]
+        return stdin_closed();
+        #[cfg(not(feature = "terminal"))]
+        false
+    }
+
+    /* clones share the terminal */
+    fn fork(&self) -> Box<dyn Console> {
+        Box::new(TerminalConsole)
+    }
+}
+
+/// The console for pipes and CI, where stdin or stdout is not a terminal
+/// (`echo input | lc3-vm prog.obj > out.txt`): input is read from stdin in
+/// chunks as it arrives and handed out a key at a time, and output is
+/// buffered instead of flushed after every character. The output is
+/// flushed whenever the guest finds no key waiting, so a prompt is visible
+/// before the program blocks on its answer, and by [`Console::flush`].
+#[derive(Debug)]
+pub struct HeadlessConsole {
+    pending: VecDeque<u8>,
+    closed: bool,
+    output: BufWriter<Stdout>,
+}
+
+impl Default for HeadlessConsole {
+    fn default() -> Self {
+        HeadlessConsole {
+            pending: VecDeque::new(),
+            closed: false,
+            output: BufWriter::new(io::stdout()),
+        }
+    }
+}
+
+impl Console for HeadlessConsole {
+    fn read_key(&mut self) -> Option<u8> {
+        #[cfg(feature = "terminal")]
+        if self.pending.is_empty() && !self.closed && check_key() {
+            let mut chunk = [0u8; 4096];
+            match read_stdin(&mut chunk) {
+                Ok(0) | Err(_) => self.closed = true,
+                Ok(count) => self.pending.extend(&chunk[..count]),
+            }
+        }
+        let key = self.pending.pop_front();
+        if key.is_none() {
+            let _ = self.output.flush();
+        }
+        key
+    }
+
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        self.output.write_all(text.as_bytes())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+
+    fn input_closed(&self) -> bool {
+        self.closed && self.pending.is_empty()
+    }
+
+    /* clones write to the same stdout and read what arrives after the fork */
+    fn fork(&self) -> Box<dyn Console> {
+        Box::new(HeadlessConsole::default())
+    }
+}
+
+/// In-memory console for a VM that must not touch the host terminal, e.g.
+/// one of many running side by side or one under test: keyboard input comes
+/// from `input` and everything the guest prints is appended to `output`.
+///
+/// When the guest waits for a key and `input` is empty, the VM halts
+/// instead of blocking.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CapturedConsole {
+    pub input: VecDeque<u8>,
+    pub output: Vec<u8>,
+}
+
+impl CapturedConsole {
+    pub fn new(input: impl Into<Vec<u8>>) -> Self {
+        CapturedConsole {
+            input: input.into().into(),
+            output: Vec::new(),
+        }
+    }
+
+    /// Everything printed so far, decoded leniently.
+    pub fn output_string(&self) -> String {
+        String::from_utf8_lossy(&self.output).into_owned()
+    }
+}
+
+impl Console for CapturedConsole {
+    fn read_key(&mut self) -> Option<u8> {
+        self.input.pop_front()
+    }
+
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        self.output.extend_from_slice(text.as_bytes());
+        Ok(())
+    }
+
+    fn input_closed(&self) -> bool {
+        self.input.is_empty()
+    }
+
+    /* the clone has its own copy of the remaining input and the output so far */
+    fn fork(&self) -> Box<dyn Console> {
+        Box::new(self.clone())
+    }
+}
+
+/// Console for a host with its own event loop (a web page, a GUI, a C
+/// front end): the host queues keys in `input` and collects `output`.
+/// Unlike [`CapturedConsole`], running out of input never halts the guest
+/// and GETC never blocks: the step reports that it is waiting instead.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueuedConsole {
+    pub input: VecDeque<u8>,
+    pub output: Vec<u8>,
+}
+
+impl Console for QueuedConsole {
+    fn read_key(&mut self) -> Option<u8> {
+        self.input.pop_front()
+    }
+
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        self.output.extend_from_slice(text.as_bytes());
+        Ok(())
+    }
+
+    fn blocking(&self) -> bool {
+        false
+    }
+
+    fn fork(&self) -> Box<dyn Console> {
+        Box::new(self.clone())
+    }
+}
+
+/// What a [`ScriptedConsole`] does once its script is used up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EofPolicy {
+    /// Go on with keys typed at the host terminal, waiting for them as usual.
+    #[default]
+    Block,
+    /// Deliver NUL (x00) for every further key.
+    Zero,
+    /// Halt a guest that waits for another key.
+    Halt,
+}
+
+impl EofPolicy {
+    /// The policy named `block`, `zero` or `halt`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "block" => Some(EofPolicy::Block),
+            "zero" => Some(EofPolicy::Zero),
+            "halt" => Some(EofPolicy::Halt),
+            _ => None,
+        }
+    }
+}
+
+/// The host terminal with keyboard input scripted in advance: the bytes of
+/// `input` are delivered one by one, through KBSR/KBDR and the console
+/// TRAPs alike, exactly as if they had been typed; `on_eof` decides what
+/// happens after the last one. Output goes to stdout.
+#[derive(Debug, Clone, Default)]
+pub struct ScriptedConsole {
+    pub input: VecDeque<u8>,
+    pub on_eof: EofPolicy,
+}
+
+impl ScriptedConsole {
+    pub fn new(input: impl Into<Vec<u8>>, on_eof: EofPolicy) -> Self {
+        ScriptedConsole {
+            input: input.into().into(),
+            on_eof,
+        }
+    }
+}
+
+impl Console for ScriptedConsole {
+    fn read_key(&mut self) -> Option<u8> {
+        if let Some(key) = self.input.pop_front() {
+            return Some(key);
+        }
+        match self.on_eof {
+            EofPolicy::Block => TerminalConsole.read_key(),
+            EofPolicy::Zero => Some(0),
+            EofPolicy::Halt => None,
+        }
+    }
+
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        TerminalConsole.write(text)
+    }
+
+    fn input_closed(&self) -> bool {
+        match self.on_eof {
+            EofPolicy::Block => self.input.is_empty() && TerminalConsole.input_closed(),
+            EofPolicy::Zero => false,
+            EofPolicy::Halt => self.input.is_empty(),
+        }
+    }
+
+    /* the clone replays the rest of the script to the same terminal */
+    fn fork(&self) -> Box<dyn Console> {
+        Box::new(self.clone())
+    }
+}
+
+/// Wraps another console to copy everything the guest prints to `log`, a
+/// file for a long run to be kept and diffed afterwards, or a `Vec<u8>` to
+/// check once the run ends (find it again with
+/// `vm.console.downcast_ref::<TeeConsole<Vec<u8>>>()`). With `echo` off the
+/// output only goes to the log; input comes from the wrapped console either
+/// way.
+pub struct TeeConsole<W = Box<dyn Write>> {
+    pub inner: Box<dyn Console>,
+    pub log: W,
+    pub echo: bool,
+}
+
+impl<W: Write> TeeConsole<W> {
+    pub fn new(inner: Box<dyn Console>, log: W, echo: bool) -> Self {
+        TeeConsole { inner, log, echo }
+    }
+}
+
+impl<W: Write + 'static> Console for TeeConsole<W> {
+    fn read_key(&mut self) -> Option<u8> {
+        self.inner.read_key()
+    }
+
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        if let Err(err) = self.log.write_all(text.as_bytes()) {
+            warn!(%err, "failed to write console log");
+        }
+        if self.echo { self.inner.write(text) } else { Ok(()) }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.log.flush()?;
+        self.inner.flush()
+    }
+
+    fn input_closed(&self) -> bool {
+        self.inner.input_closed()
+    }
+
+    fn blocking(&self) -> bool {
+        self.inner.blocking()
+    }
+}
+
+#[cfg(all(test, feature = "assembler"))]
+mod tests {
+    use super::*;
+    use crate::error::VmError;
+    use crate::program::Program;
+    use crate::vm::{REGISTER, RunState, Vm};
+
+    /* read keys with GETC and print each one until a NUL arrives */
+    const ECHO: &str = "
+                .ORIG x3000
+        LOOP    GETC
+                ADD R0, R0, #0
+                BRz DONE
+                OUT
+                BR LOOP
+        DONE    HALT
+                .END
+    ";
+
+    fn echo(console: impl Console) -> Vm {
+        let mut vm = Vm::new();
+        vm.console = Box::new(console);
+        vm.load_program(&Program::from_assembly(ECHO).unwrap());
+        vm
+    }
+
+    #[test]
+    fn captured_console_delivers_input_in_order_and_keeps_the_output() {
+        let mut vm = echo(CapturedConsole::new(b"abc\0".to_vec()));
+        assert_eq!(vm.run().unwrap(), RunState::Halted);
+        assert_eq!(vm.registers[REGISTER::PC as usize], 0x3006);
+        let console = vm.console.downcast_ref::<CapturedConsole>().unwrap();
+        assert_eq!(console.output_string(), "abcHALT");
+        assert!(console.input.is_empty());
+    }
+
+    #[test]
+    fn captured_console_halts_a_program_waiting_past_its_input() {
+        let mut vm = echo(CapturedConsole::new(b"ab".to_vec()));
+        assert_eq!(vm.run().unwrap(), RunState::Halted);
+        /* halted on the GETC with nothing left to read, which has not run */
+        assert_eq!(vm.registers[REGISTER::PC as usize], 0x3000);
+        assert_eq!(vm.console.downcast_ref::<CapturedConsole>().unwrap().output_string(), "ab");
+    }
+
+    #[test]
+    fn zero_policy_delivers_nul_after_the_script() {
+        let mut vm = echo(ScriptedConsole::new(b"hi".to_vec(), EofPolicy::Zero));
+        assert_eq!(vm.run().unwrap(), RunState::Halted);
+        /* the NUL ended the loop at its HALT */
+        assert_eq!(vm.registers[REGISTER::PC as usize], 0x3006);
+    }
+
+    #[test]
+    fn halt_policy_halts_a_program_waiting_past_the_script() {
+        let mut vm = echo(ScriptedConsole::new(b"hi".to_vec(), EofPolicy::Halt));
+        assert_eq!(vm.run().unwrap(), RunState::Halted);
+        assert_eq!(vm.registers[REGISTER::PC as usize], 0x3000);
+        assert_eq!(vm.registers[0], b'i' as u16);
+    }
+
+    #[test]
+    fn eof_policies_are_named_on_the_command_line() {
+        assert_eq!(EofPolicy::parse("block"), Some(EofPolicy::Block));
+        assert_eq!(EofPolicy::parse("zero"), Some(EofPolicy::Zero));
+        assert_eq!(EofPolicy::parse("halt"), Some(EofPolicy::Halt));
+        assert_eq!(EofPolicy::parse("wait"), None);
+    }
+
+    /* stdout piped to a reader that has gone away */
+    struct Closed;
+
+    impl Console for Closed {
+        fn read_key(&mut self) -> Option<u8> {
+            None
+        }
+
+        fn write(&mut self, _text: &str) -> io::Result<()> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+    }
+
+    #[test]
+    fn a_failed_write_ends_the_run_with_an_error() {
+        let mut vm = Vm::new();
+        vm.console = Box::new(Closed);
+        vm.load_program(&Program::from_assembly(".ORIG x3000\nLOOP OUT\nBR LOOP\n.END").unwrap());
+        match vm.run() {
+            Err(VmError::Io(err)) => assert_eq!(err.kind(), io::ErrorKind::BrokenPipe),
+            other => panic!("expected a broken pipe, got {:?}", other),
+        }
+        assert_eq!(vm.instruction_count, 1);
+    }
+}
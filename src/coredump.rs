@@ -0,0 +1,138 @@
+//! Core files: what the machine looked like when a run ended abnormally
+//! (an illegal opcode, a limit, a host error), written by `lc3-vm run
+//! --core FILE` and opened in the debugger with `--load-core FILE` to look
+//! around after the fact. A core holds the full [`VmState`] plus what the
+//! VM followed on the host side: the recent-instruction ring and the calls
+//! in progress.
+
+use std::fs;
+use std::path::Path;
+
+use crate::callstack::{CallStack, Frame};
+use crate::error::VmError;
+use crate::recent::{Executed, RecentInstructions};
+use crate::state::VmState;
+use crate::vm::{REGISTER, Vm};
+
+/* "LC3C" and a format version, then the reason, the ring, the frames and a snapshot */
+const MAGIC: &[u8; 4] = b"LC3C";
+const FORMAT_VERSION: u16 = 1;
+
+/* words of one recent instruction: count, PC, instruction, registers before and after */
+const EXECUTED_WORDS: usize = 6 + 2 * REGISTER::COUNT as usize;
+
+/// A machine stopped by something going wrong.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoreDump {
+    /// Why the run stopped, e.g. `illegal opcode 0xD000 at x3005 (B)`.
+    pub reason: String,
+    pub state: VmState,
+    /// The last instructions executed, oldest first; empty unless the VM kept them.
+    pub recent: Vec<Executed>,
+    /// The calls in progress, outermost first; empty unless the VM followed them.
+    pub frames: Vec<Frame>,
+}
+
+impl CoreDump {
+    /// Capture `vm` as it is now, stopped for `reason`.
+    pub fn capture(vm: &Vm, reason: &str) -> Self {
+        CoreDump {
+            reason: reason.to_string(),
+            state: vm.state(),
+            recent: vm.recent.as_ref().map(|recent| recent.iter().cloned().collect()).unwrap_or_default(),
+            frames: vm.call_stack.as_ref().map(|call_stack| call_stack.frames().to_vec()).unwrap_or_default(),
+        }
+    }
+
+    /// Put `vm` in the state of the core, with its recent instructions and
+    /// call stack, so the debugger can look at it.
+    pub fn restore(&self, vm: &mut Vm) -> Result<(), VmError> {
+        vm.restore_state(&self.state)?;
+        let capacity = vm.recent.as_ref().map_or(0, |recent| recent.capacity());
+        vm.recent = Some(RecentInstructions::from_executed(capacity, self.recent.clone()));
+        vm.call_stack = Some(CallStack::from_frames(self.frames.clone()));
+        Ok(())
+    }
+
+    /// Encode the core: a `LC3C` header and version, the reason, the
+    /// recent instructions and the frames as big-endian words, then the
+    /// state as [`VmState::to_bytes`] writes it.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut words = vec![FORMAT_VERSION];
+        words.push(self.reason.len() as u16);
+        words.extend(self.reason.bytes().map(u16::from));
+        words.push(self.recent.len() as u16);
+        for executed in &self.recent {
+            words.extend((0..4).rev().map(|i| (executed.count >> (i * 16)) as u16));
+            words.extend([executed.pc, executed.instruction]);
+            words.extend_from_slice(&executed.before);
+            words.extend_from_slice(&executed.after);
+        }
+        words.push(self.frames.len() as u16);
+        words.extend(self.frames.iter().flat_map(|frame| [frame.call_site, frame.entry, frame.return_address]));
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(words.iter().flat_map(|word| word.to_be_bytes()));
+        bytes.extend(self.state.to_bytes());
+        bytes
+    }
+
+    /// Decode a core written by [`CoreDump::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, VmError> {
+        let mut body = bytes.strip_prefix(MAGIC).ok_or_else(|| invalid("not a core file"))?;
+        let version = take_word(&mut body)?;
+        if version != FORMAT_VERSION {
+            return Err(invalid(&format!("unsupported core version {}", version)));
+        }
+        let len = take_word(&mut body)? as usize;
+        let reason: Vec<u8> = take_words(&mut body, len)?.iter().map(|&byte| byte as u8).collect();
+        let mut recent = Vec::new();
+        for _ in 0..take_word(&mut body)? {
+            let words = take_words(&mut body, EXECUTED_WORDS)?;
+            let (count, rest) = words.split_at(4);
+            let (before, after) = rest[2..].split_at(REGISTER::COUNT as usize);
+            recent.push(Executed {
+                count: count.iter().fold(0u64, |count, &word| count << 16 | word as u64),
+                pc: rest[0],
+                instruction: rest[1],
+                before: before.try_into().unwrap(),
+                after: after.try_into().unwrap(),
+            });
+        }
+        let len = take_word(&mut body)? as usize;
+        let frames = take_words(&mut body, len * 3)?
+            .chunks_exact(3)
+            .map(|frame| Frame { call_site: frame[0], entry: frame[1], return_address: frame[2] })
+            .collect();
+        Ok(CoreDump { reason: String::from_utf8_lossy(&reason).into_owned(), state: VmState::from_bytes(body)?, recent, frames })
+    }
+
+    /// Write the core to a file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), VmError> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    /// Read a core file written by [`CoreDump::save`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, VmError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
+
+fn invalid(reason: &str) -> VmError {
+    VmError::InvalidState(reason.to_string())
+}
+
+/* the next `count` big-endian words of `bytes`, which move past them */
+fn take_words(bytes: &mut &[u8], count: usize) -> Result<Vec<u16>, VmError> {
+    if count * 2 > bytes.len() {
+        return Err(invalid("truncated core file"));
+    }
+    let (taken, rest) = bytes.split_at(count * 2);
+    *bytes = rest;
+    Ok(taken.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect())
+}
+
+fn take_word(bytes: &mut &[u8]) -> Result<u16, VmError> {
+    Ok(take_words(bytes, 1)?[0])
+}
@@ -0,0 +1,257 @@
+//! A minimal LC-3 execution core that builds without `std` (disable the
+//! default features), for embedded targets. The caller owns the memory and
+//! the console, handing them to [`Cpu::step`] through the [`Memory`] and
+//! [`Io`] traits; nothing is allocated.
+//!
+//! The core runs user programs: the standard console TRAPs are built in,
+//! and the device region is whatever the caller's [`Memory`] makes of it.
+//! Interrupts, privilege and the debugging facilities live in the full
+//! `vm::Vm` (with `std`), so RTI and the reserved opcode fault here. `Vm`
+//! executes its instructions on this core, as [`Memory`] and [`Io`] itself.
+
+use crate::instruction::{Instruction, Operand, decode};
+
+/// The 64K words the core executes from, provided by the caller.
+pub trait Memory {
+    fn read(&mut self, address: u16) -> u16;
+    fn write(&mut self, address: u16, value: u16);
+}
+
+/// A plain array serves as memory with no devices mapped.
+impl Memory for [u16; 1 << 16] {
+    fn read(&mut self, address: u16) -> u16 {
+        self[address as usize]
+    }
+
+    fn write(&mut self, address: u16, value: u16) {
+        self[address as usize] = value;
+    }
+}
+
+/// The console behind the GETC, OUT, PUTS, IN and PUTSP traps.
+pub trait Io {
+    /// The next key, without blocking; `None` if none has arrived yet.
+    fn read_key(&mut self) -> Option<u8>;
+
+    /// Show one character the guest printed.
+    fn write_char(&mut self, character: u8);
+}
+
+/* a caller's memory and console side by side, for the traps that use both */
+struct Attached<'a, M, I> {
+    memory: &'a mut M,
+    io: &'a mut I,
+}
+
+impl<M: Memory, I: Io> Memory for Attached<'_, M, I> {
+    fn read(&mut self, address: u16) -> u16 {
+        self.memory.read(address)
+    }
+
+    fn write(&mut self, address: u16, value: u16) {
+        self.memory.write(address, value);
+    }
+}
+
+impl<M: Memory, I: Io> Io for Attached<'_, M, I> {
+    fn read_key(&mut self) -> Option<u8> {
+        self.io.read_key()
+    }
+
+    fn write_char(&mut self, character: u8) {
+        self.io.write_char(character);
+    }
+}
+
+/// What a single [`Cpu::step`] did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuStep {
+    /// The instruction executed normally (a TRAP the core does not know is skipped).
+    Continued,
+    /// HALT ran, or the CPU had already halted.
+    Halted,
+    /// GETC or IN found no key; the PC is left on the TRAP so the next step retries.
+    WaitingForInput,
+    /// The instruction at `pc` cannot run in this core; the CPU halts with the PC on it.
+    Faulted { pc: u16, instruction: u16 },
+}
+
+/// Registers and run state of the core.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cpu {
+    /// R0-R7.
+    pub registers: [u16; 8],
+    pub pc: u16,
+    /// The condition code: exactly one of N (`0b100`), Z (`0b010`) or P (`0b001`).
+    pub cond: u16,
+    pub halted: bool,
+}
+
+const FLAG_POSITIVE: u16 = 1 << 0;
+const FLAG_ZERO: u16 = 1 << 1;
+const FLAG_NEGATIVE: u16 = 1 << 2;
+
+impl Cpu {
+    /// A CPU about to execute the instruction at `pc`, with cleared registers and Z set.
+    pub fn new(pc: u16) -> Self {
+        Cpu {
+            registers: [0; 8],
+            pc,
+            cond: FLAG_ZERO,
+            halted: false,
+        }
+    }
+
+    /// Execute the instruction at the PC.
+    pub fn step(&mut self, memory: &mut impl Memory, io: &mut impl Io) -> CpuStep {
+        if self.halted {
+            return CpuStep::Halted;
+        }
+        let pc = self.pc;
+        let word = memory.read(pc);
+        self.pc = pc.wrapping_add(1);
+        match self.execute(pc, word, memory) {
+            None => CpuStep::Continued,
+            Some(Instruction::Trap { vector }) => self.trap(pc, vector, &mut Attached { memory, io }),
+            Some(_) => {
+                self.pc = pc;
+                self.halted = true;
+                CpuStep::Faulted { pc, instruction: word }
+            }
+        }
+    }
+
+    /// Execute `word`, fetched from `pc`, with the PC already moved past it.
+    /// TRAP, RTI and the reserved opcode are left to the caller and returned
+    /// decoded: [`Cpu::step`] runs the console traps and faults on the
+    /// others, and `vm::Vm` runs all three its own way.
+    pub fn execute(&mut self, pc: u16, word: u16, memory: &mut impl Memory) -> Option<Instruction> {
+        let relative = |offset: i16| pc.wrapping_add(1).wrapping_add(offset as u16);
+        match decode(word) {
+            Instruction::Br { n, z, p, offset } => {
+                let mask = (n as u16) << 2 | (z as u16) << 1 | p as u16;
+                if mask & self.cond != 0 {
+                    self.pc = relative(offset);
+                }
+            }
+            Instruction::Add { dr, sr1, src2 } => {
+                let value = self.registers[sr1 as usize].wrapping_add(self.operand(src2));
+                self.set(dr, value);
+            }
+            Instruction::And { dr, sr1, src2 } => {
+                let value = self.registers[sr1 as usize] & self.operand(src2);
+                self.set(dr, value);
+            }
+            Instruction::Not { dr, sr } => self.set(dr, !self.registers[sr as usize]),
+            Instruction::Ld { dr, offset } => {
+                let value = memory.read(relative(offset));
+                self.set(dr, value);
+            }
+            Instruction::Ldi { dr, offset } => {
+                let address = memory.read(relative(offset));
+                let value = memory.read(address);
+                self.set(dr, value);
+            }
+            Instruction::Ldr { dr, base, offset } => {
+                let value = memory.read(self.registers[base as usize].wrapping_add(offset as u16));
+                self.set(dr, value);
+            }
+            Instruction::Lea { dr, offset } => self.set(dr, relative(offset)),
+            Instruction::St { sr, offset } => memory.write(relative(offset), self.registers[sr as usize]),
+            Instruction::Sti { sr, offset } => {
+                let address = memory.read(relative(offset));
+                memory.write(address, self.registers[sr as usize]);
+            }
+            Instruction::Str { sr, base, offset } => {
+                memory.write(self.registers[base as usize].wrapping_add(offset as u16), self.registers[sr as usize]);
+            }
+            Instruction::Jmp { base } => self.pc = self.registers[base as usize],
+            Instruction::Jsr { offset } => {
+                self.registers[7] = self.pc;
+                self.pc = relative(offset);
+            }
+            Instruction::Jsrr { base } => {
+                let target = self.registers[base as usize];
+                self.registers[7] = self.pc;
+                self.pc = target;
+            }
+            instruction @ (Instruction::Trap { .. } | Instruction::Rti | Instruction::Reserved(_)) => return Some(instruction),
+        }
+        None
+    }
+
+    /// Run the built-in routine for TRAP `vector`, fetched from `pc`, on a
+    /// `system` that is both the memory and the console; [`Cpu::step`] does
+    /// this for every TRAP. An unknown vector does nothing.
+    pub fn trap(&mut self, pc: u16, vector: u8, system: &mut (impl Memory + Io)) -> CpuStep {
+        self.registers[7] = self.pc;
+        match vector {
+            /* GETC, IN */
+            0x20 | 0x23 => {
+                let Some(key) = system.read_key() else {
+                    self.pc = pc;
+                    return CpuStep::WaitingForInput;
+                };
+                if vector == 0x23 {
+                    system.write_char(key);
+                }
+                self.set(0, key as u16);
+            }
+            /* OUT */
+            0x21 => system.write_char(self.registers[0] as u8),
+            /* PUTS */
+            0x22 => {
+                let mut address = self.registers[0];
+                loop {
+                    let word = system.read(address);
+                    if word == 0 {
+                        break;
+                    }
+                    system.write_char(word as u8);
+                    address = address.wrapping_add(1);
+                }
+            }
+            /* PUTSP */
+            0x24 => {
+                let mut address = self.registers[0];
+                loop {
+                    let word = system.read(address);
+                    if word == 0 {
+                        break;
+                    }
+                    system.write_char(word as u8);
+                    if word >> 8 != 0 {
+                        system.write_char((word >> 8) as u8);
+                    }
+                    address = address.wrapping_add(1);
+                }
+            }
+            /* HALT */
+            0x25 => {
+                self.halted = true;
+                return CpuStep::Halted;
+            }
+            _ => {}
+        }
+        CpuStep::Continued
+    }
+
+    fn operand(&self, operand: Operand) -> u16 {
+        match operand {
+            Operand::Register(sr2) => self.registers[sr2 as usize],
+            Operand::Immediate(value) => value as u16,
+        }
+    }
+
+    /* write a result register and update the condition code from it */
+    fn set(&mut self, dr: u8, value: u16) {
+        self.registers[dr as usize] = value;
+        self.cond = if value == 0 {
+            FLAG_ZERO
+        } else if value >> 15 == 1 {
+            FLAG_NEGATIVE
+        } else {
+            FLAG_POSITIVE
+        };
+    }
+}
@@ -0,0 +1,726 @@
+//! Debug Adapter Protocol server, so VS Code and other editors can debug a
+//! program: `lc3-vm dap` speaks the protocol on stdin/stdout for a `launch`
+//! configuration, and `lc3-vm run --dap :4711 prog.obj` waits for an editor
+//! to `attach` over TCP.
+//!
+//! Breakpoints are placed by line in the program's assembly source: a
+//! `.asm` program is assembled when launched, and a `.obj` one uses the
+//! `.asm` beside it (and its labels the `.sym`). Memory references are word
+//! addresses such as `x3000`; the offsets and counts of memory requests are
+//! in bytes, two per word, low byte first.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{Value, json};
+use tracing::debug;
+
+use crate::address::parse_address;
+use crate::asm;
+use crate::console::QueuedConsole;
+use crate::debugger::{Debugger, DebuggerAction, parse_register, parse_value, report_hits};
+use crate::disasm;
+use crate::error::VmError;
+use crate::program::Program;
+use crate::summary;
+use crate::vm::{REGISTER, RunState, StepOutcome, Vm};
+
+/* instructions run between checks for requests (e.g. pause) while the program runs */
+const CHUNK: u64 = 10_000;
+
+/* how long to wait for a request while the program waits for input */
+const INPUT_POLL: Duration = Duration::from_millis(20);
+
+/* the LC-3 has one thread, and one scope of variables: the registers */
+const THREAD_ID: u64 = 1;
+const REGISTERS_REFERENCE: u64 = 1;
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/* bytes a memory reference and offset can name: two per word */
+const BYTE_SPACE: i64 = 0x20000;
+
+struct Session<W: Write> {
+    output: W,
+    seq: u64,
+    debugger: Debugger,
+    /* attached to a program started by `run --dap`, which goes on running on disconnect */
+    attached: bool,
+    stop_on_entry: bool,
+    /* the assembly source, and the line and address of each of its statements */
+    source: Option<PathBuf>,
+    lines: Vec<(usize, u16)>,
+    /* one past the program's last word */
+    end: u32,
+    line_breakpoints: BTreeSet<u16>,
+    function_breakpoints: BTreeSet<u16>,
+    running: bool,
+    waiting: bool,
+    exited: bool,
+    /* bytes of the guest's output already sent as output events */
+    printed: usize,
+}
+
+/// Debug `vm` for the editor sending requests on `input` and reading
+/// responses and events from `output` until it disconnects. The program
+/// is loaded by a `launch` request, or was already loaded for `attach`;
+/// after an `attach` the program goes on running once the editor leaves
+/// ([`DebuggerAction::Continue`]), otherwise it is stopped
+/// ([`DebuggerAction::Quit`]). The guest's console output is sent to the
+/// editor, and the debug console's `input TEXT` types a line at its
+/// keyboard; other debug console lines are debugger commands (`help`).
+pub fn serve(vm: &mut Vm, input: impl Read + Send + 'static, output: impl Write) -> io::Result<DebuggerAction> {
+    let original = std::mem::replace(&mut vm.console, Box::new(QueuedConsole::default()));
+    let requests = spawn_reader(input);
+    let mut session = Session {
+        output,
+        seq: 0,
+        debugger: Debugger::new(),
+        attached: false,
+        stop_on_entry: false,
+        source: None,
+        lines: Vec::new(),
+        end: 0,
+        line_breakpoints: BTreeSet::new(),
+        function_breakpoints: BTreeSet::new(),
+        running: false,
+        waiting: false,
+        exited: false,
+        printed: 0,
+    };
+    let result = session.serve(vm, &requests);
+    vm.console = original;
+    result
+}
+
+/* read messages on their own thread, so a pause request arrives while the program runs */
+fn spawn_reader(input: impl Read + Send + 'static) -> Receiver<Value> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut input = BufReader::new(input);
+        while let Ok(Some(message)) = read_message(&mut input) {
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/* one `Content-Length: N` framed JSON message; None at end of input */
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("Content-Length")
+        {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let Some(length) = length else {
+        return Err(io::Error::new(ErrorKind::InvalidData, "message without a Content-Length header"));
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (index, &byte)| group | (byte as u32) << (16 - 8 * index));
+        for index in 0..4 {
+            if index <= chunk.len() {
+                out.push(BASE64[(group >> (18 - 6 * index) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let (mut group, mut bits) = (0u32, 0);
+    for byte in text.bytes().filter(|&byte| byte != b'=' && !byte.is_ascii_whitespace()) {
+        group = group << 6 | BASE64.iter().position(|&digit| digit == byte)? as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((group >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+/* the byte address a memory reference (a word address) and a byte offset name */
+fn byte_address(arguments: &Value) -> Result<i64, String> {
+    let reference = arguments["memoryReference"].as_str().unwrap_or_default();
+    let word = parse_address(reference).ok_or_else(|| format!("`{}` is not an address", reference))?;
+    Ok(word as i64 * 2 + arguments["offset"].as_i64().unwrap_or(0))
+}
+
+fn memory_byte(vm: &Vm, address: i64) -> u8 {
+    let word = vm.memory[(address / 2) as usize];
+    if address % 2 == 0 { word as u8 } else { (word >> 8) as u8 }
+}
+
+/* `x0005 (5)`: hex, and signed decimal */
+fn format_word(value: u16) -> String {
+    format!("x{:04X} ({})", value, value as i16)
+}
+
+/* a register by name for watch and hover expressions */
+fn register_index(name: &str) -> Option<usize> {
+    match name.to_ascii_uppercase().as_str() {
+        "PC" => Some(REGISTER::PC as usize),
+        name => parse_register(name).map(|register| register as usize),
+    }
+}
+
+fn capabilities() -> Value {
+    json!({
+        "supportsConfigurationDoneRequest": true,
+        "supportsFunctionBreakpoints": true,
+        "supportsSetVariable": true,
+        "supportsEvaluateForHovers": true,
+        "supportsReadMemoryRequest": true,
+        "supportsWriteMemoryRequest": true,
+        "supportsDisassembleRequest": true,
+        "supportsTerminateRequest": true,
+    })
+}
+
+impl<W: Write> Session<W> {
+    fn serve(&mut self, vm: &mut Vm, requests: &Receiver<Value>) -> io::Result<DebuggerAction> {
+        loop {
+            let message = if !self.running {
+                requests.recv().ok()
+            } else if self.waiting {
+                match requests.recv_timeout(INPUT_POLL) {
+                    Ok(message) => Some(message),
+                    Err(RecvTimeoutError::Timeout) => {
+                        self.run_chunk(vm)?;
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => None,
+                }
+            } else {
+                match requests.try_recv() {
+                    Ok(message) => Some(message),
+                    Err(TryRecvError::Empty) => {
+                        self.run_chunk(vm)?;
+                        continue;
+                    }
+                    Err(TryRecvError::Disconnected) => None,
+                }
+            };
+            /* the editor went away without disconnecting */
+            let Some(message) = message else {
+                return Ok(DebuggerAction::Quit);
+            };
+            if message["type"] == "request"
+                && let Some(action) = self.handle(vm, &message)?
+            {
+                return Ok(action);
+            }
+        }
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        let body = message.to_string();
+        write!(self.output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        self.output.flush()
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    fn reply(&mut self, request: &Value, reply: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": reply.is_ok(),
+        });
+        match reply {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)
+    }
+
+    fn print(&mut self, category: &str, text: &str) -> io::Result<()> {
+        self.event("output", json!({ "category": category, "output": text }))
+    }
+
+    /* pass on what the guest printed since last time */
+    fn flush_output(&mut self, vm: &Vm) -> io::Result<()> {
+        let Some(console) = vm.console.downcast_ref::<QueuedConsole>() else {
+            return Ok(());
+        };
+        if console.output.len() > self.printed {
+            let text = String::from_utf8_lossy(&console.output[self.printed..]).into_owned();
+            self.printed = console.output.len();
+            self.print("stdout", &text)?;
+        }
+        Ok(())
+    }
+
+    /* carry out a request; Some ends the session */
+    fn handle(&mut self, vm: &mut Vm, request: &Value) -> io::Result<Option<DebuggerAction>> {
+        let command = request["command"].as_str().unwrap_or_default();
+        let arguments = &request["arguments"];
+        debug!(command, "dap request");
+        let stopped = !self.running && !self.exited;
+        let count = vm.instruction_count;
+        let mut action = None;
+        let reply = match command {
+            "initialize" => Ok(capabilities()),
+            "launch" | "attach" => self.launch(vm, arguments, command == "attach"),
+            "setBreakpoints" => self.set_breakpoints(vm, arguments),
+            "setFunctionBreakpoints" => Ok(self.set_function_breakpoints(vm, arguments)),
+            "setExceptionBreakpoints" => Ok(json!({ "breakpoints": [] })),
+            "configurationDone" => Ok(Value::Null),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "LC-3" }] })),
+            "stackTrace" => Ok(self.stack_trace(vm)),
+            "scopes" => Ok(json!({ "scopes": [{
+                "name": "Registers",
+                "presentationHint": "registers",
+                "variablesReference": REGISTERS_REFERENCE,
+                "expensive": false,
+            }] })),
+            "variables" => Ok(variables(vm, arguments)),
+            "setVariable" => set_variable(vm, arguments),
+            "evaluate" => self.evaluate(vm, arguments, &mut action),
+            "readMemory" => read_memory(vm, arguments),
+            "writeMemory" => write_memory(vm, arguments),
+            "disassemble" => self.disassemble(vm, arguments),
+            "continue" => Ok(json!({ "allThreadsContinued": true })),
+            "next" | "stepIn" | "stepOut" if !stopped => Err("the program is not stopped".to_string()),
+            "next" | "stepIn" | "stepOut" | "pause" => Ok(Value::Null),
+            "disconnect" => {
+                let terminate = arguments["terminateDebuggee"].as_bool().unwrap_or(!self.attached);
+                action = Some(if terminate { DebuggerAction::Quit } else { DebuggerAction::Continue });
+                Ok(Value::Null)
+            }
+            "terminate" => {
+                action = Some(DebuggerAction::Quit);
+                Ok(Value::Null)
+            }
+            _ => Err(format!("unsupported request `{}`", command)),
+        };
+        self.reply(request, reply)?;
+
+        match (command, action) {
+            ("initialize", _) => self.event("initialized", json!({}))?,
+            ("configurationDone", _) if self.stop_on_entry => self.stopped(vm, "entry")?,
+            ("configurationDone" | "continue", _) | ("evaluate", Some(DebuggerAction::Continue)) => self.resume(vm)?,
+            ("next" | "stepIn" | "stepOut", _) if stopped => self.step(vm, command)?,
+            /* debug console commands that ran instructions (step, next, ...) move the editor along */
+            ("evaluate", None) if vm.instruction_count != count => self.stopped(vm, "step")?,
+            ("pause", _) if self.running => self.stopped(vm, "pause")?,
+            ("disconnect", action) => return Ok(action),
+            (_, Some(DebuggerAction::Quit)) => {
+                self.event("terminated", json!({}))?;
+                return Ok(Some(DebuggerAction::Quit));
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    /* `program` is loaded by launch; for attach it only locates the source */
+    fn launch(&mut self, vm: &mut Vm, arguments: &Value, attach: bool) -> Result<Value, String> {
+        self.attached = attach;
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+        if let Some(path) = arguments["program"].as_str() {
+            let path = Path::new(path);
+            let source = path.with_extension("asm");
+            let program = if path.extension().is_some_and(|extension| extension == "asm") {
+                let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+                let (program, lines) = asm::assemble_with_lines(&text).map_err(|err| format!("{}: {}", path.display(), err))?;
+                self.source = Some(path.to_path_buf());
+                self.lines = lines;
+                program
+            } else {
+                if source.is_file()
+                    && let Ok((_, lines)) = fs::read_to_string(&source).map_err(|_| ()).and_then(|text| asm::assemble_with_lines(&text).map_err(|_| ()))
+                {
+                    self.source = Some(source);
+                    self.lines = lines;
+                }
+                let mut program = fs::read(path)
+                    .map_err(VmError::from)
+                    .and_then(|bytes| Program::from_bytes(&bytes))
+                    .map_err(|err| format!("{}: {}", path.display(), err))?;
+                let symbols = path.with_extension("sym");
+                if symbols.is_file() {
+                    program.load_symbols(&symbols).map_err(|err| format!("{}: {}", symbols.display(), err))?;
+                }
+                program
+            };
+            self.end = program.origin as u32 + program.words.len() as u32;
+            if !attach {
+                vm.load_program(&program);
+                vm.set_entry(program.origin);
+            }
+            vm.symbols.extend(&program.symbols);
+        }
+        Ok(Value::Null)
+    }
+
+    fn is_source(&self, path: &str) -> bool {
+        let Some(source) = &self.source else {
+            return false;
+        };
+        match (fs::canonicalize(source), fs::canonicalize(path)) {
+            (Ok(source), Ok(path)) => source == path,
+            _ => source == Path::new(path),
+        }
+    }
+
+    /* the line of the statement at `address`, or of the one whose words it lies in */
+    fn line_of(&self, address: u16) -> Option<usize> {
+        if address as u32 >= self.end {
+            return None;
+        }
+        self.lines
+            .iter().filter(|(_, start)| *start <= address).max_by_key(|(_, start)| *start).map(|&(line, _)| line)
+    }
+
+    /* replace the breakpoints in `old` with those in `new`, keeping any set elsewhere */
+    fn replace_breakpoints(vm: &mut Vm, old: &BTreeSet<u16>, new: &BTreeSet<u16>, other: &BTreeSet<u16>) {
+        for address in old.difference(other) {
+            vm.breakpoints.remove(address);
+        }
+        vm.breakpoints.extend(new);
+    }
+
+    /* a breakpoint at each line goes on the first statement at or after it */
+    fn set_breakpoints(&mut self, vm: &mut Vm, arguments: &Value) -> Result<Value, String> {
+        let path = arguments["source"]["path"].as_str().unwrap_or_default();
+        let requested = arguments["breakpoints"].as_array().cloned().unwrap_or_default();
+        if !self.is_source(path) {
+            let breakpoints: Vec<Value> = requested
+                .iter()
+                .map(|_| json!({ "verified": false, "message": "not the source of the program being debugged" }))
+                .collect();
+            return Ok(json!({ "breakpoints": breakpoints }));
+        }
+        let mut addresses = BTreeSet::new();
+        let mut breakpoints = Vec::new();
+        for breakpoint in &requested {
+            let line = breakpoint["line"].as_u64().unwrap_or(0) as usize;
+            match self.lines.iter().filter(|(statement, _)| *statement >= line).min_by_key(|(statement, _)| *statement) {
+                Some(&(line, address)) => {
+                    addresses.insert(address);
+                    breakpoints.push(json!({ "verified": true, "line": line, "instructionReference": format!("x{:04X}", address) }));
+                }
+                None => breakpoints.push(json!({ "verified": false, "message": "no instruction at or after this line" })),
+            }
+        }
+        Self::replace_breakpoints(vm, &self.line_breakpoints, &addresses, &self.function_breakpoints);
+        self.line_breakpoints = addresses;
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    /* function breakpoints name labels */
+    fn set_function_breakpoints(&mut self, vm: &mut Vm, arguments: &Value) -> Value {
+        let mut addresses = BTreeSet::new();
+        let mut breakpoints = Vec::new();
+        for breakpoint in arguments["breakpoints"].as_array().into_iter().flatten() {
+            let name = breakpoint["name"].as_str().unwrap_or_default();
+            match vm.symbols.resolve(name) {
+                Some(address) => {
+                    addresses.insert(address);
+                    let mut breakpoint = json!({ "verified": true, "instructionReference": format!("x{:04X}", address) });
+                    if let Some(line) = self.line_of(address) {
+                        breakpoint["line"] = json!(line);
+                    }
+                    breakpoints.push(breakpoint);
+                }
+                None => breakpoints.push(json!({ "verified": false, "message": format!("no label `{}`", name) })),
+            }
+        }
+        Self::replace_breakpoints(vm, &self.function_breakpoints, &addresses, &self.line_breakpoints);
+        self.function_breakpoints = addresses;
+        json!({ "breakpoints": breakpoints })
+    }
+
+    /* one frame, at the PC: the LC-3 keeps no call stack to walk */
+    /* the PC, then the call site of each call in progress */
+    fn stack_trace(&self, vm: &Vm) -> Value {
+        let pc = vm.registers[REGISTER::PC as usize];
+        let call_sites = vm.call_stack.iter().flat_map(|call_stack| call_stack.frames().iter().rev().map(|frame| frame.call_site));
+        let frames: Vec<Value> = std::iter::once(pc)
+            .chain(call_sites)
+            .enumerate()
+            .map(|(id, address)| {
+                let mut frame = json!({
+                    "id": id,
+                    "name": vm.symbols.name_for(address).unwrap_or_else(|| format!("x{:04X}", address)),
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": format!("x{:04X}", address),
+                });
+                if let (Some(source), Some(line)) = (&self.source, self.line_of(address)) {
+                    frame["source"] = self.source_json(source);
+                    frame["line"] = json!(line);
+                    frame["column"] = json!(1);
+                }
+                frame
+            })
+            .collect();
+        json!({ "totalFrames": frames.len(), "stackFrames": frames })
+    }
+
+    fn source_json(&self, source: &Path) -> Value {
+        let name = source.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        json!({ "name": name, "path": source.display().to_string() })
+    }
+
+    /* the debug console runs debugger commands; watches and hovers name a register, label or address */
+    fn evaluate(&mut self, vm: &mut Vm, arguments: &Value, action: &mut Option<DebuggerAction>) -> Result<Value, String> {
+        let expression = arguments["expression"].as_str().unwrap_or_default().trim();
+        if arguments["context"] != "repl" {
+            if let Some(register) = register_index(expression) {
+                return Ok(json!({ "result": format_word(vm.registers[register]), "variablesReference": 0 }));
+            }
+            let address = vm
+                .symbols
+                .resolve(expression)
+                .ok_or_else(|| format!("`{}` is not a register, label or address", expression))?;
+            return Ok(json!({
+                "result": format_word(vm.memory[address as usize]),
+                "variablesReference": 0,
+                "memoryReference": format!("x{:04X}", address),
+            }));
+        }
+        if let Some(text) = expression.strip_prefix("input") {
+            if let Some(console) = vm.console.downcast_mut::<QueuedConsole>() {
+                console.input.extend(text.strip_prefix(' ').unwrap_or(text).bytes().chain([b'\n']));
+            }
+            return Ok(json!({ "result": "", "variablesReference": 0 }));
+        }
+        if self.running {
+            return Err("the program is running; pause it first".to_string());
+        }
+        let mut output = Vec::new();
+        *action = self.debugger.execute(vm, expression, &mut output).map_err(|err| err.to_string())?;
+        Ok(json!({ "result": String::from_utf8_lossy(&output).trim_end(), "variablesReference": 0 }))
+    }
+
+    fn disassemble(&self, vm: &Vm, arguments: &Value) -> Result<Value, String> {
+        let start = byte_address(arguments)? / 2 + arguments["instructionOffset"].as_i64().unwrap_or(0);
+        let count = arguments["instructionCount"].as_i64().unwrap_or(0);
+        let mut instructions = Vec::new();
+        for address in start..start + count {
+            if !(0..0x10000).contains(&address) {
+                instructions.push(json!({ "address": format!("x{:X}", address), "instruction": "", "presentationHint": "invalid" }));
+                continue;
+            }
+            let (address, word) = (address as u16, vm.memory[address as usize]);
+            let mut instruction = json!({
+                "address": format!("x{:04X}", address),
+                "instructionBytes": format!("{:04X}", word),
+                "instruction": disasm::disassemble_with(address, word, &vm.symbols),
+            });
+            if let Some(name) = vm.symbols.name_for(address).filter(|name| !name.contains('+')) {
+                instruction["symbol"] = json!(name);
+            }
+            if let (Some(source), Some(line)) = (&self.source, self.line_of(address)) {
+                instruction["location"] = self.source_json(source);
+                instruction["line"] = json!(line);
+            }
+            instructions.push(instruction);
+        }
+        Ok(json!({ "instructions": instructions }))
+    }
+
+    fn resume(&mut self, vm: &mut Vm) -> io::Result<()> {
+        if vm.halted {
+            return self.stopped(vm, "exception");
+        }
+        /* like Vm::run, step off a breakpoint at the PC so continuing makes progress */
+        if vm.breakpoints.contains(&vm.registers[REGISTER::PC as usize]) {
+            if let StepOutcome::Faulted { pc, instruction } = vm.step() {
+                self.print("stderr", &format!("fault: cannot execute 0x{:04X} at 0x{:04X}\n", instruction, pc))?;
+                return self.stopped(vm, "exception");
+            }
+            if vm.halted || vm.watch_hit().is_some() || vm.register_hit().is_some() {
+                return self.stopped(vm, "data breakpoint");
+            }
+        }
+        self.running = true;
+        self.waiting = false;
+        Ok(())
+    }
+
+    fn run_chunk(&mut self, vm: &mut Vm) -> io::Result<()> {
+        let result = vm.run_for(CHUNK).map(|run| run.state);
+        match result {
+            Ok(RunState::Yielded) => {
+                self.waiting = false;
+                self.flush_output(vm)
+            }
+            Ok(RunState::WaitingForInput) => {
+                self.flush_output(vm)?;
+                if !self.waiting {
+                    self.waiting = true;
+                    self.print("console", "[waiting for input: type `input TEXT` in the debug console]\n")?;
+                }
+                Ok(())
+            }
+            result => self.report(vm, result),
+        }
+    }
+
+    fn step(&mut self, vm: &mut Vm, command: &str) -> io::Result<()> {
+        let result = match command {
+            "next" => vm.step_over(),
+            "stepOut" => vm.step_out(),
+            _ => match vm.step() {
+                StepOutcome::Faulted { pc, instruction } => Err(VmError::IllegalOpcode { pc, instruction }),
+                StepOutcome::WaitingForInput => Ok(RunState::WaitingForInput),
+                _ => Ok(RunState::Stepped),
+            },
+        };
+        self.report(vm, result)
+    }
+
+    /* a stopped event for why the program stopped running or stepping */
+    fn report(&mut self, vm: &Vm, result: Result<RunState, VmError>) -> io::Result<()> {
+        let reason = match result {
+            Ok(RunState::Breakpoint(pc)) if !self.line_breakpoints.contains(&pc) && self.function_breakpoints.contains(&pc) => {
+                "function breakpoint"
+            }
+            Ok(RunState::Breakpoint(_)) => "breakpoint",
+            Ok(RunState::Watchpoint(_) | RunState::RegisterWatch(_)) => "data breakpoint",
+            Ok(RunState::Paused) => "pause",
+            Ok(RunState::WaitingForInput) => {
+                self.print("console", "[waiting for input: type `input TEXT` in the debug console]\n")?;
+                "pause"
+            }
+            Ok(_) => "step",
+            Err(err) => {
+                self.print("stderr", &format!("fault: {}\n", err))?;
+                "exception"
+            }
+        };
+        self.stopped(vm, reason)
+    }
+
+    /* tell the editor the program stopped, or that it has ended */
+    fn stopped(&mut self, vm: &Vm, reason: &str) -> io::Result<()> {
+        self.running = false;
+        self.flush_output(vm)?;
+        if vm.halted {
+            if !self.exited {
+                self.exited = true;
+                self.event("exited", json!({ "exitCode": 0 }))?;
+                self.event("terminated", json!({}))?;
+            }
+            return Ok(());
+        }
+        let mut body = json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true });
+        let mut hits = Vec::new();
+        if report_hits(vm, &mut hits)? {
+            body["text"] = json!(String::from_utf8_lossy(&hits).trim_end());
+        }
+        self.event("stopped", body)
+    }
+}
+
+/* R0-R7, the PC, PSR and condition code */
+fn variables(vm: &Vm, arguments: &Value) -> Value {
+    if arguments["variablesReference"] != REGISTERS_REFERENCE {
+        return json!({ "variables": [] });
+    }
+    let mut variables: Vec<Value> = (0..8)
+        .map(|register| {
+            let value = vm.registers[register];
+            json!({
+                "name": format!("R{}", register),
+                "value": format_word(value),
+                "variablesReference": 0,
+                "memoryReference": format!("x{:04X}", value),
+            })
+        })
+        .collect();
+    let pc = vm.registers[REGISTER::PC as usize];
+    variables.push(json!({
+        "name": "PC",
+        "value": vm.symbols.describe(pc),
+        "variablesReference": 0,
+        "memoryReference": format!("x{:04X}", pc),
+    }));
+    variables.push(json!({ "name": "PSR", "value": format!("x{:04X}", vm.psr()), "variablesReference": 0 }));
+    let cc = summary::condition_code(vm.registers[REGISTER::COND as usize]);
+    variables.push(json!({ "name": "CC", "value": cc, "variablesReference": 0, "presentationHint": { "attributes": ["readOnly"] } }));
+    json!({ "variables": variables })
+}
+
+fn set_variable(vm: &mut Vm, arguments: &Value) -> Result<Value, String> {
+    let name = arguments["name"].as_str().unwrap_or_default();
+    let text = arguments["value"].as_str().unwrap_or_default().trim();
+    let value = parse_value(&vm.symbols, text).ok_or_else(|| format!("`{}` is not a value, e.g. x3000 or -1", text))?;
+    let shown = match name {
+        "PSR" => {
+            vm.set_psr(value);
+            format!("x{:04X}", vm.psr())
+        }
+        "PC" => {
+            vm.registers[REGISTER::PC as usize] = value;
+            vm.symbols.describe(value)
+        }
+        name => {
+            let register = parse_register(name).ok_or_else(|| format!("{} cannot be set", name))?;
+            vm.registers[register as usize] = value;
+            format_word(value)
+        }
+    };
+    Ok(json!({ "value": shown }))
+}
+
+fn read_memory(vm: &Vm, arguments: &Value) -> Result<Value, String> {
+    let start = byte_address(arguments)?;
+    let count = arguments["count"].as_i64().unwrap_or(0).max(0);
+    let end = (start + count).min(BYTE_SPACE);
+    let bytes: Vec<u8> = (start.max(0)..end).map(|address| memory_byte(vm, address)).collect();
+    Ok(json!({
+        "address": format!("x{:04X}", start.clamp(0, BYTE_SPACE - 1) / 2),
+        "data": base64_encode(&bytes),
+        "unreadableBytes": count - bytes.len() as i64,
+    }))
+}
+
+fn write_memory(vm: &mut Vm, arguments: &Value) -> Result<Value, String> {
+    let start = byte_address(arguments)?;
+    let bytes = base64_decode(arguments["data"].as_str().unwrap_or_default()).ok_or("data is not base64")?;
+    if start < 0 || start + bytes.len() as i64 > BYTE_SPACE {
+        return Err("the write goes past the end of memory".to_string());
+    }
+    for (address, byte) in (start..).zip(&bytes) {
+        let word = &mut vm.memory[(address / 2) as usize];
+        *word = if address % 2 == 0 { (*word & 0xFF00) | *byte as u16 } else { (*word & 0x00FF) | (*byte as u16) << 8 };
+    }
+    Ok(json!({ "bytesWritten": bytes.len() }))
+}
@@ -0,0 +1,676 @@
+use std::io::{self, BufRead, Write};
+
+use crate::address::parse_address;
+use crate::color::{Painter, Role};
+#[cfg(feature = "devices-extra")]
+use crate::devices::gpio::{Gpio, PIN_COUNT};
+use crate::disasm;
+use crate::expr::Expr;
+use crate::microstep;
+use crate::monitor::Monitor;
+use crate::summary;
+use crate::symbols::SymbolTable;
+use crate::vm::{REGISTER, RegisterHit, RegisterWatch, RunState, StepOutcome, Vm, WatchHit, WatchKind};
+
+/* words shown by `mem` without a count */
+const MEM_WORDS: u16 = 16;
+
+/* why reverse-step and reverse-continue cannot go back */
+const NO_HISTORY: &str = "no execution history is kept (`debug` keeps one; with `run`, pass --history N)";
+
+/* why back and travel cannot go back */
+const NO_CHECKPOINTS: &str = "no checkpoints are kept (pass --checkpoint-every N)";
+
+/* instructions disassembled before and after the PC at each stop */
+const CONTEXT_BEFORE: u16 = 3;
+const CONTEXT_AFTER: u16 = 5;
+
+const HELP: &str = "\
+commands:
+  c, continue      resume the program
+  s, step [N]      execute N instructions (default 1)
+  n, next          like step, but run a JSR/JSRR's whole subroutine as one step
+  f, finish        run until the current subroutine returns
+  rs, reverse-step [N]
+                   undo the last N instructions (default 1)
+  rc, reverse-continue
+                   run backwards to a breakpoint, or to the last write to a watched
+                   address or register
+  checkpoints      list the checkpoints kept by --checkpoint-every
+  back N           go back N instructions, from the checkpoint before them
+  travel COUNT     go to where COUNT instructions had run
+  b, break ADDR    stop before executing ADDR
+  d, delete ADDR   remove the breakpoint at ADDR
+  b, break START..END
+                   stop when execution enters the range (`..=` includes END), e.g. from a
+                   jump into data
+  d, delete START..END
+                   remove a range breakpoint
+  b, break trap [NAME|VECTOR]
+                   stop before a TRAP to NAME (e.g. PUTS) or VECTOR (e.g. x25), or any TRAP
+  d, delete trap [NAME|VECTOR]
+                   remove a trap breakpoint (all of them without NAME or VECTOR)
+  breaks           list breakpoints
+  watch ADDR       stop after an instruction reads or writes ADDR
+  rwatch ADDR      stop after an instruction reads ADDR
+  rwatch RN == V   stop after an instruction changes register RN to V (e.g. R3 == xFFFF)
+  wwatch ADDR      stop after an instruction writes ADDR
+  unwatch ADDR|RN  remove the watchpoint at ADDR, or the watches on register RN
+  watches          list watchpoints
+  u, micro         show the phases of the next instruction, then execute it
+  l, list [ADDR]   disassemble around ADDR (default: the PC, as shown at each stop)
+  bt, backtrace    show the subroutine calls in progress
+  recent           show the last instructions executed and the registers they changed
+  r, regs          show registers
+  display [EXPR]   show EXPR at every stop, e.g. `display MEM[PTR]` (without one: show them all)
+  undisplay N      stop showing display N
+  m, mem ADDR [N]  show N words of memory from ADDR (default 16), or a range START..END
+  reset            restart the program from its freshly loaded state
+  mon, monitor     open the machine monitor (peek, poke, fill, find, setreg)
+  q, quit          stop the program
+  h, help          show this help
+ADDR, and a register watch's value, may be a number (x3000, 0x3000, 12288) or a label, e.g. LOOP or DATA+2
+";
+
+#[cfg(feature = "scripting")]
+const SCRIPT_HELP: &str = "\
+  script FILE      run a rhai script that drives the debugger (see the script module docs)
+";
+
+#[cfg(feature = "devices-extra")]
+const DEVICE_HELP: &str = "\
+  devices          list attached devices
+  device attach NAME[=ARG][@ADDR]
+                   attach a device, e.g. `device attach disk=/tmp/disk.img`
+  device detach NAME
+                   detach a device once it is idle
+  pins             show the GPIO pins
+  pin N 0|1        drive GPIO input pin N low or high
+";
+
+/* `watchpoint: x4000 (COUNT) written by x3005 (LOOP+1) ST R0, COUNT: x0000 -> x0005` */
+fn describe_watch(vm: &Vm, hit: &WatchHit) -> String {
+    let access = if hit.write { "written" } else { "read" };
+    let value = if hit.write {
+        format!("x{:04X} -> x{:04X}", hit.old, hit.new)
+    } else {
+        format!("x{:04X}", hit.new)
+    };
+    format!(
+        "watchpoint: {} {} by {} {}: {}",
+        vm.symbols.describe(hit.address),
+        access,
+        vm.symbols.describe(hit.pc),
+        disasm::disassemble_with(hit.pc, hit.instruction, &vm.symbols),
+        value
+    )
+}
+
+/* disassembly from `before` instructions ahead of `center` to `after` past it, with
+   labels on their own lines; the line at the PC is marked `=>` and highlighted */
+pub(crate) fn render_context(vm: &Vm, painter: &Painter, center: u16, before: u16, after: u16) -> String {
+    let pc = vm.registers[REGISTER::PC as usize];
+    let mut out = String::new();
+    for offset in 0..=before + after {
+        let address = center.wrapping_sub(before).wrapping_add(offset);
+        let word = vm.memory[address as usize];
+        if let Some(label) = vm.symbols.name_for(address).filter(|name| !name.contains('+')) {
+            out.push_str(&format!("{}:\n", label));
+        }
+        let text = disasm::disassemble_with(address, word, &vm.symbols);
+        let line = if address == pc {
+            painter.paint(Role::Current, &format!("=> x{:04X}  {:04X}  {}", address, word, text))
+        } else {
+            format!("   {}  {:04X}  {}", painter.paint(Role::Address, &format!("x{:04X}", address)), word, painter.disassembly(&text))
+        };
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
+
+/* `PUTS`, `x22` or `0x22` as a trap vector */
+fn parse_trap(text: &str) -> Option<u8> {
+    (0x20..=0x25)
+        .find(|&vector| disasm::trap_name(vector).is_some_and(|name| name.eq_ignore_ascii_case(text)))
+        .or_else(|| parse_address(text))
+        .and_then(|vector| u8::try_from(vector).ok())
+}
+
+/* `x22 (PUTS)` */
+fn describe_trap(vector: u8) -> String {
+    match disasm::trap_name(vector as u16) {
+        Some(name) => format!("x{:02X} ({})", vector, name),
+        None => format!("x{:02X}", vector),
+    }
+}
+
+/* characters shown of a string a trap is about to print */
+const TRAP_STRING_LIMIT: usize = 64;
+
+/* what a TRAP about to run is being handed, e.g. `PUTS "Hello\n" from x3010` */
+fn describe_trap_arguments(vm: &Vm, vector: u8) -> String {
+    let r0 = vm.registers[REGISTER::R0 as usize];
+    let name = disasm::trap_name(vector as u16).map(str::to_string).unwrap_or_else(|| format!("TRAP x{:02X}", vector));
+    match vector {
+        0x21 => format!("{} {:?} (R0 = x{:04X})", name, char::from(r0 as u8), r0),
+        0x22 | 0x24 => {
+            /* PUTS prints one character a word, PUTSP two, low byte first */
+            let mut bytes = Vec::new();
+            for address in r0..=u16::MAX {
+                let word = vm.memory[address as usize];
+                let low = (word & 0xFF) as u8;
+                let high = (word >> 8) as u8;
+                if vector == 0x22 && word == 0 || vector == 0x24 && low == 0 {
+                    break;
+                }
+                bytes.push(if vector == 0x22 { word as u8 } else { low });
+                if vector == 0x24 && high != 0 {
+                    bytes.push(high);
+                }
+                if bytes.len() > TRAP_STRING_LIMIT {
+                    break;
+                }
+            }
+            let text: String = bytes.iter().take(TRAP_STRING_LIMIT).map(|&byte| char::from(byte)).collect();
+            let more = if bytes.len() > TRAP_STRING_LIMIT { "..." } else { "" };
+            format!("{} {:?}{} from x{:04X}", name, text, more, r0)
+        }
+        0x20 | 0x23 | 0x25 => name,
+        _ => format!("{} (R0 = x{:04X})", name, r0),
+    }
+}
+
+/* `R3` (or `r3`) as a register number */
+pub(crate) fn parse_register(text: &str) -> Option<u8> {
+    let number = text.strip_prefix(['R', 'r'])?.parse::<u8>().ok()?;
+    (number < 8).then_some(number)
+}
+
+/* a register value: an address-style number, a label (`DATA+2`), or negative decimal */
+pub(crate) fn parse_value(symbols: &SymbolTable, text: &str) -> Option<u16> {
+    symbols.resolve(text).or_else(|| text.parse::<i16>().ok().map(|value| value as u16))
+}
+
+/* `1: MEM[PTR] = x0041 (65)` */
+fn describe_display(number: usize, text: &str, value: u16) -> String {
+    format!("{}: {} = x{:04X} ({})", number, text, value, value as i16)
+}
+
+/* `register watch: R3 set to xFFFF (was x0000) by x3005 (LOOP+1) ADD R3, R3, #-1` */
+fn describe_register_hit(vm: &Vm, hit: &RegisterHit) -> String {
+    format!(
+        "register watch: R{} set to x{:04X} (was x{:04X}) by {} {}",
+        hit.register,
+        hit.new,
+        hit.old,
+        vm.symbols.describe(hit.pc),
+        disasm::disassemble_with(hit.pc, hit.instruction, &vm.symbols)
+    )
+}
+
+/* where the program stopped, and any watch that stopped it */
+pub(crate) fn report_stop(vm: &Vm, output: &mut impl Write) -> io::Result<()> {
+    let pc = vm.registers[REGISTER::PC as usize];
+    match vm.symbols.name_for(pc) {
+        Some(name) => writeln!(output, "[paused at 0x{:04X} ({})]", pc, name)?,
+        None => writeln!(output, "[paused at 0x{:04X}]", pc)?,
+    }
+    if let Some(vector) = vm.trap_breakpoint_at(pc) {
+        writeln!(output, "trap: {}", describe_trap_arguments(vm, vector))?;
+    }
+    report_hits(vm, output)?;
+    Ok(())
+}
+
+/* what the last instruction stepped touched that is being watched */
+pub(crate) fn report_hits(vm: &Vm, output: &mut impl Write) -> io::Result<bool> {
+    let mut hit = false;
+    if let Some(watch) = vm.watch_hit() {
+        writeln!(output, "{}", describe_watch(vm, &watch))?;
+        hit = true;
+    }
+    if let Some(register) = vm.register_hit() {
+        writeln!(output, "{}", describe_register_hit(vm, &register))?;
+        hit = true;
+    }
+    Ok(hit)
+}
+
+/// What the caller should do once the debugger prompt returns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebuggerAction {
+    Continue,
+    Quit,
+}
+
+/// A line-oriented debugger prompt operating on a paused [`Vm`].
+#[derive(Default)]
+pub struct Debugger {
+    /// Colors for the disassembly shown at each stop; plain by default.
+    pub painter: Painter,
+    /* set while `monitor` has lines going to the machine monitor */
+    monitor: Option<Monitor>,
+    /* `display` expressions, as typed and parsed */
+    displays: Vec<(String, Expr)>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read and execute commands until the user continues or quits.
+    /// End of input counts as quit.
+    pub fn repl(&mut self, vm: &mut Vm, input: &mut impl BufRead, output: &mut impl Write) -> io::Result<DebuggerAction> {
+        let pc = vm.registers[REGISTER::PC as usize];
+        writeln!(output)?;
+        report_stop(vm, output)?;
+        if !vm.halted {
+            write!(output, "{}", render_context(vm, &self.painter, pc, CONTEXT_BEFORE, CONTEXT_AFTER))?;
+        }
+        self.show_displays(vm, output)?;
+        let mut halt_reported = false;
+        loop {
+            if !vm.halted {
+                halt_reported = false;
+            } else if !halt_reported {
+                /* with a history or checkpoints the halt can still be gone back from */
+                let history = vm.history.as_ref().is_some_and(|history| !history.is_empty());
+                let checkpoints = vm.checkpoints.as_ref().is_some_and(|checkpoints| !checkpoints.is_empty());
+                match (history, checkpoints) {
+                    (false, false) => {
+                        writeln!(output, "program halted")?;
+                        return Ok(DebuggerAction::Quit);
+                    }
+                    (true, false) => writeln!(output, "program halted; `rs` and `rc` go back into it")?,
+                    (false, true) => writeln!(output, "program halted; `back` and `travel` go back into it")?,
+                    (true, true) => writeln!(output, "program halted; `rs`, `rc`, `back` and `travel` go back into it")?,
+                }
+                halt_reported = true;
+            }
+
+            write!(output, "{}", if self.monitor.is_some() { "(mon) " } else { "(lc3) " })?;
+            output.flush()?;
+
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(DebuggerAction::Quit);
+            }
+            /* a command that moved the program is a stop too */
+            let before = (vm.instruction_count, vm.registers[REGISTER::PC as usize]);
+            if let Some(action) = self.execute(vm, &line, output)? {
+                return Ok(action);
+            }
+            if (vm.instruction_count, vm.registers[REGISTER::PC as usize]) != before {
+                self.show_displays(vm, output)?;
+            }
+        }
+    }
+
+    /* the value of each display, at a stop */
+    fn show_displays(&self, vm: &Vm, output: &mut impl Write) -> io::Result<()> {
+        for (number, (text, expr)) in self.displays.iter().enumerate() {
+            writeln!(output, "{}", describe_display(number + 1, text, expr.evaluate(vm)))?;
+        }
+        Ok(())
+    }
+
+    /// Execute one command line, e.g. `break x3005`, writing what it shows
+    /// to `output`. Returns the action for `continue` and `quit`; the
+    /// caller decides how to carry them out.
+    pub fn execute(&mut self, vm: &mut Vm, line: &str, output: &mut impl Write) -> io::Result<Option<DebuggerAction>> {
+        if let Some(monitor) = self.monitor.as_mut() {
+            if !monitor.execute(vm, line, output)? {
+                self.monitor = None;
+            }
+            return Ok(None);
+        }
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return Ok(None);
+        };
+
+        match command {
+            "c" | "continue" => return Ok(Some(DebuggerAction::Continue)),
+            "q" | "quit" => return Ok(Some(DebuggerAction::Quit)),
+            "s" | "step" | "rs" | "reverse-step" => {
+                let count = match words.next().map(str::parse::<u64>) {
+                    None => 1,
+                    Some(Ok(count)) => count,
+                    Some(Err(_)) => {
+                        writeln!(output, "{} expects a number", command)?;
+                        return Ok(None);
+                    }
+                };
+                if command.starts_with('r') {
+                    if vm.history.is_none() {
+                        writeln!(output, "{}", NO_HISTORY)?;
+                        return Ok(None);
+                    }
+                    if (0..count).any(|_| !vm.step_back()) {
+                        writeln!(output, "reached the start of the recorded history")?;
+                    }
+                    writeln!(output, "PC = 0x{:04X}", vm.registers[REGISTER::PC as usize])?;
+                    return Ok(None);
+                }
+                for _ in 0..count {
+                    if let StepOutcome::Faulted { pc, instruction } = vm.step() {
+                        writeln!(output, "fault: cannot execute 0x{:04X} at 0x{:04X}", instruction, pc)?;
+                        break;
+                    }
+                    if report_hits(vm, output)? {
+                        break;
+                    }
+                }
+                writeln!(output, "PC = 0x{:04X}", vm.registers[REGISTER::PC as usize])?;
+            }
+            "n" | "next" | "f" | "finish" => {
+                let result = if command.starts_with('n') { vm.step_over() } else { vm.step_out() };
+                match result {
+                    Ok(RunState::Breakpoint(pc)) => writeln!(output, "breakpoint at 0x{:04X}", pc)?,
+                    Ok(RunState::Halted) => return Ok(None),
+                    Ok(_) => {
+                        report_hits(vm, output)?;
+                    }
+                    Err(err) => writeln!(output, "fault: {}", err)?,
+                }
+                writeln!(output, "PC = 0x{:04X}", vm.registers[REGISTER::PC as usize])?;
+            }
+            "rc" | "reverse-continue" => {
+                if vm.history.is_none() {
+                    writeln!(output, "{}", NO_HISTORY)?;
+                    return Ok(None);
+                }
+                match vm.run_back() {
+                    RunState::Breakpoint(pc) => writeln!(output, "breakpoint at 0x{:04X}", pc)?,
+                    RunState::Watchpoint(hit) => writeln!(output, "{}", describe_watch(vm, &hit))?,
+                    RunState::RegisterWatch(hit) => writeln!(output, "{}", describe_register_hit(vm, &hit))?,
+                    _ => writeln!(output, "reached the start of the recorded history")?,
+                }
+                writeln!(output, "PC = 0x{:04X}", vm.registers[REGISTER::PC as usize])?;
+            }
+            "checkpoints" => match &vm.checkpoints {
+                None => writeln!(output, "{}", NO_CHECKPOINTS)?,
+                Some(checkpoints) => {
+                    for state in checkpoints.iter() {
+                        let pc = state.registers[REGISTER::PC as usize];
+                        writeln!(output, "  {:>12}  {}", state.instruction_count, vm.symbols.describe(pc))?;
+                    }
+                }
+            },
+            "back" | "travel" => {
+                let Some(count) = words.next().and_then(|count| count.parse::<u64>().ok()) else {
+                    writeln!(output, "{} expects an instruction count", command)?;
+                    return Ok(None);
+                };
+                let target = if command == "back" { vm.instruction_count.saturating_sub(count) } else { count };
+                if target < vm.instruction_count && vm.checkpoints.is_none() {
+                    writeln!(output, "{}", NO_CHECKPOINTS)?;
+                    return Ok(None);
+                }
+                match vm.travel_to(target) {
+                    Ok(reached) if reached < target => writeln!(output, "stopped after {} instructions", reached)?,
+                    Ok(reached) => writeln!(output, "at {} instructions", reached)?,
+                    Err(err) => writeln!(output, "{}", err)?,
+                }
+                writeln!(output, "PC = 0x{:04X}", vm.registers[REGISTER::PC as usize])?;
+            }
+            "b" | "break" | "d" | "delete" if words.clone().next() == Some("trap") => {
+                words.next();
+                let vector = match words.next() {
+                    None => None,
+                    Some(text) => match parse_trap(text) {
+                        Some(vector) => Some(vector),
+                        None => {
+                            writeln!(output, "`{}` is not a trap name or vector", text)?;
+                            return Ok(None);
+                        }
+                    },
+                };
+                match (command.starts_with('b'), vector) {
+                    (true, Some(vector)) => {
+                        vm.trap_breakpoints.insert(vector);
+                        writeln!(output, "breakpoint on trap {}", describe_trap(vector))?;
+                    }
+                    (true, None) => {
+                        vm.break_on_traps = true;
+                        writeln!(output, "breakpoint on every trap")?;
+                    }
+                    (false, Some(vector)) => {
+                        if !vm.trap_breakpoints.remove(&vector) {
+                            writeln!(output, "no breakpoint on trap {}", describe_trap(vector))?;
+                        }
+                    }
+                    (false, None) => {
+                        vm.trap_breakpoints.clear();
+                        vm.break_on_traps = false;
+                    }
+                }
+            }
+            "b" | "break" | "d" | "delete" if words.clone().next().is_some_and(|word| word.contains("..")) => {
+                let Some(range) = words.next().and_then(|range| vm.symbols.resolve_range(range)) else {
+                    writeln!(output, "{} expects a range START..END of addresses or labels", command)?;
+                    return Ok(None);
+                };
+                let text = format!("0x{:04X}-0x{:04X}", range.start(), range.end());
+                if command.starts_with('b') {
+                    if !vm.range_breakpoints.contains(&range) {
+                        vm.range_breakpoints.push(range);
+                    }
+                    writeln!(output, "breakpoint on entering {}", text)?;
+                } else {
+                    let count = vm.range_breakpoints.len();
+                    vm.range_breakpoints.retain(|other| *other != range);
+                    if vm.range_breakpoints.len() == count {
+                        writeln!(output, "no breakpoint on {}", text)?;
+                    }
+                }
+            }
+            "b" | "break" | "d" | "delete" => {
+                let Some(address) = words.next().and_then(|address| vm.symbols.resolve(address)) else {
+                    writeln!(output, "{} expects an address or label", command)?;
+                    return Ok(None);
+                };
+                if command.starts_with('b') {
+                    vm.breakpoints.insert(address);
+                    writeln!(output, "breakpoint at 0x{:04X}", address)?;
+                } else if !vm.breakpoints.remove(&address) {
+                    writeln!(output, "no breakpoint at 0x{:04X}", address)?;
+                }
+            }
+            "breaks" => {
+                for address in &vm.breakpoints {
+                    writeln!(output, "  0x{:04X}", address)?;
+                }
+                for range in &vm.range_breakpoints {
+                    writeln!(output, "  0x{:04X}-0x{:04X}", range.start(), range.end())?;
+                }
+                if vm.break_on_traps {
+                    writeln!(output, "  every trap")?;
+                }
+                for vector in &vm.trap_breakpoints {
+                    writeln!(output, "  trap {}", describe_trap(*vector))?;
+                }
+            }
+            "rwatch" | "unwatch" if words.clone().next().and_then(parse_register).is_some() => {
+                let register = words.next().and_then(parse_register).unwrap();
+                if command == "unwatch" {
+                    let count = vm.register_watches.len();
+                    vm.register_watches.retain(|watch| watch.register != register);
+                    if vm.register_watches.len() == count {
+                        writeln!(output, "no watch on R{}", register)?;
+                    }
+                    return Ok(None);
+                }
+                let Some(value) = words.next().filter(|op| *op == "==").and(words.next()).and_then(|value| parse_value(&vm.symbols, value)) else {
+                    writeln!(output, "rwatch expects a register, `==` and a value, e.g. `rwatch R3 == xFFFF`")?;
+                    return Ok(None);
+                };
+                vm.register_watches.push(RegisterWatch { register, value });
+                writeln!(output, "watching R{} for x{:04X}", register, value)?;
+            }
+            "watch" | "rwatch" | "wwatch" | "unwatch" => {
+                let Some(address) = words.next().and_then(|address| vm.symbols.resolve(address)) else {
+                    writeln!(output, "{} expects an address or label", command)?;
+                    return Ok(None);
+                };
+                let kind = match command {
+                    "watch" => WatchKind::Access,
+                    "rwatch" => WatchKind::Read,
+                    "wwatch" => WatchKind::Write,
+                    _ => {
+                        if vm.watchpoints.remove(&address).is_none() {
+                            writeln!(output, "no watchpoint at 0x{:04X}", address)?;
+                        }
+                        return Ok(None);
+                    }
+                };
+                vm.watchpoints.insert(address, kind);
+                writeln!(output, "watchpoint at 0x{:04X}", address)?;
+            }
+            "watches" => {
+                for (address, kind) in &vm.watchpoints {
+                    let kind = match kind {
+                        WatchKind::Read => "read",
+                        WatchKind::Write => "write",
+                        WatchKind::Access => "read/write",
+                    };
+                    writeln!(output, "  0x{:04X} {}", address, kind)?;
+                }
+                for watch in &vm.register_watches {
+                    writeln!(output, "  R{} == x{:04X}", watch.register, watch.value)?;
+                }
+            }
+            "u" | "micro" => {
+                write!(output, "{}", microstep::render(&microstep::phases(vm)))?;
+                vm.step();
+                report_hits(vm, output)?;
+            }
+            "l" | "list" => {
+                let center = match words.next() {
+                    None => vm.registers[REGISTER::PC as usize],
+                    Some(address) => match vm.symbols.resolve(address) {
+                        Some(address) => address,
+                        None => {
+                            writeln!(output, "list expects an address or label")?;
+                            return Ok(None);
+                        }
+                    },
+                };
+                write!(output, "{}", render_context(vm, &self.painter, center, CONTEXT_BEFORE, CONTEXT_AFTER))?;
+            }
+            "bt" | "backtrace" => match &vm.call_stack {
+                Some(call_stack) => write!(output, "{}", call_stack.render(vm.registers[REGISTER::PC as usize], &vm.symbols))?,
+                None => writeln!(output, "calls are not being followed")?,
+            },
+            "recent" => match &vm.recent {
+                Some(recent) if !recent.is_empty() => write!(output, "{}", recent.render(&vm.symbols))?,
+                Some(_) => writeln!(output, "no instructions have run")?,
+                None => writeln!(output, "recent instructions are not kept (pass --crash-context N)")?,
+            },
+            "display" => {
+                let text = line.trim().strip_prefix("display").unwrap_or_default().trim();
+                if text.is_empty() {
+                    self.show_displays(vm, output)?;
+                    return Ok(None);
+                }
+                match Expr::parse(text, &vm.symbols) {
+                    Ok(expr) => {
+                        writeln!(output, "{}", describe_display(self.displays.len() + 1, text, expr.evaluate(vm)))?;
+                        self.displays.push((text.to_string(), expr));
+                    }
+                    Err(err) => writeln!(output, "display: {}", err)?,
+                }
+            }
+            "undisplay" => match words.next().and_then(|number| number.parse::<usize>().ok()) {
+                Some(number) if (1..=self.displays.len()).contains(&number) => {
+                    self.displays.remove(number - 1);
+                }
+                _ => writeln!(output, "undisplay expects a display number (1-{})", self.displays.len())?,
+            },
+            "r" | "regs" => write!(output, "{}", summary::render(vm, &[]))?,
+            "m" | "mem" => {
+                let range = match (words.next(), words.next()) {
+                    (Some(range), None) if range.contains("..") => vm.symbols.resolve_range(range),
+                    (Some(start), count) => vm.symbols.resolve(start).and_then(|start| {
+                        let count = match count {
+                            None => MEM_WORDS,
+                            Some(count) => count.parse::<u16>().ok().filter(|count| *count > 0)?,
+                        };
+                        Some(start..=start.saturating_add(count - 1))
+                    }),
+                    (None, _) => None,
+                };
+                match range {
+                    Some(range) => write!(output, "{}", summary::render_memory(vm, &[range]))?,
+                    None => writeln!(output, "mem expects an address and a word count, or START..END")?,
+                }
+            }
+            "mon" | "monitor" => {
+                writeln!(output, "monitor: `help` lists commands, `exit` goes back to the debugger")?;
+                self.monitor = Some(Monitor::new());
+            }
+            "reset" => {
+                vm.reset();
+                writeln!(output, "PC = 0x{:04X}", vm.registers[REGISTER::PC as usize])?;
+            }
+            #[cfg(feature = "devices-extra")]
+            "devices" => writeln!(output, "  {}", vm.devices.attached(&vm.bus).join(" "))?,
+            #[cfg(feature = "devices-extra")]
+            "device" => {
+                let result = match (words.next(), words.next()) {
+                    (Some("attach"), Some(spec)) => vm.attach_device(spec),
+                    (Some("detach"), Some(name)) => vm.detach_device(name),
+                    _ => Err("usage: device attach NAME[=ARG][@ADDR] | device detach NAME".to_string()),
+                };
+                if let Err(message) = result {
+                    writeln!(output, "{}", message)?;
+                }
+            }
+            #[cfg(feature = "devices-extra")]
+            "pins" => match vm.bus.get::<Gpio>() {
+                Some(gpio) => write!(output, "{}", gpio.render())?,
+                None => writeln!(output, "no GPIO bank attached")?,
+            },
+            #[cfg(feature = "devices-extra")]
+            "pin" => {
+                let pin = words.next().and_then(|pin| pin.parse::<u8>().ok()).filter(|pin| *pin < PIN_COUNT);
+                let level = match words.next() {
+                    Some("0") => Some(false),
+                    Some("1") => Some(true),
+                    _ => None,
+                };
+                match (vm.bus.get_mut::<Gpio>(), pin, level) {
+                    (None, _, _) => writeln!(output, "no GPIO bank attached")?,
+                    (Some(gpio), Some(pin), Some(high)) => gpio.set_input(pin, high),
+                    _ => writeln!(output, "pin expects a pin number (0-{}) and 0 or 1", PIN_COUNT - 1)?,
+                }
+            }
+            #[cfg(feature = "scripting")]
+            "script" => {
+                let Some(path) = words.next() else {
+                    writeln!(output, "script expects a file")?;
+                    return Ok(None);
+                };
+                match std::fs::read_to_string(path) {
+                    Ok(source) => {
+                        if let Err(err) = crate::script::run(vm, &source, output)? {
+                            writeln!(output, "script: {}", err)?;
+                        }
+                    }
+                    Err(err) => writeln!(output, "{}: {}", path, err)?,
+                }
+            }
+            "h" | "help" => {
+                write!(output, "{}", HELP)?;
+                #[cfg(feature = "scripting")]
+                write!(output, "{}", SCRIPT_HELP)?;
+                #[cfg(feature = "devices-extra")]
+                write!(output, "{}", DEVICE_HELP)?;
+            }
+            other => writeln!(output, "unknown command `{}` (try `help`)", other)?,
+        }
+        Ok(None)
+    }
+}
@@ -0,0 +1,226 @@
+//! Optional peripherals beyond the keyboard, enabled with the `devices-extra`
+//! feature. Each device lives at fixed addresses in the device region and
+//! plugs into the VM's [`Bus`]; services answer fixed TRAP vectors and are
+//! attached to a [`Vm`](crate::vm::Vm) through [`Devices`].
+
+#[cfg(feature = "clipboard")]
+pub mod clipboard;
+pub mod console;
+pub mod disk;
+pub mod dma;
+pub mod fs;
+pub mod gpio;
+pub mod host;
+pub mod serial;
+#[cfg(feature = "sound")]
+pub mod sound;
+
+use tracing::debug;
+
+use crate::address::parse_address;
+use crate::bus::{Bus, Device};
+#[cfg(feature = "clipboard")]
+use clipboard::ClipboardBridge;
+use console::AuxConsole;
+use disk::Disk;
+use dma::Dma;
+use fs::FileService;
+use gpio::Gpio;
+use host::HostEnvironment;
+#[cfg(feature = "sound")]
+use sound::Beeper;
+
+/// The TRAP services attached to a VM; `None` means not provided, in which
+/// case their trap vectors are unknown traps. The memory-mapped devices in
+/// this module live on the VM's [`Bus`] instead.
+#[derive(Debug, Default)]
+pub struct Devices {
+    pub fs: Option<FileService>,
+    pub host: Option<HostEnvironment>,
+}
+
+impl Devices {
+    /// Names of the attached devices and services, as accepted by [`Devices::detach`].
+    pub fn attached<'a>(&self, bus: &'a Bus) -> Vec<&'a str> {
+        let mut names = bus.names();
+        if self.fs.is_some() {
+            names.push("fs");
+        }
+        if self.host.is_some() {
+            names.push("host");
+        }
+        names
+    }
+
+    /// Services for a clone of the VM: the host environment is copied, while
+    /// the file service is left out since its open files cannot be shared.
+    pub fn fork(&self) -> Devices {
+        Devices {
+            fs: None,
+            host: self.host.clone(),
+        }
+    }
+
+    /// Put the services back in their power-on state (open files are closed).
+    pub fn reset(&mut self) {
+        if let Some(fs) = self.fs.as_mut() {
+            fs.reset();
+        }
+    }
+
+    /// Attach a device or service described by `NAME[=ARGUMENT][@ADDRESS]`,
+    /// e.g. `dma`, `disk=/tmp/disk.img` or `gpio@xFE50`. Devices sit at fixed
+    /// addresses, so `@ADDRESS` only confirms where the device is expected.
+    ///
+    /// Serial ports come in pairs and are attached with [`serial::SerialPort::pair`] instead.
+    pub fn attach(&mut self, spec: &str, bus: &mut Bus) -> Result<(), String> {
+        let (spec, address) = match spec.rsplit_once('@') {
+            Some((spec, address)) => {
+                let address = parse_address(address).ok_or_else(|| format!("invalid address `{}`", address))?;
+                (spec, Some(address))
+            }
+            None => (spec, None),
+        };
+        let (name, argument) = match spec.split_once('=') {
+            Some((name, argument)) => (name, Some(argument)),
+            None => (spec, None),
+        };
+        if let Some(address) = address {
+            match base_address(name) {
+                Some(base) if base == address => {}
+                Some(base) => return Err(format!("{} is fixed at x{:04X}", name, base)),
+                None => return Err(format!("{} is not memory-mapped", name)),
+            }
+        }
+        if self.attached(bus).contains(&name) {
+            return Err(format!("{} is already attached", name));
+        }
+        let argument = |what: &str| argument.ok_or_else(|| format!("{} needs {} (`{}=...`)", name, what, name));
+        let device: Box<dyn Device> = match name {
+            "console2" => Box::new(AuxConsole::connect(argument("a target")?).map_err(|err| err.to_string())?),
+            "disk" => Box::new(Disk::open(argument("an image file")?).map_err(|err| err.to_string())?),
+            "dma" => Box::new(Dma::new()),
+            "gpio" => Box::new(Gpio::new()),
+            #[cfg(feature = "sound")]
+            "sound" => Box::new(Beeper::open()?),
+            #[cfg(feature = "clipboard")]
+            "clipboard" => Box::new(ClipboardBridge::open()?),
+            "fs" => {
+                self.fs = Some(FileService::new(argument("a directory")?));
+                debug!(name, "service attached");
+                return Ok(());
+            }
+            "host" => {
+                self.host = Some(HostEnvironment::default());
+                debug!(name, "service attached");
+                return Ok(());
+            }
+            "serial" => return Err("serial ports are created in pairs and cannot be attached by name".to_string()),
+            other => return Err(format!("unknown device `{}`", other)),
+        };
+        bus.attach(device)
+    }
+
+    /// Detach a device or service by name, after letting it finish any work
+    /// in progress (a running DMA transfer completes first). A device's
+    /// addresses go back to behaving as plain memory.
+    pub fn detach(&mut self, name: &str, bus: &mut Bus, memory: &mut [u16]) -> Result<(), String> {
+        let detached = match name {
+            "fs" => self.fs.take().is_some(),
+            "host" => self.host.take().is_some(),
+            _ if !bus.names().contains(&name) && base_address(name).is_none() => {
+                return Err(format!("unknown device `{}`", name));
+            }
+            _ => bus.detach(name, memory).is_some(),
+        };
+        if !detached {
+            return Err(format!("{} is not attached", name));
+        }
+        debug!(name, "detached");
+        Ok(())
+    }
+
+    /// Handle a TRAP provided by an attached service. Returns false if none claims `trap_code`.
+    pub fn trap(&mut self, trap_code: u16, registers: &mut [u16], memory: &mut [u16]) -> bool {
+        if self.fs.as_mut().is_some_and(|fs| fs.trap(trap_code, registers, memory)) {
+            return true;
+        }
+        if self.host.as_mut().is_some_and(|host| host.trap(trap_code, registers, memory)) {
+            return true;
+        }
+        false
+    }
+}
+
+/* first register of a memory-mapped device; None for trap services */
+fn base_address(name: &str) -> Option<u16> {
+    match name {
+        "console2" => Some(console::KBSR2),
+        "disk" => Some(disk::DISK_SECTOR),
+        "dma" => Some(dma::DMA_SOURCE),
+        "gpio" => Some(gpio::GPIO_DIRECTION),
+        "serial" => Some(serial::SERIAL_RX_STATUS),
+        #[cfg(feature = "sound")]
+        "sound" => Some(sound::TONE_FREQUENCY),
+        #[cfg(feature = "clipboard")]
+        "clipboard" => Some(clipboard::CLIPSR),
+        _ => None,
+    }
+}
+
+/* the longest path or name a guest may pass, not counting its terminator */
+pub(crate) const MAX_STRING: usize = 255;
+
+/* a NUL-terminated string with one character per word; None if no NUL ends it within MAX_STRING characters */
+pub(crate) fn read_string(memory: &[u16], address: u16) -> Option<String> {
+    let mut text = String::new();
+    for offset in 0..=MAX_STRING as u16 {
+        match memory[address.wrapping_add(offset) as usize] {
+            0 => return Some(text),
+            word => text.push(char::from(word as u8)),
+        }
+    }
+    None
+}
+
+/* copy as much of `text` as fits in `size` words with its terminator; None if nothing fits */
+pub(crate) fn copy_string(memory: &mut [u16], text: &str, buffer: u16, size: u16) -> Option<u16> {
+    let len = text.len().min((size as usize).checked_sub(1)?);
+    for (i, byte) in text.bytes().take(len).enumerate() {
+        memory[buffer.wrapping_add(i as u16) as usize] = byte as u16;
+    }
+    memory[buffer.wrapping_add(len as u16) as usize] = 0;
+    Some(len as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::{MEMORY_SIZE, REGISTER};
+
+    #[test]
+    fn strings_end_at_their_terminator() {
+        let mut memory = vec![0u16; MEMORY_SIZE];
+        memory[0x4000..0x4003].copy_from_slice(&[b'o' as u16, b'k' as u16, 0]);
+        assert_eq!(read_string(&memory, 0x4000).as_deref(), Some("ok"));
+        assert_eq!(read_string(&memory, 0x4002).as_deref(), Some(""));
+    }
+
+    #[test]
+    fn a_string_without_a_terminator_in_reach_is_refused() {
+        let mut memory = vec![b'a' as u16; MEMORY_SIZE];
+        assert_eq!(read_string(&memory, 0x4000), None);
+        memory[0x4000 + MAX_STRING] = 0;
+        assert_eq!(read_string(&memory, 0x4000).map(|text| text.len()), Some(MAX_STRING));
+    }
+
+    #[test]
+    fn opening_an_unterminated_path_fails_the_trap() {
+        let mut memory = vec![b'a' as u16; MEMORY_SIZE];
+        let mut registers = [0u16; REGISTER::COUNT as usize];
+        registers[REGISTER::R0 as usize] = 0x4000;
+        let mut fs = FileService::new(std::env::temp_dir());
+        assert!(fs.trap(fs::TRAP_OPEN, &mut registers, &mut memory));
+        assert_eq!(registers[REGISTER::R0 as usize], fs::FS_ERROR);
+    }
+}
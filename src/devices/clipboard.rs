@@ -0,0 +1,114 @@
+use std::collections::VecDeque;
+use std::ops::RangeInclusive;
+
+use arboard::Clipboard;
+use tracing::{debug, warn};
+
+use crate::bus::Device;
+
+pub const CLIPSR: u16 = 0xFE40; /* clipboard command (write) and status (read) */
+pub const CLIPDR: u16 = 0xFE42; /* clipboard data, one byte per access */
+
+pub const COMMAND_PASTE: u16 = 1; /* fetch the host clipboard for reading */
+pub const COMMAND_COPY: u16 = 2; /* replace the host clipboard with the bytes written */
+
+pub const STATUS_DATA: u16 = 1 << 15; /* pasted bytes are waiting in CLIPDR */
+pub const STATUS_ERROR: u16 = 1 << 0; /* the last command failed */
+
+/// A byte-stream bridge to the host clipboard at `0xFE40`/`0xFE42`.
+///
+/// Writing [`COMMAND_PASTE`] to `CLIPSR` fetches the clipboard text; each
+/// read of `CLIPDR` then returns the next byte while `CLIPSR` shows
+/// [`STATUS_DATA`]. Bytes written to `CLIPDR` are collected and placed on the
+/// clipboard by [`COMMAND_COPY`].
+pub struct ClipboardBridge {
+    clipboard: Clipboard,
+    pasted: VecDeque<u8>,
+    copied: Vec<u8>,
+    error: bool,
+}
+
+impl ClipboardBridge {
+    pub fn open() -> Result<Self, String> {
+        let clipboard = Clipboard::new().map_err(|err| err.to_string())?;
+        Ok(ClipboardBridge {
+            clipboard,
+            pasted: VecDeque::new(),
+            copied: Vec::new(),
+            error: false,
+        })
+    }
+
+    fn command(&mut self, command: u16) {
+        let result = match command {
+            COMMAND_PASTE => self.clipboard.get_text().map(|text| {
+                debug!(bytes = text.len(), "clipboard pasted");
+                self.pasted = text.into_bytes().into();
+            }),
+            COMMAND_COPY => {
+                let text = String::from_utf8_lossy(&std::mem::take(&mut self.copied)).into_owned();
+                debug!(bytes = text.len(), "clipboard copied");
+                self.clipboard.set_text(text)
+            }
+            _ => {
+                warn!(command, "unknown clipboard command");
+                self.error = true;
+                return;
+            }
+        };
+        self.error = match result {
+            Ok(()) => false,
+            Err(err) => {
+                warn!(command, %err, "clipboard command failed");
+                true
+            }
+        };
+    }
+}
+
+impl Device for ClipboardBridge {
+    fn name(&self) -> &str {
+        "clipboard"
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        CLIPSR..=CLIPDR
+    }
+
+    fn read(&mut self, address: u16) -> u16 {
+        match address {
+            CLIPSR => {
+                let data = if self.pasted.is_empty() { 0 } else { STATUS_DATA };
+                let error = if self.error { STATUS_ERROR } else { 0 };
+                data | error
+            }
+            CLIPDR => self.pasted.pop_front().map_or(0, u16::from),
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u16) {
+        match address {
+            CLIPSR => self.command(value),
+            CLIPDR => self.copied.push(value as u8),
+            _ => {}
+        }
+    }
+
+    /* drop pasted bytes not yet read and bytes not yet copied */
+    fn reset(&mut self) {
+        self.pasted.clear();
+        self.copied.clear();
+        self.error = false;
+    }
+}
+
+impl std::fmt::Debug for ClipboardBridge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClipboardBridge")
+            .field("pasted", &self.pasted.len())
+            .field("copied", &self.copied.len())
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,132 @@
+use std::fs::OpenOptions;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::ops::RangeInclusive;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use tracing::{debug, info, warn};
+
+use crate::bus::Device;
+
+pub const KBSR2: u16 = 0xFE08; /* second keyboard status */
+pub const KBDR2: u16 = 0xFE0A; /* second keyboard data */
+pub const DSR2: u16 = 0xFE0C; /* second display status */
+pub const DDR2: u16 = 0xFE0E; /* second display data */
+
+pub const STATUS_READY: u16 = 1 << 15;
+
+/// A second keyboard/display register pair at `0xFE08..=0xFE0E`, laid out
+/// like the primary console's, for programs that talk to two users or two
+/// channels at once.
+///
+/// Input is read on a background thread so polling `KBSR2` never blocks the
+/// VM; the display is always ready and each store to `DDR2` is written out
+/// immediately.
+pub struct AuxConsole {
+    input: Receiver<u8>,
+    output: Box<dyn Write + Send>,
+    /* a received byte not yet read from KBDR2 */
+    latched: Option<u8>,
+    closed: bool,
+}
+
+impl AuxConsole {
+    pub fn new(reader: impl Read + Send + 'static, writer: impl Write + Send + 'static) -> Self {
+        let (sender, input) = mpsc::channel();
+        thread::spawn(move || {
+            for byte in BufReader::new(reader).bytes() {
+                let Ok(byte) = byte else { break };
+                if sender.send(byte).is_err() {
+                    break;
+                }
+            }
+        });
+        AuxConsole {
+            input,
+            output: Box::new(writer),
+            latched: None,
+            closed: false,
+        }
+    }
+
+    /// Attach to `tcp:HOST:PORT` (connect), `listen:PORT` (wait for one
+    /// client), or a path such as a terminal or PTY device (`/dev/pts/3`).
+    pub fn connect(spec: &str) -> io::Result<Self> {
+        if let Some(address) = spec.strip_prefix("tcp:") {
+            let stream = TcpStream::connect(address)?;
+            return Ok(AuxConsole::new(stream.try_clone()?, stream));
+        }
+        if let Some(port) = spec.strip_prefix("listen:") {
+            let listener = TcpListener::bind(("0.0.0.0", port.parse().map_err(|_| invalid_port(port))?))?;
+            info!(port, "waiting for the second console to connect");
+            let (stream, peer) = listener.accept()?;
+            debug!(%peer, "second console connected");
+            return Ok(AuxConsole::new(stream.try_clone()?, stream));
+        }
+        let file = OpenOptions::new().read(true).write(true).open(spec)?;
+        Ok(AuxConsole::new(file.try_clone()?, file))
+    }
+
+    fn poll(&mut self) {
+        if self.latched.is_some() || self.closed {
+            return;
+        }
+        match self.input.try_recv() {
+            Ok(byte) => self.latched = Some(byte),
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => {
+                debug!("second console input closed");
+                self.closed = true;
+            }
+        }
+    }
+}
+
+impl Device for AuxConsole {
+    fn name(&self) -> &str {
+        "console2"
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        KBSR2..=DDR2
+    }
+
+    fn read(&mut self, address: u16) -> u16 {
+        match address {
+            KBSR2 => {
+                self.poll();
+                if self.latched.is_some() { STATUS_READY } else { 0 }
+            }
+            KBDR2 => {
+                self.poll();
+                self.latched.take().map_or(0, u16::from)
+            }
+            DSR2 => STATUS_READY,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u16) {
+        if address == DDR2
+            && let Err(err) = self.output.write_all(&[value as u8]).and_then(|()| self.output.flush())
+        {
+            warn!(%err, "second console output failed");
+        }
+    }
+
+    /* forget a received byte the program has not read yet */
+    fn reset(&mut self) {
+        self.latched = None;
+    }
+}
+
+fn invalid_port(port: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("invalid port `{}`", port))
+}
+
+impl std::fmt::Debug for AuxConsole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuxConsole").field("latched", &self.latched).field("closed", &self.closed).finish_non_exhaustive()
+    }
+}
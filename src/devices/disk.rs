@@ -0,0 +1,170 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use tracing::{debug, warn};
+
+use crate::bus::Device;
+use crate::vm::Interrupt;
+
+pub const DISK_SECTOR: u16 = 0xFE10; /* sector number for the next command */
+pub const DISK_BUFFER: u16 = 0xFE12; /* address of the sector buffer in guest memory */
+pub const DISK_COMMAND: u16 = 0xFE14; /* writing a command starts it */
+pub const DISK_STATUS: u16 = 0xFE16; /* ready and error bits */
+
+/* words per sector; stored as 512 big-endian bytes in the image file */
+pub const SECTOR_WORDS: usize = 256;
+
+pub const COMMAND_READ: u16 = 1; /* copy a sector into the buffer */
+pub const COMMAND_WRITE: u16 = 2; /* copy the buffer into a sector */
+
+pub const STATUS_READY: u16 = 1 << 15;
+pub const STATUS_ERROR: u16 = 1 << 0;
+
+/// A block device backed by a host image file, controlled through four
+/// registers at `0xFE10..=0xFE16`.
+///
+/// The guest stores a sector number and a buffer address, then writes
+/// [`COMMAND_READ`] or [`COMMAND_WRITE`] to the command register. Transfers
+/// complete by the end of the storing instruction, so the status register
+/// always shows [`STATUS_READY`]; [`STATUS_ERROR`] is set if the last
+/// command failed.
+/// Sectors past the end of the image read as zeros, and writing to them
+/// grows the file.
+#[derive(Debug)]
+pub struct Disk {
+    file: File,
+    sector: u16,
+    buffer: u16,
+    status: u16,
+    /* a command written this instruction, carried out in the tick that ends it */
+    command: Option<u16>,
+}
+
+impl Disk {
+    /// Open an image file for reading and writing, creating it if it does not exist.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        Ok(Disk::from_file(file))
+    }
+
+    pub fn from_file(file: File) -> Self {
+        Disk {
+            file,
+            sector: 0,
+            buffer: 0,
+            status: STATUS_READY,
+            command: None,
+        }
+    }
+
+    fn seek_to_sector(&mut self) -> io::Result<()> {
+        let offset = self.sector as u64 * SECTOR_WORDS as u64 * 2;
+        self.file.seek(SeekFrom::Start(offset))?;
+        Ok(())
+    }
+
+    /* buffer addresses wrap around the top of memory */
+    fn buffer_address(&self, index: usize) -> usize {
+        self.buffer.wrapping_add(index as u16) as usize
+    }
+
+    fn read_sector(&mut self, memory: &mut [u16]) -> io::Result<()> {
+        debug!(sector = self.sector, buffer = self.buffer, "disk read");
+        self.seek_to_sector()?;
+        let mut bytes = [0u8; SECTOR_WORDS * 2];
+        let mut filled = 0;
+        while filled < bytes.len() {
+            match self.file.read(&mut bytes[filled..])? {
+                0 => break,
+                n => filled += n,
+            }
+        }
+        for (i, pair) in bytes.chunks_exact(2).enumerate() {
+            memory[self.buffer_address(i)] = u16::from_be_bytes([pair[0], pair[1]]);
+        }
+        Ok(())
+    }
+
+    fn write_sector(&mut self, memory: &[u16]) -> io::Result<()> {
+        debug!(sector = self.sector, buffer = self.buffer, "disk write");
+        let mut bytes = Vec::with_capacity(SECTOR_WORDS * 2);
+        for i in 0..SECTOR_WORDS {
+            bytes.extend_from_slice(&memory[self.buffer_address(i)].to_be_bytes());
+        }
+        self.seek_to_sector()?;
+        self.file.write_all(&bytes)?;
+        self.file.flush()
+    }
+}
+
+impl Device for Disk {
+    fn name(&self) -> &str {
+        "disk"
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        DISK_SECTOR..=DISK_STATUS
+    }
+
+    fn read(&mut self, address: u16) -> u16 {
+        match address {
+            DISK_SECTOR => self.sector,
+            DISK_BUFFER => self.buffer,
+            DISK_STATUS => self.status,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u16) {
+        match address {
+            DISK_SECTOR => self.sector = value,
+            DISK_BUFFER => self.buffer = value,
+            DISK_COMMAND => self.command = Some(value),
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, memory: &mut [u16]) -> Option<Interrupt> {
+        let command = self.command.take()?;
+        let result = match command {
+            COMMAND_READ => self.read_sector(memory),
+            COMMAND_WRITE => self.write_sector(memory),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "unknown disk command")),
+        };
+        self.status = match result {
+            Ok(()) => STATUS_READY,
+            Err(err) => {
+                warn!(sector = self.sector, command, %err, "disk command failed");
+                STATUS_READY | STATUS_ERROR
+            }
+        };
+        None
+    }
+
+    /* clear the registers; the image file is left as it is */
+    fn reset(&mut self) {
+        self.sector = 0;
+        self.buffer = 0;
+        self.status = STATUS_READY;
+        self.command = None;
+    }
+
+    /* commands complete within their instruction, so only the registers need saving */
+    fn save(&self) -> Vec<u16> {
+        vec![self.sector, self.buffer, self.status]
+    }
+
+    fn restore(&mut self, state: &[u16]) {
+        if let &[sector, buffer, status] = state {
+            self.sector = sector;
+            self.buffer = buffer;
+            self.status = status;
+        }
+    }
+
+    fn finish(&mut self, memory: &mut [u16]) {
+        self.tick(memory);
+    }
+}
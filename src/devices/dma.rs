@@ -0,0 +1,137 @@
+use std::ops::RangeInclusive;
+
+use tracing::debug;
+
+use crate::bus::Device;
+use crate::vm::Interrupt;
+
+pub const DMA_SOURCE: u16 = 0xFE20; /* first word to copy */
+pub const DMA_DESTINATION: u16 = 0xFE22; /* where the first word goes */
+pub const DMA_LENGTH: u16 = 0xFE24; /* number of words */
+pub const DMA_CONTROL: u16 = 0xFE26; /* go/interrupt-enable on write, status on read */
+
+pub const CONTROL_GO: u16 = 1 << 0; /* start a transfer */
+pub const CONTROL_INTERRUPT_ENABLE: u16 = 1 << 14;
+pub const STATUS_DONE: u16 = 1 << 15; /* no transfer in progress */
+
+/* the DMA controller's slot in the interrupt vector table, at priority level 4 */
+pub const DMA_INTERRUPT: Interrupt = Interrupt { vector: 0x82, priority: 4 };
+
+/// A block-copy engine at `0xFE20..=0xFE26` that moves one word per executed
+/// instruction, so a transfer of N words completes N instructions after it
+/// is started while the program keeps running.
+///
+/// Storing [`CONTROL_GO`] to the control register starts copying `length`
+/// words from `source` to `destination`. Reading the control register shows
+/// [`STATUS_DONE`] once the last word has been copied, along with the
+/// interrupt-enable bit. With [`CONTROL_INTERRUPT_ENABLE`] set, completion
+/// also raises [`DMA_INTERRUPT`].
+#[derive(Debug, Clone, Default)]
+pub struct Dma {
+    source: u16,
+    destination: u16,
+    length: u16,
+    interrupt_enable: bool,
+    /* words copied so far by the running transfer, if any */
+    progress: Option<u16>,
+}
+
+impl Dma {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn busy(&self) -> bool {
+        self.progress.is_some()
+    }
+}
+
+impl Device for Dma {
+    fn name(&self) -> &str {
+        "dma"
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        DMA_SOURCE..=DMA_CONTROL
+    }
+
+    fn read(&mut self, address: u16) -> u16 {
+        match address {
+            DMA_SOURCE => self.source,
+            DMA_DESTINATION => self.destination,
+            DMA_LENGTH => self.length,
+            DMA_CONTROL => {
+                let done = if self.busy() { 0 } else { STATUS_DONE };
+                let enable = if self.interrupt_enable { CONTROL_INTERRUPT_ENABLE } else { 0 };
+                done | enable
+            }
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u16) {
+        match address {
+            DMA_SOURCE => self.source = value,
+            DMA_DESTINATION => self.destination = value,
+            DMA_LENGTH => self.length = value,
+            DMA_CONTROL => {
+                self.interrupt_enable = value & CONTROL_INTERRUPT_ENABLE != 0;
+                if value & CONTROL_GO != 0 {
+                    debug!(source = self.source, destination = self.destination, length = self.length, "dma started");
+                    self.progress = Some(0);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /* copy the next word of a running transfer; the completion interrupt
+       comes when it finishes with interrupts enabled */
+    fn tick(&mut self, memory: &mut [u16]) -> Option<Interrupt> {
+        let copied = self.progress?;
+        if copied < self.length {
+            let from = self.source.wrapping_add(copied) as usize;
+            let to = self.destination.wrapping_add(copied) as usize;
+            memory[to] = memory[from];
+            self.progress = Some(copied + 1);
+            return None;
+        }
+        self.progress = None;
+        debug!(length = self.length, "dma finished");
+        self.interrupt_enable.then_some(DMA_INTERRUPT)
+    }
+
+    /* cancel any transfer and clear the registers */
+    fn reset(&mut self) {
+        *self = Dma::default();
+    }
+
+    fn fork(&self) -> Option<Box<dyn Device>> {
+        Some(Box::new(self.clone()))
+    }
+
+    /* a running transfer is saved as a busy flag and the words copied so far */
+    fn save(&self) -> Vec<u16> {
+        let (busy, copied) = self.progress.map_or((0, 0), |copied| (1, copied));
+        vec![self.source, self.destination, self.length, self.interrupt_enable as u16, busy, copied]
+    }
+
+    fn restore(&mut self, state: &[u16]) {
+        if let &[source, destination, length, interrupt_enable, busy, copied] = state {
+            *self = Dma {
+                source,
+                destination,
+                length,
+                interrupt_enable: interrupt_enable != 0,
+                progress: (busy != 0).then_some(copied),
+            };
+        }
+    }
+
+    /* complete a running transfer immediately, without raising its interrupt */
+    fn finish(&mut self, memory: &mut [u16]) {
+        while self.busy() {
+            self.tick(memory);
+        }
+    }
+}
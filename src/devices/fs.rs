@@ -0,0 +1,153 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use tracing::debug;
+
+use super::{MAX_STRING, copy_string, read_string};
+use crate::vm::REGISTER;
+
+pub const TRAP_OPEN: u16 = 0x30; /* R0 = path, R1 = mode; R0 <- handle */
+pub const TRAP_READ: u16 = 0x31; /* R0 = handle, R1 = buffer, R2 = max length; R0 <- count */
+pub const TRAP_WRITE: u16 = 0x32; /* R0 = handle, R1 = buffer, R2 = length; R0 <- count */
+pub const TRAP_CLOSE: u16 = 0x33; /* R0 = handle; R0 <- 0 */
+pub const TRAP_LIST: u16 = 0x34; /* R0 = index, R1 = buffer, R2 = max length; R0 <- name length */
+
+pub const MODE_READ: u16 = 0;
+pub const MODE_WRITE: u16 = 1; /* create or truncate */
+pub const MODE_APPEND: u16 = 2; /* create or append */
+
+/* returned in R0 when a call fails */
+pub const FS_ERROR: u16 = 0xFFFF;
+
+/* handles are small integers so guests can keep them in a register */
+const MAX_OPEN_FILES: usize = 16;
+
+/// Gives guests open/read/write/close/list access to the files directly
+/// inside a host directory, through `TRAP x30`–`x34`.
+///
+/// Paths and file names are NUL-terminated strings with one character per
+/// word, at most 255 characters long, and file data is transferred one byte per word. Paths must be plain
+/// names relative to the root; anything that could escape it (`..`, absolute
+/// paths, subdirectories) fails. Every call returns its result in R0, which
+/// is [`FS_ERROR`] (-1) on failure.
+#[derive(Debug)]
+pub struct FileService {
+    root: PathBuf,
+    handles: Vec<Option<File>>,
+}
+
+impl FileService {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        FileService {
+            root: root.into(),
+            handles: Vec::new(),
+        }
+    }
+
+    /// Close every open file.
+    pub fn reset(&mut self) {
+        self.handles.clear();
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Run the service call for `trap_code`. Returns false if the vector is not one of ours.
+    pub fn trap(&mut self, trap_code: u16, registers: &mut [u16], memory: &mut [u16]) -> bool {
+        let r0 = registers[REGISTER::R0 as usize];
+        let r1 = registers[REGISTER::R1 as usize];
+        let r2 = registers[REGISTER::R2 as usize];
+        let result = match trap_code {
+            TRAP_OPEN => match read_string(memory, r0) {
+                Some(name) => self.open(&name, r1),
+                None => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("path longer than {} characters", MAX_STRING))),
+            },
+            TRAP_READ => self.read(r0, memory, r1, r2),
+            TRAP_WRITE => self.write(r0, memory, r1, r2),
+            TRAP_CLOSE => self.close(r0),
+            TRAP_LIST => self.list(r0, memory, r1, r2),
+            _ => return false,
+        };
+        registers[REGISTER::R0 as usize] = result.unwrap_or_else(|err| {
+            debug!(trap_code, %err, "file service call failed");
+            FS_ERROR
+        });
+        true
+    }
+
+    fn resolve(&self, name: &str) -> io::Result<PathBuf> {
+        let path = Path::new(name);
+        let mut components = path.components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(_)), None) => Ok(self.root.join(path)),
+            _ => Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("`{}` is outside the sandbox", name))),
+        }
+    }
+
+    fn open(&mut self, name: &str, mode: u16) -> io::Result<u16> {
+        let path = self.resolve(name)?;
+        let mut options = OpenOptions::new();
+        match mode {
+            MODE_READ => options.read(true),
+            MODE_WRITE => options.write(true).create(true).truncate(true),
+            MODE_APPEND => options.append(true).create(true),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "unknown open mode")),
+        };
+        let file = options.open(&path)?;
+        let slot = match self.handles.iter().position(Option::is_none) {
+            Some(slot) => slot,
+            None if self.handles.len() < MAX_OPEN_FILES => {
+                self.handles.push(None);
+                self.handles.len() - 1
+            }
+            None => return Err(io::Error::other("too many open files")),
+        };
+        self.handles[slot] = Some(file);
+        debug!(name, mode, handle = slot, "file opened");
+        Ok(slot as u16)
+    }
+
+    fn file(&mut self, handle: u16) -> io::Result<&mut File> {
+        self.handles
+            .get_mut(handle as usize)
+            .and_then(Option::as_mut)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "bad file handle"))
+    }
+
+    fn read(&mut self, handle: u16, memory: &mut [u16], buffer: u16, len: u16) -> io::Result<u16> {
+        let mut bytes = vec![0; len as usize];
+        let count = self.file(handle)?.read(&mut bytes)?;
+        for (i, byte) in bytes[..count].iter().enumerate() {
+            memory[buffer.wrapping_add(i as u16) as usize] = *byte as u16;
+        }
+        Ok(count as u16)
+    }
+
+    fn write(&mut self, handle: u16, memory: &[u16], buffer: u16, len: u16) -> io::Result<u16> {
+        let bytes: Vec<u8> = (0..len).map(|i| memory[buffer.wrapping_add(i) as usize] as u8).collect();
+        self.file(handle)?.write_all(&bytes)?;
+        Ok(len)
+    }
+
+    fn close(&mut self, handle: u16) -> io::Result<u16> {
+        self.file(handle)?;
+        self.handles[handle as usize] = None;
+        Ok(0)
+    }
+
+    /* the index-th regular file in the root, by name; fails past the last one */
+    fn list(&mut self, index: u16, memory: &mut [u16], buffer: u16, max_len: u16) -> io::Result<u16> {
+        let mut names: Vec<String> = fs::read_dir(&self.root)?
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_file()))
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect();
+        names.sort();
+        let name = names
+            .get(index as usize)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no more files"))?;
+        copy_string(memory, name, buffer, max_len).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty buffer"))
+    }
+}
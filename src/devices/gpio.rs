@@ -0,0 +1,147 @@
+use std::fmt::Write;
+
+use std::ops::RangeInclusive;
+
+use tracing::debug;
+
+use crate::bus::Device;
+use crate::vm::Interrupt;
+
+pub const GPIO_DIRECTION: u16 = 0xFE50; /* 1 bits are outputs, 0 bits inputs */
+pub const GPIO_VALUE: u16 = 0xFE52; /* pin levels; writes only drive output pins */
+pub const GPIO_INTERRUPT_ENABLE: u16 = 0xFE54; /* input pins that interrupt when they change */
+pub const GPIO_CHANGED: u16 = 0xFE56; /* input pins that changed; write 1 bits to clear */
+
+pub const PIN_COUNT: u8 = 16;
+
+/* the GPIO bank's slot in the interrupt vector table, at priority level 4 */
+pub const GPIO_INTERRUPT: Interrupt = Interrupt { vector: 0x83, priority: 4 };
+
+/// Sixteen simulated digital pins at `0xFE50..=0xFE56`, one bit per pin.
+///
+/// The guest picks each pin's direction and drives the output pins; the
+/// host (e.g. the debugger's `pin` command) drives the inputs with
+/// [`Gpio::set_input`]. An input that changes level sets its bit in the
+/// changed register and, if enabled there, raises [`GPIO_INTERRUPT`].
+#[derive(Debug, Clone, Default)]
+pub struct Gpio {
+    direction: u16,
+    outputs: u16,
+    inputs: u16,
+    interrupt_enable: u16,
+    changed: u16,
+    interrupt_pending: bool,
+}
+
+impl Gpio {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Current level of every pin: outputs as driven by the guest, inputs by the host.
+    pub fn levels(&self) -> u16 {
+        (self.outputs & self.direction) | (self.inputs & !self.direction)
+    }
+
+    pub fn is_output(&self, pin: u8) -> bool {
+        self.direction & (1 << pin) != 0
+    }
+
+    /// Drive an input pin from the host. Has no visible effect on an output
+    /// pin until the guest makes it an input.
+    pub fn set_input(&mut self, pin: u8, high: bool) {
+        let bit = 1 << pin;
+        let before = self.inputs;
+        if high {
+            self.inputs |= bit;
+        } else {
+            self.inputs &= !bit;
+        }
+        if before != self.inputs && !self.is_output(pin) {
+            debug!(pin, high, "gpio input changed");
+            self.changed |= bit;
+            if self.interrupt_enable & bit != 0 {
+                self.interrupt_pending = true;
+            }
+        }
+    }
+
+    /// One row per pin: number, direction and level.
+    pub fn render(&self) -> String {
+        let levels = self.levels();
+        let mut out = String::new();
+        for pin in 0..PIN_COUNT {
+            let direction = if self.is_output(pin) { "out" } else { "in " };
+            let level = (levels >> pin) & 1;
+            let interrupt = if self.interrupt_enable & (1 << pin) != 0 { " irq" } else { "" };
+            writeln!(out, "  pin {:>2}  {}  {}{}", pin, direction, level, interrupt).unwrap();
+        }
+        out
+    }
+}
+
+impl Device for Gpio {
+    fn name(&self) -> &str {
+        "gpio"
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        GPIO_DIRECTION..=GPIO_CHANGED
+    }
+
+    fn read(&mut self, address: u16) -> u16 {
+        match address {
+            GPIO_DIRECTION => self.direction,
+            GPIO_VALUE => self.levels(),
+            GPIO_INTERRUPT_ENABLE => self.interrupt_enable,
+            GPIO_CHANGED => self.changed,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u16) {
+        match address {
+            GPIO_DIRECTION => self.direction = value,
+            GPIO_VALUE => {
+                if (self.outputs ^ value) & self.direction != 0 {
+                    debug!(levels = value & self.direction, "gpio outputs changed");
+                }
+                self.outputs = value;
+            }
+            GPIO_INTERRUPT_ENABLE => self.interrupt_enable = value,
+            GPIO_CHANGED => self.changed &= !value,
+            _ => {}
+        }
+    }
+
+    /* deliver a pending change interrupt */
+    fn tick(&mut self, _memory: &mut [u16]) -> Option<Interrupt> {
+        std::mem::take(&mut self.interrupt_pending).then_some(GPIO_INTERRUPT)
+    }
+
+    /* every pin back to an undriven input with interrupts off */
+    fn reset(&mut self) {
+        *self = Gpio::default();
+    }
+
+    fn fork(&self) -> Option<Box<dyn Device>> {
+        Some(Box::new(self.clone()))
+    }
+
+    fn save(&self) -> Vec<u16> {
+        vec![self.direction, self.outputs, self.inputs, self.interrupt_enable, self.changed, self.interrupt_pending as u16]
+    }
+
+    fn restore(&mut self, state: &[u16]) {
+        if let &[direction, outputs, inputs, interrupt_enable, changed, interrupt_pending] = state {
+            *self = Gpio {
+                direction,
+                outputs,
+                inputs,
+                interrupt_enable,
+                changed,
+                interrupt_pending: interrupt_pending != 0,
+            };
+        }
+    }
+}
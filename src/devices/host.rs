@@ -0,0 +1,58 @@
+use tracing::debug;
+
+use super::{copy_string, read_string};
+use crate::vm::REGISTER;
+
+pub const TRAP_ARGC: u16 = 0x35; /* R0 <- number of arguments */
+pub const TRAP_ARGV: u16 = 0x36; /* R0 = index, R1 = buffer, R2 = buffer size; R0 <- length */
+pub const TRAP_GETENV: u16 = 0x37; /* R0 = name, R1 = buffer, R2 = buffer size; R0 <- length */
+
+/* returned in R0 for a missing argument or variable, or an empty buffer */
+pub const HOST_ERROR: u16 = 0xFFFF;
+
+/// Gives guests read access to their command-line arguments and to an
+/// explicit list of host environment variables through `TRAP x35`–`x37`.
+///
+/// Strings are copied into a guest buffer, one character per word and
+/// NUL-terminated, truncated to fit; R0 returns the number of characters
+/// copied. Variables that were not exposed look the same as unset ones, as
+/// do names longer than 255 characters.
+#[derive(Debug, Clone, Default)]
+pub struct HostEnvironment {
+    pub args: Vec<String>,
+    pub vars: Vec<(String, String)>,
+}
+
+impl HostEnvironment {
+    pub fn new(args: Vec<String>) -> Self {
+        HostEnvironment { args, vars: Vec::new() }
+    }
+
+    /// Expose a host environment variable, if it is set.
+    pub fn expose(&mut self, name: &str) {
+        if let Ok(value) = std::env::var(name) {
+            self.vars.push((name.to_string(), value));
+        }
+    }
+
+    /// Run the call for `trap_code`. Returns false if the vector is not one of ours.
+    pub fn trap(&mut self, trap_code: u16, registers: &mut [u16], memory: &mut [u16]) -> bool {
+        let r0 = registers[REGISTER::R0 as usize];
+        let buffer = registers[REGISTER::R1 as usize];
+        let size = registers[REGISTER::R2 as usize];
+        let result = match trap_code {
+            TRAP_ARGC => Some(self.args.len() as u16),
+            TRAP_ARGV => self.args.get(r0 as usize).and_then(|arg| copy_string(memory, arg, buffer, size)),
+            TRAP_GETENV => read_string(memory, r0).and_then(|name| {
+                debug!(name, "guest environment lookup");
+                self.vars
+                    .iter()
+                    .find(|(var, _)| *var == name)
+                    .and_then(|(_, value)| copy_string(memory, value, buffer, size))
+            }),
+            _ => return false,
+        };
+        registers[REGISTER::R0 as usize] = result.unwrap_or(HOST_ERROR);
+        true
+    }
+}
@@ -0,0 +1,94 @@
+use std::ops::RangeInclusive;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+
+use tracing::trace;
+
+use crate::bus::Device;
+
+pub const SERIAL_RX_STATUS: u16 = 0xFE60; /* bit 15 set when a word has arrived */
+pub const SERIAL_RX_DATA: u16 = 0xFE62; /* the received word; reading it clears the status */
+pub const SERIAL_TX_STATUS: u16 = 0xFE64; /* bit 15 set while the other end is connected */
+pub const SERIAL_TX_DATA: u16 = 0xFE66; /* writing sends a word */
+
+pub const STATUS_READY: u16 = 1 << 15;
+
+/// One end of a virtual serial link at `0xFE60..=0xFE66`. Every word one VM
+/// writes to its transmit register arrives in the other VM's receive
+/// register, in order.
+///
+/// Create both ends with [`SerialPort::pair`] and attach one to each VM; the
+/// ends may live on different threads.
+#[derive(Debug)]
+pub struct SerialPort {
+    sender: Sender<u16>,
+    receiver: Receiver<u16>,
+    /* a received word not yet read from the data register */
+    latched: Option<u16>,
+    connected: bool,
+}
+
+impl SerialPort {
+    pub fn pair() -> (SerialPort, SerialPort) {
+        let (a_sender, b_receiver) = mpsc::channel();
+        let (b_sender, a_receiver) = mpsc::channel();
+        (SerialPort::new(a_sender, a_receiver), SerialPort::new(b_sender, b_receiver))
+    }
+
+    fn new(sender: Sender<u16>, receiver: Receiver<u16>) -> Self {
+        SerialPort {
+            sender,
+            receiver,
+            latched: None,
+            connected: true,
+        }
+    }
+
+    fn poll(&mut self) {
+        if self.latched.is_none() {
+            match self.receiver.try_recv() {
+                Ok(word) => self.latched = Some(word),
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => self.connected = false,
+            }
+        }
+    }
+}
+
+impl Device for SerialPort {
+    fn name(&self) -> &str {
+        "serial"
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        SERIAL_RX_STATUS..=SERIAL_TX_DATA
+    }
+
+    fn read(&mut self, address: u16) -> u16 {
+        match address {
+            SERIAL_RX_STATUS => {
+                self.poll();
+                if self.latched.is_some() { STATUS_READY } else { 0 }
+            }
+            SERIAL_RX_DATA => {
+                self.poll();
+                self.latched.take().unwrap_or(0)
+            }
+            SERIAL_TX_STATUS if self.connected => STATUS_READY,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u16) {
+        if address == SERIAL_TX_DATA {
+            trace!(value, "serial transmit");
+            if self.sender.send(value).is_err() {
+                self.connected = false;
+            }
+        }
+    }
+
+    /* forget a received word the program has not read yet */
+    fn reset(&mut self) {
+        self.latched = None;
+    }
+}
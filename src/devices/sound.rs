@@ -0,0 +1,94 @@
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+use rodio::source::{SineWave, Source};
+use rodio::{OutputStream, Sink};
+use tracing::debug;
+
+use crate::bus::Device;
+
+pub const TONE_FREQUENCY: u16 = 0xFE30; /* pitch in Hz */
+pub const TONE_DURATION: u16 = 0xFE32; /* length in milliseconds; writing it plays the tone */
+pub const TONE_STATUS: u16 = 0xFE34; /* bit 15 set while tones are still playing */
+
+pub const STATUS_PLAYING: u16 = 1 << 15;
+
+/* keep the sine wave well below full scale */
+const VOLUME: f32 = 0.2;
+
+/// A beeper at `0xFE30..=0xFE34` that plays square-ish sine tones through
+/// the host's default audio output.
+///
+/// Store a frequency, then a duration; tones queue up and play one after
+/// another while the program continues. A frequency of 0 queues silence,
+/// which is handy for rests between notes.
+pub struct Beeper {
+    /* the stream must outlive the sink or playback stops */
+    _stream: OutputStream,
+    sink: Sink,
+    frequency: u16,
+}
+
+impl Beeper {
+    /// Open the default audio output device.
+    pub fn open() -> Result<Self, String> {
+        let (stream, handle) = OutputStream::try_default().map_err(|err| err.to_string())?;
+        let sink = Sink::try_new(&handle).map_err(|err| err.to_string())?;
+        Ok(Beeper {
+            _stream: stream,
+            sink,
+            frequency: 0,
+        })
+    }
+
+    fn play(&mut self, milliseconds: u16) {
+        let duration = Duration::from_millis(milliseconds as u64);
+        debug!(frequency = self.frequency, milliseconds, "tone");
+        if self.frequency == 0 {
+            self.sink.append(rodio::source::Zero::<f32>::new(1, 48_000).take_duration(duration));
+        } else {
+            self.sink.append(SineWave::new(self.frequency as f32).take_duration(duration).amplify(VOLUME));
+        }
+    }
+}
+
+impl Device for Beeper {
+    fn name(&self) -> &str {
+        "sound"
+    }
+
+    fn range(&self) -> RangeInclusive<u16> {
+        TONE_FREQUENCY..=TONE_STATUS
+    }
+
+    fn read(&mut self, address: u16) -> u16 {
+        match address {
+            TONE_FREQUENCY => self.frequency,
+            TONE_STATUS => {
+                if self.sink.empty() { 0 } else { STATUS_PLAYING }
+            }
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u16) {
+        match address {
+            TONE_FREQUENCY => self.frequency = value,
+            TONE_DURATION => self.play(value),
+            _ => {}
+        }
+    }
+
+    /* stop queued tones and clear the frequency */
+    fn reset(&mut self) {
+        self.sink.clear();
+        self.sink.play();
+        self.frequency = 0;
+    }
+}
+
+impl std::fmt::Debug for Beeper {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Beeper").field("frequency", &self.frequency).finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,101 @@
+//! Line diffs of a program's output against the expected text, as printed
+//! by `--expect-output`.
+
+use std::fmt::Write;
+
+use crate::color::{Painter, Role};
+
+/* beyond this many cells the middle is not aligned, only shown removed then added */
+const MAX_CELLS: usize = 1 << 22;
+
+/* unchanged lines kept around each change when rendering */
+const CONTEXT: usize = 2;
+
+/// One line of a diff.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Line<'a> {
+    /// In both texts.
+    Same(&'a str),
+    /// Expected but not printed.
+    Missing(&'a str),
+    /// Printed but not expected.
+    Extra(&'a str),
+}
+
+/// Align `expected` and `actual` line by line, keeping the longest run of
+/// lines common to both. A missing or extra newline at the end shows up as
+/// an empty line.
+pub fn lines<'a>(expected: &'a str, actual: &'a str) -> Vec<Line<'a>> {
+    let expected: Vec<&str> = expected.split('\n').collect();
+    let actual: Vec<&str> = actual.split('\n').collect();
+    let prefix = expected.iter().zip(&actual).take_while(|(e, a)| e == a).count();
+    let suffix = expected[prefix..]
+        .iter()
+        .rev()
+        .zip(actual[prefix..].iter().rev())
+        .take_while(|(e, a)| e == a)
+        .count();
+    let (e, a) = (&expected[prefix..expected.len() - suffix], &actual[prefix..actual.len() - suffix]);
+
+    let mut diff: Vec<Line> = expected[..prefix].iter().map(|line| Line::Same(line)).collect();
+    if (e.len() + 1) * (a.len() + 1) > MAX_CELLS {
+        diff.extend(e.iter().map(|line| Line::Missing(line)));
+        diff.extend(a.iter().map(|line| Line::Extra(line)));
+    } else {
+        /* common[i][j]: length of the longest common subsequence of e[i..] and a[j..] */
+        let width = a.len() + 1;
+        let mut common = vec![0u32; (e.len() + 1) * width];
+        for i in (0..e.len()).rev() {
+            for j in (0..a.len()).rev() {
+                common[i * width + j] = if e[i] == a[j] {
+                    common[(i + 1) * width + j + 1] + 1
+                } else {
+                    common[(i + 1) * width + j].max(common[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < e.len() || j < a.len() {
+            if i < e.len() && j < a.len() && e[i] == a[j] {
+                diff.push(Line::Same(e[i]));
+                (i, j) = (i + 1, j + 1);
+            } else if j == a.len() || (i < e.len() && common[(i + 1) * width + j] >= common[i * width + j + 1]) {
+                diff.push(Line::Missing(e[i]));
+                i += 1;
+            } else {
+                diff.push(Line::Extra(a[j]));
+                j += 1;
+            }
+        }
+    }
+    diff.extend(expected[expected.len() - suffix..].iter().map(|line| Line::Same(line)));
+    diff
+}
+
+/// Show a diff with `-` before expected lines and `+` before printed ones,
+/// colored as expected and actual values, keeping two unchanged lines
+/// around each change and eliding the rest.
+pub fn render(diff: &[Line], painter: &Painter) -> String {
+    let near_change = |index: usize| {
+        let start = index.saturating_sub(CONTEXT);
+        diff[start..diff.len().min(index + CONTEXT + 1)].iter().any(|line| !matches!(line, Line::Same(_)))
+    };
+    let mut out = String::new();
+    let mut elided = false;
+    for (index, line) in diff.iter().enumerate() {
+        match line {
+            Line::Same(text) if near_change(index) => writeln!(out, "  {}", text).unwrap(),
+            Line::Same(_) => {
+                if !elided {
+                    writeln!(out, "  ...").unwrap();
+                }
+                elided = true;
+                continue;
+            }
+            Line::Missing(text) => writeln!(out, "{}", painter.paint(Role::Expected, &format!("- {}", text))).unwrap(),
+            Line::Extra(text) => writeln!(out, "{}", painter.paint(Role::Actual, &format!("+ {}", text))).unwrap(),
+        }
+        elided = false;
+    }
+    out
+}
@@ -0,0 +1,70 @@
+use crate::instruction::{Instruction, Operand, decode};
+
+/* absolute address of a PC-relative operand */
+fn target(address: u16, offset: i16) -> u16 {
+    address.wrapping_add(1).wrapping_add(offset as u16)
+}
+
+/// Name of a standard trap vector, if it has one.
+pub fn trap_name(vector: u16) -> Option<&'static str> {
+    match vector {
+        0x20 => Some("GETC"),
+        0x21 => Some("OUT"),
+        0x22 => Some("PUTS"),
+        0x23 => Some("IN"),
+        0x24 => Some("PUTSP"),
+        0x25 => Some("HALT"),
+        _ => None,
+    }
+}
+
+/// Render the instruction `word`, located at `address`, as assembly.
+/// PC-relative operands are shown as absolute target addresses.
+pub fn disassemble(address: u16, word: u16) -> String {
+    render(address, word, |target| format!("x{:04X}", target))
+}
+
+/// Like [`disassemble`], but PC-relative operands near a label are shown
+/// as the label (`BRnzp LOOP`, `LD R1, DATA+3`).
+pub fn disassemble_with(address: u16, word: u16, symbols: &crate::symbols::SymbolTable) -> String {
+    render(address, word, |target| symbols.name_for(target).unwrap_or_else(|| format!("x{:04X}", target)))
+}
+
+fn render(address: u16, word: u16, operand: impl Fn(u16) -> String) -> String {
+    match decode(word) {
+        Instruction::Br { n, z, p, offset } => {
+            if !(n || z || p) {
+                return "NOP".to_string();
+            }
+            let flag = |set: bool, name: &'static str| if set { name } else { "" };
+            format!("BR{}{}{} {}", flag(n, "n"), flag(z, "z"), flag(p, "p"), operand(target(address, offset)))
+        }
+        Instruction::Add { dr, sr1, src2 } => arithmetic("ADD", dr, sr1, src2),
+        Instruction::And { dr, sr1, src2 } => arithmetic("AND", dr, sr1, src2),
+        Instruction::Ld { dr, offset } => format!("LD R{}, {}", dr, operand(target(address, offset))),
+        Instruction::St { sr, offset } => format!("ST R{}, {}", sr, operand(target(address, offset))),
+        Instruction::Jsr { offset } => format!("JSR {}", operand(target(address, offset))),
+        Instruction::Jsrr { base } => format!("JSRR R{}", base),
+        Instruction::Ldr { dr, base, offset } => format!("LDR R{}, R{}, #{}", dr, base, offset),
+        Instruction::Str { sr, base, offset } => format!("STR R{}, R{}, #{}", sr, base, offset),
+        Instruction::Rti => "RTI".to_string(),
+        Instruction::Not { dr, sr } => format!("NOT R{}, R{}", dr, sr),
+        Instruction::Ldi { dr, offset } => format!("LDI R{}, {}", dr, operand(target(address, offset))),
+        Instruction::Sti { sr, offset } => format!("STI R{}, {}", sr, operand(target(address, offset))),
+        Instruction::Jmp { base: 7 } => "RET".to_string(),
+        Instruction::Jmp { base } => format!("JMP R{}", base),
+        Instruction::Reserved(word) => format!(".FILL x{:04X} ; reserved opcode", word),
+        Instruction::Lea { dr, offset } => format!("LEA R{}, {}", dr, operand(target(address, offset))),
+        Instruction::Trap { vector } => match trap_name(vector as u16) {
+            Some(name) => name.to_string(),
+            None => format!("TRAP x{:02X}", vector),
+        },
+    }
+}
+
+fn arithmetic(name: &str, dr: u8, sr1: u8, src2: Operand) -> String {
+    match src2 {
+        Operand::Register(sr2) => format!("{} R{}, R{}, R{}", name, dr, sr1, sr2),
+        Operand::Immediate(value) => format!("{} R{}, R{}, #{}", name, dr, sr1, value),
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::color::{Painter, Role};
+use crate::disasm::{disassemble, disassemble_with, trap_name};
+use crate::json;
+use crate::program::Program;
+use crate::symbols::SymbolTable;
+
+/* how many instructions of the entry point to disassemble */
+const ENTRY_LISTING_LEN: usize = 16;
+/* shortest run of printable words (before the terminator) reported as a string */
+const MIN_STRING_LEN: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentKind {
+    /// Instructions or data that is not recognisably a string or padding.
+    Words,
+    /// A NUL-terminated run of printable characters.
+    String,
+    /// A run of zero words, typically `.BLKW` space.
+    Zero,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub kind: SegmentKind,
+    pub start: u16,
+    pub len: usize,
+}
+
+fn is_printable(word: u16) -> bool {
+    /* printable ASCII plus tab, newline, carriage return and escape */
+    matches!(word, 0x20..=0x7E | 0x09 | 0x0A | 0x0D | 0x1B)
+}
+
+/// Classify every word of the program and merge neighbours into segments.
+pub fn segments(program: &Program) -> Vec<Segment> {
+    let words = &program.words;
+    let mut kinds = vec![SegmentKind::Words; words.len()];
+
+    let mut i = 0;
+    while i < words.len() {
+        let start = i;
+        while i < words.len() && is_printable(words[i]) {
+            i += 1;
+        }
+        if i - start >= MIN_STRING_LEN && i < words.len() && words[i] == 0 {
+            kinds[start..=i].fill(SegmentKind::String);
+            i += 1;
+        } else if i == start {
+            i += 1;
+        }
+    }
+    let mut i = 0;
+    while i < words.len() {
+        let start = i;
+        while i < words.len() && words[i] == 0 && kinds[i] == SegmentKind::Words {
+            i += 1;
+        }
+        if i - start >= 2 {
+            kinds[start..i].fill(SegmentKind::Zero);
+        } else if i == start {
+            i += 1;
+        }
+    }
+
+    let mut segments: Vec<Segment> = Vec::new();
+    for (offset, kind) in kinds.into_iter().enumerate() {
+        match segments.last_mut() {
+            Some(segment) if segment.kind == kind && kind != SegmentKind::String => segment.len += 1,
+            Some(segment) if segment.kind == kind && program.words[offset - 1] != 0 => segment.len += 1,
+            _ => segments.push(Segment {
+                kind,
+                start: program.origin.wrapping_add(offset as u16),
+                len: 1,
+            }),
+        }
+    }
+    segments
+}
+
+/* addresses of each TRAP instruction in the code/data segments, by vector */
+fn trap_sites(program: &Program, segments: &[Segment]) -> BTreeMap<u16, Vec<u16>> {
+    let mut traps: BTreeMap<u16, Vec<u16>> = BTreeMap::new();
+    for segment in segments.iter().filter(|segment| segment.kind == SegmentKind::Words) {
+        let offset = segment.start.wrapping_sub(program.origin) as usize;
+        for (i, &word) in program.words[offset..offset + segment.len].iter().enumerate() {
+            if word >> 12 == 0b1111 && (word >> 8) & 0xF == 0 {
+                traps.entry(word & 0xFF).or_default().push(segment.start.wrapping_add(i as u16));
+            }
+        }
+    }
+    traps
+}
+
+/* the entry listing stops where the first code/data segment ends */
+fn entry_len(segments: &[Segment]) -> usize {
+    match segments.first() {
+        Some(segment) if segment.kind == SegmentKind::Words => segment.len.min(ENTRY_LISTING_LEN),
+        _ => 0,
+    }
+}
+
+fn kind_name(kind: SegmentKind) -> &'static str {
+    match kind {
+        SegmentKind::Words => "code/data",
+        SegmentKind::String => "string",
+        SegmentKind::Zero => "zeros",
+    }
+}
+
+fn string_at(program: &Program, segment: &Segment) -> String {
+    let offset = segment.start.wrapping_sub(program.origin) as usize;
+    program.words[offset..offset + segment.len - 1]
+        .iter()
+        .map(|&word| char::from(word as u8).escape_default().to_string())
+        .collect()
+}
+
+/// Describe an image without running it: layout, strings, trap usage, and a
+/// disassembly of the first instructions at the origin.
+pub fn render(program: &Program, painter: &Painter) -> String {
+    let mut out = String::new();
+    let end = program.origin.wrapping_add(program.words.len() as u16).wrapping_sub(1);
+    writeln!(out, "Origin: x{:04X}", program.origin).unwrap();
+    writeln!(out, "Size:   {} words (x{:04X}-x{:04X})", program.words.len(), program.origin, end).unwrap();
+
+    let segments = segments(program);
+    writeln!(out, "\nSegments:").unwrap();
+    for segment in &segments {
+        let kind = kind_name(segment.kind);
+        let last = segment.start.wrapping_add(segment.len as u16 - 1);
+        writeln!(out, "  x{:04X}-x{:04X}  {:>5} words  {}", segment.start, last, segment.len, kind).unwrap();
+    }
+
+    writeln!(out, "\nStrings:").unwrap();
+    for segment in segments.iter().filter(|segment| segment.kind == SegmentKind::String) {
+        writeln!(out, "  x{:04X}  \"{}\"", segment.start, string_at(program, segment)).unwrap();
+    }
+
+    let traps = trap_sites(program, &segments);
+    writeln!(out, "\nTrap usage:").unwrap();
+    for (vector, sites) in &traps {
+        let name = trap_name(*vector).unwrap_or("?");
+        let sites: Vec<String> = sites.iter().map(|site| format!("x{:04X}", site)).collect();
+        writeln!(out, "  x{:02X} {:<5} {:>4}x  {}", vector, name, sites.len(), sites.join(" ")).unwrap();
+    }
+
+    let entry_len = entry_len(&segments);
+    let symbols = SymbolTable::new(&program.symbols);
+    writeln!(out, "\nEntry:").unwrap();
+    for (i, &word) in program.words.iter().take(entry_len).enumerate() {
+        let address = program.origin.wrapping_add(i as u16);
+        let address_text = painter.paint(Role::Address, &format!("x{:04X}", address));
+        writeln!(out, "  {}  {:04X}  {}", address_text, word, painter.disassembly(&disassemble_with(address, word, &symbols))).unwrap();
+    }
+    out
+}
+
+/// Disassemble every word of the image, one line per address, with the
+/// program's labels (if it has any) on the lines they name.
+pub fn render_listing(program: &Program, painter: &Painter) -> String {
+    let mut labels: BTreeMap<u16, Vec<&str>> = BTreeMap::new();
+    for (name, &address) in &program.symbols {
+        labels.entry(address).or_default().push(name);
+    }
+    let symbols = SymbolTable::new(&program.symbols);
+    let mut out = String::new();
+    for (i, &word) in program.words.iter().enumerate() {
+        let address = program.origin.wrapping_add(i as u16);
+        if let Some(names) = labels.get_mut(&address) {
+            names.sort_unstable();
+            for name in names {
+                writeln!(out, "{}:", name).unwrap();
+            }
+        }
+        let address_text = painter.paint(Role::Address, &format!("x{:04X}", address));
+        writeln!(out, "  {}  {:04X}  {}", address_text, word, painter.disassembly(&disassemble_with(address, word, &symbols))).unwrap();
+    }
+    out
+}
+
+/// The same information as [`render`] as a single JSON object (`"kind": "dump"`).
+pub fn render_json(program: &Program) -> String {
+    let segments = segments(program);
+    let segment_items = json::array(segments.iter().map(|segment| {
+        format!(
+            "{{\"start\":{},\"length\":{},\"kind\":{}}}",
+            segment.start,
+            segment.len,
+            json::string(kind_name(segment.kind))
+        )
+    }));
+    let strings = json::array(segments.iter().filter(|segment| segment.kind == SegmentKind::String).map(|segment| {
+        let offset = segment.start.wrapping_sub(program.origin) as usize;
+        let text: String = program.words[offset..offset + segment.len - 1].iter().map(|&word| char::from(word as u8)).collect();
+        format!("{{\"address\":{},\"text\":{}}}", segment.start, json::string(&text))
+    }));
+    let traps = json::array(trap_sites(program, &segments).iter().map(|(vector, sites)| {
+        let name = trap_name(*vector).map_or("null".to_string(), json::string);
+        format!(
+            "{{\"vector\":{},\"name\":{},\"sites\":{}}}",
+            vector,
+            name,
+            json::array(sites.iter().map(u16::to_string))
+        )
+    }));
+    let entry = json::array(program.words.iter().take(entry_len(&segments)).enumerate().map(|(i, &word)| {
+        let address = program.origin.wrapping_add(i as u16);
+        format!(
+            "{{\"address\":{},\"word\":{},\"text\":{}}}",
+            address,
+            word,
+            json::string(&disassemble(address, word))
+        )
+    }));
+    format!(
+        "{{\"kind\":\"dump\",\"origin\":{},\"size\":{},\"segments\":{},\"strings\":{},\"traps\":{},\"entry\":{}}}",
+        program.origin,
+        program.words.len(),
+        segment_items,
+        strings,
+        traps,
+        entry
+    )
+}
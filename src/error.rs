@@ -0,0 +1,48 @@
+use std::fmt;
+use std::io;
+
+/// Why loading or running a program failed.
+#[derive(Debug)]
+pub enum VmError {
+    /// The object image is malformed, e.g. empty or an odd number of bytes.
+    InvalidImage(String),
+    /// The instruction at `pc` cannot be executed (a reserved opcode with no handler).
+    IllegalOpcode { pc: u16, instruction: u16 },
+    /// A saved [`VmState`](crate::state::VmState) does not fit the machine, e.g. the wrong memory size.
+    InvalidState(String),
+    /// Guest arguments do not fit in the page reserved for them.
+    InvalidArguments(String),
+    /// An [`InputRecording`](crate::recording::InputRecording) file is malformed.
+    InvalidRecording(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VmError::InvalidImage(reason) => write!(f, "invalid image: {}", reason),
+            VmError::IllegalOpcode { pc, instruction } => {
+                write!(f, "illegal opcode 0x{:04X} at 0x{:04X}", instruction, pc)
+            }
+            VmError::InvalidState(reason) => write!(f, "invalid state: {}", reason),
+            VmError::InvalidArguments(reason) => write!(f, "invalid arguments: {}", reason),
+            VmError::InvalidRecording(reason) => write!(f, "invalid recording: {}", reason),
+            VmError::Io(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for VmError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VmError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for VmError {
+    fn from(err: io::Error) -> Self {
+        VmError::Io(err)
+    }
+}
@@ -0,0 +1,57 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/// Something observable that happened while the VM was running.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VmEvent {
+    /// The guest executed TRAP HALT; `pc` is the address of the TRAP instruction.
+    Halted { pc: u16 },
+    /// A TRAP instruction was executed (including HALT).
+    TrapExecuted { pc: u16, trap_code: u16 },
+    /// A BR instruction whose condition matched moved the PC.
+    BranchTaken { from: u16, to: u16 },
+    /// [`Vm::run`](crate::vm::Vm::run) stopped at a breakpoint.
+    BreakpointHit { pc: u16 },
+    /// [`Vm::run`](crate::vm::Vm::run) stopped after the instruction at `pc`
+    /// touched the watched `address`.
+    WatchpointHit { pc: u16, address: u16 },
+    /// An interrupt was accepted; `pc` is where the interrupted program resumes.
+    InterruptTaken { vector: u8, pc: u16 },
+}
+
+pub type EventListener = Box<dyn FnMut(&VmEvent)>;
+
+/// Fans events out to registered callbacks and channel subscribers.
+#[derive(Default)]
+pub struct EventBus {
+    listeners: Vec<EventListener>,
+    senders: Vec<Sender<VmEvent>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe<F>(&mut self, listener: F)
+    where
+        F: FnMut(&VmEvent) + 'static,
+    {
+        self.listeners.push(Box::new(listener));
+    }
+
+    /// Returns a receiver that gets a copy of every event from now on.
+    /// Dropping the receiver unsubscribes it.
+    pub fn channel(&mut self) -> Receiver<VmEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.senders.push(sender);
+        receiver
+    }
+
+    pub fn emit(&mut self, event: VmEvent) {
+        for listener in self.listeners.iter_mut() {
+            listener(&event);
+        }
+        self.senders.retain(|sender| sender.send(event.clone()).is_ok());
+    }
+}
@@ -0,0 +1,136 @@
+//! Expressions over the machine state for the debugger's `display`:
+//! registers (`R0`-`R7`, `PC`, `PSR`), numbers, labels, memory reads
+//! written `MEM[ADDR]`, `+` and `-`, and parentheses, e.g.
+//! `MEM[MEM[x4000] + 2]`. Arithmetic wraps at 16 bits like the machine's.
+
+use crate::address::parse_address;
+use crate::symbols::SymbolTable;
+use crate::vm::{REGISTER, Vm};
+
+/// A parsed expression; labels are already turned into their addresses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Number(u16),
+    /// R0-R7 or the PC, by [`REGISTER`] index.
+    Register(usize),
+    Psr,
+    /// The word at an address.
+    Memory(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Negate(Box<Expr>),
+}
+
+impl Expr {
+    /// Parse `text`, looking labels up in `symbols`.
+    pub fn parse(text: &str, symbols: &SymbolTable) -> Result<Expr, String> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser { tokens: &tokens, position: 0, symbols };
+        let expr = parser.sum()?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(expr),
+            Some(token) => Err(format!("unexpected `{}`", token)),
+        }
+    }
+
+    /// The expression's value in the machine as it is now.
+    pub fn evaluate(&self, vm: &Vm) -> u16 {
+        match self {
+            Expr::Number(value) => *value,
+            Expr::Register(index) => vm.registers[*index],
+            Expr::Psr => vm.psr(),
+            Expr::Memory(address) => vm.memory[address.evaluate(vm) as usize],
+            Expr::Add(left, right) => left.evaluate(vm).wrapping_add(right.evaluate(vm)),
+            Expr::Sub(left, right) => left.evaluate(vm).wrapping_sub(right.evaluate(vm)),
+            Expr::Negate(operand) => operand.evaluate(vm).wrapping_neg(),
+        }
+    }
+}
+
+/* names and numbers, and the punctuation between them */
+fn tokenize(text: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if "[]()+-".contains(c) {
+            tokens.push(c.to_string());
+            chars.next();
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_ascii_alphanumeric() || **c == '_') {
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(word);
+        } else {
+            return Err(format!("unexpected `{}`", c));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [String],
+    position: usize,
+    symbols: &'a SymbolTable,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<&str> {
+        let token = self.tokens.get(self.position)?;
+        self.position += 1;
+        Some(token)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(format!("expected `{}`, found `{}`", expected, token)),
+            None => Err(format!("expected `{}`", expected)),
+        }
+    }
+
+    /* term (+|- term)* */
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut expr = self.term()?;
+        while let Some(operator) = self.tokens.get(self.position).filter(|token| *token == "+" || *token == "-") {
+            let add = operator == "+";
+            self.position += 1;
+            let right = Box::new(self.term()?);
+            expr = if add { Expr::Add(Box::new(expr), right) } else { Expr::Sub(Box::new(expr), right) };
+        }
+        Ok(expr)
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        let symbols = self.symbols;
+        let Some(token) = self.next() else {
+            return Err("expression ends early".to_string());
+        };
+        match token {
+            "-" => Ok(Expr::Negate(Box::new(self.term()?))),
+            "(" => {
+                let expr = self.sum()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            name if name.eq_ignore_ascii_case("MEM") => {
+                self.expect("[")?;
+                let address = self.sum()?;
+                self.expect("]")?;
+                Ok(Expr::Memory(Box::new(address)))
+            }
+            name if name.eq_ignore_ascii_case("PC") => Ok(Expr::Register(REGISTER::PC as usize)),
+            name if name.eq_ignore_ascii_case("PSR") => Ok(Expr::Psr),
+            name if name.len() == 2 && name.starts_with(['R', 'r']) && matches!(name.as_bytes()[1], b'0'..=b'7') => {
+                Ok(Expr::Register((name.as_bytes()[1] - b'0') as usize))
+            }
+            name => parse_address(name)
+                .or_else(|| symbols.address_of(name))
+                .map(Expr::Number)
+                .ok_or_else(|| format!("`{}` is not a register, number or label", name)),
+        }
+    }
+}
@@ -0,0 +1,181 @@
+//! C-compatible interface, built with the `ffi` feature as a shared library
+//! (`cargo rustc --lib --crate-type cdylib --release --features ffi`) and
+//! declared in `include/lc3_vm.h`. A VM is an opaque pointer from
+//! [`lc3_vm_new`] that must be released with [`lc3_vm_free`]; its console
+//! is a [`QueuedConsole`] fed with [`lc3_vm_push_input`] and drained with
+//! [`lc3_vm_take_output`], so no call ever blocks.
+//!
+//! Every function taking a `vm` pointer requires one returned by
+//! [`lc3_vm_new`] and not yet freed, used from one thread at a time.
+
+use std::slice;
+
+use crate::console::QueuedConsole;
+use crate::vm::{REGISTER, StepOutcome, Vm};
+
+/// [`lc3_vm_step`] results.
+pub const LC3_CONTINUED: i32 = 0;
+pub const LC3_TRAPPED: i32 = 1;
+pub const LC3_HALTED: i32 = 2;
+pub const LC3_FAULTED: i32 = 3;
+pub const LC3_WAITING_FOR_INPUT: i32 = 4;
+
+/* every VM handed out has a queued console */
+fn console(vm: &mut Vm) -> &mut QueuedConsole {
+    vm.console.downcast_mut().expect("FFI VMs keep their queued console")
+}
+
+/// Create a VM with empty memory. Never returns null.
+#[unsafe(no_mangle)]
+pub extern "C" fn lc3_vm_new() -> *mut Vm {
+    let mut vm = Vm::new();
+    vm.console = Box::new(QueuedConsole::default());
+    Box::into_raw(Box::new(vm))
+}
+
+/// Release a VM. Null is ignored.
+///
+/// # Safety
+/// `vm` must be null or a live pointer from [`lc3_vm_new`]; it is invalid afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lc3_vm_free(vm: *mut Vm) {
+    if !vm.is_null() {
+        drop(unsafe { Box::from_raw(vm) });
+    }
+}
+
+/// Load an object image of `len` bytes. Returns 0 on success and -1 if
+/// the image is malformed.
+///
+/// # Safety
+/// `vm` must be live and `bytes` must point to `len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lc3_vm_load(vm: *mut Vm, bytes: *const u8, len: usize) -> i32 {
+    let vm = unsafe { &mut *vm };
+    let bytes = if len == 0 { &[][..] } else { unsafe { slice::from_raw_parts(bytes, len) } };
+    match vm.load_from_bytes(bytes) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+/// Execute one instruction; returns one of the `LC3_*` step results.
+///
+/// # Safety
+/// `vm` must be live.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lc3_vm_step(vm: *mut Vm) -> i32 {
+    match unsafe { &mut *vm }.step() {
+        StepOutcome::Continued => LC3_CONTINUED,
+        StepOutcome::Trapped(_) => LC3_TRAPPED,
+        StepOutcome::Halted => LC3_HALTED,
+        StepOutcome::Faulted { .. } => LC3_FAULTED,
+        StepOutcome::WaitingForInput => LC3_WAITING_FOR_INPUT,
+    }
+}
+
+/// Execute up to `budget` instructions, stopping early at HALT, a fault or
+/// a GETC/IN with no queued input. Returns the number executed.
+///
+/// # Safety
+/// `vm` must be live.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lc3_vm_run(vm: *mut Vm, budget: u64) -> u64 {
+    let vm = unsafe { &mut *vm };
+    let start = vm.instruction_count;
+    for _ in 0..budget {
+        if !matches!(vm.step(), StepOutcome::Continued | StepOutcome::Trapped(_)) {
+            break;
+        }
+    }
+    vm.instruction_count - start
+}
+
+/// Start the loaded image over.
+///
+/// # Safety
+/// `vm` must be live.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lc3_vm_reset(vm: *mut Vm) {
+    unsafe { &mut *vm }.reset();
+}
+
+/// 1 once the program has halted, 0 otherwise.
+///
+/// # Safety
+/// `vm` must be live.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lc3_vm_halted(vm: *const Vm) -> i32 {
+    unsafe { &*vm }.halted as i32
+}
+
+/// # Safety
+/// `vm` must be live.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lc3_vm_read_mem(vm: *const Vm, address: u16) -> u16 {
+    unsafe { &*vm }.memory[address as usize]
+}
+
+/// # Safety
+/// `vm` must be live.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lc3_vm_write_mem(vm: *mut Vm, address: u16, value: u16) {
+    unsafe { &mut *vm }.memory[address as usize] = value;
+}
+
+/// Register `index`: 0-7 for R0-R7, 8 for the PC, 9 for COND. Out-of-range indexes read as 0.
+///
+/// # Safety
+/// `vm` must be live.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lc3_vm_read_reg(vm: *const Vm, index: u32) -> u16 {
+    let vm = unsafe { &*vm };
+    vm.registers.get(index as usize).copied().unwrap_or(0)
+}
+
+/// Set register `index`, numbered as for [`lc3_vm_read_reg`]; out-of-range indexes are ignored.
+///
+/// # Safety
+/// `vm` must be live.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lc3_vm_write_reg(vm: *mut Vm, index: u32, value: u16) {
+    let vm = unsafe { &mut *vm };
+    if let Some(register) = vm.registers.get_mut(index as usize) {
+        *register = value;
+    }
+}
+
+/// The program counter.
+///
+/// # Safety
+/// `vm` must be live.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lc3_vm_pc(vm: *const Vm) -> u16 {
+    unsafe { &*vm }.registers[REGISTER::PC as usize]
+}
+
+/// Queue a key for the keyboard.
+///
+/// # Safety
+/// `vm` must be live.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lc3_vm_push_input(vm: *mut Vm, key: u8) {
+    console(unsafe { &mut *vm }).input.push_back(key);
+}
+
+/// Copy up to `capacity` bytes of pending output into `buffer`, removing
+/// them from the VM, and return how many were copied. The output is not
+/// NUL-terminated.
+///
+/// # Safety
+/// `vm` must be live and `buffer` must point to `capacity` writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn lc3_vm_take_output(vm: *mut Vm, buffer: *mut u8, capacity: usize) -> usize {
+    let output = &mut console(unsafe { &mut *vm }).output;
+    let count = output.len().min(capacity);
+    if count > 0 {
+        unsafe { slice::from_raw_parts_mut(buffer, count) }.copy_from_slice(&output[..count]);
+        output.drain(..count);
+    }
+    count
+}
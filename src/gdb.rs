@@ -0,0 +1,619 @@
+//! GDB remote serial protocol stub (`lc3-vm run --gdb :3333`), so GDB and
+//! front ends that speak its protocol can debug a program over TCP.
+//!
+//! GDB counts addresses in bytes while the LC-3 addresses 16-bit words, so,
+//! as in GDB ports to other word-addressed machines, byte address `2 * A`
+//! is the low byte of word `A` and `2 * A + 1` its high byte: x3000 is
+//! `0x6000` to GDB. The registers are R0-R7 and PSR, 16 bits each, and the
+//! PC as a 32-bit byte address, in that order (R0-R7, PC, PSR).
+
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+use tracing::debug;
+
+use crate::console::{CapturedConsole, Console};
+use crate::debugger::DebuggerAction;
+use crate::vm::{REGISTER, RunState, StepOutcome, Vm, WatchKind};
+
+/* instructions run between checks for GDB's interrupt (Ctrl+C) while continuing */
+const CHUNK: u64 = 10_000;
+
+/* the byte GDB sends to interrupt a running target */
+const INTERRUPT: u8 = 0x03;
+
+/* how often a program waiting for a key looks for it, and for GDB's interrupt */
+const INPUT_POLL: Duration = Duration::from_millis(10);
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.lc3.core">
+    <reg name="r0" bitsize="16" type="int16"/>
+    <reg name="r1" bitsize="16" type="int16"/>
+    <reg name="r2" bitsize="16" type="int16"/>
+    <reg name="r3" bitsize="16" type="int16"/>
+    <reg name="r4" bitsize="16" type="int16"/>
+    <reg name="r5" bitsize="16" type="int16"/>
+    <reg name="r6" bitsize="16" type="data_ptr"/>
+    <reg name="r7" bitsize="16" type="int16"/>
+    <reg name="pc" bitsize="32" type="code_ptr"/>
+    <reg name="psr" bitsize="16" type="uint16"/>
+  </feature>
+</target>
+"#;
+
+/* GDB's register numbers */
+const PC_REGISTER: usize = 8;
+const PSR_REGISTER: usize = 9;
+
+struct Connection {
+    stream: TcpStream,
+    /* bytes read but not yet used */
+    pending: VecDeque<u8>,
+    /* false once GDB turns acknowledgements off (QStartNoAckMode) */
+    acks: bool,
+    /* the last packet sent, resent when GDB asks for it again */
+    last: String,
+}
+
+impl Connection {
+    /* the next byte from GDB, waiting for it; None once GDB has disconnected */
+    fn byte(&mut self) -> io::Result<Option<u8>> {
+        if self.pending.is_empty() {
+            let mut buffer = [0u8; 1024];
+            let count = self.stream.read(&mut buffer)?;
+            self.pending.extend(&buffer[..count]);
+        }
+        Ok(self.pending.pop_front())
+    }
+
+    /* whether GDB has asked to interrupt the target, without waiting */
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut buffer = [0u8; 1024];
+        let read = self.stream.read(&mut buffer);
+        self.stream.set_nonblocking(false)?;
+        match read {
+            Ok(count) => self.pending.extend(&buffer[..count]),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+            Err(err) => return Err(err),
+        }
+        match self.pending.iter().position(|&byte| byte == INTERRUPT) {
+            Some(index) => {
+                self.pending.remove(index);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /* the body of the next packet, acknowledged; None once GDB has disconnected */
+    fn packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            match self.byte()? {
+                None => return Ok(None),
+                Some(b'$') => {}
+                Some(b'-') => {
+                    let last = std::mem::take(&mut self.last);
+                    self.send(&last)?;
+                    continue;
+                }
+                /* acks, and interrupts while already stopped */
+                Some(_) => continue,
+            }
+            let mut body = Vec::new();
+            loop {
+                match self.byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => body.push(byte),
+                }
+            }
+            let mut checksum = [0u8; 2];
+            for digit in &mut checksum {
+                match self.byte()? {
+                    None => return Ok(None),
+                    Some(byte) => *digit = byte,
+                }
+            }
+            let expected = std::str::from_utf8(&checksum).ok().and_then(|text| u8::from_str_radix(text, 16).ok());
+            let sum = body.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+            if self.acks {
+                let ack: &[u8] = if expected == Some(sum) { b"+" } else { b"-" };
+                self.stream.write_all(ack)?;
+                if expected != Some(sum) {
+                    continue;
+                }
+            }
+            return Ok(Some(String::from_utf8_lossy(&body).into_owned()));
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let sum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(self.stream, "${}#{:02x}", data, sum)?;
+        self.stream.flush()?;
+        self.last = data.to_string();
+        Ok(())
+    }
+}
+
+/// Debug `vm` for the GDB client connected on `stream` until it detaches
+/// (the program then goes on running: [`DebuggerAction::Continue`]), kills
+/// the program or disconnects ([`DebuggerAction::Quit`]). The guest's
+/// console stays where it is; GDB only sees the machine.
+pub fn serve(vm: &mut Vm, stream: TcpStream) -> io::Result<DebuggerAction> {
+    stream.set_nodelay(true)?;
+    let mut connection = Connection {
+        stream,
+        pending: VecDeque::new(),
+        acks: true,
+        last: String::new(),
+    };
+    while let Some(packet) = connection.packet()? {
+        debug!(%packet, "gdb packet");
+        let reply = match packet.as_bytes().first() {
+            Some(b'?') => stop_reply(vm, None),
+            Some(b'g') => read_registers(vm),
+            Some(b'G') => write_registers(vm, &packet[1..]),
+            Some(b'p') => read_register(vm, &packet[1..]),
+            Some(b'P') => write_register(vm, &packet[1..]),
+            Some(b'm') => read_memory(vm, &packet[1..]),
+            Some(b'M') => write_memory(vm, &packet[1..]),
+            Some(b'Z') => breakpoint(vm, &packet[1..], true),
+            Some(b'z') => breakpoint(vm, &packet[1..], false),
+            Some(b's') => {
+                resume_at(vm, &packet[1..]);
+                step(vm)
+            }
+            Some(b'c') => {
+                resume_at(vm, &packet[1..]);
+                run(vm, &mut connection)?
+            }
+            Some(b'k') => return Ok(DebuggerAction::Quit),
+            Some(b'D') => {
+                connection.send("OK")?;
+                return Ok(DebuggerAction::Continue);
+            }
+            Some(b'H') => "OK".to_string(),
+            _ => query(&packet).unwrap_or_default(),
+        };
+        connection.send(&reply)?;
+        if packet == "QStartNoAckMode" {
+            connection.acks = false;
+        }
+    }
+    Ok(DebuggerAction::Quit)
+}
+
+/* replies to the general queries GDB makes while connecting; None for the unsupported */
+fn query(packet: &str) -> Option<String> {
+    if packet.starts_with("qSupported") {
+        return Some("PacketSize=4000;qXfer:features:read+;QStartNoAckMode+;swbreak+".to_string());
+    }
+    if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+        let (offset, length) = range.split_once(',')?;
+        let offset = usize::from_str_radix(offset, 16).ok()?.min(TARGET_XML.len());
+        let length = usize::from_str_radix(length, 16).ok()?;
+        let chunk = &TARGET_XML[offset..TARGET_XML.len().min(offset + length)];
+        let more = offset + chunk.len() < TARGET_XML.len();
+        return Some(format!("{}{}", if more { "m" } else { "l" }, chunk));
+    }
+    let reply = match packet {
+        "QStartNoAckMode" => "OK",
+        "qAttached" => "1",
+        "qC" => "QC1",
+        "qfThreadInfo" => "m1",
+        "qsThreadInfo" => "l",
+        "qOffsets" => "Text=0;Data=0;Bss=0",
+        _ => return None,
+    };
+    Some(reply.to_string())
+}
+
+fn hex_u16(out: &mut String, value: u16) {
+    for byte in value.to_le_bytes() {
+        write!(out, "{:02x}", byte).unwrap();
+    }
+}
+
+/* little-endian hex as GDB sends register values */
+fn parse_le(hex: &str) -> Option<u32> {
+    if hex.is_empty() || hex.len() > 8 || !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2).rev().try_fold(0u32, |value, index| {
+        Some(value << 8 | u8::from_str_radix(hex.get(index..index + 2)?, 16).ok()? as u32)
+    })
+}
+
+fn register_value(vm: &Vm, number: usize) -> Option<String> {
+    let mut out = String::new();
+    match number {
+        0..8 => hex_u16(&mut out, vm.registers[number]),
+        PC_REGISTER => {
+            let address = vm.registers[REGISTER::PC as usize] as u32 * 2;
+            for byte in address.to_le_bytes() {
+                write!(out, "{:02x}", byte).unwrap();
+            }
+        }
+        PSR_REGISTER => hex_u16(&mut out, vm.psr()),
+        _ => return None,
+    }
+    Some(out)
+}
+
+fn set_register(vm: &mut Vm, number: usize, value: u32) -> bool {
+    match number {
+        0..8 => vm.registers[number] = value as u16,
+        PC_REGISTER => vm.registers[REGISTER::PC as usize] = (value / 2) as u16,
+        PSR_REGISTER => vm.set_psr(value as u16),
+        _ => return false,
+    }
+    true
+}
+
+fn read_registers(vm: &Vm) -> String {
+    (0..=PSR_REGISTER).filter_map(|number| register_value(vm, number)).collect()
+}
+
+fn write_registers(vm: &mut Vm, hex: &str) -> String {
+    let mut rest = hex;
+    for number in 0..=PSR_REGISTER {
+        let width = if number == PC_REGISTER { 8 } else { 4 };
+        let Some(value) = rest.get(..width).and_then(parse_le) else {
+            return "E01".to_string();
+        };
+        set_register(vm, number, value);
+        rest = &rest[width..];
+    }
+    "OK".to_string()
+}
+
+fn read_register(vm: &Vm, number: &str) -> String {
+    usize::from_str_radix(number, 16)
+        .ok()
+        .and_then(|number| register_value(vm, number))
+        .unwrap_or_else(|| "E01".to_string())
+}
+
+fn write_register(vm: &mut Vm, assignment: &str) -> String {
+    let Some((number, value)) = assignment.split_once('=') else {
+        return "E01".to_string();
+    };
+    match (usize::from_str_radix(number, 16), parse_le(value)) {
+        (Ok(number), Some(value)) if set_register(vm, number, value) => "OK".to_string(),
+        _ => "E01".to_string(),
+    }
+}
+
+/* `ADDR,LENGTH` in hex */
+fn parse_span(text: &str) -> Option<(u32, u32)> {
+    let (address, length) = text.split_once(',')?;
+    Some((u32::from_str_radix(address, 16).ok()?, u32::from_str_radix(length, 16).ok()?))
+}
+
+fn read_memory(vm: &Vm, span: &str) -> String {
+    let Some((start, length)) = parse_span(span) else {
+        return "E01".to_string();
+    };
+    let mut out = String::new();
+    for byte_address in start..start.saturating_add(length).min(0x20000) {
+        let word = vm.memory[(byte_address / 2) as usize];
+        let byte = if byte_address % 2 == 0 { word & 0xFF } else { word >> 8 };
+        write!(out, "{:02x}", byte).unwrap();
+    }
+    out
+}
+
+fn write_memory(vm: &mut Vm, request: &str) -> String {
+    let Some((span, data)) = request.split_once(':') else {
+        return "E01".to_string();
+    };
+    let Some((start, length)) = parse_span(span) else {
+        return "E01".to_string();
+    };
+    /* two hex digits per byte, so slicing them below cannot split a character */
+    if !data.is_ascii() || data.len() != length as usize * 2 || start.saturating_add(length) > 0x20000 {
+        return "E01".to_string();
+    }
+    for (index, byte_address) in (start..start + length).enumerate() {
+        let Ok(byte) = u8::from_str_radix(&data[index * 2..index * 2 + 2], 16) else {
+            return "E01".to_string();
+        };
+        let word = &mut vm.memory[(byte_address / 2) as usize];
+        *word = if byte_address % 2 == 0 { (*word & 0xFF00) | byte as u16 } else { (*word & 0x00FF) | (byte as u16) << 8 };
+    }
+    "OK".to_string()
+}
+
+/* `TYPE,ADDR,KIND`: Z0/Z1 are breakpoints, Z2-Z4 write, read and access watchpoints */
+fn breakpoint(vm: &mut Vm, request: &str, insert: bool) -> String {
+    let mut fields = request.split(',');
+    let (Some(kind), Some(address), Some(length)) = (fields.next(), fields.next(), fields.next()) else {
+        return "E01".to_string();
+    };
+    let (Ok(address), Ok(length)) = (u32::from_str_radix(address, 16), u32::from_str_radix(length, 16)) else {
+        return "E01".to_string();
+    };
+    /* the last byte covered, within the LC-3's 128K byte addresses */
+    let Some(end) = address.checked_add(length.max(1) - 1).filter(|&end| end < 0x20000) else {
+        return "E01".to_string();
+    };
+    let word = (address / 2) as u16;
+    let watch = match kind {
+        "0" | "1" => {
+            if insert {
+                vm.breakpoints.insert(word);
+            } else {
+                vm.breakpoints.remove(&word);
+            }
+            return "OK".to_string();
+        }
+        "2" => WatchKind::Write,
+        "3" => WatchKind::Read,
+        "4" => WatchKind::Access,
+        _ => return String::new(),
+    };
+    for word in word..=(end / 2) as u16 {
+        if insert {
+            vm.watchpoints.insert(word, watch);
+        } else {
+            vm.watchpoints.remove(&word);
+        }
+    }
+    "OK".to_string()
+}
+
+/* `s ADDR` and `c ADDR` resume at ADDR */
+fn resume_at(vm: &mut Vm, address: &str) {
+    if let Ok(address) = u32::from_str_radix(address, 16) {
+        vm.registers[REGISTER::PC as usize] = (address / 2) as u16;
+    }
+}
+
+fn step(vm: &mut Vm) -> String {
+    match vm.step() {
+        StepOutcome::Faulted { .. } => "S04".to_string(),
+        _ => stop_reply(vm, None),
+    }
+}
+
+/* the guest's console while GDB continues the program: a GETC or IN with no
+   key waiting returns to `run` instead of blocking, so an interrupt still gets through */
+struct Polled(Box<dyn Console>);
+
+impl Console for Polled {
+    fn read_key(&mut self) -> Option<u8> {
+        self.0.read_key()
+    }
+
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        self.0.write(text)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+
+    fn input_closed(&self) -> bool {
+        self.0.input_closed()
+    }
+
+    fn blocking(&self) -> bool {
+        false
+    }
+
+    fn fork(&self) -> Box<dyn Console> {
+        self.0.fork()
+    }
+}
+
+/* continue until something stops the program or GDB interrupts it */
+fn run(vm: &mut Vm, connection: &mut Connection) -> io::Result<String> {
+    let console = std::mem::replace(&mut vm.console, Box::new(CapturedConsole::default()));
+    vm.console = Box::new(Polled(console));
+    let reply = run_polled(vm, connection);
+    if let Some(polled) = vm.console.downcast_mut::<Polled>() {
+        let console = std::mem::replace(&mut polled.0, Box::new(CapturedConsole::default()));
+        vm.console = console;
+    }
+    reply
+}
+
+fn run_polled(vm: &mut Vm, connection: &mut Connection) -> io::Result<String> {
+    if vm.halted {
+        return Ok(stop_reply(vm, None));
+    }
+    /* like Vm::run, step off a breakpoint at the PC so continuing makes progress */
+    if vm.breakpoints.contains(&vm.registers[REGISTER::PC as usize]) {
+        if let StepOutcome::Faulted { .. } = vm.step() {
+            return Ok("S04".to_string());
+        }
+        if vm.halted || vm.watch_hit().is_some() {
+            return Ok(stop_reply(vm, None));
+        }
+    }
+    loop {
+        let state = match vm.run_for(CHUNK) {
+            Ok(result) => result.state,
+            Err(_) => return Ok("S04".to_string()),
+        };
+        match state {
+            RunState::Yielded => {
+                if connection.interrupted()? {
+                    return Ok("S02".to_string());
+                }
+            }
+            RunState::WaitingForInput => {
+                if connection.interrupted()? {
+                    return Ok("S02".to_string());
+                }
+                thread::sleep(INPUT_POLL);
+            }
+            RunState::Paused => return Ok("S02".to_string()),
+            state => return Ok(stop_reply(vm, Some(state))),
+        }
+    }
+}
+
+/* why the program is stopped, in GDB's stop reply format */
+fn stop_reply(vm: &Vm, state: Option<RunState>) -> String {
+    if vm.halted {
+        return "W00".to_string();
+    }
+    match state {
+        Some(RunState::Breakpoint(_)) => "T05swbreak:;".to_string(),
+        _ => match vm.watch_hit() {
+            Some(hit) => {
+                let kind = match vm.watchpoints.get(&hit.address) {
+                    Some(WatchKind::Read) => "rwatch",
+                    Some(WatchKind::Access) => "awatch",
+                    _ => "watch",
+                };
+                format!("T05{}:{:x};", kind, hit.address as u32 * 2)
+            }
+            None => "S05".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    /* our end of a connection, and GDB's */
+    fn connect() -> (Connection, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let gdb = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let connection = Connection {
+            stream,
+            pending: VecDeque::new(),
+            acks: true,
+            last: String::new(),
+        };
+        (connection, gdb)
+    }
+
+    #[test]
+    fn packets_are_acknowledged_and_bad_checksums_asked_for_again() {
+        let (mut connection, mut gdb) = connect();
+        gdb.write_all(b"+$m6000,4#00$m6000,4#93").unwrap();
+        assert_eq!(connection.packet().unwrap().as_deref(), Some("m6000,4"));
+        let mut acks = [0u8; 2];
+        gdb.read_exact(&mut acks).unwrap();
+        assert_eq!(&acks, b"-+");
+        drop(gdb);
+        assert_eq!(connection.packet().unwrap(), None);
+    }
+
+    #[test]
+    fn an_interrupt_is_found_among_pending_bytes() {
+        let (mut connection, mut gdb) = connect();
+        assert!(!connection.interrupted().unwrap());
+        gdb.write_all(&[b'+', INTERRUPT]).unwrap();
+        while !connection.interrupted().unwrap() {}
+        assert_eq!(connection.pending, [b'+']);
+    }
+
+    /* a terminal nobody types at */
+    struct Idle;
+
+    impl Console for Idle {
+        fn read_key(&mut self) -> Option<u8> {
+            None
+        }
+
+        fn write(&mut self, _text: &str) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn an_interrupt_stops_a_program_waiting_for_a_key() {
+        let (mut connection, mut gdb) = connect();
+        let mut vm = Vm::new();
+        vm.console = Box::new(Idle);
+        vm.memory[0x3000] = 0xF020; /* GETC */
+        vm.registers[REGISTER::PC as usize] = 0x3000;
+        gdb.write_all(&[INTERRUPT]).unwrap();
+        assert_eq!(run(&mut vm, &mut connection).unwrap(), "S02");
+        assert_eq!(vm.registers[REGISTER::PC as usize], 0x3000);
+        assert!(vm.console.downcast_ref::<Idle>().is_some());
+    }
+
+    #[test]
+    fn register_values_are_little_endian_hex() {
+        assert_eq!(parse_le("0030"), Some(0x3000));
+        assert_eq!(parse_le("00600000"), Some(0x6000));
+        assert_eq!(parse_le("003"), None);
+        assert_eq!(parse_le("zz"), None);
+        assert_eq!(parse_le(""), None);
+        let mut vm = Vm::new();
+        assert_eq!(write_register(&mut vm, "8=02600000"), "OK");
+        assert_eq!(vm.registers[REGISTER::PC as usize], 0x3001);
+        assert_eq!(read_register(&vm, "8"), "02600000");
+        assert_eq!(write_register(&mut vm, "8"), "E01");
+    }
+
+    #[test]
+    fn memory_is_read_and_written_a_byte_at_a_time() {
+        let mut vm = Vm::new();
+        vm.memory[0x3000] = 0x1234;
+        assert_eq!(read_memory(&vm, "6000,3"), "341200");
+        assert_eq!(write_memory(&mut vm, "6001,1:ab"), "OK");
+        assert_eq!(vm.memory[0x3000], 0xAB34);
+        assert_eq!(read_memory(&vm, "6000"), "E01");
+    }
+
+    #[test]
+    fn malformed_memory_writes_are_refused() {
+        let mut vm = Vm::new();
+        assert_eq!(write_memory(&mut vm, "6000,2:ab"), "E01");
+        assert_eq!(write_memory(&mut vm, "6000,2:a\u{e9}b"), "E01");
+        assert_eq!(write_memory(&mut vm, "6000,1:zz"), "E01");
+        assert_eq!(write_memory(&mut vm, "1ffff,2:abcd"), "E01");
+        assert_eq!(vm.memory[0x3000], 0);
+    }
+
+    #[test]
+    fn watchpoints_cover_every_word_of_their_range() {
+        let mut vm = Vm::new();
+        assert_eq!(breakpoint(&mut vm, "2,6001,2", true), "OK");
+        assert_eq!(vm.watchpoints.get(&0x3000), Some(&WatchKind::Write));
+        assert_eq!(vm.watchpoints.get(&0x3001), Some(&WatchKind::Write));
+        assert_eq!(vm.watchpoints.get(&0x3002), None);
+        assert_eq!(breakpoint(&mut vm, "0,6004,2", true), "OK");
+        assert!(vm.breakpoints.contains(&0x3002));
+        assert_eq!(breakpoint(&mut vm, "0,6004,2", false), "OK");
+        assert!(vm.breakpoints.is_empty());
+    }
+
+    #[test]
+    fn breakpoints_past_the_address_space_are_refused() {
+        let mut vm = Vm::new();
+        assert_eq!(breakpoint(&mut vm, "2,fffffffe,4", true), "E01");
+        assert_eq!(breakpoint(&mut vm, "3,1fffe,4", true), "E01");
+        assert_eq!(breakpoint(&mut vm, "0,6000", true), "E01");
+        assert!(vm.watchpoints.is_empty());
+        /* an unsupported kind gets the empty reply */
+        assert_eq!(breakpoint(&mut vm, "9,6000,2", true), "");
+    }
+
+    #[test]
+    fn the_target_description_is_sent_in_chunks() {
+        let first = query("qXfer:features:read:target.xml:0,10").unwrap();
+        assert_eq!(first, format!("m{}", &TARGET_XML[..0x10]));
+        let rest = query(&format!("qXfer:features:read:target.xml:10,{:x}", TARGET_XML.len())).unwrap();
+        assert_eq!(rest, format!("l{}", &TARGET_XML[0x10..]));
+        assert_eq!(query("qUnknown"), None);
+    }
+}
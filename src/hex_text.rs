@@ -0,0 +1,35 @@
+//! Text images with one 16-bit word per line, as emitted by `lc3as` and
+//! many course toolchains (`.hex` and `.bin` files). Like an object file,
+//! the first word is the origin. A word is written in hex (`3000`, `x3000`
+//! or `0x3000`) or as 16 binary digits (`0011000000000000`); comments after
+//! `;`, `#` or `//` and blank lines are ignored.
+
+use crate::error::VmError;
+use crate::program::Program;
+
+fn parse_word(token: &str) -> Option<u16> {
+    if token.len() == 16 && token.bytes().all(|byte| byte == b'0' || byte == b'1') {
+        return u16::from_str_radix(token, 2).ok();
+    }
+    let hex = token.strip_prefix("0x").or_else(|| token.strip_prefix(['x', 'X'])).unwrap_or(token);
+    if hex.is_empty() || hex.len() > 4 {
+        return None;
+    }
+    u16::from_str_radix(hex, 16).ok()
+}
+
+/// Parse a text image into a program at the origin on its first line.
+pub fn parse(text: &str) -> Result<Program, VmError> {
+    let mut words = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = [";", "#", "//"].iter().fold(line, |line, marker| line.split(marker).next().unwrap_or(line)).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let word = parse_word(line)
+            .ok_or_else(|| VmError::InvalidImage(format!("line {}: `{}` is not a hex or 16-digit binary word", index + 1, line)))?;
+        words.push(word);
+    }
+    let (&origin, words) = words.split_first().ok_or_else(|| VmError::InvalidImage("missing the origin word".to_string()))?;
+    Ok(Program::from_words(origin, words))
+}
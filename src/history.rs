@@ -0,0 +1,103 @@
+//! A record of recent execution for stepping backwards: a full
+//! [`VmState`] snapshot every so many instructions, and between them, for
+//! each instruction, the registers and processor status it started from and
+//! the old value of every memory word it wrote. Undoing an instruction puts
+//! those back; arriving at a snapshot restores it whole, devices included.
+
+use std::collections::VecDeque;
+
+use crate::state::VmState;
+
+/// Instructions kept by `lc3-vm debug` unless `--history` says otherwise.
+pub const DEFAULT_CAPACITY: usize = 100_000;
+
+/* the history is trimmed a snapshot's worth of instructions at a time */
+const SNAPSHOTS: usize = 8;
+
+/* one instruction: the state it started from (memory and devices left out)
+   and the (address, old value) of each memory write, in order */
+#[derive(Debug, Clone)]
+pub(crate) struct Step {
+    pub(crate) before: VmState,
+    pub(crate) writes: Vec<(u16, u16)>,
+}
+
+/// The last [`History::capacity`] instructions, give or take a snapshot
+/// interval, kept in [`Vm::history`](crate::vm::Vm::history) for
+/// [`Vm::step_back`](crate::vm::Vm::step_back) and
+/// [`Vm::run_back`](crate::vm::Vm::run_back).
+#[derive(Debug, Clone)]
+pub struct History {
+    capacity: usize,
+    /* instructions between snapshots */
+    interval: u64,
+    snapshots: VecDeque<VmState>,
+    steps: VecDeque<Step>,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        History {
+            capacity,
+            interval: (capacity / SNAPSHOTS).max(1) as u64,
+            snapshots: VecDeque::new(),
+            steps: VecDeque::new(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Instructions that can be undone.
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Forget everything recorded, e.g. once the machine has been reset.
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.steps.clear();
+    }
+
+    /* start recording the instruction about to run from `before`; `state`
+       gives the full state when a snapshot is due */
+    pub(crate) fn begin(&mut self, before: VmState, state: impl FnOnce() -> VmState) {
+        /* the last step did not finish (it waits for a key): go on with it */
+        if self.steps.back().is_some_and(|step| step.before.instruction_count == before.instruction_count) {
+            return;
+        }
+        let count = before.instruction_count;
+        if self.snapshots.back().is_none_or(|snapshot| count >= snapshot.instruction_count + self.interval) {
+            self.snapshots.push_back(state());
+            if self.snapshots.len() > SNAPSHOTS {
+                self.snapshots.pop_front();
+                let oldest = self.snapshots[0].instruction_count;
+                while self.steps.front().is_some_and(|step| step.before.instruction_count < oldest) {
+                    self.steps.pop_front();
+                }
+            }
+        }
+        self.steps.push_back(Step { before, writes: Vec::new() });
+    }
+
+    /* note a memory write by the instruction being recorded */
+    pub(crate) fn write(&mut self, address: u16, old: u16) {
+        if let Some(step) = self.steps.back_mut() {
+            step.writes.push((address, old));
+        }
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<Step> {
+        self.steps.pop_back()
+    }
+
+    /* the snapshot taken at `instruction_count`, if there is one */
+    pub(crate) fn snapshot_at(&self, instruction_count: u64) -> Option<&VmState> {
+        self.snapshots.iter().rev().find(|snapshot| snapshot.instruction_count == instruction_count)
+    }
+}
@@ -0,0 +1,159 @@
+//! Intel HEX images, as produced by many assemblers, EPROM tools and hex
+//! editors. LC-3 words are stored big-endian at byte address `2 * address`,
+//! so the upper half of memory needs extended linear address records.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::error::VmError;
+use crate::program::Program;
+
+/* data bytes per record written */
+const RECORD_LEN: usize = 16;
+
+const DATA: u8 = 0x00;
+const END_OF_FILE: u8 = 0x01;
+const EXTENDED_SEGMENT_ADDRESS: u8 = 0x02;
+const START_SEGMENT_ADDRESS: u8 = 0x03;
+const EXTENDED_LINEAR_ADDRESS: u8 = 0x04;
+const START_LINEAR_ADDRESS: u8 = 0x05;
+
+fn invalid(line: usize, reason: &str) -> VmError {
+    VmError::InvalidImage(format!("Intel HEX line {}: {}", line, reason))
+}
+
+/// Parse Intel HEX text into one program spanning the lowest to the highest
+/// word it sets; words in gaps between records are zero. Start address
+/// records are accepted and ignored.
+pub fn parse(text: &str) -> Result<Program, VmError> {
+    let mut bytes = BTreeMap::new();
+    let mut base = 0u32;
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let hex = line.strip_prefix(':').ok_or_else(|| invalid(number, "missing the `:` record mark"))?;
+        if hex.len() % 2 != 0 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(invalid(number, "not a sequence of hex byte pairs"));
+        }
+        let record: Vec<u8> = (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect();
+        if record.len() < 5 || record.len() != record[0] as usize + 5 {
+            return Err(invalid(number, "byte count does not match the record length"));
+        }
+        if record.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) != 0 {
+            return Err(invalid(number, "checksum mismatch"));
+        }
+        let offset = u16::from_be_bytes([record[1], record[2]]) as u32;
+        let data = &record[4..record.len() - 1];
+        match record[3] {
+            DATA => {
+                for (i, &byte) in data.iter().enumerate() {
+                    /* byte addresses past x1FFFF are words past xFFFF */
+                    let Some(address) = base.checked_add(offset + i as u32).filter(|&address| address < 2 << 16) else {
+                        return Err(invalid(number, "data beyond the LC-3 address space"));
+                    };
+                    bytes.insert(address, byte);
+                }
+            }
+            END_OF_FILE => break,
+            EXTENDED_SEGMENT_ADDRESS | EXTENDED_LINEAR_ADDRESS if data.len() == 2 => {
+                let value = u16::from_be_bytes([data[0], data[1]]) as u32;
+                base = if record[3] == EXTENDED_SEGMENT_ADDRESS { value << 4 } else { value << 16 };
+            }
+            START_SEGMENT_ADDRESS | START_LINEAR_ADDRESS => {}
+            kind => return Err(invalid(number, &format!("unsupported record type {:02X}", kind))),
+        }
+    }
+    let (Some(&first), Some(&last)) = (bytes.keys().next(), bytes.keys().next_back()) else {
+        return Err(VmError::InvalidImage("Intel HEX file has no data".to_string()));
+    };
+    let origin = first / 2;
+    let mut words = vec![0u16; (last / 2 - origin + 1) as usize];
+    for (address, byte) in bytes {
+        let word = &mut words[(address / 2 - origin) as usize];
+        *word |= if address % 2 == 0 { (byte as u16) << 8 } else { byte as u16 };
+    }
+    Ok(Program::from_words(origin as u16, &words))
+}
+
+fn record(out: &mut String, kind: u8, offset: u16, data: &[u8]) {
+    let mut bytes = vec![data.len() as u8];
+    bytes.extend(offset.to_be_bytes());
+    bytes.push(kind);
+    bytes.extend_from_slice(data);
+    let checksum = bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)).wrapping_neg();
+    out.push(':');
+    for byte in bytes.iter().chain([&checksum]) {
+        write!(out, "{:02X}", byte).unwrap();
+    }
+    out.push('\n');
+}
+
+/// Encode `program` as Intel HEX: data records of 16 bytes, extended
+/// linear address records where the byte address crosses 64K, and an end
+/// of file record.
+pub fn write(program: &Program) -> String {
+    let bytes: Vec<u8> = program.words.iter().flat_map(|word| word.to_be_bytes()).collect();
+    let mut out = String::new();
+    let mut base = 0u32;
+    let mut address = program.origin as u32 * 2;
+    let mut rest = &bytes[..];
+    while !rest.is_empty() {
+        if address >> 16 != base {
+            base = address >> 16;
+            record(&mut out, EXTENDED_LINEAR_ADDRESS, 0, &(base as u16).to_be_bytes());
+        }
+        /* a record may not run past the end of its 64K segment */
+        let len = rest.len().min(RECORD_LEN).min(0x10000 - (address & 0xFFFF) as usize);
+        record(&mut out, DATA, address as u16, &rest[..len]);
+        rest = &rest[len..];
+        address += len as u32;
+    }
+    record(&mut out, END_OF_FILE, 0, &[]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_set_big_endian_words() {
+        let program = parse(":046000003000123426\n:00000001FF\n").unwrap();
+        assert_eq!(program.origin, 0x3000);
+        assert_eq!(program.words, [0x3000, 0x1234]);
+    }
+
+    #[test]
+    fn extended_linear_addresses_reach_the_upper_half() {
+        let program = parse(":020000040001F9\n:02FFFE00ABCD89\n:00000001FF\n").unwrap();
+        assert_eq!(program.origin, 0xFFFF);
+        assert_eq!(program.words, [0xABCD]);
+    }
+
+    #[test]
+    fn written_images_parse_back() {
+        let program = Program::from_words(0x7FF8, &(0..40).collect::<Vec<u16>>());
+        let parsed = parse(&write(&program)).unwrap();
+        assert_eq!((parsed.origin, parsed.words), (program.origin, program.words));
+    }
+
+    #[test]
+    fn data_past_xffff_is_refused() {
+        /* a segment base of x2000 puts byte x20000 right after the last word */
+        let past = ":020000040002F8\n:01000000AA55\n";
+        assert!(matches!(parse(past), Err(VmError::InvalidImage(_))));
+        /* a linear base this high overflows a 32-bit byte address */
+        let overflow = ":02000004FFFFFC\n:02FFFF00AABB9B\n";
+        assert!(matches!(parse(overflow), Err(VmError::InvalidImage(_))));
+    }
+
+    #[test]
+    fn malformed_records_are_refused() {
+        for text in [":046000003000123427\n", "046000003000123426\n", ":04600000300012\n", ":00000007F9\n", ""] {
+            assert!(matches!(parse(text), Err(VmError::InvalidImage(_))), "{:?}", text);
+        }
+    }
+}
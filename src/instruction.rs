@@ -0,0 +1,125 @@
+//! Structured decoding of LC-3 instruction words. [`decode`] splits a word
+//! into its opcode and fields and [`encode`] puts them back together, so
+//! tools can inspect or build instructions without repeating the bit
+//! layouts. PC-relative offsets stay relative; add them to the address after
+//! the instruction to get the target.
+
+/// The second source of ADD and AND: a register or a 5-bit immediate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operand {
+    Register(u8),
+    Immediate(i16),
+}
+
+/// One decoded instruction. Register numbers are 0-7 and offsets are
+/// sign-extended to `i16`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Instruction {
+    /// Branch if any of the selected condition codes is set; none selected is a NOP.
+    Br { n: bool, z: bool, p: bool, offset: i16 },
+    Add { dr: u8, sr1: u8, src2: Operand },
+    Ld { dr: u8, offset: i16 },
+    St { sr: u8, offset: i16 },
+    Jsr { offset: i16 },
+    Jsrr { base: u8 },
+    And { dr: u8, sr1: u8, src2: Operand },
+    Ldr { dr: u8, base: u8, offset: i16 },
+    Str { sr: u8, base: u8, offset: i16 },
+    Rti,
+    Not { dr: u8, sr: u8 },
+    Ldi { dr: u8, offset: i16 },
+    Sti { sr: u8, offset: i16 },
+    /// Jump to the address in `base`; `RET` is `Jmp { base: 7 }`.
+    Jmp { base: u8 },
+    /// The reserved opcode `0b1101`, with the whole instruction word.
+    Reserved(u16),
+    Lea { dr: u8, offset: i16 },
+    Trap { vector: u8 },
+}
+
+fn sign_extend(value: u16, bit_count: u8) -> i16 {
+    let shift = 16 - bit_count;
+    ((value << shift) as i16) >> shift
+}
+
+/* the low `bit_count` bits of a signed field */
+fn field(value: i16, bit_count: u8) -> u16 {
+    value as u16 & ((1 << bit_count) - 1)
+}
+
+fn register(word: u16, shift: u8) -> u8 {
+    ((word >> shift) & 0x7) as u8
+}
+
+/// Split `word` into its opcode and operands. Every word decodes to
+/// something; bits the opcode does not use are ignored.
+pub fn decode(word: u16) -> Instruction {
+    let dr = register(word, 9);
+    let sr1 = register(word, 6);
+    let offset9 = sign_extend(word & 0x1FF, 9);
+    let offset6 = sign_extend(word & 0x3F, 6);
+    let src2 = if word & 0x20 != 0 {
+        Operand::Immediate(sign_extend(word & 0x1F, 5))
+    } else {
+        Operand::Register(register(word, 0))
+    };
+    match word >> 12 {
+        0b0000 => Instruction::Br {
+            n: word & 0x800 != 0,
+            z: word & 0x400 != 0,
+            p: word & 0x200 != 0,
+            offset: offset9,
+        },
+        0b0001 => Instruction::Add { dr, sr1, src2 },
+        0b0010 => Instruction::Ld { dr, offset: offset9 },
+        0b0011 => Instruction::St { sr: dr, offset: offset9 },
+        0b0100 if word & 0x800 != 0 => Instruction::Jsr { offset: sign_extend(word & 0x7FF, 11) },
+        0b0100 => Instruction::Jsrr { base: sr1 },
+        0b0101 => Instruction::And { dr, sr1, src2 },
+        0b0110 => Instruction::Ldr { dr, base: sr1, offset: offset6 },
+        0b0111 => Instruction::Str { sr: dr, base: sr1, offset: offset6 },
+        0b1000 => Instruction::Rti,
+        0b1001 => Instruction::Not { dr, sr: sr1 },
+        0b1010 => Instruction::Ldi { dr, offset: offset9 },
+        0b1011 => Instruction::Sti { sr: dr, offset: offset9 },
+        0b1100 => Instruction::Jmp { base: sr1 },
+        0b1101 => Instruction::Reserved(word),
+        0b1110 => Instruction::Lea { dr, offset: offset9 },
+        _ => Instruction::Trap { vector: (word & 0xFF) as u8 },
+    }
+}
+
+/// Assemble `instruction` into its word, the inverse of [`decode`].
+/// Registers and offsets are truncated to their field widths, and unused
+/// bits take their standard values (e.g. NOT's low six bits are all ones).
+pub fn encode(instruction: Instruction) -> u16 {
+    let reg = |register: u8, shift: u8| ((register & 0x7) as u16) << shift;
+    let with_src2 = |opcode: u16, dr: u8, sr1: u8, src2: Operand| {
+        let last = match src2 {
+            Operand::Register(sr2) => reg(sr2, 0),
+            Operand::Immediate(value) => 0x20 | field(value, 5),
+        };
+        (opcode << 12) | reg(dr, 9) | reg(sr1, 6) | last
+    };
+    match instruction {
+        Instruction::Br { n, z, p, offset } => {
+            ((n as u16) << 11) | ((z as u16) << 10) | ((p as u16) << 9) | field(offset, 9)
+        }
+        Instruction::Add { dr, sr1, src2 } => with_src2(0b0001, dr, sr1, src2),
+        Instruction::Ld { dr, offset } => (0b0010 << 12) | reg(dr, 9) | field(offset, 9),
+        Instruction::St { sr, offset } => (0b0011 << 12) | reg(sr, 9) | field(offset, 9),
+        Instruction::Jsr { offset } => (0b0100 << 12) | 0x800 | field(offset, 11),
+        Instruction::Jsrr { base } => (0b0100 << 12) | reg(base, 6),
+        Instruction::And { dr, sr1, src2 } => with_src2(0b0101, dr, sr1, src2),
+        Instruction::Ldr { dr, base, offset } => (0b0110 << 12) | reg(dr, 9) | reg(base, 6) | field(offset, 6),
+        Instruction::Str { sr, base, offset } => (0b0111 << 12) | reg(sr, 9) | reg(base, 6) | field(offset, 6),
+        Instruction::Rti => 0b1000 << 12,
+        Instruction::Not { dr, sr } => (0b1001 << 12) | reg(dr, 9) | reg(sr, 6) | 0x3F,
+        Instruction::Ldi { dr, offset } => (0b1010 << 12) | reg(dr, 9) | field(offset, 9),
+        Instruction::Sti { sr, offset } => (0b1011 << 12) | reg(sr, 9) | field(offset, 9),
+        Instruction::Jmp { base } => (0b1100 << 12) | reg(base, 6),
+        Instruction::Reserved(word) => (0b1101 << 12) | (word & 0x0FFF),
+        Instruction::Lea { dr, offset } => (0b1110 << 12) | reg(dr, 9) | field(offset, 9),
+        Instruction::Trap { vector } => (0b1111 << 12) | vector as u16,
+    }
+}
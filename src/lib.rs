@@ -0,0 +1,112 @@
+//! An LC-3 virtual machine that can be embedded in other programs.
+//!
+//! [`vm::Vm`] owns the memory, registers and execution state. Load an image
+//! with [`Vm::load_image`](vm::Vm::load_image) (or from bytes, a reader or a
+//! [`program::Program`]), then drive it with [`Vm::run`](vm::Vm::run) or
+//! [`Vm::step`](vm::Vm::step); [`Vm::reset`](vm::Vm::reset) starts the
+//! loaded image over. The `lc3-vm` binary is a thin front end over this crate.
+//!
+//! Everything but [`instruction`], [`cpu`] and [`address`] needs the default
+//! `std` feature; without it the crate is `no_std`, and [`cpu::Cpu`] runs
+//! programs on memory and I/O the caller provides.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+#![allow(clippy::upper_case_acronyms)]
+
+pub mod address;
+#[cfg(feature = "assembler")]
+pub mod asm;
+#[cfg(feature = "batch")]
+pub mod batch;
+#[cfg(feature = "std")]
+pub mod bus;
+#[cfg(feature = "std")]
+pub mod callstack;
+#[cfg(feature = "std")]
+pub mod checkpoints;
+#[cfg(feature = "std")]
+pub mod color;
+#[cfg(feature = "std")]
+pub mod console;
+#[cfg(feature = "std")]
+pub mod coredump;
+pub mod cpu;
+#[cfg(feature = "dap")]
+pub mod dap;
+#[cfg(feature = "debugger")]
+pub mod debugger;
+#[cfg(feature = "devices-extra")]
+pub mod devices;
+#[cfg(feature = "std")]
+pub mod diff;
+#[cfg(feature = "std")]
+pub mod disasm;
+#[cfg(feature = "std")]
+pub mod dump;
+#[cfg(feature = "std")]
+pub mod error;
+#[cfg(feature = "std")]
+pub mod events;
+#[cfg(feature = "debugger")]
+pub mod expr;
+#[cfg(feature = "debugger")]
+pub mod gdb;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "std")]
+pub mod hex_text;
+#[cfg(feature = "std")]
+pub mod history;
+#[cfg(feature = "std")]
+pub mod ihex;
+pub mod instruction;
+#[cfg(feature = "std")]
+pub mod json;
+#[cfg(feature = "std")]
+pub mod limits;
+#[cfg(feature = "std")]
+pub mod microstep;
+#[cfg(feature = "debugger")]
+pub mod monitor;
+#[cfg(feature = "std")]
+pub mod observer;
+#[cfg(feature = "std")]
+pub mod profiler;
+#[cfg(feature = "std")]
+pub mod program;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "std")]
+pub mod recent;
+#[cfg(feature = "std")]
+pub mod recording;
+#[cfg(feature = "scripting")]
+pub mod script;
+#[cfg(feature = "std")]
+pub mod shared;
+#[cfg(feature = "std")]
+pub mod state;
+#[cfg(feature = "std")]
+pub mod status;
+#[cfg(feature = "std")]
+pub mod summary;
+#[cfg(feature = "assembler")]
+pub mod testing;
+#[cfg(feature = "terminal")]
+pub mod terminal;
+#[cfg(feature = "std")]
+pub mod symbols;
+#[cfg(feature = "std")]
+pub mod throttle;
+#[cfg(feature = "std")]
+pub mod timeline;
+#[cfg(feature = "std")]
+pub mod trace;
+#[cfg(feature = "tui")]
+pub mod tui;
+#[cfg(feature = "std")]
+pub mod vm;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "std")]
+pub mod watchdog;
@@ -0,0 +1,73 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/* the clock is read this often rather than on every instruction */
+const CHECK_INTERVAL: u64 = 1024;
+
+/// Which limit stopped the guest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitExceeded {
+    /// It executed this many instructions without halting.
+    Instructions(u64),
+    /// It ran for this long without halting.
+    Timeout(Duration),
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitExceeded::Instructions(count) => write!(f, "instruction limit of {} reached", count),
+            LimitExceeded::Timeout(timeout) => write!(f, "timeout of {:.1?} reached", timeout),
+        }
+    }
+}
+
+/// Bounds on how long a guest may run, for programs that may never halt
+/// (e.g. an untested submission stuck in a loop). Both count from the first
+/// instruction executed after the limits are set or the VM is reset; once
+/// either is reached the VM halts before the next instruction and records
+/// which one in `exceeded`.
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    pub max_instructions: Option<u64>,
+    pub timeout: Option<Duration>,
+    /// Set once a limit has stopped the guest.
+    pub exceeded: Option<LimitExceeded>,
+    /* instruction count and time at the first check */
+    started: Option<(u64, Instant)>,
+}
+
+impl Limits {
+    pub fn new(max_instructions: Option<u64>, timeout: Option<Duration>) -> Self {
+        Limits {
+            max_instructions,
+            timeout,
+            ..Limits::default()
+        }
+    }
+
+    /// Start counting again, as if the program had just been loaded.
+    pub fn reset(&mut self) {
+        self.exceeded = None;
+        self.started = None;
+    }
+
+    /// Check the limits before the instruction after `count` runs; `waiting`
+    /// reads the clock every time, for a guest spinning on input. Returns
+    /// the limit that was reached, if any.
+    pub fn check(&mut self, count: u64, waiting: bool) -> Option<LimitExceeded> {
+        let (start_count, start_time) = *self.started.get_or_insert_with(|| (count, Instant::now()));
+        if let Some(max) = self.max_instructions
+            && count - start_count >= max
+        {
+            self.exceeded = Some(LimitExceeded::Instructions(max));
+        }
+        if let Some(timeout) = self.timeout
+            && (waiting || count.is_multiple_of(CHECK_INTERVAL))
+            && start_time.elapsed() >= timeout
+        {
+            self.exceeded = Some(LimitExceeded::Timeout(timeout));
+        }
+        self.exceeded
+    }
+}
@@ -1,429 +1,1734 @@
-use std::{env, fs::File, io::{self, Read, Write}, usize, mem};
-use libc::{termios, tcgetattr, tcsetattr, ICANON, ECHO, TCSANOW, fd_set, timeval, FD_SET, FD_ZERO, select};
-
-const MEMORY_SIZE: usize = 1 << 16;
-
-static mut ORIGINAL_TERMIOS: Option<termios> = None;
-
-static mut KEY_READY: bool = false;
-static mut KEY_VALUE: u16 = 0;
-
-const PC_START: u16 = 0x3000; /* default starting position for the program counter */
-
-
-enum REGISTER {
-    R0,
-    R1,
-    R2,
-    R3,
-    R4,
-    R5,
-    R6,
-    R7,
-    PC, /* program counter */
-    COND,
-    COUNT
-}
-
-#[derive(Debug)]
-enum InstructionSet
-{
-    BR, /* branch */
-    ADD,    /* add  */
-    LD,     /* load */
-    ST,     /* store */
-    JSR,    /* jump register */
-    AND,    /* bitwise and */
-    LDR,    /* load register */
-    STR,    /* store register */
-    RTI,    /* unused */
-    NOT,    /* bitwise not */
-    LDI,    /* load indirect */
-    STI,    /* store indirect */
-    JMP,    /* jump */
-    RES,    /* reserved (unused) */
-    LEA,    /* load effective address */
-    TRAP    /* execute trap */
-}
-
-#[derive(Debug)]
-enum ConditionFlags
-{
-    POS = 1 << 0, /* P */
-    ZRO = 1 << 1, /* Z */
-    NEG = 1 << 2, /* N */
-}
-
-#[derive(Debug)]
-enum TrapCodes {
-    GETC = 0x20,  /* get character from keyboard, not echoed onto the terminal */
-    OUT = 0x21,   /* output a character */
-    PUTS = 0x22,  /* output a word string */
-    IN = 0x23,    /* get character from keyboard, echoed onto the terminal */
-    PUTSP = 0x24, /* output a byte string */
-    HALT = 0x25   /* halt the program */
-}
-
-enum MemoryMappedRegisters {
-    KBSR = 0xFE00, /* keyboard status */
-    KBDR = 0xFE02  /* keyboard data */
-}
-
-fn sign_extend(value: u16, bit_count: u8) -> u16 {
-    let result = if (value >> (bit_count - 1)) & 0x1 == 1 {
-        value | (0xFFFF << bit_count)
-    } else {
-        value
-    };
-    result
+use std::{collections::HashMap, env, fs::{self, File}, io::{self, BufReader, BufWriter, IsTerminal, Read}, ops::RangeInclusive, path::Path, process, time::{Duration, Instant}};
+#[cfg(feature = "debugger")]
+use std::net::TcpListener;
+#[cfg(feature = "dap")]
+use std::net::Shutdown;
+#[cfg(feature = "devices-extra")]
+use std::{sync::{Arc, atomic::{AtomicBool, Ordering}}, thread::{self, JoinHandle}};
+
+use tracing::warn;
+use tracing_subscriber::EnvFilter;
+
+use lc3_vm::address::{parse_address, parse_range};
+use lc3_vm::callstack::CallStack;
+use lc3_vm::color::{ColorMode, Painter, Role, Theme};
+use lc3_vm::coredump::CoreDump;
+use lc3_vm::console::{EofPolicy, HeadlessConsole, ScriptedConsole, TeeConsole, TerminalConsole};
+
+#[cfg(feature = "batch")]
+use lc3_vm::batch::{BatchJob, run_batch};
+#[cfg(feature = "debugger")]
+use lc3_vm::debugger::{Debugger, DebuggerAction};
+#[cfg(feature = "debugger")]
+use lc3_vm::monitor::Monitor;
+#[cfg(feature = "debugger")]
+use lc3_vm::checkpoints::{self, Checkpoints};
+#[cfg(feature = "debugger")]
+use lc3_vm::history::{DEFAULT_CAPACITY, History};
+#[cfg(feature = "devices-extra")]
+use lc3_vm::bus::Device;
+#[cfg(feature = "devices-extra")]
+use lc3_vm::console::CapturedConsole;
+#[cfg(feature = "devices-extra")]
+use lc3_vm::devices::{console::AuxConsole, disk::Disk, dma::Dma, fs::FileService, gpio::Gpio, host::HostEnvironment, serial::SerialPort};
+#[cfg(feature = "clipboard")]
+use lc3_vm::devices::clipboard::ClipboardBridge;
+#[cfg(feature = "sound")]
+use lc3_vm::devices::sound::Beeper;
+use lc3_vm::diff;
+use lc3_vm::dump;
+use lc3_vm::json;
+use lc3_vm::limits::Limits;
+use lc3_vm::microstep;
+use lc3_vm::profiler::Profiler;
+use lc3_vm::program::Program;
+use lc3_vm::recent::{self, RecentInstructions};
+use lc3_vm::recording::InputRecording;
+use lc3_vm::status::StatusLine;
+use lc3_vm::summary;
+use lc3_vm::symbols;
+use lc3_vm::throttle::Throttle;
+use lc3_vm::timeline::{DEFAULT_SAMPLE_INTERVAL, Timeline};
+use lc3_vm::trace;
+use lc3_vm::terminal::{RawMode, install_signal_handlers};
+use lc3_vm::error::VmError;
+use lc3_vm::vm::{IllegalPolicy, Injection, REGISTER, RunState, StepOutcome, Vm};
+use lc3_vm::watchdog::{Watchdog, WatchdogPolicy};
+
+const USAGE: &str = "\
+usage: lc3-vm [run] [OPTIONS] <image.obj | -> [MORE.obj | -...] [GUEST ARGS...] [-- GUEST ARGS...]
+       lc3-vm [run] [OPTIONS] --raw FILE --at ADDR [...]
+       lc3-vm debug [OPTIONS] <image.obj | -> [GUEST ARGS...]
+       lc3-vm asm <source.asm> [-o OUT.obj]
+       lc3-vm disasm [--color WHEN] [--theme FILE] <image.obj | ->
+       lc3-vm dump [--color WHEN] [--theme FILE] [--json | --ihex] <image.obj | ->
+       lc3-vm batch [--input FILE] [--max-instructions N] [--json] <image.obj>...
+       lc3-vm test [--max-instructions N] [--json] <dir>
+       lc3-vm dap [-v | -vv | -q]
+       lc3-vm help
+
+commands:
+  run                          run an image (the default when no command is given)
+  debug                        run an image, opening the debugger before the first
+                               instruction (debugger builds)
+  asm                          assemble a source file into an object image, written to
+                               OUT.obj (Intel HEX for OUT.hex) or next to the source,
+                               and its labels to OUT.sym (assembler builds)
+  disasm                       disassemble every word of an image
+  dump                         describe an image: segments, strings, trap usage, entry
+  batch                        run many images in parallel without a terminal (batch builds)
+  test                         run every NAME.obj in a directory with NAME.in as its input
+                               and check its output against NAME.expected (batch builds)
+  dap                          serve the Debug Adapter Protocol on stdin/stdout, for an
+                               editor's launch configuration (dap builds)
+  help, --help, -h             print this help
+
+options (run, debug):
+  -v, -vv                      log what the emulator does to stderr: images loaded, devices
+                               attached, terminal mode changes (-v); also traps, keys and
+                               device register accesses (-vv)
+  -q                           log errors only, not warnings
+  --config FILE                read default options from a TOML file: `name = value` for
+                               each `--name value`, `name = true` for flags, arrays to
+                               repeat; options on the command line take precedence
+  --json                       machine-readable output: run summaries, dump, trace
+                               results and profiles are printed as JSON objects
+  --color auto|always|never    colorize disassembly and diagnostics (default auto)
+  --theme FILE                 color theme: `role = SGR` lines (mnemonic, register,
+                               immediate, address, error, expected, actual)
+  --summary                    print registers and instruction count at halt
+  --summary-range START..END   include a memory range in the summary
+  --dump-registers             print the registers to stderr when the VM stops
+  --exit-code-r0               exit with the low byte of R0 at HALT
+  --dump-mem START..END        print a hex dump of a memory range once the run ends
+                               (repeatable)
+  --dump-mem-to FILE           write the --dump-mem output to FILE instead of stdout
+  --break-at-start             open the debugger before the first instruction
+  --history N                  keep the last N instructions for the debugger's reverse-step
+                               and reverse-continue (default 100000 for debug, 0 for run)
+  --checkpoint-every N         snapshot the machine every N instructions, keeping the last 16,
+                               for the debugger's back and travel
+  --gdb [HOST]:PORT            wait for GDB to connect (e.g. :3333, on localhost) and let
+                               it control the program (debugger builds)
+  --dap [HOST]:PORT            wait for an editor to attach over the Debug Adapter
+                               Protocol and let it control the program (dap builds)
+  --script FILE                run a rhai script driving the debugger before the program
+                               runs on (scripting builds)
+  --tui                        full-screen debugger with register, disassembly, memory and
+                               console panes (tui builds)
+  --microstep                  print each instruction's fetch/decode/execute phases
+                               (MAR, MDR, IR) to stderr as it runs
+  --status                     show a live progress line on stderr (no debugger hotkey)
+  --profile FILE               write an execution profile to FILE
+  --timeline FILE              write an execution timeline to FILE (.html: with a viewer)
+  --watchdog SECS              dump registers, recent instructions and the suspected loop
+                               to stderr when the program makes no progress for SECS
+  --watchdog-abort             halt (exit status 1) instead of continuing once it fires
+  --format obj|ihex|hex-text   how images are encoded (default: by extension: `.ihex` is
+                               Intel HEX, `.bin` one word per line as text, `.hex` either
+                               one, anything else an object file)
+  --ihex                       dump: print the image as Intel HEX instead of describing it
+  --raw FILE --at ADDR         also load FILE, headerless big-endian words, at ADDR
+                               (repeatable; the object image may then be left out)
+  --fill WORD                  fill memory with WORD (e.g. 0xDEAD) before loading images
+  --sym FILE                   read labels from a symbol table (repeatable; prog.sym beside
+                               prog.obj is read without it)
+  --entry ADDR|IMAGE           start execution at ADDR (e.g. 0x0200) or at the origin of
+                               one of the images, instead of the last object image's
+                               origin (or the first raw image's, if there is none)
+  --echo-getc                  show keys read by GETC, which the guest does not echo
+  --on-illegal POLICY          on a reserved opcode or RTI in user mode: halt with a
+                               diagnostic (default), ignore, exception (x01/x00), panic
+  --stdin-file FILE            type the bytes of FILE at the guest's keyboard
+  --on-eof block|zero|halt     after --stdin-file runs out: read the terminal (default),
+                               deliver x00 keys, or halt the program
+  --record FILE                log every key the guest receives, with the instruction count
+                               it arrived at, to FILE when the run ends
+  --replay FILE                feed the keys of a --record log back at the same instruction
+                               counts, then read the terminal again
+  --stdout-file FILE           write the guest's console output to FILE instead of stdout
+  --tee                        with --stdout-file, print the output to stdout as well
+  --expect-output FILE         compare the guest's output with FILE when the run ends;
+                               on a mismatch print a diff and exit with status 1
+  --arg TEXT                   pass TEXT to the program as an argument (repeatable;
+                               count, pointers and strings are stored from xFD00)
+  --max-steps N                stop the program (exit status 1) after N instructions
+  --timeout SECS               stop the program (exit status 1) after SECS of run time
+  --crash-context N            on an error, illegal opcode or limit, show the last N
+                               instructions executed (default 16, 0 for none)
+  --core FILE                  on an error, illegal opcode or limit, write memory, registers
+                               and the recent instructions to FILE
+  --load-core FILE             open the debugger on a --core file to see how the run ended
+                               (the image may then be left out; debugger builds)
+  --bench                      discard the guest's output, run without tracing, and report
+                               instructions executed, wall time and MIPS on stderr
+                               (not with --trace, --record-trace, --compare-trace or
+                               --expect-output)
+  --hz N                       run about N instructions per second, e.g. 5 for a demo
+  --save-state FILE            write a snapshot of the machine to FILE when the run ends
+  --load-state FILE            continue from a snapshot instead of the image's start
+                               (a snapshot taken at HALT resumes after the HALT)
+  --trace FILE                 write the disassembly of every executed instruction to FILE
+                               as it runs (`-` for stdout)
+  --record-trace FILE          write a JSONL trace of every instruction to FILE
+  --compare-trace FILE         stop at the first step that differs from FILE
+  --interrupt WHAT@STEP        inject an interrupt after STEP instructions: key=C (keyboard,
+                               delivering C), timer, or VECTOR[:PRIORITY] (repeatable)
+  --interrupts FILE            read --interrupt values from FILE, one per line
+  --disk FILE                  attach a disk image at xFE10 (devices-extra builds)
+  --fs-root DIR                give TRAP x30-x34 file access inside DIR (devices-extra builds)
+  --dma                        attach the DMA block-copy controller at xFE20 (devices-extra builds)
+  --gpio                       attach 16 GPIO pins at xFE50; drive inputs from the debugger
+                               with `pin N 0|1` (devices-extra builds)
+  --link PEER.obj              run PEER.obj alongside, joined by a serial link at xFE60
+                               (devices-extra builds)
+  --env NAME                   let the program read environment variable NAME (devices-extra builds)
+  --console2 TARGET            second console at xFE08: tcp:HOST:PORT, listen:PORT,
+                               or a terminal/PTY path (devices-extra builds)
+  --sound                      attach the tone generator at xFE30 (sound builds)
+  --clipboard                  attach the host clipboard bridge at xFE40 (clipboard builds)";
+
+/* the `batch` subcommand: many images, each run headless on its own core */
+#[cfg(feature = "batch")]
+struct BatchOptions {
+    images: Vec<String>,
+    input_path: Option<String>,
+    max_instructions: u64,
 }
 
-fn update_flags(addr: u16, registers: &mut [u16]) {
-    let value = registers[addr as usize];
-    if value == 0 {
-        registers[REGISTER::COND as usize] = ConditionFlags::ZRO as u16;
-    } else if (value >> 15) == 1 {
-        registers[REGISTER::COND as usize] = ConditionFlags::NEG as u16;
-    } else {
-        registers[REGISTER::COND as usize] = ConditionFlags::POS as u16;
+/* how an image file is encoded */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ImageFormat {
+    Object,
+    IntelHex,
+    HexText,
+}
+
+impl ImageFormat {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "obj" => Some(ImageFormat::Object),
+            "ihex" => Some(ImageFormat::IntelHex),
+            "hex-text" => Some(ImageFormat::HexText),
+            _ => None,
+        }
+    }
+
+    /* the format of an image without --format: `.ihex` is Intel HEX, `.bin` hex text, and
+       `.hex` either, told apart by the `:` Intel HEX records start with; anything else,
+       stdin included, is an object file */
+    fn detect(path: &str, bytes: &[u8]) -> Self {
+        if path.ends_with(".ihex") || (path.ends_with(".hex") && bytes.trim_ascii_start().starts_with(b":")) {
+            ImageFormat::IntelHex
+        } else if path.ends_with(".hex") || path.ends_with(".bin") {
+            ImageFormat::HexText
+        } else {
+            ImageFormat::Object
+        }
     }
 }
 
-fn get_instructions(file_path: &str) -> io::Result<Vec<u16>> {
-    let mut file = File::open(file_path)?;
-    
-    let mut buf = Vec::new();
-    file.read_to_end(&mut buf)?;
+/* what the binary was asked to do; `debug` is `Run` with `break_at_start` */
+#[derive(Default, PartialEq, Eq)]
+enum Command {
+    #[default]
+    Run,
+    Dump,
+    Disasm,
+    #[cfg(feature = "assembler")]
+    Asm { output_path: String },
+    #[cfg(feature = "batch")]
+    Test { max_instructions: u64 },
+    #[cfg(feature = "dap")]
+    Dap,
+    Help,
+}
 
-    // Must be an even number of bytes
-    assert!(buf.len() % 2 == 0);
+#[derive(Default)]
+struct Options {
+    command: Command,
+    file_path: String,
+    /* further images loaded with the first, e.g. a user program after an OS */
+    extra_image_paths: Vec<String>,
+    format: Option<ImageFormat>,
+    ihex: bool,
+    /* headerless images and where they go, loaded after the object images */
+    raw_images: Vec<(String, u16)>,
+    #[cfg(feature = "batch")]
+    batch: Option<BatchOptions>,
+    json: bool,
+    color: ColorMode,
+    theme_path: Option<String>,
+    summary: bool,
+    summary_ranges: Vec<RangeInclusive<u16>>,
+    dump_registers: bool,
+    exit_code_r0: bool,
+    dump_ranges: Vec<RangeInclusive<u16>>,
+    dump_path: Option<String>,
+    profile_path: Option<String>,
+    timeline_path: Option<String>,
+    status: bool,
+    microstep: bool,
+    watchdog: Option<Duration>,
+    watchdog_abort: bool,
+    entry: Option<String>,
+    fill: Option<u16>,
+    sym_paths: Vec<String>,
+    guest_args: Vec<String>,
+    echo_getc: bool,
+    on_illegal: IllegalPolicy,
+    /* -1 for -q, 1 for -v, 2 for -vv */
+    verbosity: i8,
+    stdin_file: Option<String>,
+    record_path: Option<String>,
+    replay_path: Option<String>,
+    stdout_file: Option<String>,
+    tee: bool,
+    expect_output_path: Option<String>,
+    on_eof: EofPolicy,
+    max_steps: Option<u64>,
+    timeout: Option<Duration>,
+    crash_context: Option<usize>,
+    core_path: Option<String>,
+    hz: Option<u64>,
+    bench: bool,
+    save_state_path: Option<String>,
+    trace_path: Option<String>,
+    load_state_path: Option<String>,
+    record_trace_path: Option<String>,
+    compare_trace_path: Option<String>,
+    injections: Vec<(u64, Injection)>,
+    #[cfg(feature = "debugger")]
+    break_at_start: bool,
+    #[cfg(feature = "debugger")]
+    load_core_path: Option<String>,
+    #[cfg(feature = "debugger")]
+    history: Option<usize>,
+    #[cfg(feature = "debugger")]
+    checkpoint_every: Option<u64>,
+    #[cfg(feature = "debugger")]
+    gdb_address: Option<String>,
+    #[cfg(feature = "dap")]
+    dap_address: Option<String>,
+    #[cfg(feature = "scripting")]
+    script_path: Option<String>,
+    #[cfg(feature = "tui")]
+    tui: bool,
+    #[cfg(feature = "devices-extra")]
+    disk_path: Option<String>,
+    #[cfg(feature = "devices-extra")]
+    fs_root: Option<String>,
+    #[cfg(feature = "devices-extra")]
+    dma: bool,
+    #[cfg(feature = "devices-extra")]
+    gpio: bool,
+    #[cfg(feature = "devices-extra")]
+    link_path: Option<String>,
+    #[cfg(feature = "devices-extra")]
+    console2: Option<String>,
+    #[cfg(feature = "devices-extra")]
+    env_vars: Vec<String>,
+    #[cfg(feature = "sound")]
+    sound: bool,
+    #[cfg(feature = "clipboard")]
+    clipboard: bool,
+}
 
-    let mut words = Vec::new();
-    for chunk in buf.chunks_exact(2) {
-        let word = u16::from_be_bytes([chunk[0], chunk[1]]);
-        words.push(word);
-    }
-    return Ok(words);
+/* replace `--config FILE` with the options FILE declares, ahead of the rest so the command line wins */
+fn expand_config(args: Vec<String>) -> Result<Vec<String>, String> {
+    let end = args.iter().position(|arg| arg == "--").unwrap_or(args.len());
+    let Some(index) = args[..end].iter().position(|arg| arg == "--config") else {
+        return Ok(args);
+    };
+    let path = args[..end].get(index + 1).ok_or("--config needs a file")?;
+    let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+    let table: toml::Table = text.parse().map_err(|err| format!("{}: {}", path, err))?;
+    let mut expanded = Vec::new();
+    config_args(&table, &mut expanded).map_err(|err| format!("{}: {}", path, err))?;
+    expanded.extend(args[..index].iter().cloned());
+    expanded.extend(args[index + 2..].iter().cloned());
+    Ok(expanded)
 }
 
-fn load_memory(instructions: Vec<u16>) -> [u16; MEMORY_SIZE] {
-    let mut memory: [u16; MEMORY_SIZE] = [0; MEMORY_SIZE];
-    let origin = instructions[0];
-    let modified_instruction = &instructions[1..];
-    for (i, instruction) in modified_instruction.iter().enumerate() {
-        memory[(origin as usize + i) as usize] = *instruction;
+/* `name = value` becomes `--name value`; tables only group keys, e.g. `[devices]` */
+fn config_args(table: &toml::Table, out: &mut Vec<String>) -> Result<(), String> {
+    for (name, value) in table {
+        let values = match value {
+            toml::Value::Table(table) => {
+                config_args(table, out)?;
+                continue;
+            }
+            toml::Value::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            match value {
+                toml::Value::Boolean(true) => out.push(format!("--{}", name)),
+                toml::Value::Boolean(false) => {}
+                toml::Value::String(text) => out.extend([format!("--{}", name), text.clone()]),
+                toml::Value::Integer(number) => out.extend([format!("--{}", name), number.to_string()]),
+                toml::Value::Float(number) => out.extend([format!("--{}", name), number.to_string()]),
+                _ => return Err(format!("`{}` must be a string, number, boolean or array of them", name)),
+            }
+        }
     }
-    return memory;
+    Ok(())
 }
 
-fn initialize_registers(origin: u16) -> [u16; REGISTER::COUNT as usize] {
-    let mut registers: [u16; REGISTER::COUNT as usize] = [0; REGISTER::COUNT as usize];
-    /* since exactly one condition flag should be set at any given time, set the Z flag */
-    registers[REGISTER::COND as usize] = ConditionFlags::ZRO as u16;
-    /* set the PC to starting position */
-    registers[REGISTER::PC as usize] = origin;
-    return registers;
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut file_path = None;
+    let mut args = args.iter().peekable();
+    match args.peek().map(|arg| arg.as_str()) {
+        Some("run") => {
+            args.next();
+        }
+        #[cfg(feature = "debugger")]
+        Some("debug") => {
+            args.next();
+            options.break_at_start = true;
+        }
+        #[cfg(not(feature = "debugger"))]
+        Some("debug") => return Err("`debug` needs a build with the debugger feature".to_string()),
+        Some("dump") => {
+            args.next();
+            options.command = Command::Dump;
+        }
+        Some("disasm") => {
+            args.next();
+            options.command = Command::Disasm;
+        }
+        #[cfg(feature = "assembler")]
+        Some("asm") => {
+            args.next();
+            return parse_asm_args(args.cloned().collect());
+        }
+        #[cfg(not(feature = "assembler"))]
+        Some("asm") => return Err("`asm` needs a build with the assembler feature".to_string()),
+        #[cfg(feature = "batch")]
+        Some("batch") => {
+            args.next();
+            return parse_batch_args(args.cloned().collect());
+        }
+        #[cfg(not(feature = "batch"))]
+        Some("batch") => return Err("`batch` needs a build with the batch feature".to_string()),
+        #[cfg(feature = "batch")]
+        Some("test") => {
+            args.next();
+            return parse_test_args(args.cloned().collect());
+        }
+        #[cfg(not(feature = "batch"))]
+        Some("test") => return Err("`test` needs a build with the batch feature".to_string()),
+        #[cfg(feature = "dap")]
+        Some("dap") => {
+            args.next();
+            return parse_dap_args(args.cloned().collect());
+        }
+        #[cfg(not(feature = "dap"))]
+        Some("dap") => return Err("`dap` needs a build with the dap feature".to_string()),
+        Some("help") => options.command = Command::Help,
+        _ => {}
+    }
+    let args = expand_config(args.cloned().collect())?;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--help" | "-h" => {
+                options.command = Command::Help;
+                return Ok(options);
+            }
+            "--color" => {
+                let value = args.next().ok_or("--color needs a value")?;
+                options.color = ColorMode::parse(value).ok_or_else(|| format!("invalid color mode `{}`", value))?;
+            }
+            "--theme" => {
+                let value = args.next().ok_or("--theme needs a file path")?;
+                options.theme_path = Some(value.clone());
+            }
+            "--json" => options.json = true,
+            "--summary" => options.summary = true,
+            "-q" | "--quiet" => options.verbosity = -1,
+            "-v" | "--verbose" => options.verbosity = 1,
+            "-vv" => options.verbosity = 2,
+            "--dump-registers" => options.dump_registers = true,
+            "--exit-code-r0" => options.exit_code_r0 = true,
+            "--status" => options.status = true,
+            "--microstep" => options.microstep = true,
+            "--watchdog" => {
+                let value = args.next().ok_or("--watchdog needs a number of seconds")?;
+                let seconds = value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|seconds| seconds.is_finite() && *seconds > 0.0)
+                    .ok_or_else(|| format!("invalid watchdog interval `{}`", value))?;
+                options.watchdog = Some(Duration::from_secs_f64(seconds));
+            }
+            "--watchdog-abort" => options.watchdog_abort = true,
+            "--sym" => {
+                let value = args.next().ok_or("--sym needs a file")?;
+                options.sym_paths.push(value.clone());
+            }
+            "--fill" => {
+                let value = args.next().ok_or("--fill needs a word")?;
+                options.fill = Some(parse_address(value).ok_or_else(|| format!("invalid fill word `{}`", value))?);
+            }
+            "--entry" => {
+                let value = args.next().ok_or("--entry needs an address or image")?;
+                options.entry = Some(value.clone());
+            }
+            "--echo-getc" => options.echo_getc = true,
+            "--on-illegal" => {
+                let value = args.next().ok_or("--on-illegal needs halt, ignore, exception or panic")?;
+                options.on_illegal =
+                    IllegalPolicy::parse(value).ok_or_else(|| format!("unknown illegal-instruction policy `{}`", value))?;
+            }
+            "--stdin-file" => {
+                let value = args.next().ok_or("--stdin-file needs a file")?;
+                options.stdin_file = Some(value.clone());
+            }
+            "--record" => {
+                let value = args.next().ok_or("--record needs a file path")?;
+                options.record_path = Some(value.clone());
+            }
+            "--replay" => {
+                let value = args.next().ok_or("--replay needs a file path")?;
+                options.replay_path = Some(value.clone());
+            }
+            "--on-eof" => {
+                let value = args.next().ok_or("--on-eof needs block, zero or halt")?;
+                options.on_eof = EofPolicy::parse(value).ok_or_else(|| format!("unknown end-of-file policy `{}`", value))?;
+            }
+            "--stdout-file" => {
+                let value = args.next().ok_or("--stdout-file needs a file")?;
+                options.stdout_file = Some(value.clone());
+            }
+            "--tee" => options.tee = true,
+            "--expect-output" => {
+                let value = args.next().ok_or("--expect-output needs a file")?;
+                options.expect_output_path = Some(value.clone());
+            }
+            "--arg" => {
+                let value = args.next().ok_or("--arg needs a value")?;
+                options.guest_args.push(value.clone());
+            }
+            "--format" => {
+                let value = args.next().ok_or("--format needs obj, ihex or hex-text")?;
+                options.format = Some(ImageFormat::parse(value).ok_or_else(|| format!("unknown image format `{}`", value))?);
+            }
+            "--ihex" => options.ihex = true,
+            "--raw" => {
+                let path = args.next().ok_or("--raw needs a file path")?;
+                if args.next().map(String::as_str) != Some("--at") {
+                    return Err(format!("--raw {} needs `--at ADDR` after it", path));
+                }
+                let value = args.next().ok_or("--at needs an address")?;
+                let address = parse_address(value).ok_or_else(|| format!("invalid address `{}`", value))?;
+                options.raw_images.push((path.clone(), address));
+            }
+            "--max-steps" => {
+                let value = args.next().ok_or("--max-steps needs a number of instructions")?;
+                options.max_steps = Some(value.parse().map_err(|_| format!("invalid instruction count `{}`", value))?);
+            }
+            "--crash-context" => {
+                let value = args.next().ok_or("--crash-context needs a number of instructions")?;
+                options.crash_context = Some(value.parse().map_err(|_| format!("invalid instruction count `{}`", value))?);
+            }
+            "--core" => {
+                let value = args.next().ok_or("--core needs a file path")?;
+                options.core_path = Some(value.clone());
+            }
+            /* a core is looked at, not run on */
+            #[cfg(feature = "debugger")]
+            "--load-core" => {
+                let value = args.next().ok_or("--load-core needs a file path")?;
+                options.load_core_path = Some(value.clone());
+                options.break_at_start = true;
+            }
+            "--bench" => options.bench = true,
+            "--hz" => {
+                let value = args.next().ok_or("--hz needs a number of instructions per second")?;
+                options.hz = Some(value.parse().ok().filter(|&hz| hz > 0).ok_or_else(|| format!("invalid rate `{}`", value))?);
+            }
+            "--timeout" => {
+                let value = args.next().ok_or("--timeout needs a number of seconds")?;
+                let seconds = value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|seconds| seconds.is_finite() && *seconds > 0.0)
+                    .ok_or_else(|| format!("invalid timeout `{}`", value))?;
+                options.timeout = Some(Duration::from_secs_f64(seconds));
+            }
+            "--save-state" => {
+                let value = args.next().ok_or("--save-state needs a file path")?;
+                options.save_state_path = Some(value.clone());
+            }
+            "--load-state" => {
+                let value = args.next().ok_or("--load-state needs a file path")?;
+                options.load_state_path = Some(value.clone());
+            }
+            "--summary-range" => {
+                let value = args.next().ok_or("--summary-range needs a value")?;
+                let range = parse_range(value).ok_or_else(|| format!("invalid range `{}`", value))?;
+                options.summary = true;
+                options.summary_ranges.push(range);
+            }
+            "--dump-mem" => {
+                let value = args.next().ok_or("--dump-mem needs a range")?;
+                options.dump_ranges.push(parse_range(value).ok_or_else(|| format!("invalid range `{}`", value))?);
+            }
+            "--dump-mem-to" => {
+                let value = args.next().ok_or("--dump-mem-to needs a file path")?;
+                options.dump_path = Some(value.clone());
+            }
+            "--timeline" => {
+                let value = args.next().ok_or("--timeline needs a file path")?;
+                options.timeline_path = Some(value.clone());
+            }
+            "--profile" => {
+                let value = args.next().ok_or("--profile needs a file path")?;
+                options.profile_path = Some(value.clone());
+            }
+            "--trace" => {
+                let value = args.next().ok_or("--trace needs a file path")?;
+                options.trace_path = Some(value.clone());
+            }
+            "--record-trace" => {
+                let value = args.next().ok_or("--record-trace needs a file path")?;
+                options.record_trace_path = Some(value.clone());
+            }
+            "--interrupt" => {
+                let value = args.next().ok_or("--interrupt needs a value")?;
+                options.injections.push(parse_injection(value)?);
+            }
+            "--interrupts" => {
+                let path = args.next().ok_or("--interrupts needs a file path")?;
+                let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+                for line in text.lines().map(|line| line.split('#').next().unwrap_or("").trim()) {
+                    if !line.is_empty() {
+                        options.injections.push(parse_injection(line)?);
+                    }
+                }
+            }
+            "--compare-trace" => {
+                let value = args.next().ok_or("--compare-trace needs a file path")?;
+                options.compare_trace_path = Some(value.clone());
+            }
+            #[cfg(feature = "debugger")]
+            "--break-at-start" => options.break_at_start = true,
+            #[cfg(feature = "debugger")]
+            "--history" => {
+                let value = args.next().ok_or("--history needs an instruction count")?;
+                options.history = Some(value.parse().map_err(|_| format!("invalid instruction count `{}`", value))?);
+            }
+            #[cfg(feature = "debugger")]
+            "--checkpoint-every" => {
+                let value = args.next().ok_or("--checkpoint-every needs an instruction count")?;
+                let interval = value.parse::<u64>().ok().filter(|interval| *interval > 0);
+                options.checkpoint_every = Some(interval.ok_or_else(|| format!("invalid instruction count `{}`", value))?);
+            }
+            #[cfg(feature = "scripting")]
+            "--script" => {
+                let value = args.next().ok_or("--script needs a file path")?;
+                options.script_path = Some(value.clone());
+            }
+            #[cfg(feature = "debugger")]
+            "--gdb" => {
+                let value = args.next().ok_or("--gdb needs an address, e.g. :3333")?;
+                /* a bare port listens on localhost only */
+                let address = if value.starts_with(':') { format!("127.0.0.1{}", value) } else { value.clone() };
+                options.gdb_address = Some(address);
+            }
+            #[cfg(feature = "dap")]
+            "--dap" => {
+                let value = args.next().ok_or("--dap needs an address, e.g. :4711")?;
+                let address = if value.starts_with(':') { format!("127.0.0.1{}", value) } else { value.clone() };
+                options.dap_address = Some(address);
+            }
+            #[cfg(not(feature = "dap"))]
+            "--dap" => return Err("--dap needs a build with the dap feature".to_string()),
+            #[cfg(feature = "tui")]
+            "--tui" => options.tui = true,
+            #[cfg(not(feature = "tui"))]
+            "--tui" => return Err("--tui needs a build with the tui feature".to_string()),
+            #[cfg(feature = "devices-extra")]
+            "--disk" => {
+                let value = args.next().ok_or("--disk needs an image file path")?;
+                options.disk_path = Some(value.clone());
+            }
+            #[cfg(feature = "devices-extra")]
+            "--dma" => options.dma = true,
+            #[cfg(feature = "devices-extra")]
+            "--gpio" => options.gpio = true,
+            #[cfg(feature = "devices-extra")]
+            "--link" => {
+                let value = args.next().ok_or("--link needs an image path")?;
+                options.link_path = Some(value.clone());
+            }
+            #[cfg(feature = "devices-extra")]
+            "--console2" => {
+                let value = args.next().ok_or("--console2 needs a target")?;
+                options.console2 = Some(value.clone());
+            }
+            #[cfg(feature = "sound")]
+            "--sound" => options.sound = true,
+            #[cfg(feature = "clipboard")]
+            "--clipboard" => options.clipboard = true,
+            #[cfg(feature = "devices-extra")]
+            "--fs-root" => {
+                let value = args.next().ok_or("--fs-root needs a directory")?;
+                options.fs_root = Some(value.clone());
+            }
+            #[cfg(feature = "devices-extra")]
+            "--env" => {
+                let value = args.next().ok_or("--env needs a variable name")?;
+                options.env_vars.push(value.clone());
+            }
+            "--" if file_path.is_some() => options.guest_args.extend(args.by_ref().cloned()),
+            flag if flag.starts_with("--") => return Err(format!("unknown option `{}`", flag)),
+            path if file_path.is_none() => file_path = Some(path.to_string()),
+            /* object files (or stdin) right after the first image are loaded with it */
+            image if looks_like_image(image) && options.guest_args.is_empty() => options.extra_image_paths.push(image.to_string()),
+            arg => options.guest_args.push(arg.to_string()),
+        }
+    }
+    if options.command == Command::Help {
+        return Ok(options);
+    }
+    /* a run may consist of raw images only, or a core, leaving the path empty */
+    options.file_path = match file_path {
+        Some(path) => path,
+        None if options.command == Command::Run && !options.raw_images.is_empty() => String::new(),
+        #[cfg(feature = "debugger")]
+        None if options.command == Command::Run && options.load_core_path.is_some() => String::new(),
+        None => return Err("missing image path".to_string()),
+    };
+    if std::iter::once(&options.file_path).chain(&options.extra_image_paths).filter(|path| *path == "-").count() > 1 {
+        return Err("only one image can be read from stdin".to_string());
+    }
+    if let Some(entry) = &options.entry
+        && parse_address(entry).is_none()
+        && !is_image_path(&options, entry)
+    {
+        return Err(format!("--entry {} is neither an address nor one of the images", entry));
+    }
+    if options.record_trace_path.is_some() && options.compare_trace_path.is_some() {
+        return Err("--record-trace and --compare-trace cannot be combined".to_string());
+    }
+    #[cfg(feature = "debugger")]
+    if options.load_core_path.is_some() && options.load_state_path.is_some() {
+        return Err("--load-core and --load-state cannot be combined".to_string());
+    }
+    if options.tee && options.stdout_file.is_none() {
+        return Err("--tee needs --stdout-file".to_string());
+    }
+    if options.replay_path.is_some() && options.stdin_file.is_some() {
+        return Err("--replay and --stdin-file cannot be combined".to_string());
+    }
+    /* a benchmark measures the interpreter, not the trace writer, and discards the output to check */
+    if options.bench {
+        let conflicts = [
+            ("--trace", options.trace_path.is_some()),
+            ("--record-trace", options.record_trace_path.is_some()),
+            ("--compare-trace", options.compare_trace_path.is_some()),
+            ("--expect-output", options.expect_output_path.is_some()),
+        ];
+        if let Some((option, _)) = conflicts.iter().find(|(_, set)| *set) {
+            return Err(format!("--bench cannot be combined with {}", option));
+        }
+    }
+    #[cfg(feature = "debugger")]
+    if options.gdb_address.is_some() {
+        let conflicts = [
+            ("--record-trace", options.record_trace_path.is_some()),
+            ("--compare-trace", options.compare_trace_path.is_some()),
+            ("--microstep", options.microstep),
+            ("--status", options.status),
+            ("--bench", options.bench),
+        ];
+        if let Some((option, _)) = conflicts.iter().find(|(_, set)| *set) {
+            return Err(format!("--gdb cannot be combined with {}", option));
+        }
+    }
+    #[cfg(feature = "dap")]
+    if options.dap_address.is_some() {
+        let conflicts = [
+            ("--gdb", options.gdb_address.is_some()),
+            ("--record-trace", options.record_trace_path.is_some()),
+            ("--compare-trace", options.compare_trace_path.is_some()),
+            ("--microstep", options.microstep),
+            ("--status", options.status),
+            ("--bench", options.bench),
+        ];
+        if let Some((option, _)) = conflicts.iter().find(|(_, set)| *set) {
+            return Err(format!("--dap cannot be combined with {}", option));
+        }
+    }
+    /* the script runs where the debugger would, ahead of a plain run */
+    #[cfg(feature = "scripting")]
+    if options.script_path.is_some() {
+        let conflicts = [
+            ("--record-trace", options.record_trace_path.is_some()),
+            ("--compare-trace", options.compare_trace_path.is_some()),
+            ("--microstep", options.microstep),
+            ("--status", options.status),
+            ("--bench", options.bench),
+            #[cfg(feature = "tui")]
+            ("--tui", options.tui),
+        ];
+        if let Some((option, _)) = conflicts.iter().find(|(_, set)| *set) {
+            return Err(format!("--script cannot be combined with {}", option));
+        }
+    }
+    /* the full-screen debugger owns the terminal and the guest's console */
+    #[cfg(feature = "tui")]
+    if options.tui {
+        let conflicts = [
+            ("--gdb", options.gdb_address.is_some()),
+            #[cfg(feature = "dap")]
+            ("--dap", options.dap_address.is_some()),
+            ("--stdin-file", options.stdin_file.is_some()),
+            ("--stdout-file", options.stdout_file.is_some()),
+            ("--expect-output", options.expect_output_path.is_some()),
+            ("--trace -", options.trace_path.as_deref() == Some("-")),
+            ("--record-trace", options.record_trace_path.is_some()),
+            ("--compare-trace", options.compare_trace_path.is_some()),
+            ("--microstep", options.microstep),
+            ("--status", options.status),
+            ("--bench", options.bench),
+        ];
+        if let Some((option, _)) = conflicts.iter().find(|(_, set)| *set) {
+            return Err(format!("--tui cannot be combined with {}", option));
+        }
+    }
+    Ok(options)
 }
 
-pub fn disable_input_buffering() {
-    unsafe {
-        let mut t = mem::zeroed::<termios>();
-        tcgetattr(0, &mut t);
-        ORIGINAL_TERMIOS = Some(t);
+#[cfg(feature = "assembler")]
+fn parse_asm_args(args: Vec<String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut output_path = None;
+    let mut source_path = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--help" | "-h" => {
+                options.command = Command::Help;
+                return Ok(options);
+            }
+            "-o" | "--output" => output_path = Some(args.next().ok_or("-o needs a file path")?),
+            flag if flag.starts_with('-') => return Err(format!("unknown asm option `{}`", flag)),
+            _ if source_path.is_some() => return Err(format!("unexpected argument `{}`", arg)),
+            _ => source_path = Some(arg),
+        }
+    }
+    let source_path = source_path.ok_or("missing source path")?;
+    /* prog.asm assembles to prog.obj beside it */
+    let output_path = output_path.unwrap_or_else(|| Path::new(&source_path).with_extension("obj").to_string_lossy().into_owned());
+    options.command = Command::Asm { output_path };
+    options.file_path = source_path;
+    Ok(options)
+}
 
-        t.c_lflag &= !(ICANON | ECHO);
-        tcsetattr(0, TCSANOW, &t);
+#[cfg(feature = "batch")]
+fn parse_batch_args(args: Vec<String>) -> Result<Options, String> {
+    let mut batch = BatchOptions {
+        images: Vec::new(),
+        input_path: None,
+        max_instructions: 10_000_000,
+    };
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--help" | "-h" => {
+                options.command = Command::Help;
+                return Ok(options);
+            }
+            "--json" => options.json = true,
+            "--input" => batch.input_path = Some(args.next().ok_or("--input needs a file path")?),
+            "--max-instructions" => {
+                let value = args.next().ok_or("--max-instructions needs a number")?;
+                batch.max_instructions = value.parse().map_err(|_| format!("invalid instruction count `{}`", value))?;
+            }
+            flag if flag.starts_with("--") => return Err(format!("unknown batch option `{}`", flag)),
+            _ => batch.images.push(arg),
+        }
+    }
+    if batch.images.is_empty() {
+        return Err("batch needs at least one image".to_string());
     }
+    options.batch = Some(batch);
+    Ok(options)
 }
 
-pub fn restore_input_buffering() {
-    unsafe {
-        if let Some(t) = ORIGINAL_TERMIOS {
-            tcsetattr(0, TCSANOW, &t);
+#[cfg(feature = "batch")]
+fn parse_test_args(args: Vec<String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut max_instructions = 10_000_000;
+    let mut dir = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--help" | "-h" => {
+                options.command = Command::Help;
+                return Ok(options);
+            }
+            "--json" => options.json = true,
+            "--max-instructions" => {
+                let value = args.next().ok_or("--max-instructions needs a number")?;
+                max_instructions = value.parse().map_err(|_| format!("invalid instruction count `{}`", value))?;
+            }
+            flag if flag.starts_with("--") => return Err(format!("unknown test option `{}`", flag)),
+            _ if dir.is_some() => return Err(format!("unexpected argument `{}`", arg)),
+            _ => dir = Some(arg),
         }
     }
+    options.file_path = dir.ok_or("test needs a directory")?;
+    options.command = Command::Test { max_instructions };
+    Ok(options)
 }
 
-fn write_to_memory(memory: &mut [u16], address: u16, value: u16) {
-    memory[address as usize] = value;
+/* the program comes from the editor's launch request; only logging is set here */
+#[cfg(feature = "dap")]
+fn parse_dap_args(args: Vec<String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    for arg in args {
+        match arg.as_str() {
+            "--help" | "-h" => {
+                options.command = Command::Help;
+                return Ok(options);
+            }
+            "-q" | "--quiet" => options.verbosity = -1,
+            "-v" | "--verbose" => options.verbosity = 1,
+            "-vv" => options.verbosity = 2,
+            _ => return Err(format!("unexpected argument `{}`", arg)),
+        }
+    }
+    options.command = Command::Dap;
+    Ok(options)
 }
 
-pub fn check_key() -> bool {
-    unsafe {
-        let mut readfds = std::mem::zeroed::<fd_set>();
-        FD_ZERO(&mut readfds);
-        FD_SET(0, &mut readfds); // stdin
+/* run every image in parallel and report in argument order; exits 1 if any hit the limit */
+#[cfg(feature = "batch")]
+fn run_batch_command(batch: &BatchOptions, json: bool) {
+    let input = match &batch.input_path {
+        Some(path) => fs::read(path).unwrap_or_else(|err| fail(&format!("input {}: {}", path, err))),
+        None => Vec::new(),
+    };
+    let jobs: Vec<BatchJob> = batch
+        .images
+        .iter()
+        .map(|path| BatchJob {
+            name: path.clone(),
+            program: Program::from_file(path).unwrap_or_else(|err| fail(&format!("{}: {}", path, err))),
+            input: input.clone(),
+            max_instructions: batch.max_instructions,
+        })
+        .collect();
+    let results = run_batch(&jobs);
+    for result in &results {
+        if json {
+            println!("{}", result.to_json());
+        } else {
+            let status = if result.halted { "halted" } else { "stopped at the instruction limit" };
+            println!("== {} ({}, {} instructions)", result.name, status, result.instructions);
+            println!("{}", String::from_utf8_lossy(&result.output));
+        }
+    }
+    if results.iter().any(|result| !result.halted) {
+        process::exit(1);
+    }
+}
 
-        let mut timeout = timeval {
-            tv_sec: 0,
-            tv_usec: 0,
+/* run each NAME.obj in `dir` (sorted by name) on NAME.in and compare its output with
+   NAME.expected, HALT banners aside; exits 1 unless every test passes */
+#[cfg(feature = "batch")]
+fn run_test_command(dir: &str, max_instructions: u64, options: &Options) {
+    let entries = fs::read_dir(dir).unwrap_or_else(|err| fail(&format!("{}: {}", dir, err)));
+    let mut images: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "obj"))
+        .collect();
+    images.sort();
+    if images.is_empty() {
+        fail(&format!("{}: no .obj files", dir));
+    }
+    let read_sibling = |image: &Path, extension: &str| fs::read(image.with_extension(extension)).ok();
+    let jobs: Vec<BatchJob> = images
+        .iter()
+        .map(|path| BatchJob {
+            name: path.file_stem().unwrap_or_default().to_string_lossy().into_owned(),
+            program: Program::from_file(path).unwrap_or_else(|err| fail(&format!("{}: {}", path.display(), err))),
+            input: read_sibling(path, "in").unwrap_or_default(),
+            max_instructions,
+        })
+        .collect();
+    let results = run_batch(&jobs);
+    let painter = painter_for(&io::stdout(), options);
+    let mut failed = 0;
+    for (path, result) in images.iter().zip(&results) {
+        let expected = read_sibling(path, "expected").map(|bytes| String::from_utf8_lossy(&bytes).into_owned());
+        let printed = String::from_utf8_lossy(&result.output);
+        let actual = printed.strip_suffix("HALT").unwrap_or(&printed);
+        let expected = expected.as_deref().map(|text| text.strip_suffix("HALT").unwrap_or(text));
+        let failure = if !result.halted {
+            Some(format!("stopped after {} instructions without halting", result.instructions))
+        } else if expected.is_some_and(|expected| expected != actual) {
+            Some("output differs".to_string())
+        } else {
+            None
         };
+        failed += failure.is_some() as usize;
+        if options.json {
+            println!(
+                "{{\"kind\":\"test-result\",\"name\":{},\"passed\":{},\"reason\":{},\"instructions\":{}}}",
+                json::string(&result.name),
+                failure.is_none(),
+                failure.as_deref().map_or("null".to_string(), json::string),
+                result.instructions
+            );
+            continue;
+        }
+        match &failure {
+            None => println!("{} {}", painter.paint(Role::Expected, "PASS"), result.name),
+            Some(reason) => println!("{} {}: {}", painter.paint(Role::Error, "FAIL"), result.name, reason),
+        }
+        if let Some(expected) = expected
+            && expected != actual
+        {
+            print!("{}", diff::render(&diff::lines(expected, actual), &painter));
+        }
+    }
+    let passed = results.len() - failed;
+    if options.json {
+        println!("{{\"kind\":\"test-summary\",\"passed\":{},\"failed\":{}}}", passed, failed);
+    } else {
+        println!("\n{} passed, {} failed", passed, failed);
+    }
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+/* `WHAT@STEP`, e.g. `key=A@1000` or `timer@2500` */
+fn parse_injection(text: &str) -> Result<(u64, Injection), String> {
+    let invalid = || format!("invalid interrupt `{}` (expected key=C@STEP, timer@STEP or VECTOR[:PRIORITY]@STEP)", text);
+    let (what, step) = text.rsplit_once('@').ok_or_else(invalid)?;
+    let step = step.parse().map_err(|_| invalid())?;
+    let injection = Injection::parse(what).ok_or_else(invalid)?;
+    Ok((step, injection))
+}
 
-        select(
-            1,
-            &mut readfds,
-            std::ptr::null_mut(),
-            std::ptr::null_mut(),
-            &mut timeout,
-        ) > 0
+/* the report format follows the file extension (JSON for .json, text otherwise) unless --json is given */
+fn write_profile(path: &str, profiler: &Profiler, json: bool) {
+    let report = if json || path.ends_with(".json") {
+        profiler.report_json()
+    } else {
+        profiler.report_text()
+    };
+    if let Err(err) = fs::write(path, report) {
+        warn!(path, %err, "failed to write profile");
     }
 }
 
-pub fn get_char() -> u8 {
-    use std::io::Read;
-    let mut buf = [0u8; 1];
-    std::io::stdin().read_exact(&mut buf).unwrap();
-    buf[0]
+fn painter_for(stream: &impl IsTerminal, options: &Options) -> Painter {
+    let theme = match &options.theme_path {
+        Some(path) => fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| Theme::parse(&text))
+            .unwrap_or_else(|message| fail(&format!("invalid theme {}: {}", path, message))),
+        None => Theme::default(),
+    };
+    Painter::new(options.color.enabled_for(stream), theme)
 }
 
+/* print a diagnostic (colored if stderr is a terminal) and exit with status 2;
+   the terminal must not be in raw mode, since exiting skips its restore */
+fn fail(message: &str) -> ! {
+    let painter = Painter::new(ColorMode::Auto.enabled_for(&io::stderr()), Theme::default());
+    eprintln!("{} {}", painter.paint(Role::Error, "error:"), message);
+    process::exit(2);
+}
 
-fn read_from_memory(memory: &mut [u16], address: u16) -> u16 {
-    unsafe {
-        if address == MemoryMappedRegisters::KBSR as u16 {
-            if !KEY_READY && check_key() {
-                KEY_VALUE = get_char() as u16;
-                KEY_READY = true;
+/* returns false if the run diverged from the golden trace */
+fn run_traced(vm: &mut Vm, options: &Options, raw_mode: &RawMode) -> io::Result<bool> {
+    if let Some(path) = &options.record_trace_path {
+        let mut out = BufWriter::new(File::create(path)?);
+        trace::record(vm, &mut out)?;
+    } else if let Some(path) = &options.compare_trace_path {
+        let golden = BufReader::new(File::open(path)?);
+        match trace::compare(vm, golden)? {
+            Err(divergence) if options.json => {
+                println!("\n{}", divergence.to_json());
+                return Ok(false);
             }
-            return if KEY_READY { 1 << 15 } else { 0 };
+            Err(divergence) => {
+                raw_mode.suspend();
+                eprintln!("\n{}", divergence.render(&painter_for(&io::stderr(), options)));
+                return Ok(false);
+            }
+            Ok(steps) if options.json => println!("\n{{\"kind\":\"trace-match\",\"steps\":{}}}", steps),
+            Ok(_) => {}
         }
+    }
+    Ok(true)
+}
 
-        if address == MemoryMappedRegisters::KBDR as u16 {
-            KEY_READY = false; // clear latch
-            return KEY_VALUE;
+/* compare what the guest printed with the contents of `path`, ignoring the HALT
+   banner on either; report a mismatch and return whether they matched */
+fn check_output(vm: &Vm, expected: &str, path: &str, options: &Options) -> bool {
+    let printed = vm
+        .console
+        .downcast_ref::<TeeConsole<Vec<u8>>>()
+        .map(|console| String::from_utf8_lossy(&console.log).into_owned())
+        .unwrap_or_default();
+    let expected = expected.strip_suffix("HALT").unwrap_or(expected);
+    let actual = printed.strip_suffix("HALT").unwrap_or(&printed);
+    if expected == actual {
+        if options.json {
+            println!("\n{{\"kind\":\"output-match\"}}");
         }
+        return true;
     }
-
-    memory[address as usize]
+    if options.json {
+        println!(
+            "\n{{\"kind\":\"output-mismatch\",\"expected\":{},\"actual\":{}}}",
+            json::string(expected),
+            json::string(actual)
+        );
+    } else {
+        let painter = painter_for(&io::stderr(), options);
+        eprint!("\noutput differs from {}:\n{}", path, diff::render(&diff::lines(expected, actual), &painter));
+    }
+    false
 }
 
-fn run_program(memory: &mut [u16], registers: &mut [u16], tracing: &mut Vec<InstructionSet>) {
-    let mut running = true;
+/* run to HALT, describing every instruction's phases on stderr before it executes */
+fn run_microstepped(vm: &mut Vm) -> Result<(), VmError> {
+    while !vm.halted {
+        let pc = vm.registers[REGISTER::PC as usize];
+        eprint!("x{:04X}:\r\n{}", pc, microstep::render(&microstep::phases(vm)).replace('\n', "\r\n"));
+        if let StepOutcome::Faulted { pc, instruction } = vm.step() {
+            return Err(VmError::IllegalOpcode { pc, instruction });
+        }
+    }
+    Ok(())
+}
 
-    while running {
-        let pc = registers[REGISTER::PC as usize];
-        let instruction = read_from_memory(memory, pc);
-        registers[REGISTER::PC as usize] = pc.wrapping_add(1);
+/* run to HALT in chunks, redrawing the status line between them */
+fn run_with_status(vm: &mut Vm) -> Result<(), VmError> {
+    const CHUNK: u64 = 1 << 16;
+    let mut status = StatusLine::new();
+    let mut stderr = io::stderr();
+    let result = loop {
+        match vm.run_for(CHUNK) {
+            Ok(run) if run.state == RunState::Halted => break Ok(()),
+            Ok(_) => {
+                let _ = status.tick(vm, &mut stderr);
+            }
+            Err(err) => break Err(err),
+        }
+    };
+    let _ = status.finish(vm, &mut stderr);
+    result
+}
 
-        let op = instruction >> 12;
-        match op {
-            x if x == InstructionSet::ADD as u16 => {
-                let dest_reg = (instruction >> 9) & 0x7; // destination register
-                let operand_1_reg = (instruction >> 6) & 0x7;
-                let immediate_mode = if (instruction >> 5) & 0x1 == 1 { true } else { false };
-                if !immediate_mode {
-                    let operand_2_reg = instruction & 0x7;
-                    registers[dest_reg as usize] = registers[operand_1_reg as usize].wrapping_add(registers[operand_2_reg as usize]);
-                } else {
-                    let imm5 = instruction & 0x1F;
-                    let imm5_sext = sign_extend(imm5, 5);
-                    registers[dest_reg as usize] = registers[operand_1_reg as usize].wrapping_add(imm5_sext);
-                }
-                tracing.push(InstructionSet::ADD);
-                update_flags(dest_reg, registers);
-            }
-            x if x == InstructionSet::ST as u16 => {
-                let src_reg = (instruction >> 9) & 0x7;
-                let pc_offset = instruction & 0x1FF;
-                let pc_offset_sext = sign_extend(pc_offset, 9);
-                let address = registers[REGISTER::PC as usize].wrapping_add(pc_offset_sext);
-                let value = registers[src_reg as usize];
-                write_to_memory(memory, address, value);
-                tracing.push(InstructionSet::ST);
-            }
-            x if x == InstructionSet::JSR as u16 => {
-                registers[REGISTER::R7 as usize] = registers[REGISTER::PC as usize];
-                if ((instruction >> 11) & 0x1) == 0 {
-                    let base_reg = (instruction >> 6) & 0x7;
-                    registers[REGISTER::PC as usize] = registers[base_reg as usize]
-                } else {
-                    let pc_offset = instruction & 0x7FF;
-                    let pc_offset_sext = sign_extend(pc_offset, 11);
-                    registers[REGISTER::PC as usize] = registers[REGISTER::PC as usize].wrapping_add(pc_offset_sext);
-                }
-                tracing.push(InstructionSet::JSR);
-            }
-            x if x == InstructionSet::AND as u16 => {
-                let dest_reg = (instruction >> 9) & 0x7;
-                let operand_1_reg = (instruction >> 6) & 0x7;
-                if ((instruction >> 5) & 0x1) == 0 {
-                    let operand_2_reg = instruction & 0x7;
-                    registers[dest_reg as usize] = registers[operand_1_reg as usize] & registers[operand_2_reg as usize];
-                } else {
-                    let imm5 = instruction & 0x1F;
-                    let imm5_sext = sign_extend(imm5, 5);
-                    registers[dest_reg as usize] = registers[operand_1_reg as usize] & (imm5_sext);
-                }
-                tracing.push(InstructionSet::AND);
-                update_flags(dest_reg, registers);
-            }
-            x if x == InstructionSet::LDR as u16 => {
-                let dest_reg = (instruction >> 9) & 0x7;
-                let base_reg = (instruction >> 6) & 0x7;
-                let offset_6 = instruction & 0x3F;
-                let offset_6_sext = sign_extend(offset_6, 6);
-                let address = registers[base_reg as usize].wrapping_add(offset_6_sext);
-                registers[dest_reg as usize] = read_from_memory(memory, address);
-                tracing.push(InstructionSet::LDR);
-                update_flags(dest_reg, registers);
-            }
-            x if x == InstructionSet::LD as u16 => {
-                let dest_reg = (instruction >> 9) & 0x7;
-                let pc_offset = instruction & 0x1FF;
-                let pc_offset_sext = sign_extend(pc_offset, 9);
-                let address = registers[REGISTER::PC as usize].wrapping_add(pc_offset_sext);
-                registers[dest_reg as usize] = read_from_memory(memory, address);
-                tracing.push(InstructionSet::LD);
-                update_flags(dest_reg, registers);
-            }
-            x if x == InstructionSet::LDI as u16 => {
-                let dest_reg = (instruction >> 9) & 0x7;
-                let pc_offset = instruction & 0x1FF;
-                let pc_offset_sext = sign_extend(pc_offset, 9);
-                let address_1 = registers[REGISTER::PC as usize].wrapping_add(pc_offset_sext);
-                let address_2 = read_from_memory(memory, address_1);
-                registers[dest_reg as usize] = read_from_memory(memory, address_2);
-                tracing.push(InstructionSet::LDI);
-                update_flags(dest_reg, registers);
-            }
-            x if x == InstructionSet::STR as u16 => {
-                let src_reg = (instruction >> 9) & 0x7;
-                let base_reg = (instruction >> 6) & 0x7;
-                let offset_6 = instruction & 0x3F;
-                let offset_6_sext = sign_extend(offset_6, 6);
-                let address = registers[base_reg as usize].wrapping_add(offset_6_sext);
-                let value = registers[src_reg as usize];
-                write_to_memory(memory, address, value);
-                tracing.push(InstructionSet::STR);
-            }
-            x if x == InstructionSet::NOT as u16 => {
-                let dest_reg = (instruction >> 9) & 0x7;
-                let operand_reg = (instruction >> 6) & 0x7;
-                registers[dest_reg as usize] = !registers[operand_reg as usize];
-                tracing.push(InstructionSet::NOT);
-                update_flags(dest_reg, registers);
-            }
-            x if x == InstructionSet::STI as u16 => {
-                let src_reg = (instruction >> 9) & 0x7;
-                let pc_offset = instruction & 0x1FF;
-                let pc_offset_sext = sign_extend(pc_offset, 9);
-                let address_1 =  registers[REGISTER::PC as usize].wrapping_add(pc_offset_sext);
-                let address_2 = read_from_memory(memory, address_1);
-                let value = registers[src_reg as usize];
-                write_to_memory(memory, address_2, value);
-                tracing.push(InstructionSet::STI);
-            }
-            x if x == InstructionSet::JMP as u16 => {
-                let base_reg = (instruction >> 6) & 0x7;
-                registers[REGISTER::PC as usize] = registers[base_reg as usize];
-                tracing.push(InstructionSet::JMP);
-            }
-            x if x == InstructionSet::LEA as u16 => {
-                let dest_reg = (instruction >> 9) & 0x7;
-                let pc_offset = instruction & 0x1FF;
-                let pc_offset_sext = sign_extend(pc_offset, 9);
-                registers[dest_reg as usize] = registers[REGISTER::PC as usize].wrapping_add(pc_offset_sext);
-                tracing.push(InstructionSet::LEA);
-                update_flags(dest_reg, registers);
-            }
-            x if x == InstructionSet::BR as u16 => {
-                tracing.push(InstructionSet::BR);
-                let cond_flag = (instruction >> 9) & 0x7;
-                if (cond_flag & registers[REGISTER::COND as usize]) != 0 {
-                    let pc_offset = instruction & 0x1FF;
-                    let pc_offset_sext = sign_extend(pc_offset, 9);
-                    registers[REGISTER::PC as usize] = registers[REGISTER::PC as usize].wrapping_add(pc_offset_sext);
-                }
+#[cfg(feature = "debugger")]
+fn run(vm: &mut Vm, options: &Options, raw_mode: &RawMode) -> Result<(), VmError> {
+    vm.break_key = Some(lc3_vm::vm::DEFAULT_BREAK_KEY);
+    vm.monitor_key = Some(lc3_vm::vm::DEFAULT_MONITOR_KEY);
+    if let Some(address) = &options.gdb_address {
+        let served = TcpListener::bind(address).and_then(|listener| {
+            eprint!("waiting for GDB on {}\r\n", listener.local_addr()?);
+            let (stream, peer) = listener.accept()?;
+            eprint!("GDB connected from {}\r\n", peer);
+            lc3_vm::gdb::serve(vm, stream)
+        });
+        match served {
+            Ok(DebuggerAction::Continue) => {}
+            Ok(DebuggerAction::Quit) => return Ok(()),
+            Err(err) => {
+                raw_mode.suspend();
+                fail(&format!("gdb: {}", err));
             }
-            x if x == InstructionSet::TRAP as u16 => {
-                registers[REGISTER::R7 as usize] = registers[REGISTER::PC as usize];
-                let trap_code = instruction & 0xFF;
-                tracing.push(InstructionSet::TRAP);
-                match trap_code {
-                    x if x == TrapCodes::GETC as u16 => {
-                        while read_from_memory(memory, MemoryMappedRegisters::KBSR as u16) == 0 {}
-                        let input_char = read_from_memory(memory, MemoryMappedRegisters::KBDR as u16);
-                        registers[REGISTER::R0 as usize] = input_char;
-                        update_flags(REGISTER::R0 as u16, registers);
-                    }
-                    x if x == TrapCodes::HALT as u16 => {
-                        print!("HALT");
-                        io::stdout().flush().unwrap();
-                        running = false;
-                    }
-                    x if x == TrapCodes::IN as u16 => {
-                        print!("Enter a character: ");
-                        io::stdout().flush().unwrap();
+        }
+    }
+    #[cfg(feature = "dap")]
+    if let Some(address) = &options.dap_address {
+        let served = TcpListener::bind(address).and_then(|listener| {
+            eprint!("waiting for an editor on {}\r\n", listener.local_addr()?);
+            let (stream, peer) = listener.accept()?;
+            eprint!("editor connected from {}\r\n", peer);
+            let action = lc3_vm::dap::serve(vm, stream.try_clone()?, &stream);
+            let _ = stream.shutdown(Shutdown::Both);
+            action
+        });
+        match served {
+            Ok(DebuggerAction::Continue) => {}
+            Ok(DebuggerAction::Quit) => return Ok(()),
+            Err(err) => {
+                raw_mode.suspend();
+                fail(&format!("dap: {}", err));
+            }
+        }
+    }
+    #[cfg(feature = "scripting")]
+    if let Some(path) = &options.script_path {
+        let result = fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|source| lc3_vm::script::run(vm, &source, &mut io::stdout()).map_err(|err| err.to_string())?);
+        if let Err(err) = result {
+            raw_mode.suspend();
+            fail(&format!("{}: {}", path, err));
+        }
+    }
+    let mut debugger = Debugger::new();
+    debugger.painter = painter_for(&io::stdout(), options);
+    let mut state = if options.break_at_start { RunState::Paused } else { vm.run()? };
+    while state != RunState::Halted {
+        raw_mode.suspend();
+        if state == RunState::Paused && vm.take_monitor_request() {
+            Monitor::new().repl(vm, &mut io::stdin().lock(), &mut io::stdout())?;
+            raw_mode.resume();
+            state = vm.run()?;
+            continue;
+        }
+        let action = debugger.repl(vm, &mut io::stdin().lock(), &mut io::stdout())?;
+        raw_mode.resume();
+        if action == DebuggerAction::Quit {
+            break;
+        }
+        state = vm.run()?;
+    }
+    Ok(())
+}
 
-                        while read_from_memory(memory, MemoryMappedRegisters::KBSR as u16) == 0 {}
+#[cfg(not(feature = "debugger"))]
+fn run(vm: &mut Vm, _options: &Options, _raw_mode: &RawMode) -> Result<(), VmError> {
+    while vm.run()? != RunState::Halted {}
+    Ok(())
+}
 
-                        let input_char = read_from_memory(memory, MemoryMappedRegisters::KBDR as u16);
-                        registers[REGISTER::R0 as usize] = input_char;
+#[cfg(feature = "devices-extra")]
+fn attach_devices(vm: &mut Vm, options: &Options) -> Option<LinkedVm> {
+    if let Some(path) = &options.disk_path {
+        let disk = Disk::open(path).unwrap_or_else(|err| fail(&format!("disk {}: {}", path, err)));
+        plug(vm, Box::new(disk));
+    }
+    if let Some(root) = &options.fs_root {
+        vm.devices.fs = Some(FileService::new(root));
+    }
+    if options.dma {
+        plug(vm, Box::new(Dma::new()));
+    }
+    if options.gpio {
+        plug(vm, Box::new(Gpio::new()));
+    }
+    let mut host = HostEnvironment::new(options.guest_args.clone());
+    for name in &options.env_vars {
+        host.expose(name);
+    }
+    vm.devices.host = Some(host);
+    if let Some(target) = &options.console2 {
+        let console = AuxConsole::connect(target).unwrap_or_else(|err| fail(&format!("second console {}: {}", target, err)));
+        plug(vm, Box::new(console));
+    }
+    #[cfg(feature = "sound")]
+    if options.sound {
+        let beeper = Beeper::open().unwrap_or_else(|err| fail(&format!("audio output: {}", err)));
+        plug(vm, Box::new(beeper));
+    }
+    #[cfg(feature = "clipboard")]
+    if options.clipboard {
+        let bridge = ClipboardBridge::open().unwrap_or_else(|err| fail(&format!("clipboard: {}", err)));
+        plug(vm, Box::new(bridge));
+    }
+    options.link_path.as_ref().map(|path| {
+        let (port, peer_port) = SerialPort::pair();
+        plug(vm, Box::new(port));
+        LinkedVm::spawn(load_program(path, None), peer_port)
+    })
+}
 
-                        println!("{}", input_char as u8 as char);
-                        io::stdout().flush().unwrap();
+/* attach a device given on the command line; they sit at distinct addresses, so this only fails on a bug */
+#[cfg(feature = "devices-extra")]
+fn plug(vm: &mut Vm, device: Box<dyn Device>) {
+    vm.bus.attach(device).unwrap_or_else(|err| fail(&err));
+}
 
-                        update_flags(REGISTER::R0 as u16, registers);
-                    }
-                    x if x == TrapCodes::OUT as u16 => {
-                        let character: u8 = (registers[REGISTER::R0 as usize] & 0xFF).try_into().unwrap();
-                        print!("{}", character as char);
-                        io::stdout().flush().unwrap();
-                    }
-                    x if x == TrapCodes::PUTS as u16 => {
-                        let mut starting_addr = registers[REGISTER::R0 as usize];
-                        let mut word: String = String::new();
-                        while read_from_memory(memory, starting_addr) != 0 {
-                            let character: u8 = (memory[starting_addr as usize] & 0xFF).try_into().unwrap();
-                            word.push(character.try_into().unwrap());
-                            starting_addr += 1;
-                        }
-                        print!("{}", word);
-                        io::stdout().flush().unwrap();
-                    }
-                    x if x == TrapCodes::PUTSP as u16 => {
-                        let mut starting_addr = registers[REGISTER::R0 as usize];
-                        let mut word: String = String::new();
-                        while read_from_memory(memory, starting_addr) != 0 {
-                            let char_1: u8 = (memory[starting_addr as usize] & 0xFF).try_into().unwrap();
-                            let char_2: u8 = (memory[starting_addr as usize] >> 8).try_into().unwrap();
-                            word.push(char_1.try_into().unwrap());
-                            if char_2 != 0 {
-                                word.push(char_2.try_into().unwrap());
-                            }
-                            starting_addr += 1;
-                        }
-                        print!("{}", word);
-                        io::stdout().flush().unwrap();
-                    }
-                    _ => {
-                          
-                    }
-                }
+/* a second image on its own thread, connected to the first through the serial link */
+#[cfg(feature = "devices-extra")]
+struct LinkedVm {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+#[cfg(feature = "devices-extra")]
+impl LinkedVm {
+    /* the peer gets a console of its own, so it never reads the terminal's keys or writes over the main program */
+    fn spawn(program: Program, port: SerialPort) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::clone(&stop);
+        let thread = thread::spawn(move || {
+            let mut vm = Vm::new();
+            vm.console = Box::new(CapturedConsole::default());
+            vm.load_program(&program);
+            plug(&mut vm, Box::new(port));
+            while !vm.halted && !stopped.load(Ordering::Relaxed) {
+                vm.step();
             }
-            x if (x == InstructionSet::RES as u16) | (x == InstructionSet::RTI as u16) => {
-                panic!("Not implemented")
+        });
+        LinkedVm { stop, thread }
+    }
+
+    /* stop the peer once the main program is done with the link */
+    fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if self.thread.join().is_err() {
+            warn!("linked VM panicked");
+        }
+    }
+}
+
+#[cfg(feature = "assembler")]
+fn assemble_file(source_path: &str, output_path: &str) {
+    let source = fs::read_to_string(source_path).unwrap_or_else(|err| fail(&format!("{}: {}", source_path, err)));
+    let program = Program::from_assembly(&source).unwrap_or_else(|err| fail(&format!("{}: {}", source_path, err)));
+    let image = if output_path.ends_with(".hex") || output_path.ends_with(".ihex") {
+        program.to_ihex().into_bytes()
+    } else {
+        program.to_bytes()
+    };
+    if let Err(err) = fs::write(output_path, image) {
+        fail(&format!("{}: {}", output_path, err));
+    }
+    /* prog.sym beside prog.obj, as lc3as writes it */
+    let sym_path = Path::new(output_path).with_extension("sym");
+    if let Err(err) = fs::write(&sym_path, program.to_sym()) {
+        fail(&format!("{}: {}", sym_path.display(), err));
+    }
+}
+
+/* the labels of every --sym file */
+fn read_symbol_files(options: &Options) -> HashMap<String, u16> {
+    let mut labels = HashMap::new();
+    for path in &options.sym_paths {
+        let parsed = fs::read_to_string(path).map_err(VmError::from).and_then(|text| symbols::parse(&text));
+        labels.extend(parsed.unwrap_or_else(|err| fail(&format!("{}: {}", path, err))));
+    }
+    labels
+}
+
+fn looks_like_image(arg: &str) -> bool {
+    arg == "-" || [".obj", ".hex", ".ihex", ".bin"].iter().any(|extension| arg.ends_with(extension))
+}
+
+fn is_image_path(options: &Options, path: &str) -> bool {
+    options.file_path == path
+        || options.extra_image_paths.iter().any(|image| image == path)
+        || options.raw_images.iter().any(|(image, _)| image == path)
+}
+
+/* read every image of a run, object images first, refusing images that would overwrite each other */
+fn load_images(options: &Options) -> Vec<(String, Program)> {
+    let mut images: Vec<(String, Program)> = Vec::new();
+    let objects = std::iter::once(&options.file_path).filter(|path| !path.is_empty()).chain(&options.extra_image_paths);
+    let raws = options.raw_images.iter().map(|(path, address)| (path, Some(*address)));
+    for (path, raw_address) in objects.map(|path| (path, None)).chain(raws) {
+        let program = match raw_address {
+            Some(address) => fs::read(path)
+                .map_err(VmError::from)
+                .and_then(|bytes| Program::from_raw_bytes(address, &bytes))
+                .unwrap_or_else(|err| fail(&format!("{}: {}", path, err))),
+            None => load_program(path, options.format),
+        };
+        for (other_path, other) in &images {
+            if let Some(overlap) = other.overlap(&program) {
+                fail(&format!("{} and {} overlap at x{:04X}-x{:04X}", other_path, path, overlap.start(), overlap.end()));
             }
-            _ => {  }
+        }
+        images.push((path.clone(), program));
+    }
+    images
+}
 
+fn load_program(file_path: &str, format: Option<ImageFormat>) -> Program {
+    // "-" reads the image from stdin
+    let (name, bytes) = if file_path == "-" {
+        let mut bytes = Vec::new();
+        ("stdin", io::stdin().lock().read_to_end(&mut bytes).map(|_| bytes))
+    } else {
+        (file_path, fs::read(file_path))
+    };
+    let program = bytes.map_err(VmError::from).and_then(|bytes| {
+        let format = format.unwrap_or_else(|| ImageFormat::detect(file_path, &bytes));
+        if format == ImageFormat::Object {
+            return Program::from_bytes(&bytes);
         }
+        let text = String::from_utf8(bytes).map_err(|_| VmError::InvalidImage("text image is not UTF-8".to_string()))?;
+        if format == ImageFormat::IntelHex { Program::from_ihex(&text) } else { Program::from_hex_text(&text) }
+    });
+    let mut program = program.unwrap_or_else(|err| fail(&format!("{}: {}", name, err)));
+    /* labels come from prog.sym beside prog.obj, if the assembler left one */
+    let sym_path = Path::new(file_path).with_extension("sym");
+    if file_path != "-" && sym_path.is_file() {
+        program.load_symbols(&sym_path).unwrap_or_else(|err| fail(&format!("{}: {}", sym_path.display(), err)));
     }
+    program
+}
+
+/* diagnostics go to stderr, filtered by -q/-v/-vv or else by LC3_LOG (e.g.
+   `LC3_LOG=debug`); warnings by default */
+fn init_logging(verbosity: i8) {
+    let filter = match verbosity {
+        ..0 => EnvFilter::new("error"),
+        0 => EnvFilter::try_from_env("LC3_LOG").unwrap_or_else(|_| EnvFilter::new("warn")),
+        1 => EnvFilter::new("lc3_vm=debug,warn"),
+        _ => EnvFilter::new("lc3_vm=trace,warn"),
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .init();
 }
 
 fn main() {
-    disable_input_buffering();
-
-    // Get program from file in terminal
-    let args: Vec<String> = env::args().collect();
-    let file_path = &args[1];
-    // Process file and get instruction
-    let instructions = get_instructions(&file_path).unwrap();
-    // Load to memory and initialize register
-    let origin = instructions[0];
-    let mut memory = load_memory(instructions);
-    let mut registers = initialize_registers(origin);
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = parse_args(&args).unwrap_or_else(|message| fail(&format!("{}\n{}", message, USAGE)));
+    init_logging(options.verbosity);
+
+    match &options.command {
+        Command::Run => {}
+        Command::Help => {
+            println!("{}", USAGE);
+            return;
+        }
+        Command::Dump => {
+            let mut program = load_program(&options.file_path, options.format);
+            program.symbols.extend(read_symbol_files(&options));
+            if options.ihex {
+                print!("{}", program.to_ihex());
+            } else if options.json {
+                println!("{}", dump::render_json(&program));
+            } else {
+                print!("{}", dump::render(&program, &painter_for(&io::stdout(), &options)));
+            }
+            return;
+        }
+        Command::Disasm => {
+            let mut program = load_program(&options.file_path, options.format);
+            program.symbols.extend(read_symbol_files(&options));
+            print!("{}", dump::render_listing(&program, &painter_for(&io::stdout(), &options)));
+            return;
+        }
+        #[cfg(feature = "assembler")]
+        Command::Asm { output_path } => {
+            assemble_file(&options.file_path, output_path);
+            return;
+        }
+        #[cfg(feature = "batch")]
+        Command::Test { max_instructions } => {
+            run_test_command(&options.file_path, *max_instructions, &options);
+            return;
+        }
+        #[cfg(feature = "dap")]
+        Command::Dap => {
+            let mut vm = Vm::new();
+            vm.call_stack = Some(CallStack::new());
+            lc3_vm::dap::serve(&mut vm, io::stdin(), io::stdout()).unwrap_or_else(|err| fail(&format!("dap: {}", err)));
+            return;
+        }
+    }
+
+    #[cfg(feature = "batch")]
+    if let Some(batch) = &options.batch {
+        run_batch_command(batch, options.json);
+        return;
+    }
+
+    if let Err(err) = install_signal_handlers() {
+        warn!(%err, "failed to install signal handlers");
+    }
+    // Process files and get instructions
+    let images = load_images(&options);
+    // Load to memory and initialize register; the PC starts at the last object image's origin
+    let mut vm = Vm::new();
+    if let Some(word) = options.fill {
+        vm.memory.fill(word);
+    }
+    vm.echo_getc = options.echo_getc;
+    vm.on_illegal = options.on_illegal;
+    /* reverse-step and reverse-continue need a record of what ran */
+    #[cfg(feature = "debugger")]
+    match options.history.unwrap_or(if options.break_at_start { DEFAULT_CAPACITY } else { 0 }) {
+        0 => {}
+        capacity => vm.history = Some(History::new(capacity)),
+    }
+    #[cfg(feature = "debugger")]
+    if let Some(interval) = options.checkpoint_every {
+        vm.checkpoints = Some(Checkpoints::new(interval, checkpoints::DEFAULT_CAPACITY));
+    }
+    for (_, program) in &images {
+        vm.load_program(program);
+    }
+    vm.symbols.extend(&read_symbol_files(&options));
+    let object_count = images.len() - options.raw_images.len();
+    /* without any image there is only a core, which brings its own PC */
+    let origin = images.get(object_count.saturating_sub(1)).map(|(_, program)| program.origin);
+    let entry = match &options.entry {
+        Some(entry) => images
+            .iter()
+            .find(|(path, _)| path == entry)
+            .map(|(_, program)| program.origin)
+            .or_else(|| parse_address(entry)),
+        None => origin,
+    };
+    if let Some(entry) = entry {
+        vm.set_entry(entry);
+    }
+    if !options.guest_args.is_empty() {
+        vm.set_args(&options.guest_args).unwrap_or_else(|err| fail(&err.to_string()));
+    }
+    /* pipes and files get buffered I/O; only a terminal needs every key and character at once */
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        vm.console = Box::new(HeadlessConsole::default());
+    }
+    if let Some(path) = &options.stdin_file {
+        let script = fs::read(path).unwrap_or_else(|err| fail(&format!("{}: {}", path, err)));
+        vm.console = Box::new(ScriptedConsole::new(script, options.on_eof));
+    }
+    if options.record_path.is_some() {
+        vm.recording = Some(InputRecording::default());
+    }
+    if let Some(path) = &options.replay_path {
+        vm.replay = Some(InputRecording::load(path).unwrap_or_else(|err| fail(&format!("{}: {}", path, err))));
+    }
+    if let Some(path) = &options.stdout_file {
+        let log = File::create(path).unwrap_or_else(|err| fail(&format!("{}: {}", path, err)));
+        let inner = std::mem::replace(&mut vm.console, Box::new(TerminalConsole));
+        vm.console = Box::new(TeeConsole::new(inner, BufWriter::new(log), options.tee));
+    }
+    let expected_output = options.expect_output_path.as_ref().map(|path| {
+        let expected = fs::read(path).unwrap_or_else(|err| fail(&format!("{}: {}", path, err)));
+        let inner = std::mem::replace(&mut vm.console, Box::new(TerminalConsole));
+        vm.console = Box::new(TeeConsole::new(inner, Vec::new(), true));
+        String::from_utf8_lossy(&expected).into_owned()
+    });
+    #[cfg(feature = "devices-extra")]
+    let linked = attach_devices(&mut vm, &options);
+    if let Some(path) = &options.load_state_path {
+        vm.load_state(path).unwrap_or_else(|err| fail(&format!("{}: {}", path, err)));
+        /* a checkpoint taken at HALT carries on with the instruction after it */
+        vm.halted = false;
+    }
+    if options.timeline_path.is_some() {
+        vm.timeline = Some(Timeline::new(DEFAULT_SAMPLE_INTERVAL));
+    }
+    for &(step, injection) in &options.injections {
+        vm.schedule(step, injection);
+    }
+    if let Some(timeout) = options.watchdog {
+        let policy = if options.watchdog_abort { WatchdogPolicy::Abort } else { WatchdogPolicy::Continue };
+        vm.watchdog = Some(Watchdog::new(timeout, policy));
+    }
+    if options.profile_path.is_some() {
+        vm.profiler = Some(Profiler::new(vm.registers[REGISTER::PC as usize]));
+    }
+    if options.max_steps.is_some() || options.timeout.is_some() {
+        vm.limits = Some(Limits::new(options.max_steps, options.timeout));
+    }
+    /* a benchmark measures the interpreter alone */
+    match options.crash_context.unwrap_or(if options.bench { 0 } else { recent::DEFAULT_CAPACITY }) {
+        0 => {}
+        capacity => vm.recent = Some(RecentInstructions::new(capacity)),
+    }
+    if !options.bench {
+        vm.call_stack = Some(CallStack::new());
+    }
+    /* after the ring and the call stack are set up, so the core's replace them */
+    #[cfg(feature = "debugger")]
+    if let Some(path) = &options.load_core_path {
+        let core = CoreDump::load(path).unwrap_or_else(|err| fail(&format!("{}: {}", path, err)));
+        core.restore(&mut vm).unwrap_or_else(|err| fail(&format!("{}: {}", path, err)));
+        /* the illegal opcode that halted the run is looked at, not treated as a HALT */
+        vm.halted = false;
+        eprintln!("core from {}: {}", path, core.reason);
+    }
+    if let Some(hz) = options.hz {
+        vm.throttle = Some(Throttle::new(hz));
+    }
+    /* a benchmark measures the interpreter, not the terminal */
+    if options.bench {
+        let inner = std::mem::replace(&mut vm.console, Box::new(TerminalConsole));
+        vm.console = Box::new(TeeConsole::new(inner, io::sink(), false));
+    }
+    if let Some(path) = &options.trace_path {
+        vm.trace_output = Some(if path == "-" {
+            Box::new(io::stdout())
+        } else {
+            let file = File::create(path).unwrap_or_else(|err| fail(&format!("{}: {}", path, err)));
+            Box::new(BufWriter::new(file))
+        });
+    }
     // Run program
-    let mut tracing: Vec<InstructionSet> = Vec::new();
-    run_program(&mut memory, &mut registers, &mut tracing);
+    let raw_mode = RawMode::enable();
+    let mut matched_trace = true;
+    let started = (Instant::now(), vm.instruction_count);
+    #[cfg(feature = "tui")]
+    let tui = options.tui;
+    #[cfg(not(feature = "tui"))]
+    let tui = false;
+    let result = if tui {
+        #[cfg(feature = "tui")]
+        lc3_vm::tui::run(&mut vm, options.break_at_start).unwrap_or_else(|err| fail(&format!("tui: {}", err)));
+        Ok(())
+    } else if options.bench {
+        run(&mut vm, &options, &raw_mode)
+    } else if options.record_trace_path.is_some() || options.compare_trace_path.is_some() {
+        matched_trace = run_traced(&mut vm, &options, &raw_mode).unwrap_or_else(|err| {
+            raw_mode.suspend();
+            fail(&format!("trace: {}", err))
+        });
+        Ok(())
+    } else if options.microstep {
+        run_microstepped(&mut vm)
+    } else if options.status {
+        run_with_status(&mut vm)
+    } else {
+        run(&mut vm, &options, &raw_mode)
+    };
+    let elapsed = started.0.elapsed();
+    #[cfg(feature = "devices-extra")]
+    if let Some(linked) = linked {
+        linked.stop();
+    }
 
-    restore_input_buffering();
+    drop(raw_mode);
+
+    if let Some(output) = vm.trace_output.as_mut()
+        && let Err(err) = output.flush()
+    {
+        warn!(%err, "failed to write trace");
+    }
+    /* output that could not be written (e.g. piped to `head`, which has exited) ends
+       the run with that error alone: the guest did nothing wrong */
+    let output_failed = matches!(result, Err(VmError::Io(_)));
+    if let Err(err) = vm.console.flush()
+        && !output_failed
+    {
+        warn!(%err, "failed to write console output");
+    }
+
+    if let Some(path) = &options.save_state_path
+        && let Err(err) = vm.save_state(path)
+    {
+        fail(&format!("{}: {}", path, err));
+    }
+    if let (Some(path), Some(recording)) = (&options.record_path, &vm.recording)
+        && let Err(err) = recording.save(path)
+    {
+        fail(&format!("{}: {}", path, err));
+    }
+
+    if let (Some(path), Some(profiler)) = (&options.profile_path, &vm.profiler) {
+        write_profile(path, profiler, options.json);
+    }
+    if let (Some(path), Some(timeline)) = (&options.timeline_path, &vm.timeline) {
+        let contents = if path.ends_with(".html") { timeline.to_html() } else { timeline.to_json() };
+        if let Err(err) = fs::write(path, contents) {
+            warn!(path, %err, "failed to write timeline");
+        }
+    }
+
+    if options.bench {
+        let instructions = vm.instruction_count - started.1;
+        let mips = instructions as f64 / elapsed.as_secs_f64().max(f64::MIN_POSITIVE) / 1e6;
+        if options.json {
+            println!(
+                "\n{{\"kind\":\"bench\",\"instructions\":{},\"seconds\":{:.6},\"mips\":{:.3}}}",
+                instructions,
+                elapsed.as_secs_f64(),
+                mips
+            );
+        } else {
+            eprintln!("bench: {} instructions in {:.3?} ({:.2} MIPS)", instructions, elapsed, mips);
+        }
+    }
+
+    if !options.dump_ranges.is_empty() {
+        let dump = if options.json {
+            options.dump_ranges.iter().map(|range| summary::render_memory_json(&vm, range) + "\n").collect()
+        } else {
+            summary::render_memory(&vm, &options.dump_ranges)
+        };
+        match &options.dump_path {
+            Some(path) => {
+                if let Err(err) = fs::write(path, dump) {
+                    warn!(path, %err, "failed to write memory dump");
+                }
+            }
+            None => print!("\n{}", dump),
+        }
+    }
+
+    if options.json {
+        println!("\n{}", summary::render_json(&vm, &options.summary_ranges));
+    } else if options.summary {
+        println!();
+        print!("{}", summary::render(&vm, &options.summary_ranges));
+    }
+
+    if options.dump_registers {
+        eprint!("\n{}", summary::render_registers(&vm));
+    }
+
+    let output_matched = match (&expected_output, &options.expect_output_path) {
+        (Some(expected), Some(path)) => check_output(&vm, expected, path, &options),
+        _ => true,
+    };
+
+    let exceeded = vm.limits.as_ref().and_then(|limits| limits.exceeded);
+    if let Some(path) = &options.core_path {
+        let reason = match (&result, exceeded) {
+            (Err(VmError::IllegalOpcode { pc, instruction }), _) => Some(format!("illegal opcode 0x{:04X} at {}", instruction, vm.symbols.describe(*pc))),
+            (Err(err), _) => Some(err.to_string()),
+            (Ok(()), Some(exceeded)) => Some(format!("{} at {}", exceeded, vm.symbols.describe(vm.registers[REGISTER::PC as usize]))),
+            (Ok(()), None) => None,
+        };
+        if let Some(reason) = reason
+            && let Err(err) = CoreDump::capture(&vm, &reason).save(path)
+        {
+            warn!(path, %err, "failed to write core file");
+        }
+    }
+    if ((result.is_err() && !output_failed) || exceeded.is_some())
+        && let Some(recent) = vm.recent.as_ref().filter(|recent| !recent.is_empty())
+    {
+        eprint!("{}", recent.render(&vm.symbols));
+    }
+    if ((result.is_err() && !output_failed) || exceeded.is_some())
+        && let Some(call_stack) = vm.call_stack.as_ref().filter(|call_stack| call_stack.depth() > 0)
+    {
+        eprint!("backtrace:\n{}", call_stack.render(vm.registers[REGISTER::PC as usize], &vm.symbols));
+    }
+    if let Some(exceeded) = exceeded {
+        eprintln!("stopped: {} at {}", exceeded, vm.symbols.describe(vm.registers[REGISTER::PC as usize]));
+    }
+    let watchdog_aborted = vm.watchdog.as_ref().is_some_and(|watchdog| {
+        watchdog.policy == WatchdogPolicy::Abort && watchdog.fired > 0
+    });
+    match result {
+        Err(VmError::IllegalOpcode { pc, instruction }) => {
+            fail(&format!("illegal opcode 0x{:04X} at {}", instruction, vm.symbols.describe(pc)));
+        }
+        Err(err) => fail(&err.to_string()),
+        Ok(()) => {}
+    }
+    if !matched_trace || !output_matched || watchdog_aborted || exceeded.is_some() {
+        process::exit(1);
+    }
+    if options.exit_code_r0 && vm.halted {
+        process::exit((vm.registers[0] & 0xFF) as i32);
+    }
 }
@@ -0,0 +1,179 @@
+use std::fmt::Write;
+
+use crate::disasm::disassemble;
+use crate::vm::{REGISTER, Vm};
+
+/// One phase of the instruction cycle, with the datapath registers as they
+/// stand at its end. `None` means the phase leaves that register untouched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Phase {
+    pub name: &'static str,
+    pub mar: Option<u16>,
+    pub mdr: Option<u16>,
+    pub ir: Option<u16>,
+    /// Register transfers in textbook notation, e.g. `MAR <- PC, PC <- PC + 1`.
+    pub transfers: String,
+}
+
+fn phase(name: &'static str, mar: Option<u16>, mdr: Option<u16>, ir: Option<u16>, transfers: String) -> Phase {
+    Phase { name, mar, mdr, ir, transfers }
+}
+
+fn sext(value: u16, bits: u8) -> u16 {
+    let shift = 16 - bits;
+    (((value << shift) as i16) >> shift) as u16
+}
+
+/// Break the instruction at the current PC into fetch, decode, operand
+/// fetch, execute and writeback phases, showing MAR, MDR and IR as the
+/// LC-3 datapath would. Nothing is executed; memory is read without
+/// touching devices, so device registers show their stored values.
+pub fn phases(vm: &Vm) -> Vec<Phase> {
+    let reg = |r: u16| vm.registers[r as usize];
+    let mem = |address: u16| vm.memory[address as usize];
+    let pc = reg(REGISTER::PC as u16);
+    let ir = mem(pc);
+    let next_pc = pc.wrapping_add(1);
+    let dr = (ir >> 9) & 0x7;
+    let sr1 = (ir >> 6) & 0x7;
+    let sr2 = ir & 0x7;
+    let offset9 = next_pc.wrapping_add(sext(ir & 0x1FF, 9));
+    let offset6 = reg(sr1).wrapping_add(sext(ir & 0x3F, 6));
+
+    let mut out = vec![
+        phase(
+            "FETCH",
+            Some(pc),
+            Some(ir),
+            Some(ir),
+            format!("MAR <- PC (x{:04X}), PC <- PC + 1, MDR <- M[MAR], IR <- MDR", pc),
+        ),
+        phase("DECODE", None, None, None, format!("opcode {:04b}: {}", ir >> 12, disassemble(pc, ir))),
+    ];
+    let none = || phase("", None, None, None, String::new());
+    let (operands, execute, writeback) = match ir >> 12 {
+        0b0001 | 0b0101 => {
+            let add = ir >> 12 == 0b0001;
+            let (b, b_text) = if ir & 0x20 != 0 {
+                let imm = sext(ir & 0x1F, 5);
+                (imm, format!("#{}", imm as i16))
+            } else {
+                (reg(sr2), format!("R{} (x{:04X})", sr2, reg(sr2)))
+            };
+            let result = if add { reg(sr1).wrapping_add(b) } else { reg(sr1) & b };
+            (
+                phase("OPERAND FETCH", None, None, None, format!("A <- R{} (x{:04X}), B <- {}", sr1, reg(sr1), b_text)),
+                phase("EXECUTE", None, None, None, format!("ALU: A {} B = x{:04X}", if add { "+" } else { "AND" }, result)),
+                phase("WRITEBACK", None, None, None, format!("R{} <- x{:04X}, set CC", dr, result)),
+            )
+        }
+        0b1001 => (
+            phase("OPERAND FETCH", None, None, None, format!("A <- R{} (x{:04X})", sr1, reg(sr1))),
+            phase("EXECUTE", None, None, None, format!("ALU: NOT A = x{:04X}", !reg(sr1))),
+            phase("WRITEBACK", None, None, None, format!("R{} <- x{:04X}, set CC", dr, !reg(sr1))),
+        ),
+        0b0010 | 0b0110 | 0b1010 => {
+            let address = if ir >> 12 == 0b0110 { offset6 } else { offset9 };
+            let (mar, mdr, text) = if ir >> 12 == 0b1010 {
+                let pointer = mem(address);
+                (
+                    pointer,
+                    mem(pointer),
+                    format!(
+                        "MAR <- x{:04X}, MDR <- M[MAR] (x{:04X}), MAR <- MDR, MDR <- M[MAR]",
+                        address, pointer
+                    ),
+                )
+            } else {
+                (address, mem(address), format!("MAR <- x{:04X}, MDR <- M[MAR]", address))
+            };
+            (
+                phase("OPERAND FETCH", Some(mar), Some(mdr), None, text),
+                none(),
+                phase("WRITEBACK", None, None, None, format!("R{} <- MDR (x{:04X}), set CC", dr, mdr)),
+            )
+        }
+        0b0011 | 0b0111 | 0b1011 => {
+            let address = if ir >> 12 == 0b0111 { offset6 } else { offset9 };
+            let (mar, text) = if ir >> 12 == 0b1011 {
+                let pointer = mem(address);
+                (pointer, format!("MAR <- x{:04X}, MDR <- M[MAR], MAR <- MDR (x{:04X})", address, pointer))
+            } else {
+                (address, format!("MAR <- x{:04X}", address))
+            };
+            (
+                phase("OPERAND FETCH", Some(mar), Some(reg(dr)), None, format!("{}, MDR <- R{}", text, dr)),
+                none(),
+                phase("WRITEBACK", Some(mar), Some(reg(dr)), None, format!("M[x{:04X}] <- MDR (x{:04X})", mar, reg(dr))),
+            )
+        }
+        0b1110 => (
+            none(),
+            phase("EXECUTE", None, None, None, format!("address = PC + SEXT(offset9) = x{:04X}", offset9)),
+            phase("WRITEBACK", None, None, None, format!("R{} <- x{:04X}, set CC", dr, offset9)),
+        ),
+        0b0000 => {
+            let cc = reg(REGISTER::COND as u16);
+            let taken = dr & cc != 0;
+            (
+                none(),
+                phase(
+                    "EXECUTE",
+                    None,
+                    None,
+                    None,
+                    format!("nzp {:03b} AND CC {:03b}: {}", dr, cc, if taken { "taken" } else { "not taken" }),
+                ),
+                if taken {
+                    phase("WRITEBACK", None, None, None, format!("PC <- x{:04X}", offset9))
+                } else {
+                    none()
+                },
+            )
+        }
+        0b0100 | 0b1100 => {
+            let target = if ir >> 12 == 0b0100 && ir & 0x800 != 0 {
+                next_pc.wrapping_add(sext(ir & 0x7FF, 11))
+            } else {
+                reg(sr1)
+            };
+            let link = if ir >> 12 == 0b0100 { format!("R7 <- x{:04X}, ", next_pc) } else { String::new() };
+            (
+                none(),
+                phase("EXECUTE", None, None, None, format!("target = x{:04X}", target)),
+                phase("WRITEBACK", None, None, None, format!("{}PC <- x{:04X}", link, target)),
+            )
+        }
+        0b1111 => {
+            let vector = ir & 0xFF;
+            (
+                phase("OPERAND FETCH", Some(vector), Some(mem(vector)), None, format!("MAR <- ZEXT(x{:02X}), MDR <- M[MAR]", vector)),
+                phase("EXECUTE", None, None, None, "service routine (run by the host)".to_string()),
+                phase("WRITEBACK", None, None, None, format!("R7 <- x{:04X}", next_pc)),
+            )
+        }
+        _ => (none(), phase("EXECUTE", None, None, None, "privileged or reserved opcode".to_string()), none()),
+    };
+    out.extend([operands, execute, writeback].into_iter().filter(|phase| !phase.name.is_empty()));
+    out
+}
+
+/// Lay the phases out as a table, one row per phase.
+pub fn render(phases: &[Phase]) -> String {
+    let show = |value: Option<u16>| value.map_or("    ".to_string(), |value| format!("{:04X}", value));
+    let mut out = String::new();
+    writeln!(out, "  {:<14} {:>5} {:>5} {:>5}  transfers", "phase", "MAR", "MDR", "IR").unwrap();
+    for phase in phases {
+        writeln!(
+            out,
+            "  {:<14} {:>5} {:>5} {:>5}  {}",
+            phase.name,
+            show(phase.mar),
+            show(phase.mdr),
+            show(phase.ir),
+            phase.transfers
+        )
+        .unwrap();
+    }
+    out
+}
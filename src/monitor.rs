@@ -0,0 +1,170 @@
+//! A machine monitor in the old style: a prompt for looking at and
+//! changing memory and registers by hand while the program is stopped.
+//! It opens from the debugger (`monitor`) or with its own host key,
+//! `Ctrl+^` by default, while a program runs.
+
+use std::io::{self, BufRead, Write};
+use std::ops::RangeInclusive;
+
+use crate::debugger::{parse_register, parse_value};
+use crate::summary;
+use crate::symbols::SymbolTable;
+use crate::vm::{REGISTER, Vm};
+
+/* words shown by `peek` without a count */
+const PEEK_WORDS: u16 = 16;
+
+/* matches listed by `find` before it gives up */
+const FIND_LIMIT: usize = 32;
+
+const HELP: &str = "\
+monitor commands:
+  peek [ADDR] [N]        show N words from ADDR (default 16, from where the last peek ended)
+  poke ADDR VAL [VAL...] store the values at ADDR and the words after it
+  fill START..END VAL    store VAL in every word of a range
+  find PATTERN [START..END]
+                         list where the words (e.g. `x5020 x1021`) or the string (`\"HELLO\"`,
+                         one character a word) occur
+  setreg REG VAL         set R0-R7, PC or PSR
+  regs                   show registers
+  x, exit                leave the monitor
+  help                   show this help
+an ADDR or VAL may be a number (x3000, 0x3000, 12288, -1) or a label, e.g. LOOP or DATA+2
+";
+
+/// The monitor prompt, operating on a stopped [`Vm`].
+#[derive(Debug, Default)]
+pub struct Monitor {
+    /* where a `peek` without an address goes on from */
+    next_peek: Option<u16>,
+}
+
+impl Monitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read and execute commands until `exit` or the end of input.
+    pub fn repl(&mut self, vm: &mut Vm, input: &mut impl BufRead, output: &mut impl Write) -> io::Result<()> {
+        writeln!(output, "\n[monitor at 0x{:04X}; `help` lists commands, `exit` leaves]", vm.registers[REGISTER::PC as usize])?;
+        loop {
+            write!(output, "(mon) ")?;
+            output.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 || !self.execute(vm, &line, output)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Execute one command line, e.g. `poke x4000 5`. Returns false for
+    /// `exit`.
+    pub fn execute(&mut self, vm: &mut Vm, line: &str, output: &mut impl Write) -> io::Result<bool> {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return Ok(true);
+        };
+        match command {
+            "x" | "exit" | "q" | "quit" => return Ok(false),
+            "peek" => {
+                let start = match words.next() {
+                    None => self.next_peek.unwrap_or(vm.registers[REGISTER::PC as usize]),
+                    Some(text) => match vm.symbols.resolve(text) {
+                        Some(address) => address,
+                        None => {
+                            writeln!(output, "peek expects an address or label")?;
+                            return Ok(true);
+                        }
+                    },
+                };
+                let Some(count) = words.next().map_or(Some(PEEK_WORDS), |count| count.parse::<u16>().ok().filter(|count| *count > 0)) else {
+                    writeln!(output, "peek expects a word count")?;
+                    return Ok(true);
+                };
+                let end = start.saturating_add(count - 1);
+                write!(output, "{}", summary::render_memory(vm, &[start..=end]))?;
+                self.next_peek = end.checked_add(1);
+            }
+            "poke" => {
+                let address = words.next().and_then(|address| vm.symbols.resolve(address));
+                let values: Option<Vec<u16>> = words.map(|value| parse_value(&vm.symbols, value)).collect();
+                match (address, values) {
+                    (Some(address), Some(values)) if !values.is_empty() => {
+                        for (offset, value) in values.iter().enumerate() {
+                            vm.memory[address.wrapping_add(offset as u16) as usize] = *value;
+                        }
+                    }
+                    _ => writeln!(output, "poke expects an address and one or more values")?,
+                }
+            }
+            "fill" => match (words.next().and_then(|range| vm.symbols.resolve_range(range)), words.next().and_then(|value| parse_value(&vm.symbols, value))) {
+                (Some(range), Some(value)) => {
+                    for address in range {
+                        vm.memory[address as usize] = value;
+                    }
+                }
+                _ => writeln!(output, "fill expects a range START..END and a value")?,
+            },
+            "find" => {
+                let rest = line.trim_start().strip_prefix("find").unwrap_or_default().trim();
+                let Some((pattern, range)) = parse_pattern(&vm.symbols, rest) else {
+                    writeln!(output, "find expects words or a \"string\", then optionally START..END")?;
+                    return Ok(true);
+                };
+                let mut found = 0;
+                for start in range {
+                    let matches = pattern.iter().enumerate().all(|(offset, word)| {
+                        let address = start as usize + offset;
+                        address < vm.memory.len() && vm.memory[address] == *word
+                    });
+                    if matches {
+                        writeln!(output, "  {}", vm.symbols.describe(start))?;
+                        found += 1;
+                        if found == FIND_LIMIT {
+                            writeln!(output, "  (stopped after {} matches)", FIND_LIMIT)?;
+                            break;
+                        }
+                    }
+                }
+                if found == 0 {
+                    writeln!(output, "not found")?;
+                }
+            }
+            "setreg" => {
+                let register = words.next();
+                let value = words.next().and_then(|value| parse_value(&vm.symbols, value));
+                match (register, value) {
+                    (Some(name), Some(value)) if name.eq_ignore_ascii_case("pc") => vm.registers[REGISTER::PC as usize] = value,
+                    (Some(name), Some(value)) if name.eq_ignore_ascii_case("psr") => vm.set_psr(value),
+                    (Some(name), Some(value)) if parse_register(name).is_some() => {
+                        vm.registers[parse_register(name).unwrap() as usize] = value;
+                    }
+                    _ => writeln!(output, "setreg expects R0-R7, PC or PSR and a value")?,
+                }
+            }
+            "regs" => write!(output, "{}", summary::render(vm, &[]))?,
+            "help" => write!(output, "{}", HELP)?,
+            other => writeln!(output, "unknown monitor command `{}` (try `help`)", other)?,
+        }
+        Ok(true)
+    }
+}
+
+/* `"TEXT" [RANGE]` or `WORD... [RANGE]`: the words to look for, and where (all of memory by default) */
+fn parse_pattern(symbols: &SymbolTable, text: &str) -> Option<(Vec<u16>, RangeInclusive<u16>)> {
+    let (pattern, range) = if let Some(quoted) = text.strip_prefix('"') {
+        let (string, rest) = quoted.split_once('"')?;
+        (string.chars().map(|c| c as u16).collect(), rest.trim())
+    } else {
+        let (values, range) = match text.rsplit_once(char::is_whitespace) {
+            Some((values, range)) if range.contains("..") => (values, range),
+            _ => (text, ""),
+        };
+        (values.split_whitespace().map(|value| parse_value(symbols, value)).collect::<Option<Vec<u16>>>()?, range)
+    };
+    if pattern.is_empty() {
+        return None;
+    }
+    let range = if range.is_empty() { 0..=u16::MAX } else { symbols.resolve_range(range)? };
+    Some((pattern, range))
+}
@@ -0,0 +1,25 @@
+//! Instrumentation for tools built on the VM — profilers, coverage,
+//! teaching visualizers. An [`Observer`] added with
+//! [`Vm::add_observer`](crate::vm::Vm::add_observer) sees every instruction
+//! before it executes and every memory access the guest makes.
+
+use std::any::Any;
+
+use crate::instruction::Instruction;
+
+/// Callbacks invoked as the guest runs; every method defaults to doing nothing.
+///
+/// Observers only watch: they cannot change what the VM does. To veto or
+/// rewrite device accesses use [`Vm::set_mmio_hook`](crate::vm::Vm::set_mmio_hook).
+pub trait Observer: Any {
+    /// The instruction at `pc` has been fetched (`word`, decoded as
+    /// `instruction`) and is about to execute.
+    fn on_instruction(&mut self, _pc: u16, _word: u16, _instruction: Instruction) {}
+
+    /// The guest loaded `value` from `address`. Instruction fetches are
+    /// reported through [`Observer::on_instruction`] instead.
+    fn on_mem_read(&mut self, _address: u16, _value: u16) {}
+
+    /// The guest stored `value` at `address`.
+    fn on_mem_write(&mut self, _address: u16, _value: u16) {}
+}
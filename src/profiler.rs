@@ -0,0 +1,104 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+const RET: u16 = 0xC1C0; /* JMP R7 */
+
+/// Execution counts for one subroutine, keyed by its entry address.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SubroutineStats {
+    pub calls: u64,
+    /// Instructions executed in the subroutine's own body.
+    pub self_instructions: u64,
+    /// Instructions executed in the body and everything it called.
+    pub total_instructions: u64,
+}
+
+/// Counts how often each address executes and attributes instructions to
+/// subroutines by following JSR/JSRR and RET.
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    pub address_counts: BTreeMap<u16, u64>,
+    pub subroutines: BTreeMap<u16, SubroutineStats>,
+    /* entry addresses of the active calls, outermost first */
+    call_stack: Vec<u16>,
+}
+
+impl Profiler {
+    pub fn new(entry: u16) -> Self {
+        let mut profiler = Profiler::default();
+        profiler.call_stack.push(entry);
+        profiler.subroutines.entry(entry).or_default().calls = 1;
+        profiler
+    }
+
+    /// Record one executed instruction; `next_pc` is the PC after it ran.
+    pub fn record(&mut self, pc: u16, instruction: u16, next_pc: u16) {
+        *self.address_counts.entry(pc).or_default() += 1;
+
+        if let Some(&current) = self.call_stack.last() {
+            self.subroutines.entry(current).or_default().self_instructions += 1;
+        }
+        /* every active frame sees the instruction in its inclusive count */
+        let mut seen = Vec::with_capacity(self.call_stack.len());
+        for &entry in &self.call_stack {
+            if !seen.contains(&entry) {
+                seen.push(entry);
+                self.subroutines.entry(entry).or_default().total_instructions += 1;
+            }
+        }
+
+        if instruction >> 12 == 0b0100 {
+            self.call_stack.push(next_pc);
+            self.subroutines.entry(next_pc).or_default().calls += 1;
+        } else if instruction == RET && self.call_stack.len() > 1 {
+            self.call_stack.pop();
+        }
+    }
+
+    pub fn report_text(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "Subroutines (by total instructions):").unwrap();
+        writeln!(out, "  {:>6}  {:>8}  {:>12}  {:>12}", "entry", "calls", "self", "total").unwrap();
+        let mut subroutines: Vec<_> = self.subroutines.iter().collect();
+        subroutines.sort_by(|a, b| b.1.total_instructions.cmp(&a.1.total_instructions).then(a.0.cmp(b.0)));
+        for (entry, stats) in subroutines {
+            writeln!(
+                out,
+                "  0x{:04X}  {:>8}  {:>12}  {:>12}",
+                entry, stats.calls, stats.self_instructions, stats.total_instructions
+            )
+            .unwrap();
+        }
+
+        writeln!(out, "\nAddresses (by execution count):").unwrap();
+        let mut addresses: Vec<_> = self.address_counts.iter().collect();
+        addresses.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (address, count) in addresses {
+            writeln!(out, "  0x{:04X}  {:>12}", address, count).unwrap();
+        }
+        out
+    }
+
+    pub fn report_json(&self) -> String {
+        let subroutines: Vec<String> = self
+            .subroutines
+            .iter()
+            .map(|(entry, stats)| {
+                format!(
+                    "{{\"entry\":{},\"calls\":{},\"self\":{},\"total\":{}}}",
+                    entry, stats.calls, stats.self_instructions, stats.total_instructions
+                )
+            })
+            .collect();
+        let addresses: Vec<String> = self
+            .address_counts
+            .iter()
+            .map(|(address, count)| format!("{{\"address\":{},\"count\":{}}}", address, count))
+            .collect();
+        format!(
+            "{{\"kind\":\"profile\",\"subroutines\":[{}],\"addresses\":[{}]}}\n",
+            subroutines.join(","),
+            addresses.join(",")
+        )
+    }
+}
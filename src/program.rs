@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use tracing::debug;
+
+#[cfg(feature = "assembler")]
+use crate::asm::{self, AsmError};
+use crate::error::VmError;
+use crate::hex_text;
+use crate::ihex;
+use crate::symbols;
+
+/// A loadable LC-3 image: the words to place in memory, the address they
+/// start at, and any labels known for them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Program {
+    pub origin: u16,
+    pub words: Vec<u16>,
+    pub symbols: HashMap<String, u16>,
+}
+
+impl Program {
+    pub fn from_words(origin: u16, words: &[u16]) -> Self {
+        Program {
+            origin,
+            words: words.to_vec(),
+            symbols: HashMap::new(),
+        }
+    }
+
+    #[cfg(feature = "assembler")]
+    pub fn from_assembly(source: &str) -> Result<Self, AsmError> {
+        asm::assemble(source)
+    }
+
+    /// Read an object file: a big-endian origin word followed by the image.
+    pub fn from_file(file_path: impl AsRef<Path>) -> Result<Self, VmError> {
+        Program::from_reader(File::open(file_path)?)
+    }
+
+    /// Read an object image from any byte source, e.g. stdin or a socket.
+    pub fn from_reader(mut reader: impl Read) -> Result<Self, VmError> {
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf)?;
+        Program::from_bytes(&buf)
+    }
+
+    /// Parse an object image held in memory, e.g. one embedded with `include_bytes!`.
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, VmError> {
+        debug!(bytes = buf.len(), "parsing object image");
+        if buf.len() < 2 {
+            return Err(VmError::InvalidImage("missing the origin word".to_string()));
+        }
+        // Must be an even number of bytes
+        if !buf.len().is_multiple_of(2) {
+            return Err(VmError::InvalidImage(format!("odd length ({} bytes)", buf.len())));
+        }
+
+        let mut words = Vec::new();
+        for chunk in buf.chunks_exact(2) {
+            let word = u16::from_be_bytes([chunk[0], chunk[1]]);
+            words.push(word);
+        }
+        Ok(Program::from_words(words[0], &words[1..]))
+    }
+
+    /// The addresses both images occupy, if they overlap, e.g. to reject
+    /// an OS image and a user program that would overwrite each other. An
+    /// image running past xFFFF wraps around to x0000, as
+    /// [`Vm::load_program`](crate::vm::Vm::load_program) places it; where
+    /// the images meet at both ends of memory, the lower range is returned.
+    pub fn overlap(&self, other: &Program) -> Option<RangeInclusive<u16>> {
+        let (spans, other_spans) = (self.spans(), other.spans());
+        spans
+            .iter()
+            .flat_map(|&(start, end)| other_spans.iter().map(move |&(other_start, other_end)| (start.max(other_start), end.min(other_end))))
+            .filter(|(first, last)| first < last)
+            .min()
+            .map(|(first, last)| first as u16..=(last - 1) as u16)
+    }
+
+    /* the half-open address ranges the image occupies: two when it wraps past xFFFF */
+    fn spans(&self) -> Vec<(usize, usize)> {
+        let start = self.origin as usize;
+        let end = start + self.words.len().min(1 << 16);
+        if end <= 1 << 16 { vec![(start, end)] } else { vec![(start, 1 << 16), (0, end - (1 << 16))] }
+    }
+
+    /// Parse a headerless stream of big-endian words, e.g. a data blob or a
+    /// hand-encoded program, to be placed at `origin`.
+    pub fn from_raw_bytes(origin: u16, buf: &[u8]) -> Result<Self, VmError> {
+        if !buf.len().is_multiple_of(2) {
+            return Err(VmError::InvalidImage(format!("odd length ({} bytes)", buf.len())));
+        }
+        let words: Vec<u16> = buf.chunks_exact(2).map(|chunk| u16::from_be_bytes([chunk[0], chunk[1]])).collect();
+        Ok(Program::from_words(origin, &words))
+    }
+
+    /// Parse a text image of one hex or binary word per line; see [`hex_text::parse`].
+    pub fn from_hex_text(text: &str) -> Result<Self, VmError> {
+        hex_text::parse(text)
+    }
+
+    /// Parse an Intel HEX image; see [`ihex::parse`].
+    pub fn from_ihex(text: &str) -> Result<Self, VmError> {
+        ihex::parse(text)
+    }
+
+    /// Encode as Intel HEX; see [`ihex::write`].
+    pub fn to_ihex(&self) -> String {
+        ihex::write(self)
+    }
+
+    /// Encode as an object image, the inverse of [`Program::from_bytes`].
+    /// Symbols are not part of the format; see [`Program::to_sym`].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        std::iter::once(self.origin).chain(self.words.iter().copied()).flat_map(u16::to_be_bytes).collect()
+    }
+
+    /// Add the labels of a `.sym` symbol table file; see [`symbols::parse`].
+    pub fn load_symbols(&mut self, path: impl AsRef<Path>) -> Result<(), VmError> {
+        let text = std::fs::read_to_string(path)?;
+        self.symbols.extend(symbols::parse(&text)?);
+        Ok(())
+    }
+
+    /// The labels as a `.sym` symbol table file.
+    pub fn to_sym(&self) -> String {
+        symbols::render(&self.symbols)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(origin: u16, len: usize) -> Program {
+        Program::from_words(origin, &vec![0; len])
+    }
+
+    #[test]
+    fn overlap_is_the_shared_range() {
+        assert_eq!(image(0x3000, 0x10).overlap(&image(0x3008, 0x10)), Some(0x3008..=0x300F));
+        assert_eq!(image(0x3008, 0x10).overlap(&image(0x3000, 0x10)), Some(0x3008..=0x300F));
+        assert_eq!(image(0x3000, 0x100).overlap(&image(0x3010, 1)), Some(0x3010..=0x3010));
+    }
+
+    #[test]
+    fn adjacent_images_do_not_overlap() {
+        assert_eq!(image(0x3000, 0x10).overlap(&image(0x3010, 0x10)), None);
+        assert_eq!(image(0x3000, 0).overlap(&image(0x3000, 0x10)), None);
+    }
+
+    #[test]
+    fn an_image_past_xffff_wraps_into_low_memory() {
+        assert_eq!(image(0xFFF0, 0x20).overlap(&image(0x0008, 0x10)), Some(0x0008..=0x000F));
+        assert_eq!(image(0x0000, 0x10).overlap(&image(0xFFFF, 2)), Some(0x0000..=0x0000));
+        assert_eq!(image(0xFFF0, 0x20).overlap(&image(0x0010, 0x10)), None);
+        /* meeting at both ends, the lower range is reported */
+        assert_eq!(image(0xFFF0, 0x20).overlap(&image(0x0000, 0x10000)), Some(0x0000..=0x000F));
+    }
+}
@@ -0,0 +1,161 @@
+//! Python extension module, built with the `python` feature by maturin
+//! (`maturin develop --features python`, see `pyproject.toml`) and imported
+//! as `lc3_vm`. The module holds one class, `lc3_vm.Vm`, for autograders
+//! and notebooks: load an image, queue input, run, then inspect memory,
+//! registers and the captured output. The guest's console is a
+//! [`QueuedConsole`], so a GETC or IN with no queued input stops the run
+//! instead of waiting on the notebook's stdin.
+
+use pyo3::exceptions::{PyIndexError, PyValueError};
+use pyo3::prelude::*;
+
+use crate::console::QueuedConsole;
+use crate::error::VmError;
+#[cfg(feature = "assembler")]
+use crate::program::Program;
+use crate::vm::{REGISTER, StepOutcome, Vm};
+
+fn to_py(err: VmError) -> PyErr {
+    match err {
+        VmError::Io(err) => err.into(),
+        err => PyValueError::new_err(err.to_string()),
+    }
+}
+
+/// An LC-3 machine with captured console I/O.
+#[pyclass(name = "Vm", unsendable)]
+pub struct PyVm {
+    vm: Vm,
+}
+
+impl Default for PyVm {
+    fn default() -> Self {
+        let mut vm = Vm::new();
+        vm.console = Box::new(QueuedConsole::default());
+        PyVm { vm }
+    }
+}
+
+impl PyVm {
+    fn console(&mut self) -> &mut QueuedConsole {
+        self.vm.console.downcast_mut().expect("Python VMs keep their queued console")
+    }
+}
+
+#[pymethods]
+impl PyVm {
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load an object image (origin word followed by the program) from bytes.
+    fn load(&mut self, image: &[u8]) -> PyResult<()> {
+        self.vm.load_from_bytes(image).map_err(to_py)
+    }
+
+    /// Load an object file from disk.
+    fn load_file(&mut self, path: &str) -> PyResult<()> {
+        self.vm.load_image(path).map_err(to_py)
+    }
+
+    /// Assemble LC-3 source and load the result.
+    #[cfg(feature = "assembler")]
+    fn load_assembly(&mut self, source: &str) -> PyResult<()> {
+        let program = Program::from_assembly(source).map_err(|err| PyValueError::new_err(err.to_string()))?;
+        self.vm.load_program(&program);
+        Ok(())
+    }
+
+    /// Execute one instruction and name what happened: "continued",
+    /// "trapped", "halted", "faulted" or "waiting_for_input".
+    fn step(&mut self) -> &'static str {
+        match self.vm.step() {
+            StepOutcome::Continued => "continued",
+            StepOutcome::Trapped(_) => "trapped",
+            StepOutcome::Halted => "halted",
+            StepOutcome::Faulted { .. } => "faulted",
+            StepOutcome::WaitingForInput => "waiting_for_input",
+        }
+    }
+
+    /// Execute up to `budget` instructions, stopping early at HALT, a fault
+    /// or a GETC/IN with no queued input. Returns the number executed.
+    #[pyo3(signature = (budget = 1_000_000))]
+    fn run(&mut self, budget: u64) -> u64 {
+        let start = self.vm.instruction_count;
+        for _ in 0..budget {
+            if !matches!(self.vm.step(), StepOutcome::Continued | StepOutcome::Trapped(_)) {
+                break;
+            }
+        }
+        self.vm.instruction_count - start
+    }
+
+    /// Start the loaded image over. Queued input and captured output are kept.
+    fn reset(&mut self) {
+        self.vm.reset();
+    }
+
+    /// Queue keys for the keyboard, taken one per GETC/IN.
+    fn push_input(&mut self, text: &str) {
+        self.console().input.extend(text.bytes());
+    }
+
+    /// Everything the program printed since the last call.
+    fn take_output(&mut self) -> String {
+        let output = std::mem::take(&mut self.console().output);
+        String::from_utf8_lossy(&output).into_owned()
+    }
+
+    fn read_memory(&self, address: u16) -> u16 {
+        self.vm.memory[address as usize]
+    }
+
+    fn write_memory(&mut self, address: u16, value: u16) {
+        self.vm.memory[address as usize] = value;
+    }
+
+    /// R0-R7 by number.
+    fn register(&self, index: usize) -> PyResult<u16> {
+        match index {
+            0..=7 => Ok(self.vm.registers[index]),
+            _ => Err(PyIndexError::new_err("registers are R0-R7")),
+        }
+    }
+
+    fn set_register(&mut self, index: usize, value: u16) -> PyResult<()> {
+        match index {
+            0..=7 => {
+                self.vm.registers[index] = value;
+                Ok(())
+            }
+            _ => Err(PyIndexError::new_err("registers are R0-R7")),
+        }
+    }
+
+    #[getter]
+    fn pc(&self) -> u16 {
+        self.vm.registers[REGISTER::PC as usize]
+    }
+
+    #[setter]
+    fn set_pc(&mut self, pc: u16) {
+        self.vm.registers[REGISTER::PC as usize] = pc;
+    }
+
+    #[getter]
+    fn halted(&self) -> bool {
+        self.vm.halted
+    }
+
+    #[getter]
+    fn instruction_count(&self) -> u64 {
+        self.vm.instruction_count
+    }
+}
+
+#[pymodule]
+fn lc3_vm(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyVm>()
+}
@@ -0,0 +1,121 @@
+//! The last few instructions executed, with the registers each changed,
+//! kept so that when a run ends in an error, an illegal opcode or a limit
+//! the lead-up can be shown without running it again under `--trace`.
+
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+
+use crate::disasm::disassemble_with;
+use crate::symbols::SymbolTable;
+use crate::vm::REGISTER;
+
+/// Instructions `lc3-vm run` keeps unless `--crash-context` says otherwise.
+pub const DEFAULT_CAPACITY: usize = 16;
+
+/* registers named in the effects column, in REGISTER order up to COND */
+const NAMES: [&str; 10] = ["R0", "R1", "R2", "R3", "R4", "R5", "R6", "R7", "PC", "COND"];
+
+/* condition codes as letters, e.g. `Z` */
+fn condition(flags: u16) -> String {
+    [(4, 'N'), (2, 'Z'), (1, 'P')].iter().filter(|(bit, _)| flags & bit != 0).map(|(_, letter)| *letter).collect()
+}
+
+/// One executed instruction and the registers around it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Executed {
+    /// Instructions executed before this one.
+    pub count: u64,
+    pub pc: u16,
+    pub instruction: u16,
+    pub before: [u16; REGISTER::COUNT as usize],
+    pub after: [u16; REGISTER::COUNT as usize],
+}
+
+/// A ring of the last [`RecentInstructions::capacity`] instructions, kept in
+/// [`Vm::recent`](crate::vm::Vm::recent).
+#[derive(Debug, Clone)]
+pub struct RecentInstructions {
+    capacity: usize,
+    ring: VecDeque<Executed>,
+}
+
+impl RecentInstructions {
+    pub fn new(capacity: usize) -> Self {
+        RecentInstructions { capacity: capacity.max(1), ring: VecDeque::new() }
+    }
+
+    /* a ring holding `executed`, oldest first, e.g. from a core file */
+    pub(crate) fn from_executed(capacity: usize, executed: Vec<Executed>) -> Self {
+        let mut recent = RecentInstructions::new(capacity.max(executed.len()));
+        recent.ring.extend(executed);
+        recent
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    /// The instructions, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &Executed> {
+        self.ring.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.ring.clear();
+    }
+
+    /* an instruction was fetched; one retried (a GETC waiting for a key) replaces its last try */
+    pub(crate) fn fetched(&mut self, count: u64, pc: u16, instruction: u16, registers: [u16; REGISTER::COUNT as usize]) {
+        if self.ring.back().is_some_and(|last| last.count == count && last.pc == pc) {
+            self.ring.pop_back();
+        }
+        if self.ring.len() == self.capacity {
+            self.ring.pop_front();
+        }
+        self.ring.push_back(Executed { count, pc, instruction, before: registers, after: registers });
+    }
+
+    /* the registers the last instruction fetched left behind */
+    pub(crate) fn finished(&mut self, registers: [u16; REGISTER::COUNT as usize]) {
+        if let Some(last) = self.ring.back_mut() {
+            last.after = registers;
+        }
+    }
+
+    /// The instructions as text, one a line: count, address, word,
+    /// disassembly and the registers it changed (the PC only when it
+    /// jumped), e.g. `  #41  x3002  16FF  ADD R3, R3, #-1  R3 x0005->x0004, COND P->Z`.
+    pub fn render(&self, symbols: &SymbolTable) -> String {
+        let mut out = format!("last {} instructions:\n", self.ring.len());
+        for executed in &self.ring {
+            let text = disassemble_with(executed.pc, executed.instruction, symbols);
+            let mut effects = Vec::new();
+            for (register, name) in NAMES.iter().enumerate() {
+                let (old, new) = (executed.before[register], executed.after[register]);
+                if old == new {
+                    continue;
+                }
+                if register == REGISTER::PC as usize {
+                    if new != executed.pc.wrapping_add(1) {
+                        effects.push(format!("PC=x{:04X}", new));
+                    }
+                } else if register == REGISTER::COND as usize {
+                    effects.push(format!("COND {}->{}", condition(old), condition(new)));
+                } else {
+                    effects.push(format!("{} x{:04X}->x{:04X}", name, old, new));
+                }
+            }
+            let line = format!("  #{:<8} x{:04X}  {:04X}  {:<24}  {}", executed.count, executed.pc, executed.instruction, text, effects.join(", "));
+            writeln!(out, "{}", line.trim_end()).unwrap();
+        }
+        out
+    }
+}
@@ -0,0 +1,86 @@
+//! Keyboard input with the instruction count at which the guest received
+//! each key, so an interactive session can be played back exactly
+//! (`--record` and `--replay`). A recording is a text file of `COUNT KEY`
+//! lines, the key in hex:
+//!
+//! ```text
+//! # lc3-vm input recording: instruction count, key
+//! 10452 x68
+//! 10977 x0A
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use crate::address::parse_address;
+use crate::error::VmError;
+
+/// Keys in the order the guest received them, for
+/// [`Vm::recording`](crate::vm::Vm::recording) (appended to as keys arrive)
+/// and [`Vm::replay`](crate::vm::Vm::replay) (taken from the front as they
+/// come due).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputRecording {
+    /// The instruction count at which each key was latched, and the key.
+    pub keys: VecDeque<(u64, u8)>,
+}
+
+impl InputRecording {
+    /// Parse a recording; `#` lines and blank lines are skipped.
+    pub fn parse(text: &str) -> Result<Self, VmError> {
+        let mut keys = VecDeque::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || VmError::InvalidRecording(format!("line {}: `{}` is not an instruction count and a key", index + 1, line));
+            let (count, key) = line.split_once(char::is_whitespace).ok_or_else(invalid)?;
+            let count = count.parse::<u64>().map_err(|_| invalid())?;
+            let key = parse_address(key.trim()).and_then(|key| u8::try_from(key).ok()).ok_or_else(invalid)?;
+            keys.push_back((count, key));
+        }
+        Ok(InputRecording { keys })
+    }
+
+    /// The recording as text for [`InputRecording::parse`].
+    pub fn render(&self) -> String {
+        let mut out = String::from("# lc3-vm input recording: instruction count, key\n");
+        for (count, key) in &self.keys {
+            writeln!(out, "{} x{:02X}", count, key).unwrap();
+        }
+        out
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, VmError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), VmError> {
+        fs::write(path, self.render())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_rendered_recording_parses_back() {
+        let recording = InputRecording {
+            keys: [(10452, b'h'), (10977, b'\n')].into(),
+        };
+        assert_eq!(InputRecording::parse(&recording.render()).unwrap(), recording);
+    }
+
+    #[test]
+    fn a_malformed_line_is_reported_as_a_recording_error() {
+        let err = InputRecording::parse("# keys\n10452 x68\n10977\n").unwrap_err();
+        assert!(matches!(err, VmError::InvalidRecording(_)));
+        assert_eq!(err.to_string(), "invalid recording: line 3: `10977` is not an instruction count and a key");
+        assert!(InputRecording::parse("12 x100").is_err());
+    }
+}
@@ -0,0 +1,214 @@
+//! Debugger automation in [rhai](https://rhai.rs): a script gets the
+//! debugger's primitives as functions and drives a stopped [`Vm`] with
+//! them, e.g. to step until a word changes and then dump an array:
+//!
+//! ```text
+//! let old = mem("RESULT");
+//! while mem("RESULT") == old && step() {}
+//! print(dump("ARRAY", 8));
+//! ```
+//!
+//! Addresses are numbers or, as strings, anything the debugger accepts
+//! (`"LOOP"`, `"DATA+2"`, `"x4000"`); registers are named `"R0"`-`"R7"`,
+//! `"PC"` or `"PSR"`. The functions:
+//!
+//! - `step()`, `step(n)`: execute instructions, past breakpoints; false once
+//!   the program has halted
+//! - `cont()`: run to a breakpoint, watchpoint or HALT, returning why it
+//!   stopped: `"breakpoint"`, `"watchpoint"`, `"register watch"`,
+//!   `"halted"`, `"paused"` or `"waiting for input"`
+//! - `reg(name)`, `set_reg(name, value)`, `mem(addr)`, `set_mem(addr, value)`
+//! - `evaluate(expr)`: a `display` expression such as `"MEM[MEM[x4000]]"`
+//! - `pc()`, `instructions()`, `halted()`, `label(name)`
+//! - `break_at(addr)`, `delete_break(addr)`, `watch(addr)`, `unwatch(addr)`
+//! - `dump(addr, count)`, `disasm(addr)`: text, as `mem` and `list` show it
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use rhai::{Engine, EvalAltResult, ImmutableString};
+
+use crate::disasm::disassemble_with;
+use crate::expr::Expr;
+use crate::summary;
+use crate::vm::{REGISTER, RunState, StepOutcome, Vm, WatchKind};
+
+type Shared = Rc<RefCell<Vm>>;
+type Fallible<T> = Result<T, Box<EvalAltResult>>;
+
+/* an address given as a number or as text the debugger would accept */
+trait Address {
+    fn resolve(&self, vm: &Vm) -> Fallible<u16>;
+}
+
+impl Address for i64 {
+    fn resolve(&self, _: &Vm) -> Fallible<u16> {
+        u16::try_from(*self).map_err(|_| format!("{} is not an address", self).into())
+    }
+}
+
+impl Address for ImmutableString {
+    fn resolve(&self, vm: &Vm) -> Fallible<u16> {
+        vm.symbols.resolve(self).ok_or_else(|| format!("`{}` is not an address or label", self).into())
+    }
+}
+
+/* a register by name, as an index into the registers; PSR is None */
+fn register(name: &str) -> Fallible<Option<usize>> {
+    let upper = name.to_ascii_uppercase();
+    match upper.as_str() {
+        "PC" => Ok(Some(REGISTER::PC as usize)),
+        "PSR" => Ok(None),
+        _ => match upper.strip_prefix('R').and_then(|number| number.parse::<usize>().ok()) {
+            Some(number) if number < 8 => Ok(Some(number)),
+            _ => Err(format!("`{}` is not a register", name).into()),
+        },
+    }
+}
+
+/* functions taking an address, registered for numbers and for strings */
+fn register_address_fns<A: Address + Clone + 'static>(engine: &mut Engine, vm: &Shared) {
+    let shared = vm.clone();
+    engine.register_fn("mem", move |address: A| -> Fallible<i64> {
+        let vm = shared.borrow();
+        Ok(vm.memory[address.resolve(&vm)? as usize] as i64)
+    });
+    let shared = vm.clone();
+    engine.register_fn("set_mem", move |address: A, value: i64| -> Fallible<()> {
+        let mut vm = shared.borrow_mut();
+        let address = address.resolve(&vm)?;
+        vm.memory[address as usize] = value as u16;
+        Ok(())
+    });
+    let shared = vm.clone();
+    engine.register_fn("break_at", move |address: A| -> Fallible<()> {
+        let mut vm = shared.borrow_mut();
+        let address = address.resolve(&vm)?;
+        vm.breakpoints.insert(address);
+        Ok(())
+    });
+    let shared = vm.clone();
+    engine.register_fn("delete_break", move |address: A| -> Fallible<bool> {
+        let mut vm = shared.borrow_mut();
+        let address = address.resolve(&vm)?;
+        Ok(vm.breakpoints.remove(&address))
+    });
+    let shared = vm.clone();
+    engine.register_fn("watch", move |address: A| -> Fallible<()> {
+        let mut vm = shared.borrow_mut();
+        let address = address.resolve(&vm)?;
+        vm.watchpoints.insert(address, WatchKind::Access);
+        Ok(())
+    });
+    let shared = vm.clone();
+    engine.register_fn("unwatch", move |address: A| -> Fallible<bool> {
+        let mut vm = shared.borrow_mut();
+        let address = address.resolve(&vm)?;
+        Ok(vm.watchpoints.remove(&address).is_some())
+    });
+    let shared = vm.clone();
+    engine.register_fn("dump", move |address: A, count: i64| -> Fallible<String> {
+        let vm = shared.borrow();
+        let start = address.resolve(&vm)?;
+        let end = start.saturating_add((count.clamp(1, 0x10000) - 1) as u16);
+        Ok(summary::render_memory(&vm, &[start..=end]))
+    });
+    let shared = vm.clone();
+    engine.register_fn("disasm", move |address: A| -> Fallible<String> {
+        let vm = shared.borrow();
+        let address = address.resolve(&vm)?;
+        Ok(disassemble_with(address, vm.memory[address as usize], &vm.symbols))
+    });
+}
+
+/* execute up to `count` instructions; false once halted */
+fn step(vm: &mut Vm, count: i64) -> Fallible<bool> {
+    for _ in 0..count {
+        match vm.step() {
+            StepOutcome::Faulted { pc, instruction } => {
+                return Err(format!("cannot execute 0x{:04X} at {}", instruction, vm.symbols.describe(pc)).into());
+            }
+            StepOutcome::Halted | StepOutcome::WaitingForInput => break,
+            _ => {}
+        }
+    }
+    Ok(!vm.halted)
+}
+
+fn engine(vm: &Shared, printed: &Rc<RefCell<String>>) -> Engine {
+    let mut engine = Engine::new();
+    let buffer = printed.clone();
+    engine.on_print(move |text| {
+        let mut buffer = buffer.borrow_mut();
+        buffer.push_str(text);
+        buffer.push('\n');
+    });
+    register_address_fns::<i64>(&mut engine, vm);
+    register_address_fns::<ImmutableString>(&mut engine, vm);
+
+    let shared = vm.clone();
+    engine.register_fn("step", move || step(&mut shared.borrow_mut(), 1));
+    let shared = vm.clone();
+    engine.register_fn("step", move |count: i64| step(&mut shared.borrow_mut(), count));
+    let shared = vm.clone();
+    engine.register_fn("cont", move || -> Fallible<String> {
+        let mut vm = shared.borrow_mut();
+        let state = vm.run().map_err(|err| err.to_string())?;
+        Ok(match state {
+            RunState::Halted => "halted",
+            RunState::Breakpoint(_) => "breakpoint",
+            RunState::Watchpoint(_) => "watchpoint",
+            RunState::RegisterWatch(_) => "register watch",
+            RunState::WaitingForInput => "waiting for input",
+            RunState::Paused | RunState::Stepped | RunState::Yielded => "paused",
+        }
+        .to_string())
+    });
+    let shared = vm.clone();
+    engine.register_fn("reg", move |name: &str| -> Fallible<i64> {
+        let vm = shared.borrow();
+        Ok(match register(name)? {
+            Some(index) => vm.registers[index],
+            None => vm.psr(),
+        } as i64)
+    });
+    let shared = vm.clone();
+    engine.register_fn("set_reg", move |name: &str, value: i64| -> Fallible<()> {
+        let mut vm = shared.borrow_mut();
+        match register(name)? {
+            Some(index) => vm.registers[index] = value as u16,
+            None => vm.set_psr(value as u16),
+        }
+        Ok(())
+    });
+    let shared = vm.clone();
+    engine.register_fn("evaluate", move |text: &str| -> Fallible<i64> {
+        let vm = shared.borrow();
+        let expr = Expr::parse(text, &vm.symbols)?;
+        Ok(expr.evaluate(&vm) as i64)
+    });
+    let shared = vm.clone();
+    engine.register_fn("label", move |name: &str| -> Fallible<i64> {
+        let vm = shared.borrow();
+        vm.symbols.address_of(name).map(i64::from).ok_or_else(|| format!("no label `{}`", name).into())
+    });
+    let shared = vm.clone();
+    engine.register_fn("pc", move || shared.borrow().registers[REGISTER::PC as usize] as i64);
+    let shared = vm.clone();
+    engine.register_fn("instructions", move || shared.borrow().instruction_count as i64);
+    let shared = vm.clone();
+    engine.register_fn("halted", move || shared.borrow().halted);
+    engine
+}
+
+/// Run a rhai script against `vm`, writing what it prints to `output`.
+/// A script error is returned as its message, after whatever it printed.
+pub fn run(vm: &mut Vm, source: &str, output: &mut impl Write) -> io::Result<Result<(), String>> {
+    let shared = Rc::new(RefCell::new(std::mem::take(vm)));
+    let printed = Rc::new(RefCell::new(String::new()));
+    let result = engine(&shared, &printed).run(source).map_err(|err| err.to_string());
+    *vm = Rc::try_unwrap(shared).ok().expect("the engine is gone").into_inner();
+    output.write_all(printed.borrow().as_bytes())?;
+    Ok(result)
+}
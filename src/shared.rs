@@ -0,0 +1,115 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// A buffer of words the host application and the guest both see. Clones
+/// share the same storage, so the host keeps one handle (possibly on
+/// another thread) while the VM maps another into the guest's address
+/// space with [`Vm::map_shared`](crate::vm::Vm::map_shared).
+#[derive(Debug, Clone, Default)]
+pub struct SharedMemory {
+    words: Arc<Mutex<Vec<u16>>>,
+}
+
+impl SharedMemory {
+    /// A zero-filled buffer of `len` words.
+    pub fn new(len: usize) -> Self {
+        Self::from_words(vec![0; len])
+    }
+
+    pub fn from_words(words: Vec<u16>) -> Self {
+        SharedMemory {
+            words: Arc::new(Mutex::new(words)),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The word at `offset`; `None` past the end.
+    pub fn read(&self, offset: usize) -> Option<u16> {
+        self.lock().get(offset).copied()
+    }
+
+    /// Store a word; false past the end.
+    pub fn write(&self, offset: usize, value: u16) -> bool {
+        match self.lock().get_mut(offset) {
+            Some(word) => {
+                *word = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Lock the buffer for bulk access, e.g. to fill it with a slice at once.
+    /// The guest stalls on its next access to the window until the guard is dropped.
+    pub fn lock(&self) -> MutexGuard<'_, Vec<u16>> {
+        /* a host thread that panicked mid-update leaves plain words behind; keep using them */
+        self.words.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn to_vec(&self) -> Vec<u16> {
+        self.lock().clone()
+    }
+}
+
+pub type SharedWriteListener = Box<dyn FnMut(usize, u16)>;
+
+/// A [`SharedMemory`] mapped at a guest address, optionally with a callback
+/// told about every word the guest stores into it.
+pub struct SharedWindow {
+    pub start: u16,
+    pub memory: SharedMemory,
+    on_write: Option<SharedWriteListener>,
+}
+
+impl SharedWindow {
+    pub fn new(start: u16, memory: &SharedMemory) -> Self {
+        SharedWindow {
+            start,
+            memory: memory.clone(),
+            on_write: None,
+        }
+    }
+
+    /// Call `listener` with the offset and value of each guest store into the window.
+    pub fn on_write<F>(mut self, listener: F) -> Self
+    where
+        F: FnMut(usize, u16) + 'static,
+    {
+        self.on_write = Some(Box::new(listener));
+        self
+    }
+
+    /// The last guest address covered; `None` for an empty window.
+    pub fn end(&self) -> Option<u16> {
+        let len = self.memory.len();
+        (len > 0).then(|| self.start.wrapping_add(len as u16 - 1))
+    }
+
+    /* the offset of `address` within the window, if it is inside */
+    pub(crate) fn offset(&self, address: u16) -> Option<usize> {
+        let offset = address.wrapping_sub(self.start) as usize;
+        (address >= self.start && offset < self.memory.len()).then_some(offset)
+    }
+
+    pub(crate) fn read(&self, address: u16) -> Option<u16> {
+        self.memory.read(self.offset(address)?)
+    }
+
+    /* store a guest write; false if `address` is outside the window */
+    pub(crate) fn write(&mut self, address: u16, value: u16) -> bool {
+        let Some(offset) = self.offset(address) else {
+            return false;
+        };
+        self.memory.write(offset, value);
+        if let Some(listener) = self.on_write.as_mut() {
+            listener(offset, value);
+        }
+        true
+    }
+}
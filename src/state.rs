@@ -0,0 +1,164 @@
+//! A plain-data copy of everything that determines how a [`Vm`](crate::vm::Vm)
+//! continues: memory, registers, processor status, the keyboard latch,
+//! pending interrupts and the registers of attached devices. With the
+//! `serde` feature it can be written out in any serde format and read back;
+//! [`VmState::save`] and [`VmState::load`] use a compact snapshot file of
+//! big-endian words that needs no extra dependencies.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::error::VmError;
+use crate::vm::{Interrupt, MEMORY_SIZE, REGISTER};
+
+/// Machine state captured by [`Vm::state`](crate::vm::Vm::state) and put
+/// back with [`Vm::restore_state`](crate::vm::Vm::restore_state).
+///
+/// Host-side configuration (breakpoints, hooks, the console, profilers) is
+/// not part of it, and neither are connections or open files: devices
+/// record only their registers, keyed by name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VmState {
+    /// All 65536 words of memory.
+    pub memory: Vec<u16>,
+    /// R0-R7, PC and COND, indexed by [`REGISTER`].
+    pub registers: [u16; REGISTER::COUNT as usize],
+    /// Privilege and priority bits of the processor status register.
+    pub psr: u16,
+    /// R6 of the mode that is not running.
+    pub saved_ssp: u16,
+    pub saved_usp: u16,
+    pub halted: bool,
+    pub instruction_count: u64,
+    /// The KBSR/KBDR latch: whether a key is waiting, and the last key.
+    pub key_ready: bool,
+    pub key_value: u16,
+    pub pending_interrupts: Vec<Interrupt>,
+    /// Register contents of attached devices, from [`Device::save`](crate::bus::Device::save).
+    pub devices: BTreeMap<String, Vec<u16>>,
+}
+
+/* "LC3S" and a format version, ahead of the fields in declaration order */
+const MAGIC: &[u8; 4] = b"LC3S";
+const FORMAT_VERSION: u16 = 1;
+
+impl VmState {
+    /// Encode the state in the snapshot file format: a `LC3S` header and
+    /// version, then every field as big-endian words like an object image.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut words = vec![FORMAT_VERSION];
+        words.extend_from_slice(&self.registers);
+        words.extend([self.psr, self.saved_ssp, self.saved_usp]);
+        words.push(self.halted as u16 | (self.key_ready as u16) << 1);
+        words.push(self.key_value);
+        words.extend((0..4).rev().map(|i| (self.instruction_count >> (i * 16)) as u16));
+        words.push(self.pending_interrupts.len() as u16);
+        words.extend(self.pending_interrupts.iter().map(|interrupt| (interrupt.vector as u16) << 8 | interrupt.priority as u16));
+        words.push(self.devices.len() as u16);
+        for (name, state) in &self.devices {
+            words.push(name.len() as u16);
+            words.extend(name.bytes().map(u16::from));
+            words.push(state.len() as u16);
+            words.extend_from_slice(state);
+        }
+        words.push(self.memory.len() as u16);
+        words.extend_from_slice(&self.memory);
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(words.iter().flat_map(|word| word.to_be_bytes()));
+        bytes
+    }
+
+    /// Decode a snapshot written by [`VmState::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, VmError> {
+        let body = bytes.strip_prefix(MAGIC).ok_or_else(|| invalid("not a snapshot file"))?;
+        if body.len() % 2 != 0 {
+            return Err(invalid("odd number of bytes"));
+        }
+        let words: Vec<u16> = body.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+        let mut reader = Words { words: &words };
+        let version = reader.word()?;
+        if version != FORMAT_VERSION {
+            return Err(invalid(&format!("unsupported snapshot version {}", version)));
+        }
+        let mut registers = [0; REGISTER::COUNT as usize];
+        registers.copy_from_slice(reader.take(REGISTER::COUNT as usize)?);
+        let [psr, saved_ssp, saved_usp, flags, key_value] = reader.take(5)?.try_into().unwrap();
+        let instruction_count = reader.take(4)?.iter().fold(0u64, |count, &word| count << 16 | word as u64);
+        let count = reader.word()? as usize;
+        let pending_interrupts = reader
+            .take(count)?
+            .iter()
+            .map(|&word| Interrupt {
+                vector: (word >> 8) as u8,
+                priority: (word & 0xFF) as u8,
+            })
+            .collect();
+        let mut devices = BTreeMap::new();
+        for _ in 0..reader.word()? {
+            let len = reader.word()? as usize;
+            let name = reader.take(len)?.iter().map(|&byte| char::from(byte as u8)).collect();
+            let len = reader.word()? as usize;
+            devices.insert(name, reader.take(len)?.to_vec());
+        }
+        /* a full memory of 65536 words wraps the length word to 0 */
+        let len = match reader.word()? {
+            0 => MEMORY_SIZE,
+            len => len as usize,
+        };
+        let memory = reader.take(len)?.to_vec();
+        if !reader.words.is_empty() {
+            return Err(invalid("trailing data"));
+        }
+        Ok(VmState {
+            memory,
+            registers,
+            psr,
+            saved_ssp,
+            saved_usp,
+            halted: flags & 1 != 0,
+            instruction_count,
+            key_ready: flags & 2 != 0,
+            key_value,
+            pending_interrupts,
+            devices,
+        })
+    }
+
+    /// Write the state to a snapshot file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), VmError> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    /// Read a snapshot file written by [`VmState::save`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, VmError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
+
+fn invalid(reason: &str) -> VmError {
+    VmError::InvalidState(reason.to_string())
+}
+
+/* a cursor over the words of a snapshot */
+struct Words<'a> {
+    words: &'a [u16],
+}
+
+impl<'a> Words<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u16], VmError> {
+        if count > self.words.len() {
+            return Err(invalid("truncated snapshot"));
+        }
+        let (taken, rest) = self.words.split_at(count);
+        self.words = rest;
+        Ok(taken)
+    }
+
+    fn word(&mut self) -> Result<u16, VmError> {
+        Ok(self.take(1)?[0])
+    }
+}
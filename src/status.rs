@@ -0,0 +1,66 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use crate::vm::{REGISTER, Vm};
+
+/* how often the line is redrawn */
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A single self-overwriting progress line (instructions, IPS, PC) for long
+/// runs, written with carriage returns so it stays on one terminal row.
+pub struct StatusLine {
+    started: Instant,
+    last_update: Instant,
+    last_count: u64,
+}
+
+impl Default for StatusLine {
+    fn default() -> Self {
+        let now = Instant::now();
+        StatusLine {
+            started: now,
+            last_update: now,
+            last_count: 0,
+        }
+    }
+}
+
+impl StatusLine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Redraw the line if the refresh interval has passed.
+    pub fn tick(&mut self, vm: &Vm, out: &mut impl Write) -> io::Result<()> {
+        let now = Instant::now();
+        let elapsed = now - self.last_update;
+        if elapsed < REFRESH_INTERVAL {
+            return Ok(());
+        }
+        let ips = (vm.instruction_count - self.last_count) as f64 / elapsed.as_secs_f64();
+        self.last_update = now;
+        self.last_count = vm.instruction_count;
+        self.draw(vm, ips, out)
+    }
+
+    /// Draw the final totals and move to a fresh line.
+    pub fn finish(&mut self, vm: &Vm, out: &mut impl Write) -> io::Result<()> {
+        let ips = vm.instruction_count as f64 / self.started.elapsed().as_secs_f64().max(f64::EPSILON);
+        self.draw(vm, ips, out)?;
+        writeln!(out)
+    }
+
+    fn draw(&self, vm: &Vm, ips: f64, out: &mut impl Write) -> io::Result<()> {
+        let pc = vm.registers[REGISTER::PC as usize];
+        let symbol = vm.symbols.name_for(pc).map(|name| format!(" ({})", name)).unwrap_or_default();
+        write!(
+            out,
+            "\r\x1b[K[lc3] {} instructions  {:.2} MIPS  PC=x{:04X}{}",
+            vm.instruction_count,
+            ips / 1_000_000.0,
+            pc,
+            symbol
+        )?;
+        out.flush()
+    }
+}
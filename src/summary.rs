@@ -0,0 +1,100 @@
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+
+use crate::json;
+use crate::vm::{ConditionFlags, REGISTER, Vm};
+
+/// Render the condition register as the set flag's letter (`N`, `Z` or `P`).
+pub fn condition_code(cond: u16) -> &'static str {
+    match cond {
+        x if x == ConditionFlags::NEG as u16 => "N",
+        x if x == ConditionFlags::ZRO as u16 => "Z",
+        x if x == ConditionFlags::POS as u16 => "P",
+        _ => "?",
+    }
+}
+
+/// Format the final machine state: registers, condition code, instruction
+/// count, and a hex dump of each requested memory range.
+pub fn render(vm: &Vm, ranges: &[RangeInclusive<u16>]) -> String {
+    let mut out = render_registers(vm);
+    writeln!(out, "Instructions executed: {}", vm.instruction_count).unwrap();
+
+    for range in ranges {
+        writeln!(out, "Memory 0x{:04X}-0x{:04X}:", range.start(), range.end()).unwrap();
+        for (i, address) in range.clone().enumerate() {
+            if i % 8 == 0 {
+                if i != 0 {
+                    out.push('\n');
+                }
+                write!(out, "  0x{:04X}:", address).unwrap();
+            }
+            write!(out, " {:04X}", vm.memory[address as usize]).unwrap();
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Format R0-R7 in hex and signed decimal, the PC and the condition code.
+pub fn render_registers(vm: &Vm) -> String {
+    let mut out = String::new();
+    let registers = &vm.registers;
+    writeln!(out, "Registers:").unwrap();
+    for (reg, &value) in registers.iter().take(8).enumerate() {
+        writeln!(out, "  R{}   0x{:04X}  {:>6}", reg, value, value as i16).unwrap();
+    }
+    let pc = registers[REGISTER::PC as usize];
+    match vm.symbols.name_for(pc) {
+        Some(name) => writeln!(out, "  PC   0x{:04X}  {}", pc, name).unwrap(),
+        None => writeln!(out, "  PC   0x{:04X}", pc).unwrap(),
+    }
+    writeln!(out, "  COND {}", condition_code(registers[REGISTER::COND as usize])).unwrap();
+    out
+}
+
+/// Format memory ranges as a hex dump: eight words per line after the
+/// address, then their low bytes as text (`.` where not printable ASCII).
+pub fn render_memory(vm: &Vm, ranges: &[RangeInclusive<u16>]) -> String {
+    let mut out = String::new();
+    for range in ranges {
+        writeln!(out, "Memory x{:04X}-x{:04X}:", range.start(), range.end()).unwrap();
+        let words: Vec<(u16, u16)> = range.clone().map(|address| (address, vm.memory[address as usize])).collect();
+        for line in words.chunks(8) {
+            write!(out, "  x{:04X} ", line[0].0).unwrap();
+            for (_, word) in line {
+                write!(out, " {:04X}", word).unwrap();
+            }
+            let text: String = line
+                .iter()
+                .map(|&(_, word)| if (0x20..0x7F).contains(&word) { word as u8 as char } else { '.' })
+                .collect();
+            writeln!(out, "{:pad$}  {}", "", text, pad = (8 - line.len()) * 5).unwrap();
+        }
+    }
+    out
+}
+
+/// One memory range as a JSON object (`"kind": "memory"`).
+pub fn render_memory_json(vm: &Vm, range: &RangeInclusive<u16>) -> String {
+    let words = json::array(range.clone().map(|address| vm.memory[address as usize].to_string()));
+    format!("{{\"kind\":\"memory\",\"start\":{},\"end\":{},\"words\":{}}}", range.start(), range.end(), words)
+}
+
+/// The same information as [`render`] as a single JSON object (`"kind": "summary"`).
+pub fn render_json(vm: &Vm, ranges: &[RangeInclusive<u16>]) -> String {
+    let registers = json::array(vm.registers.iter().take(8).map(u16::to_string));
+    let memory = json::array(ranges.iter().map(|range| {
+        let words = json::array(range.clone().map(|address| vm.memory[address as usize].to_string()));
+        format!("{{\"start\":{},\"end\":{},\"words\":{}}}", range.start(), range.end(), words)
+    }));
+    format!(
+        "{{\"kind\":\"summary\",\"registers\":{},\"pc\":{},\"cond\":{},\"instructions\":{},\"halted\":{},\"memory\":{}}}",
+        registers,
+        vm.registers[REGISTER::PC as usize],
+        json::string(condition_code(vm.registers[REGISTER::COND as usize])),
+        vm.instruction_count,
+        vm.halted,
+        memory
+    )
+}
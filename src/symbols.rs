@@ -0,0 +1,161 @@
+//! Labels for addresses, from the assembler or from a `.sym` symbol table
+//! file as written by `lc3as` (and by `lc3-vm asm`):
+//!
+//! ```text
+//! // Symbol table
+//! // Scope level 0:
+//! //    Symbol Name       Page Address
+//! //    ----------------  ------------
+//! //    LOOP              3004
+//! ```
+//!
+//! Plain `NAME ADDRESS` lines without the `//` are accepted too, and an
+//! address may carry an `x` or `0x` prefix.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+
+use crate::error::VmError;
+
+/* an address further than this past the nearest label is shown as a plain address */
+const MAX_OFFSET: u16 = 256;
+
+fn parse_address(token: &str) -> Option<u16> {
+    let hex = token.strip_prefix("0x").or_else(|| token.strip_prefix(['x', 'X'])).unwrap_or(token);
+    if hex.is_empty() || hex.len() > 4 {
+        return None;
+    }
+    u16::from_str_radix(hex, 16).ok()
+}
+
+fn is_label(token: &str) -> bool {
+    token.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && token.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Parse the text of a `.sym` file into label addresses. Comment lines that
+/// are not `NAME ADDRESS` pairs (the headers) are skipped; any other line
+/// that is not one is an error.
+pub fn parse(text: &str) -> Result<HashMap<String, u16>, VmError> {
+    let mut symbols = HashMap::new();
+    for (index, line) in text.lines().enumerate() {
+        let (comment, line) = match line.trim().strip_prefix("//") {
+            Some(rest) => (true, rest.trim()),
+            None => (false, line.trim()),
+        };
+        if line.is_empty() {
+            continue;
+        }
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens[..] {
+            [name, address] if is_label(name) && parse_address(address).is_some() => {
+                symbols.insert(name.to_string(), parse_address(address).unwrap());
+            }
+            _ if comment => {}
+            _ => {
+                return Err(VmError::InvalidImage(format!(
+                    "symbol file line {}: `{}` is not a label and an address",
+                    index + 1,
+                    line
+                )));
+            }
+        }
+    }
+    Ok(symbols)
+}
+
+/// Write labels in the `.sym` format [`parse`] reads, sorted by address.
+pub fn render(symbols: &HashMap<String, u16>) -> String {
+    let mut sorted: Vec<(&u16, &String)> = symbols.iter().map(|(name, address)| (address, name)).collect();
+    sorted.sort();
+    let mut out = String::from("// Symbol table\n// Scope level 0:\n//\tSymbol Name       Page Address\n//\t----------------  ------------\n");
+    for (address, name) in sorted {
+        writeln!(out, "//\t{:<16}  {:04X}", name, address).unwrap();
+    }
+    out
+}
+
+/// Every label known for the loaded program, for naming addresses in
+/// traces, dumps and messages.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolTable {
+    /* sorted by address, then name */
+    labels: Vec<(u16, String)>,
+}
+
+impl SymbolTable {
+    pub fn new(symbols: &HashMap<String, u16>) -> Self {
+        let mut table = SymbolTable::default();
+        table.extend(symbols);
+        table
+    }
+
+    /// Add labels; a name already known moves to its new address.
+    pub fn extend(&mut self, symbols: &HashMap<String, u16>) {
+        self.labels.retain(|(_, name)| !symbols.contains_key(name));
+        self.labels.extend(symbols.iter().map(|(name, &address)| (address, name.clone())));
+        self.labels.sort();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// The address of label `name`.
+    pub fn address_of(&self, name: &str) -> Option<u16> {
+        self.labels.iter().find(|(_, label)| label == name).map(|&(address, _)| address)
+    }
+
+    /// An address as typed at a prompt: a number as
+    /// [`parse_address`](crate::address::parse_address) reads it, a label,
+    /// or a label with an offset, e.g. `LOOP+2` or `DATA-x1`.
+    pub fn resolve(&self, text: &str) -> Option<u16> {
+        let text = text.trim();
+        if let Some(address) = crate::address::parse_address(text) {
+            return Some(address);
+        }
+        if let Some(address) = self.address_of(text) {
+            return Some(address);
+        }
+        let (name, offset) = text.rsplit_once(['+', '-'])?;
+        let (base, offset) = (self.address_of(name.trim())?, crate::address::parse_address(offset)?);
+        match text.as_bytes()[name.len()] {
+            b'+' => Some(base.wrapping_add(offset)),
+            _ => Some(base.wrapping_sub(offset)),
+        }
+    }
+
+    /// `START..END` (exclusive) or `START..=END` (inclusive), each end
+    /// read by [`SymbolTable::resolve`], as an inclusive range.
+    pub fn resolve_range(&self, text: &str) -> Option<RangeInclusive<u16>> {
+        if let Some((start, end)) = text.split_once("..=") {
+            return Some(self.resolve(start)?..=self.resolve(end)?);
+        }
+        let (start, end) = text.split_once("..")?;
+        let (start, end) = (self.resolve(start)?, self.resolve(end)?);
+        if end <= start {
+            return None;
+        }
+        Some(start..=end - 1)
+    }
+
+    /// The nearest label at or below `address`, as `LABEL` or `LABEL+offset`.
+    pub fn name_for(&self, address: u16) -> Option<String> {
+        let index = self.labels.partition_point(|(label_address, _)| *label_address <= address);
+        let (label_address, name) = self.labels.get(index.checked_sub(1)?)?;
+        match address - label_address {
+            0 => Some(name.clone()),
+            offset if offset <= MAX_OFFSET => Some(format!("{}+{}", name, offset)),
+            _ => None,
+        }
+    }
+
+    /// `x3041 (LOOP+2)`, or just `x3041` with no label nearby.
+    pub fn describe(&self, address: u16) -> String {
+        match self.name_for(address) {
+            Some(name) => format!("x{:04X} ({})", address, name),
+            None => format!("x{:04X}", address),
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use std::io;
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tracing::debug;
+use libc::{termios, tcgetattr, tcsetattr, ICANON, ECHO, TCSANOW, fd_set, timeval, FD_SET, FD_ZERO, select};
+use libc::{c_int, sigaction, sigemptyset, sighandler_t, SA_RESTART, SIGUSR1, SIGUSR2};
+
+/* set by the signal handler, consumed by the run loop; storing to an atomic
+   is all the handler does, which keeps it async-signal-safe */
+static DUMP_REQUESTED: AtomicBool = AtomicBool::new(false);
+static TRACE_TOGGLE_REQUESTED: AtomicBool = AtomicBool::new(false);
+/* stdin reached end of file, e.g. after the image was piped in through it */
+static STDIN_CLOSED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_signal(signal: c_int) {
+    match signal {
+        SIGUSR1 => DUMP_REQUESTED.store(true, Ordering::Relaxed),
+        SIGUSR2 => TRACE_TOGGLE_REQUESTED.store(true, Ordering::Relaxed),
+        _ => {}
+    }
+}
+
+/// Route SIGUSR1 (dump the VM state) and SIGUSR2 (toggle instruction
+/// tracing) to a running VM, which picks them up between instructions.
+pub fn install_signal_handlers() -> io::Result<()> {
+    unsafe {
+        let mut action = mem::zeroed::<sigaction>();
+        action.sa_sigaction = handle_signal as extern "C" fn(c_int) as sighandler_t;
+        action.sa_flags = SA_RESTART;
+        sigemptyset(&mut action.sa_mask);
+        for signal in [SIGUSR1, SIGUSR2] {
+            if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+    debug!("signal handlers installed");
+    Ok(())
+}
+
+/// Whether SIGUSR1 arrived since the last call.
+pub fn take_dump_request() -> bool {
+    DUMP_REQUESTED.swap(false, Ordering::Relaxed)
+}
+
+/// Whether SIGUSR2 arrived since the last call.
+pub fn take_trace_toggle_request() -> bool {
+    TRACE_TOGGLE_REQUESTED.swap(false, Ordering::Relaxed)
+}
+
+/// Keeps stdin unbuffered and unechoed, so keys reach the guest as they are
+/// typed, for as long as it is alive. Dropping it puts back the settings it
+/// found, also when unwinding from a panic.
+pub struct RawMode {
+    /* the settings to restore; None if stdin is not a terminal */
+    original: Option<termios>,
+}
+
+impl RawMode {
+    pub fn enable() -> Self {
+        let original = unsafe {
+            let mut t = mem::zeroed::<termios>();
+            if tcgetattr(0, &mut t) != 0 {
+                debug!("stdin is not a terminal; leaving input buffering unchanged");
+                None
+            } else {
+                Some(t)
+            }
+        };
+        let raw = RawMode { original };
+        raw.resume();
+        raw
+    }
+
+    /// Temporarily restore the original settings, e.g. for a line-oriented prompt.
+    pub fn suspend(&self) {
+        if let Some(t) = &self.original {
+            debug!("restoring terminal mode");
+            unsafe {
+                tcsetattr(0, TCSANOW, t);
+            }
+        }
+    }
+
+    /// Disable canonical mode and echo again after [`RawMode::suspend`].
+    pub fn resume(&self) {
+        if let Some(mut t) = self.original {
+            debug!("disabling canonical mode and echo");
+            t.c_lflag &= !(ICANON | ECHO);
+            unsafe {
+                tcsetattr(0, TCSANOW, &t);
+            }
+        }
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        self.suspend();
+    }
+}
+
+pub fn check_key() -> bool {
+    unsafe {
+        let mut readfds = std::mem::zeroed::<fd_set>();
+        FD_ZERO(&mut readfds);
+        FD_SET(0, &mut readfds); // stdin
+
+        let mut timeout = timeval {
+            tv_sec: 0,
+            tv_usec: 0,
+        };
+
+        select(
+            1,
+            &mut readfds,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            &mut timeout,
+        ) > 0
+    }
+}
+
+/// Read one byte from stdin; `None` once stdin has reached end of file.
+pub fn get_char() -> Option<u8> {
+    use std::io::Read;
+    let mut buf = [0u8; 1];
+    if std::io::stdin().read_exact(&mut buf).is_err() {
+        STDIN_CLOSED.store(true, Ordering::Relaxed);
+        return None;
+    }
+    Some(buf[0])
+}
+
+/// Read whatever stdin has ready, up to `buf.len()` bytes, straight from
+/// the file descriptor: nothing is left in the standard library's buffer
+/// where [`check_key`] cannot see it. Returns 0 at end of file.
+pub fn read_stdin(buf: &mut [u8]) -> io::Result<usize> {
+    let count = unsafe { libc::read(0, buf.as_mut_ptr().cast(), buf.len()) };
+    if count < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if count == 0 {
+        STDIN_CLOSED.store(true, Ordering::Relaxed);
+    }
+    Ok(count as usize)
+}
+
+/// Whether [`get_char`] or [`read_stdin`] has found stdin at end of file.
+pub fn stdin_closed() -> bool {
+    STDIN_CLOSED.load(Ordering::Relaxed)
+}
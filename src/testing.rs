@@ -0,0 +1,201 @@
+use crate::console::CapturedConsole;
+use crate::program::Program;
+use crate::vm::Vm;
+
+/// How many instructions an [`lc3_test!`](crate::lc3_test) program may run
+/// before it is treated as stuck.
+pub const DEFAULT_MAX_INSTRUCTIONS: u64 = 1_000_000;
+
+/// A finished test program: the VM as it was left and what it printed.
+pub struct TestRun {
+    pub vm: Vm,
+    /// The console output, without the `HALT` banner the halt trap prints.
+    pub output: String,
+}
+
+/// Assemble `source`, run it to HALT on a captured console fed with `input`,
+/// and hand back the final state. Panics if the source does not assemble or
+/// the program is still running after `max_instructions`, so a broken test
+/// fails with a message instead of hanging.
+pub fn run(source: &str, input: &[u8], max_instructions: u64) -> TestRun {
+    let program = Program::from_assembly(source).unwrap_or_else(|err| panic!("assembly failed: {}", err));
+    let mut vm = Vm::new();
+    vm.console = Box::new(CapturedConsole::new(input.to_vec()));
+    vm.load_program(&program);
+    vm.run_for(max_instructions).unwrap_or_else(|err| panic!("{}", err));
+    assert!(vm.halted, "program did not halt within {} instructions", max_instructions);
+    let output = vm.console.downcast_ref::<CapturedConsole>().map(CapturedConsole::output_string).unwrap_or_default();
+    TestRun {
+        output: output.strip_suffix("HALT").unwrap_or(&output).to_string(),
+        vm,
+    }
+}
+
+/// Expand into a `#[test]` that assembles a program, runs it to HALT and
+/// checks what it left behind. Everything after `source` is optional but
+/// must come in this order:
+///
+/// - `input: b"..."` — bytes the keyboard delivers, in order
+/// - `max_instructions: N` — the step limit (default [`DEFAULT_MAX_INSTRUCTIONS`])
+/// - `output: "..."` — the exact text the program must print before HALT
+/// - `registers: { R0: 5, PC: 0x3004 }` — expected register values
+/// - `memory: { 0x3100: 7 }` — expected memory words
+#[macro_export]
+macro_rules! lc3_test {
+    (
+        $name:ident,
+        source: $source:expr
+        $(, input: $input:expr)?
+        $(, max_instructions: $max:expr)?
+        $(, output: $output:expr)?
+        $(, registers: { $($register:ident : $value:expr),* $(,)? })?
+        $(, memory: { $($address:expr => $word:expr),* $(,)? })?
+        $(,)?
+    ) => {
+        #[test]
+        fn $name() {
+            #[allow(unused_mut, unused_assignments)]
+            let mut input: &[u8] = &[];
+            $(input = $input;)?
+            #[allow(unused_mut, unused_assignments)]
+            let mut max_instructions = $crate::testing::DEFAULT_MAX_INSTRUCTIONS;
+            $(max_instructions = $max;)?
+            #[allow(unused_variables)]
+            let run = $crate::testing::run($source, input, max_instructions);
+            $(assert_eq!(run.output, $output, "program output");)?
+            $($(
+                assert_eq!(
+                    run.vm.registers[$crate::vm::REGISTER::$register as usize],
+                    ($value) as u16,
+                    "register {}",
+                    stringify!($register)
+                );
+            )*)?
+            $($(
+                assert_eq!(
+                    run.vm.memory[($address) as usize],
+                    ($word) as u16,
+                    "memory at x{:04X}",
+                    $address
+                );
+            )*)?
+        }
+    };
+}
+
+
+#[cfg(test)]
+mod tests {
+    /* the console traps, end to end */
+
+    lc3_test!(
+        out_prints_the_low_byte_of_r0,
+        source: "
+                .ORIG x3000
+                LD R0, CHAR
+                OUT
+                HALT
+        CHAR    .FILL x0141
+                .END
+        ",
+        output: "A",
+    );
+
+    lc3_test!(
+        puts_prints_one_character_per_word,
+        source: "
+                .ORIG x3000
+                LEA R0, TEXT
+                PUTS
+                HALT
+        TEXT    .STRINGZ \"hello\\n\"
+                .END
+        ",
+        output: "hello\n",
+    );
+
+    lc3_test!(
+        putsp_prints_two_characters_per_word_low_byte_first,
+        source: "
+                .ORIG x3000
+                LEA R0, TEXT
+                PUTSP
+                HALT
+        TEXT    .FILL x6261
+                .FILL x0063
+                .FILL x0000
+                .END
+        ",
+        output: "abc",
+    );
+
+    lc3_test!(
+        getc_reads_a_key_without_echoing_it,
+        source: "
+                .ORIG x3000
+                GETC
+                ADD R1, R0, #0
+                GETC
+                HALT
+                .END
+        ",
+        input: b"xy",
+        output: "",
+        registers: { R0: b'y', R1: b'x', COND: 0b001 },
+    );
+
+    lc3_test!(
+        in_prompts_and_echoes_the_key,
+        source: "
+                .ORIG x3000
+                IN
+                HALT
+                .END
+        ",
+        input: b"q",
+        output: "Enter a character: q\n",
+        registers: { R0: b'q' },
+    );
+
+    lc3_test!(
+        trap_saves_the_return_address_in_r7,
+        source: "
+                .ORIG x3000
+                LD R0, CHAR
+                OUT
+                ADD R1, R7, #0
+                HALT
+        CHAR    .FILL x0021
+                .END
+        ",
+        output: "!",
+        registers: { R1: 0x3002, R7: 0x3004, PC: 0x3004 },
+    );
+
+    lc3_test!(
+        an_unknown_trap_vector_is_skipped,
+        source: "
+                .ORIG x3000
+                TRAP x7F
+                AND R2, R2, #0
+                ADD R2, R2, #7
+                HALT
+                .END
+        ",
+        registers: { R2: 7 },
+    );
+
+    lc3_test!(
+        halt_stops_before_the_next_instruction,
+        source: "
+                .ORIG x3000
+                HALT
+                ST R0, FLAG
+                HALT
+        FLAG    .FILL #9
+                .END
+        ",
+        output: "",
+        memory: { 0x3003 => 9 },
+    );
+}
@@ -0,0 +1,47 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+/* shorter waits are saved up rather than slept, so fast rates do not sleep per instruction */
+const MIN_SLEEP: Duration = Duration::from_millis(1);
+
+/* a guest this far behind schedule (waiting for a key, paused in the debugger) starts
+   a new schedule instead of racing to catch up */
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+/// Paces a guest to about `hz` instructions per second of wall-clock time,
+/// e.g. slow enough to watch a demo run. The VM sleeps before an
+/// instruction that is ahead of schedule.
+#[derive(Debug, Clone)]
+pub struct Throttle {
+    pub hz: u64,
+    /* instruction count and time the schedule counts from */
+    started: Option<(u64, Instant)>,
+}
+
+impl Throttle {
+    pub fn new(hz: u64) -> Self {
+        Throttle { hz: hz.max(1), started: None }
+    }
+
+    /// Start a new schedule from the next instruction.
+    pub fn reset(&mut self) {
+        self.started = None;
+    }
+
+    /// Wait until the instruction after `count` is due. Returns whether it
+    /// slept, i.e. the guest is running slower than it could.
+    pub fn pace(&mut self, count: u64) -> bool {
+        let now = Instant::now();
+        let (start_count, start_time) = *self.started.get_or_insert((count, now));
+        let due = start_time + Duration::from_secs_f64(count.saturating_sub(start_count) as f64 / self.hz as f64);
+        if now > due + MAX_LAG {
+            self.started = Some((count, now));
+            return false;
+        }
+        if due < now + MIN_SLEEP {
+            return false;
+        }
+        thread::sleep(due - now);
+        true
+    }
+}
@@ -0,0 +1,92 @@
+use crate::json;
+
+/* the viewer page; the data is spliced in where this placeholder appears */
+const VIEWER: &str = include_str!("timeline.html");
+const DATA_PLACEHOLDER: &str = "/*TIMELINE_DATA*/null";
+
+/* PC samples are taken this often unless configured otherwise */
+pub const DEFAULT_SAMPLE_INTERVAL: u64 = 100;
+
+const OPCODE_JSR: u16 = 0b0100;
+const OPCODE_TRAP: u16 = 0b1111;
+const RET: u16 = 0xC1C0; /* JMP R7 */
+
+/// A compact record of a whole run for later visual exploration: periodic
+/// PC samples plus every call, return, trap and interrupt, each stamped with
+/// the instruction count at which it happened.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Timeline {
+    pub sample_interval: u64,
+    /// `(instruction count, pc)`
+    pub samples: Vec<(u64, u16)>,
+    /// `(instruction count, call site, subroutine entry)`
+    pub calls: Vec<(u64, u16, u16)>,
+    /// `(instruction count, address of the RET)`
+    pub returns: Vec<(u64, u16)>,
+    /// `(instruction count, pc, trap vector, R0 afterwards)`; R0 carries the
+    /// character for the console traps.
+    pub traps: Vec<(u64, u16, u16, u16)>,
+    /// `(instruction count, vector, interrupted pc)`
+    pub interrupts: Vec<(u64, u8, u16)>,
+    pub instructions: u64,
+}
+
+impl Timeline {
+    pub fn new(sample_interval: u64) -> Self {
+        Timeline {
+            sample_interval: sample_interval.max(1),
+            ..Timeline::default()
+        }
+    }
+
+    /// Record one executed instruction; `count` is the instruction count
+    /// after it ran and `r0` the value of R0 afterwards.
+    pub fn record(&mut self, count: u64, pc: u16, instruction: u16, next_pc: u16, r0: u16) {
+        self.instructions = count;
+        if count % self.sample_interval == 1 || self.sample_interval == 1 {
+            self.samples.push((count, pc));
+        }
+        match instruction >> 12 {
+            OPCODE_JSR => self.calls.push((count, pc, next_pc)),
+            OPCODE_TRAP => self.traps.push((count, pc, instruction & 0xFF, r0)),
+            _ if instruction == RET => self.returns.push((count, pc)),
+            _ => {}
+        }
+    }
+
+    pub fn interrupt(&mut self, count: u64, vector: u8, pc: u16) {
+        self.interrupts.push((count, vector, pc));
+    }
+
+    /// The timeline as one JSON object (`"kind": "timeline"`); events are
+    /// arrays of numbers in the field order documented on [`Timeline`].
+    pub fn to_json(&self) -> String {
+        let samples = json::array(self.samples.iter().map(|(count, pc)| format!("[{},{}]", count, pc)));
+        let calls = json::array(self.calls.iter().map(|(count, from, to)| format!("[{},{},{}]", count, from, to)));
+        let returns = json::array(self.returns.iter().map(|(count, pc)| format!("[{},{}]", count, pc)));
+        let traps = json::array(
+            self.traps
+                .iter()
+                .map(|(count, pc, vector, r0)| format!("[{},{},{},{}]", count, pc, vector, r0)),
+        );
+        let interrupts = json::array(
+            self.interrupts
+                .iter()
+                .map(|(count, vector, pc)| format!("[{},{},{}]", count, vector, pc)),
+        );
+        format!(
+            "{{\"kind\":\"timeline\",\"version\":1,\"instructions\":{},\"sample_interval\":{},\"samples\":{},\"calls\":{},\"returns\":{},\"traps\":{},\"interrupts\":{}}}",
+            self.instructions, self.sample_interval, samples, calls, returns, traps, interrupts
+        )
+    }
+
+    /// A self-contained HTML page that plots the timeline; open it in any browser.
+    pub fn to_html(&self) -> String {
+        VIEWER.replace(DATA_PLACEHOLDER, &self.to_json())
+    }
+
+    /// The viewer page without data; it offers to open a timeline JSON file instead.
+    pub fn viewer() -> &'static str {
+        VIEWER
+    }
+}
@@ -0,0 +1,196 @@
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use crate::color::{Painter, Role};
+use crate::summary::condition_code;
+use crate::vm::{REGISTER, Vm};
+
+/// Machine state after one executed instruction, as stored one per line in
+/// a JSONL trace file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub step: u64,
+    /// Address the instruction was fetched from.
+    pub pc: u16,
+    pub instruction: u16,
+    /// R0-R7 after the instruction.
+    pub registers: [u16; 8],
+    pub next_pc: u16,
+    pub cond: u16,
+}
+
+impl TraceEntry {
+    pub fn capture(vm: &Vm, step: u64, pc: u16, instruction: u16) -> Self {
+        let mut registers = [0; 8];
+        registers.copy_from_slice(&vm.registers[..8]);
+        TraceEntry {
+            step,
+            pc,
+            instruction,
+            registers,
+            next_pc: vm.registers[REGISTER::PC as usize],
+            cond: vm.registers[REGISTER::COND as usize],
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let registers: Vec<String> = self.registers.iter().map(u16::to_string).collect();
+        format!(
+            "{{\"step\":{},\"pc\":{},\"instruction\":{},\"registers\":[{}],\"next_pc\":{},\"cond\":{}}}",
+            self.step,
+            self.pc,
+            self.instruction,
+            registers.join(","),
+            self.next_pc,
+            self.cond
+        )
+    }
+
+    /// Parse a line written by [`TraceEntry::to_json`]. Key order does not matter.
+    pub fn from_json(line: &str) -> Option<Self> {
+        let body = line.trim().strip_prefix('{')?.strip_suffix('}')?;
+        let mut entry = TraceEntry {
+            step: 0,
+            pc: 0,
+            instruction: 0,
+            registers: [0; 8],
+            next_pc: 0,
+            cond: 0,
+        };
+        let mut rest = body;
+        while !rest.is_empty() {
+            let (key, after) = rest.strip_prefix('"')?.split_once("\":")?;
+            let (value, after) = if let Some(array) = after.strip_prefix('[') {
+                let (array, after) = array.split_once(']')?;
+                (array, after)
+            } else {
+                after.split_once(',').map_or((after, ""), |(value, after)| (value, after))
+            };
+            match key {
+                "step" => entry.step = value.trim().parse().ok()?,
+                "pc" => entry.pc = value.trim().parse().ok()?,
+                "instruction" => entry.instruction = value.trim().parse().ok()?,
+                "next_pc" => entry.next_pc = value.trim().parse().ok()?,
+                "cond" => entry.cond = value.trim().parse().ok()?,
+                "registers" => {
+                    let values: Vec<u16> = value.split(',').map(|v| v.trim().parse().ok()).collect::<Option<_>>()?;
+                    entry.registers = values.try_into().ok()?;
+                }
+                _ => {}
+            }
+            rest = after.strip_prefix(',').unwrap_or(after);
+        }
+        Some(entry)
+    }
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "step {} at 0x{:04X} [{:04X}] ->", self.step, self.pc, self.instruction)?;
+        for (reg, value) in self.registers.iter().enumerate() {
+            write!(f, " R{}={:04X}", reg, value)?;
+        }
+        write!(f, " PC={:04X} COND={}", self.next_pc, condition_code(self.cond))
+    }
+}
+
+/// Where a run stopped matching its golden trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Divergence {
+    /// Both executed a step but ended in different states.
+    State { expected: TraceEntry, actual: TraceEntry },
+    /// The golden trace has more steps but the VM halted.
+    HaltedEarly { expected: TraceEntry },
+    /// The VM kept running past the end of the golden trace.
+    RanLonger { actual: TraceEntry },
+}
+
+impl Divergence {
+    /// Describe the divergence, coloring the expected and actual states.
+    pub fn render(&self, painter: &Painter) -> String {
+        let expected = |entry: &TraceEntry| painter.paint(Role::Expected, &entry.to_string());
+        let actual = |entry: &TraceEntry| painter.paint(Role::Actual, &entry.to_string());
+        match self {
+            Divergence::State { expected: e, actual: a } => {
+                format!("trace diverged\n  expected: {}\n  actual:   {}", expected(e), actual(a))
+            }
+            Divergence::HaltedEarly { expected: e } => {
+                format!("program halted but the trace continues\n  expected: {}", expected(e))
+            }
+            Divergence::RanLonger { actual: a } => {
+                format!("program kept running past the end of the trace\n  actual:   {}", actual(a))
+            }
+        }
+    }
+}
+
+impl Divergence {
+    /// A JSON object (`"kind": "trace-divergence"`) with the reason and the
+    /// trace entries involved, each in the trace file format.
+    pub fn to_json(&self) -> String {
+        let (reason, expected, actual) = match self {
+            Divergence::State { expected, actual } => ("state", Some(expected), Some(actual)),
+            Divergence::HaltedEarly { expected } => ("halted-early", Some(expected), None),
+            Divergence::RanLonger { actual } => ("ran-longer", None, Some(actual)),
+        };
+        let entry = |entry: Option<&TraceEntry>| entry.map_or("null".to_string(), TraceEntry::to_json);
+        format!(
+            "{{\"kind\":\"trace-divergence\",\"reason\":\"{}\",\"expected\":{},\"actual\":{}}}",
+            reason,
+            entry(expected),
+            entry(actual)
+        )
+    }
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.render(&Painter::plain()))
+    }
+}
+
+/* execute one instruction and describe the result */
+fn traced_step(vm: &mut Vm, step: u64) -> TraceEntry {
+    let pc = vm.registers[REGISTER::PC as usize];
+    let instruction = vm.memory[pc as usize];
+    vm.step();
+    TraceEntry::capture(vm, step, pc, instruction)
+}
+
+/// Run to HALT, writing a [`TraceEntry`] line for every instruction.
+pub fn record(vm: &mut Vm, out: &mut impl Write) -> io::Result<()> {
+    let mut step = 0;
+    while !vm.halted {
+        writeln!(out, "{}", traced_step(vm, step).to_json())?;
+        step += 1;
+    }
+    out.flush()
+}
+
+/// Run in lock-step with a golden trace, stopping at the first divergence.
+/// Returns the number of matching steps when the run and the trace agree.
+pub fn compare(vm: &mut Vm, golden: impl BufRead) -> io::Result<Result<u64, Divergence>> {
+    let mut step = 0;
+    for line in golden.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let expected = TraceEntry::from_json(&line).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("malformed trace line {}", step + 1))
+        })?;
+        if vm.halted {
+            return Ok(Err(Divergence::HaltedEarly { expected }));
+        }
+        let actual = traced_step(vm, step);
+        if actual != expected {
+            return Ok(Err(Divergence::State { expected, actual }));
+        }
+        step += 1;
+    }
+    if !vm.halted {
+        let actual = traced_step(vm, step);
+        return Ok(Err(Divergence::RanLonger { actual }));
+    }
+    Ok(Ok(step))
+}
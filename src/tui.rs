@@ -0,0 +1,336 @@
+//! Full-screen debugger (`lc3-vm debug --tui`): registers, disassembly
+//! around the PC, a memory hexdump and the guest's console output on one
+//! screen, with the debugger's commands typed into a command bar.
+
+use std::io;
+use std::time::{Duration, Instant};
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::color::Painter;
+use crate::console::QueuedConsole;
+use crate::debugger::{Debugger, DebuggerAction, render_context, report_stop};
+use crate::summary::condition_code;
+use crate::vm::{REGISTER, RunState, StepOutcome, Vm};
+
+/* instructions run between looks at the keyboard while the program runs */
+const CHUNK: u64 = 10_000;
+
+/* redraws while running are at most this often */
+const FRAME: Duration = Duration::from_millis(33);
+
+/* how long to wait for a key while the guest waits for one */
+const INPUT_POLL: Duration = Duration::from_millis(50);
+
+/* debugger output lines kept for the log pane */
+const LOG_LINES: usize = 500;
+
+/* words per hexdump row */
+const ROW_WORDS: u16 = 8;
+
+const KEYS: &str = " F5 continue  F6 pause  F10 next  F11 step  Shift+F11 finish  PgUp/PgDn memory  Ctrl+C quit ";
+
+struct Tui {
+    debugger: Debugger,
+    /* registers when the program last stopped, to highlight what changed since */
+    previous: [u16; REGISTER::COUNT as usize],
+    memory_start: u16,
+    memory_rows: u16,
+    command: String,
+    log: Vec<String>,
+    running: bool,
+    /* the guest is waiting for a key */
+    waiting: bool,
+    quit: bool,
+}
+
+/// Debug `vm` full screen until the user quits or the program halts and
+/// the user then quits. `paused` opens the command bar before the first
+/// instruction; otherwise the program starts running at once. The guest's
+/// output is shown in its own pane, and keys typed while it runs go to it.
+/// What it printed is passed on to the original console on the way out.
+pub fn run(vm: &mut Vm, paused: bool) -> io::Result<()> {
+    let original = std::mem::replace(&mut vm.console, Box::new(QueuedConsole::default()));
+    let pc = vm.registers[REGISTER::PC as usize];
+    let mut tui = Tui {
+        debugger: Debugger::new(),
+        previous: vm.registers,
+        memory_start: pc & !(ROW_WORDS - 1),
+        memory_rows: 0,
+        command: String::new(),
+        log: Vec::new(),
+        running: false,
+        waiting: false,
+        quit: false,
+    };
+    if paused {
+        tui.stopped(vm);
+    } else {
+        tui.resume(vm);
+    }
+    let mut terminal = ratatui::init();
+    let result = tui.event_loop(vm, &mut terminal);
+    ratatui::restore();
+
+    let console = std::mem::replace(&mut vm.console, original);
+    if let Some(queued) = console.downcast_ref::<QueuedConsole>() {
+        vm.console.write(&String::from_utf8_lossy(&queued.output))?;
+    }
+    result
+}
+
+impl Tui {
+    fn event_loop(&mut self, vm: &mut Vm, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        let mut drawn: Option<Instant> = None;
+        while !self.quit {
+            if !self.running || drawn.is_none_or(|drawn| drawn.elapsed() >= FRAME) {
+                terminal.draw(|frame| self.draw(frame, vm))?;
+                drawn = Some(Instant::now());
+            }
+            if self.running && !self.waiting {
+                self.run_chunk(vm);
+                if event::poll(Duration::ZERO)? {
+                    self.handle(vm, event::read()?)?;
+                }
+            } else if !self.running || event::poll(INPUT_POLL)? {
+                self.handle(vm, event::read()?)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn run_chunk(&mut self, vm: &mut Vm) {
+        match vm.run_for(CHUNK) {
+            Ok(result) => match result.state {
+                RunState::Yielded => {}
+                RunState::WaitingForInput => self.waiting = true,
+                _ => self.stopped(vm),
+            },
+            Err(err) => {
+                self.push_log(&format!("fault: {}", err));
+                self.stopped(vm);
+            }
+        }
+    }
+
+    fn handle(&mut self, vm: &mut Vm, event: Event) -> io::Result<()> {
+        let Event::Key(key) = event else {
+            return Ok(());
+        };
+        if key.kind == KeyEventKind::Release {
+            return Ok(());
+        }
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('c') if control => self.quit = true,
+            KeyCode::F(6) if self.running => self.stopped(vm),
+            KeyCode::Char(']') if self.running && control => self.stopped(vm),
+            KeyCode::PageUp => self.memory_start = self.memory_start.wrapping_sub(self.memory_rows * ROW_WORDS),
+            KeyCode::PageDown => self.memory_start = self.memory_start.wrapping_add(self.memory_rows * ROW_WORDS),
+            KeyCode::Home => self.memory_start = vm.registers[REGISTER::PC as usize] & !(ROW_WORDS - 1),
+            _ if self.running => self.type_to_guest(vm, key),
+            KeyCode::F(5) => self.execute(vm, "continue")?,
+            KeyCode::F(10) => self.execute(vm, "next")?,
+            KeyCode::F(11) if key.modifiers.contains(KeyModifiers::SHIFT) => self.execute(vm, "finish")?,
+            KeyCode::F(11) => self.execute(vm, "step")?,
+            KeyCode::Char(c) if !control => self.command.push(c),
+            KeyCode::Backspace => {
+                self.command.pop();
+            }
+            KeyCode::Enter => {
+                let command = std::mem::take(&mut self.command);
+                self.execute(vm, &command)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn type_to_guest(&mut self, vm: &mut Vm, key: KeyEvent) {
+        let byte = match key.code {
+            KeyCode::Char(c) if c.is_ascii() => c as u8,
+            KeyCode::Enter => b'\n',
+            KeyCode::Backspace => 0x08,
+            KeyCode::Tab => b'\t',
+            KeyCode::Esc => 0x1B,
+            _ => return,
+        };
+        if let Some(console) = vm.console.downcast_mut::<QueuedConsole>() {
+            console.input.push_back(byte);
+        }
+        self.waiting = false;
+    }
+
+    /* run a debugger command as if typed at the prompt */
+    fn execute(&mut self, vm: &mut Vm, command: &str) -> io::Result<()> {
+        self.push_log(&format!("(lc3) {}", command));
+        let (count, registers, halted) = (vm.instruction_count, vm.registers, vm.halted);
+        let mut output = Vec::new();
+        let action = self.debugger.execute(vm, command, &mut output)?;
+        self.push_log(&String::from_utf8_lossy(&output));
+        if vm.instruction_count != count {
+            self.previous = registers;
+            self.memory_start = self.memory_start_for(vm);
+        }
+        match action {
+            Some(DebuggerAction::Continue) => self.resume(vm),
+            Some(DebuggerAction::Quit) => self.quit = true,
+            None if vm.halted && !halted => self.push_log("program halted"),
+            None => {}
+        }
+        Ok(())
+    }
+
+    fn resume(&mut self, vm: &mut Vm) {
+        self.previous = vm.registers;
+        self.running = true;
+        self.waiting = false;
+        /* like Vm::run, step off a breakpoint at the PC so continuing makes progress */
+        if vm.breakpoints.contains(&vm.registers[REGISTER::PC as usize])
+            && let StepOutcome::Faulted { pc, instruction } = vm.step()
+        {
+            self.push_log(&format!("fault: cannot execute 0x{:04X} at 0x{:04X}", instruction, pc));
+            self.stopped(vm);
+        }
+    }
+
+    fn stopped(&mut self, vm: &mut Vm) {
+        self.running = false;
+        self.waiting = false;
+        if vm.halted {
+            self.push_log("program halted");
+        } else {
+            let mut output = Vec::new();
+            let _ = report_stop(vm, &mut output);
+            self.push_log(&String::from_utf8_lossy(&output));
+        }
+        self.memory_start = self.memory_start_for(vm);
+    }
+
+    /* keep the scroll position unless the PC has left the rows shown */
+    fn memory_start_for(&self, vm: &Vm) -> u16 {
+        let pc = vm.registers[REGISTER::PC as usize];
+        if pc.wrapping_sub(self.memory_start) < self.memory_rows.max(1) * ROW_WORDS {
+            self.memory_start
+        } else {
+            pc & !(ROW_WORDS - 1)
+        }
+    }
+
+    fn push_log(&mut self, text: &str) {
+        self.log.extend(text.lines().map(str::to_string));
+        let excess = self.log.len().saturating_sub(LOG_LINES);
+        self.log.drain(..excess);
+    }
+
+    fn draw(&mut self, frame: &mut Frame, vm: &Vm) {
+        let [top, middle, log, command, keys] = Layout::vertical([
+            Constraint::Percentage(45),
+            Constraint::Fill(1),
+            Constraint::Length(8),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [registers, disassembly] = Layout::horizontal([Constraint::Length(24), Constraint::Fill(1)]).areas(top);
+        let [memory, output] = Layout::horizontal([Constraint::Length(62), Constraint::Fill(1)]).areas(middle);
+
+        self.draw_registers(frame, vm, registers);
+        self.draw_disassembly(frame, vm, disassembly);
+        self.draw_memory(frame, vm, memory);
+        draw_output(frame, vm, output);
+        let shown = self.log.len().saturating_sub(log.height.saturating_sub(2) as usize);
+        let lines: Vec<Line> = self.log[shown..].iter().map(|line| Line::raw(line.as_str())).collect();
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(" Debugger ")), log);
+
+        let prompt = if !self.running {
+            format!("(lc3) {}", self.command)
+        } else if self.waiting {
+            "waiting for a key; type to the program (F6 pauses)".to_string()
+        } else {
+            format!("running, {} instructions (F6 pauses)", vm.instruction_count)
+        };
+        frame.render_widget(Paragraph::new(prompt.as_str()), command);
+        if !self.running {
+            frame.set_cursor_position((command.x + prompt.chars().count() as u16, command.y));
+        }
+        frame.render_widget(Paragraph::new(KEYS).style(Style::new().add_modifier(Modifier::REVERSED)), keys);
+    }
+
+    fn draw_registers(&self, frame: &mut Frame, vm: &Vm, area: Rect) {
+        let changed = Style::new().fg(Color::Yellow).add_modifier(Modifier::BOLD);
+        let mut lines: Vec<Line> = (0..8)
+            .map(|index| {
+                let value = vm.registers[index];
+                let text = format!("R{}  x{:04X} {:>6}", index, value, value as i16);
+                if value != self.previous[index] { Line::styled(text, changed) } else { Line::raw(text) }
+            })
+            .collect();
+        let pc = vm.registers[REGISTER::PC as usize];
+        lines.push(Line::raw(format!("PC  x{:04X}", pc)));
+        lines.push(Line::raw(format!("CC  {}", condition_code(vm.registers[REGISTER::COND as usize]))));
+        lines.push(Line::raw(format!("PSR x{:04X}", vm.psr())));
+        lines.push(Line::raw(format!("#   {}", vm.instruction_count)));
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(" Registers ")), area);
+    }
+
+    fn draw_disassembly(&self, frame: &mut Frame, vm: &Vm, area: Rect) {
+        let pc = vm.registers[REGISTER::PC as usize];
+        let height = area.height.saturating_sub(2);
+        let before = height / 3;
+        let text = render_context(vm, &Painter::plain(), pc, before, height.saturating_sub(before + 1));
+        let lines: Vec<Line> = text
+            .lines()
+            .map(|line| match line {
+                _ if line.starts_with("=>") => Line::styled(line.to_string(), Style::new().add_modifier(Modifier::REVERSED)),
+                _ if line.ends_with(':') => Line::styled(line.to_string(), Style::new().fg(Color::Cyan)),
+                _ => Line::raw(line.to_string()),
+            })
+            .collect();
+        let title = match vm.symbols.name_for(pc) {
+            Some(name) => format!(" Disassembly: {} ", name),
+            None => " Disassembly ".to_string(),
+        };
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(title)), area);
+    }
+
+    fn draw_memory(&mut self, frame: &mut Frame, vm: &Vm, area: Rect) {
+        self.memory_rows = area.height.saturating_sub(2);
+        let pc = vm.registers[REGISTER::PC as usize];
+        let lines: Vec<Line> = (0..self.memory_rows)
+            .map(|row| {
+                let start = self.memory_start.wrapping_add(row * ROW_WORDS);
+                let mut spans = vec![Span::styled(format!("x{:04X} ", start), Style::new().fg(Color::Blue))];
+                let mut text = String::new();
+                for column in 0..ROW_WORDS {
+                    let address = start.wrapping_add(column);
+                    let word = vm.memory[address as usize];
+                    let style = if address == pc { Style::new().add_modifier(Modifier::REVERSED) } else { Style::new() };
+                    spans.push(Span::raw(" "));
+                    spans.push(Span::styled(format!("{:04X}", word), style));
+                    text.push(if (0x20..0x7F).contains(&word) { word as u8 as char } else { '.' });
+                }
+                spans.push(Span::raw(format!("  {}", text)));
+                Line::from(spans)
+            })
+            .collect();
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(" Memory ")), area);
+    }
+}
+
+/* the end of what the guest printed, as much as fits */
+fn draw_output(frame: &mut Frame, vm: &Vm, area: Rect) {
+    let printed = match vm.console.downcast_ref::<QueuedConsole>() {
+        Some(console) => String::from_utf8_lossy(&console.output).replace('\r', ""),
+        None => String::new(),
+    };
+    let lines: Vec<&str> = printed.split('\n').collect();
+    let shown = lines.len().saturating_sub(area.height.saturating_sub(2) as usize);
+    let lines: Vec<Line> = lines[shown..].iter().map(|line| Line::raw(*line)).collect();
+    frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(" Console ")), area);
+}
@@ -0,0 +1,2181 @@
+use std::any::Any;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Read, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::rc::Rc;
+
+use std::sync::mpsc::Receiver;
+
+use tracing::{debug, debug_span, error, trace, warn};
+
+use crate::bus::Bus;
+#[cfg(feature = "devices-extra")]
+use crate::devices::Devices;
+use crate::console::{Console, TerminalConsole, eprint_report};
+use crate::cpu::{Cpu, CpuStep, Io, Memory};
+use crate::disasm::disassemble_with;
+use crate::error::VmError;
+use crate::events::{EventBus, VmEvent};
+use crate::callstack::CallStack;
+use crate::checkpoints::Checkpoints;
+use crate::history::History;
+use crate::instruction::{Instruction, decode};
+use crate::limits::Limits;
+use crate::symbols::SymbolTable;
+use crate::throttle::Throttle;
+use crate::observer::Observer;
+use crate::profiler::Profiler;
+use crate::program::Program;
+use crate::recent::RecentInstructions;
+use crate::recording::InputRecording;
+use crate::shared::SharedWindow;
+use crate::state::VmState;
+use crate::timeline::Timeline;
+use crate::watchdog::{Watchdog, WatchdogPolicy};
+#[cfg(feature = "terminal")]
+use crate::terminal::{take_dump_request, take_trace_toggle_request};
+
+pub const MEMORY_SIZE: usize = 1 << 16;
+
+pub const PC_START: u16 = 0x3000; /* default starting position for the program counter */
+
+/* Ctrl+], the default host key for breaking into the debugger */
+pub const DEFAULT_BREAK_KEY: u16 = 0x1D;
+
+/* Ctrl+^ (Ctrl+6 on most keyboards), the default host key for the machine monitor */
+pub const DEFAULT_MONITOR_KEY: u16 = 0x1E;
+
+/* how many instructions run between keyboard polls when a break key is set */
+const KEY_POLL_INTERVAL: u64 = 1024;
+
+/* interrupt service routine addresses are stored at INTERRUPT_VECTOR_TABLE + vector */
+pub const INTERRUPT_VECTOR_TABLE: u16 = 0x0100;
+
+/* the supervisor stack grows down from here, below the user program space */
+pub const INITIAL_SUPERVISOR_STACK: u16 = 0x3000;
+
+/* PSR bit 15: set while running in user mode */
+const PSR_USER_MODE: u16 = 1 << 15;
+
+/* first address of the memory-mapped device region (the last page of memory) */
+pub const DEVICE_REGION_START: u16 = 0xFE00;
+
+/* command-line arguments for the guest fill the page below the devices, see Vm::set_args */
+pub const ARGS_START: u16 = 0xFD00;
+
+pub enum REGISTER {
+    R0,
+    R1,
+    R2,
+    R3,
+    R4,
+    R5,
+    R6,
+    R7,
+    PC, /* program counter */
+    COND,
+    COUNT
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum InstructionSet
+{
+    BR, /* branch */
+    ADD,    /* add  */
+    LD,     /* load */
+    ST,     /* store */
+    JSR,    /* jump register */
+    AND,    /* bitwise and */
+    LDR,    /* load register */
+    STR,    /* store register */
+    RTI,    /* return from interrupt */
+    NOT,    /* bitwise not */
+    LDI,    /* load indirect */
+    STI,    /* store indirect */
+    JMP,    /* jump */
+    RES,    /* reserved (unused) */
+    LEA,    /* load effective address */
+    TRAP    /* execute trap */
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConditionFlags
+{
+    POS = 1 << 0, /* P */
+    ZRO = 1 << 1, /* Z */
+    NEG = 1 << 2, /* N */
+}
+
+#[derive(Debug)]
+pub enum TrapCodes {
+    GETC = 0x20,  /* get character from keyboard, not echoed onto the terminal */
+    OUT = 0x21,   /* output a character */
+    PUTS = 0x22,  /* output a word string */
+    IN = 0x23,    /* get character from keyboard, echoed onto the terminal */
+    PUTSP = 0x24, /* output a byte string */
+    HALT = 0x25   /* halt the program */
+}
+
+pub enum MemoryMappedRegisters {
+    KBSR = 0xFE00, /* keyboard status */
+    KBDR = 0xFE02  /* keyboard data */
+}
+
+/// Why [`Vm::run`] returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunState {
+    Halted,
+    /// Stopped on request (e.g. the break key); calling `run` again resumes.
+    Paused,
+    /// About to execute the instruction at a breakpoint address.
+    Breakpoint(u16),
+    /// The last instruction touched a watched address; calling `run` again
+    /// goes on with the next one.
+    Watchpoint(WatchHit),
+    /// The last instruction set a watched register to its target value;
+    /// calling `run` again goes on with the next one.
+    RegisterWatch(RegisterHit),
+    /// [`Vm::step_over`] or [`Vm::step_out`] got where it was going.
+    Stepped,
+    /// [`Vm::run_for`] used up its instruction budget; calling it again resumes.
+    Yielded,
+    /// GETC or IN is waiting for a key the console cannot block for. Hand
+    /// it over with [`Vm::provide_input`] (or queue it in the console) and
+    /// run again; the TRAP then completes.
+    WaitingForInput,
+}
+
+/// Which accesses to a watched address stop [`Vm::run`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    /// Reads and writes.
+    Access,
+}
+
+impl WatchKind {
+    fn matches(self, write: bool) -> bool {
+        match self {
+            WatchKind::Read => !write,
+            WatchKind::Write => write,
+            WatchKind::Access => true,
+        }
+    }
+}
+
+/// A watched address touched by an instruction. Instruction fetches do not
+/// count; the reads and writes of the built-in TRAP routines do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    pub address: u16,
+    /// Whether the address was written rather than read.
+    pub write: bool,
+    /// Address and word of the instruction that touched it.
+    pub pc: u16,
+    pub instruction: u16,
+    /// The value before the access; the same as `new` for a read.
+    pub old: u16,
+    pub new: u16,
+}
+
+/// Stop [`Vm::run`] after any instruction that changes general-purpose
+/// register `register` (0-7) to `value`, wherever it is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterWatch {
+    pub register: u8,
+    pub value: u16,
+}
+
+/// A watched register set to its target value by an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterHit {
+    pub register: u8,
+    /// Address and word of the instruction that set it.
+    pub pc: u16,
+    pub instruction: u16,
+    pub old: u16,
+    pub new: u16,
+}
+
+/// How a [`Vm::run_for`] call ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunResult {
+    pub state: RunState,
+    /// Instructions executed by this call.
+    pub executed: u64,
+}
+
+/// What a single [`Vm::step`] did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// An instruction ran and execution continues at the new PC.
+    Continued,
+    /// A TRAP other than HALT ran; holds the trap vector.
+    Trapped(u16),
+    /// The guest is halted: it ran HALT (or the console's input ran out) during
+    /// this step, or had already halted before it.
+    Halted,
+    /// The instruction at `pc` cannot execute (a reserved opcode with no
+    /// handler, or RTI in user mode) and [`Vm::on_illegal`] is
+    /// [`IllegalPolicy::Halt`]. The PC is left on it and the VM is halted.
+    Faulted { pc: u16, instruction: u16 },
+    /// GETC or IN found no key, and either a break was requested while
+    /// waiting or the console does not block. The instruction did not run;
+    /// the next step retries it.
+    WaitingForInput,
+}
+
+/// Iterator returned by [`Vm::steps`]. Each item is the address of an
+/// executed instruction, the instruction, and the registers right after it.
+pub struct Steps<'a> {
+    vm: &'a mut Vm,
+}
+
+impl Iterator for Steps<'_> {
+    type Item = (u16, Instruction, [u16; REGISTER::COUNT as usize]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let count = self.vm.instruction_count;
+        self.vm.step();
+        /* halted, faulted or waiting for input: nothing ran */
+        if self.vm.instruction_count == count {
+            return None;
+        }
+        let (pc, word) = self.vm.fetched;
+        Some((pc, decode(word), self.vm.registers))
+    }
+}
+
+/// Whether a device-region access is a load or a store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MmioDirection {
+    Read,
+    Write,
+}
+
+/// A single access to the memory-mapped device region.
+///
+/// For reads `value` is what the built-in devices (or plain memory) would
+/// return; for writes it is the value the guest is storing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MmioAccess {
+    pub address: u16,
+    pub value: u16,
+    pub direction: MmioDirection,
+}
+
+/// What the VM should do with a device-region access after the hook has seen it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MmioAction {
+    /// Carry on as normal.
+    Pass,
+    /// Drop the access: writes are discarded and reads return 0.
+    Veto,
+    /// Reads return this value instead; writes store it instead.
+    Replace(u16),
+}
+
+/// A request for the processor's attention from a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Interrupt {
+    /// Index into the interrupt vector table at `0x0100`.
+    pub vector: u8,
+    /// Priority level 0-7; the interrupt is taken once it exceeds the running priority.
+    pub priority: u8,
+}
+
+/* the keyboard's slot in the interrupt vector table */
+pub const KEYBOARD_INTERRUPT: Interrupt = Interrupt { vector: 0x80, priority: 4 };
+
+/* exception vectors, entered at the running priority */
+pub const PRIVILEGE_EXCEPTION: u8 = 0x00;
+pub const ILLEGAL_OPCODE_EXCEPTION: u8 = 0x01;
+
+/// What the VM does with an instruction it cannot execute: the reserved
+/// opcode with no [`Vm::set_reserved_handler`] handler, or RTI in user mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IllegalPolicy {
+    /// Stop with [`StepOutcome::Faulted`], the PC left on the instruction.
+    #[default]
+    Halt,
+    /// Log a warning and go on with the next instruction.
+    Ignore,
+    /// Raise the LC-3 exception the guest's OS handles: vector x01 for the
+    /// reserved opcode, x00 for RTI in user mode.
+    Exception,
+    /// Panic the host, e.g. to get a backtrace of an embedding application.
+    Panic,
+}
+
+impl IllegalPolicy {
+    /// The policy named `halt`, `ignore`, `exception` or `panic`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "halt" => Some(IllegalPolicy::Halt),
+            "ignore" => Some(IllegalPolicy::Ignore),
+            "exception" => Some(IllegalPolicy::Exception),
+            "panic" => Some(IllegalPolicy::Panic),
+            _ => None,
+        }
+    }
+}
+
+/* a periodic timer, for injected interrupts; no timer device drives it */
+pub const TIMER_INTERRUPT: Interrupt = Interrupt { vector: 0x81, priority: 4 };
+
+/// Something to inject when the instruction count reaches a given value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Injection {
+    /// Deliver a key to the keyboard and raise [`KEYBOARD_INTERRUPT`].
+    Key(u8),
+    Interrupt(Interrupt),
+}
+
+impl Injection {
+    /// Parse `key=C`, `timer`, or `VECTOR[:PRIORITY]` (e.g. `x85:6`; priority 4 by default).
+    pub fn parse(text: &str) -> Option<Self> {
+        if let Some(key) = text.strip_prefix("key=") {
+            let mut chars = key.chars();
+            return match (chars.next(), chars.next()) {
+                (Some(c), None) if c.is_ascii() => Some(Injection::Key(c as u8)),
+                _ => None,
+            };
+        }
+        if text == "timer" {
+            return Some(Injection::Interrupt(TIMER_INTERRUPT));
+        }
+        let (vector, priority) = text.split_once(':').unwrap_or((text, "4"));
+        let vector = u8::try_from(crate::address::parse_address(vector)?).ok()?;
+        let priority = priority.parse().ok().filter(|priority| *priority <= 7)?;
+        Some(Injection::Interrupt(Interrupt { vector, priority }))
+    }
+}
+
+pub type MmioHook = Box<dyn FnMut(&MmioAccess) -> MmioAction>;
+
+pub type ReservedHandler = Box<dyn FnMut(&mut Vm, u16)>;
+
+pub type TrapHandler = Box<dyn FnMut(&mut Vm)>;
+
+pub struct Vm {
+    pub memory: [u16; MEMORY_SIZE],
+    pub registers: [u16; REGISTER::COUNT as usize],
+    pub instruction_count: u64,
+    pub halted: bool,
+    /// Host key that pauses [`Vm::run`] instead of reaching the guest; `None` disables it.
+    pub break_key: Option<u16>,
+    break_requested: bool,
+    /// Host key that pauses [`Vm::run`] like [`Vm::break_key`], asking for
+    /// the machine monitor instead of the debugger (see
+    /// [`Vm::take_monitor_request`]); `None` disables it.
+    pub monitor_key: Option<u16>,
+    monitor_requested: bool,
+    /* the last run stopped at the breakpoint under the PC, which the next run steps over */
+    at_breakpoint: bool,
+    /* the keyboard latch behind KBSR/KBDR: set when a host key arrives,
+       cleared when the guest reads KBDR, which keeps returning the last key */
+    key_ready: bool,
+    key_value: u16,
+    /* IN printed its prompt but is still waiting for the key */
+    in_prompt_shown: bool,
+    /* the first failure to write to the console, which ends the run */
+    console_error: Option<io::Error>,
+    /* address and word of the last instruction fetched, for Steps */
+    fetched: (u16, u16),
+    /// Addresses at which [`Vm::run`] stops before executing the instruction there.
+    pub breakpoints: BTreeSet<u16>,
+    /// Trap vectors at which [`Vm::run`] stops before executing a TRAP to
+    /// them, reporting a [`RunState::Breakpoint`] at the TRAP.
+    pub trap_breakpoints: BTreeSet<u8>,
+    /// Address ranges at which [`Vm::run`] stops when the PC enters them
+    /// from outside (a jump into one, or running on into its start),
+    /// reporting a [`RunState::Breakpoint`] at the first address reached.
+    pub range_breakpoints: Vec<RangeInclusive<u16>>,
+    /// When set, [`Vm::run`] stops before every TRAP, as for
+    /// [`Vm::trap_breakpoints`].
+    pub break_on_traps: bool,
+    /// Addresses at which [`Vm::run`] stops after an instruction reads or
+    /// writes them, per their [`WatchKind`].
+    pub watchpoints: BTreeMap<u16, WatchKind>,
+    /* the first watched access of the current step */
+    watch_hit: Option<WatchHit>,
+    /// Register values at which [`Vm::run`] stops, see [`RegisterWatch`].
+    pub register_watches: Vec<RegisterWatch>,
+    /* the first watched register the current step set */
+    register_hit: Option<RegisterHit>,
+    /// Keyboard input and display output; the host terminal unless replaced,
+    /// e.g. by a [`CapturedConsole`](crate::console::CapturedConsole) that keeps both in memory.
+    pub console: Box<dyn Console>,
+    /// When set, every executed instruction is recorded here.
+    pub profiler: Option<Profiler>,
+    /// When set, calls, traps, interrupts and PC samples are recorded here.
+    pub timeline: Option<Timeline>,
+    /// When set, every executed instruction is disassembled to stderr.
+    pub trace_stderr: bool,
+    /// When set, keys taken by the built-in GETC are shown on the console as
+    /// IN shows them; R0, the flags and the guest's output are unchanged.
+    pub echo_getc: bool,
+    /// What to do with the reserved opcode (when no handler is set) and RTI
+    /// in user mode; halting by default.
+    pub on_illegal: IllegalPolicy,
+    /// When set, every executed instruction is disassembled to this writer,
+    /// e.g. a file (`--trace FILE`); it is dropped if a write fails.
+    pub trace_output: Option<Box<dyn Write>>,
+    /// When set, a stalled guest is reported on stderr (and halted, per its policy).
+    pub watchdog: Option<Watchdog>,
+    /// When set, the guest is halted once it exceeds an instruction count or a run time.
+    pub limits: Option<Limits>,
+    /// When set, the guest is slowed to about this many instructions per
+    /// second, and the keyboard and signals are polled whenever it waits.
+    pub throttle: Option<Throttle>,
+    /// When set, recent instructions are recorded here so that
+    /// [`Vm::step_back`] and [`Vm::run_back`] can undo them.
+    pub history: Option<History>,
+    /// When set, the whole machine is snapshotted every so many
+    /// instructions, so [`Vm::travel_to`] can go back a long way at once.
+    pub checkpoints: Option<Checkpoints>,
+    /// When set, the last few instructions executed are kept here, to show
+    /// what led up to an error.
+    pub recent: Option<RecentInstructions>,
+    /// When set, subroutine calls and returns are followed here, for
+    /// backtraces.
+    pub call_stack: Option<CallStack>,
+    /// When set, every key the guest receives from the host (the console
+    /// or [`Vm::provide_input`]) is appended here with the instruction
+    /// count, for [`Vm::replay`] to play back.
+    pub recording: Option<InputRecording>,
+    /// When set, keys are taken from here instead of the console, each at
+    /// the instruction count it was recorded at, until it runs out. Keys
+    /// typed meanwhile are dropped, but the break key still works.
+    pub replay: Option<InputRecording>,
+    /// Labels of the loaded programs, used to name addresses in traces,
+    /// summaries and the debugger. [`Vm::load_program`] adds the program's.
+    pub symbols: SymbolTable,
+    /* privilege (bit 15) and priority (bits 10-8); the condition bits live in COND */
+    psr: u16,
+    /* R6 of the mode that is not running */
+    saved_ssp: u16,
+    saved_usp: u16,
+    pending_interrupts: Vec<Interrupt>,
+    /* injections keyed by the instruction count at which they fire; kept
+       after firing so a reset replays them */
+    scheduled: BTreeMap<u64, Vec<Injection>>,
+    /* instruction counts below this have had their injections fired */
+    next_injection: u64,
+    /* memory, PC and condition code right after the last load, restored by
+       reset; shared between clones */
+    loaded_memory: Option<Rc<[u16]>>,
+    loaded_pc: u16,
+    loaded_cond: u16,
+    /// Memory-mapped peripherals beyond the keyboard, attached with [`Bus::attach`].
+    pub bus: Bus,
+    /// TRAP services (files, host environment), attached by setting their fields.
+    #[cfg(feature = "devices-extra")]
+    pub devices: Devices,
+    /* host buffers mapped over guest memory, see map_shared */
+    shared: Vec<SharedWindow>,
+    mmio_hook: Option<MmioHook>,
+    reserved_handler: Option<ReservedHandler>,
+    trap_handlers: BTreeMap<u8, TrapHandler>,
+    events: EventBus,
+    observers: Vec<Box<dyn Observer>>,
+}
+
+/// Fork the machine, e.g. at a decision point to explore both branches.
+///
+/// The clone gets its own copy of memory, registers, processor status, the
+/// keyboard latch, pending and scheduled interrupts, breakpoints, the
+/// profiler, timeline, limits, throttle, history, checkpoints, recent instructions, call stack and input recording and replay, and the image [`Vm::reset`] returns to. Host-side
+/// attachments are handled as follows:
+///
+/// - the console is duplicated with [`Console::fork`];
+/// - bus devices are duplicated with [`Device::fork`](crate::bus::Device::fork);
+///   those bound to host resources (files, connections, audio) are left
+///   off the clone, whose addresses for them read as plain memory;
+/// - the host environment service is copied; the file service, with its
+///   open files, is not;
+/// - shared windows, the MMIO hook, reserved-opcode and trap handlers, event
+///   subscribers, observers, the trace output and the watchdog are not
+///   carried over, so the clone never calls back into the host; register
+///   them again on it if needed.
+impl Clone for Vm {
+    fn clone(&self) -> Self {
+        Vm {
+            memory: self.memory,
+            registers: self.registers,
+            instruction_count: self.instruction_count,
+            halted: self.halted,
+            break_key: self.break_key,
+            monitor_key: self.monitor_key,
+            monitor_requested: false,
+            break_requested: self.break_requested,
+            at_breakpoint: self.at_breakpoint,
+            key_ready: self.key_ready,
+            key_value: self.key_value,
+            in_prompt_shown: self.in_prompt_shown,
+            console_error: None,
+            fetched: self.fetched,
+            breakpoints: self.breakpoints.clone(),
+            trap_breakpoints: self.trap_breakpoints.clone(),
+            range_breakpoints: self.range_breakpoints.clone(),
+            break_on_traps: self.break_on_traps,
+            watchpoints: self.watchpoints.clone(),
+            watch_hit: self.watch_hit,
+            register_watches: self.register_watches.clone(),
+            register_hit: self.register_hit,
+            console: self.console.fork(),
+            profiler: self.profiler.clone(),
+            timeline: self.timeline.clone(),
+            trace_stderr: self.trace_stderr,
+            echo_getc: self.echo_getc,
+            on_illegal: self.on_illegal,
+            trace_output: None,
+            watchdog: None,
+            limits: self.limits.clone(),
+            throttle: self.throttle.clone(),
+            history: self.history.clone(),
+            checkpoints: self.checkpoints.clone(),
+            recent: self.recent.clone(),
+            call_stack: self.call_stack.clone(),
+            recording: self.recording.clone(),
+            replay: self.replay.clone(),
+            symbols: self.symbols.clone(),
+            psr: self.psr,
+            saved_ssp: self.saved_ssp,
+            saved_usp: self.saved_usp,
+            pending_interrupts: self.pending_interrupts.clone(),
+            scheduled: self.scheduled.clone(),
+            next_injection: self.next_injection,
+            loaded_memory: self.loaded_memory.clone(),
+            loaded_pc: self.loaded_pc,
+            loaded_cond: self.loaded_cond,
+            bus: self.bus.fork(),
+            #[cfg(feature = "devices-extra")]
+            devices: self.devices.fork(),
+            shared: Vec::new(),
+            mmio_hook: None,
+            reserved_handler: None,
+            trap_handlers: BTreeMap::new(),
+            events: EventBus::new(),
+            observers: Vec::new(),
+        }
+    }
+}
+
+#[cfg(feature = "devices-extra")]
+fn update_flags(addr: u16, registers: &mut [u16]) {
+    let value = registers[addr as usize];
+    if value == 0 {
+        registers[REGISTER::COND as usize] = ConditionFlags::ZRO as u16;
+    } else if (value >> 15) == 1 {
+        registers[REGISTER::COND as usize] = ConditionFlags::NEG as u16;
+    } else {
+        registers[REGISTER::COND as usize] = ConditionFlags::POS as u16;
+    }
+}
+
+fn initialize_registers(origin: u16) -> [u16; REGISTER::COUNT as usize] {
+    let mut registers: [u16; REGISTER::COUNT as usize] = [0; REGISTER::COUNT as usize];
+    /* since exactly one condition flag should be set at any given time, set the Z flag */
+    registers[REGISTER::COND as usize] = ConditionFlags::ZRO as u16;
+    /* set the PC to starting position */
+    registers[REGISTER::PC as usize] = origin;
+    registers
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Configures how a [`Vm`] starts: where the PC points, the initial
+/// condition code, and what memory holds. Useful for OS-style images that
+/// do not start at [`PC_START`], e.g.
+/// `VmBuilder::new().with_image(os).pc_start(0x0200).build()`.
+///
+/// The built VM treats this configuration as its loaded state, so
+/// [`Vm::reset`] comes back to it.
+#[derive(Debug, Clone, Default)]
+pub struct VmBuilder {
+    pc_start: Option<u16>,
+    condition: Option<ConditionFlags>,
+    regions: Vec<(u16, Vec<u16>)>,
+    images: Vec<Program>,
+}
+
+impl VmBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start executing at `pc` instead of the origin of the last image
+    /// (or [`PC_START`] without one).
+    pub fn pc_start(mut self, pc: u16) -> Self {
+        self.pc_start = Some(pc);
+        self
+    }
+
+    /// Start with this condition code set instead of Z.
+    pub fn condition(mut self, flag: ConditionFlags) -> Self {
+        self.condition = Some(flag);
+        self
+    }
+
+    /// Place `words` in memory starting at `start`. Regions are written in
+    /// the order given, before any image, and wrap around at the top of memory.
+    pub fn memory_region(mut self, start: u16, words: &[u16]) -> Self {
+        self.regions.push((start, words.to_vec()));
+        self
+    }
+
+    /// Load an image at its origin. Several images may be given; later ones
+    /// overwrite earlier ones where they overlap, and the PC starts at the
+    /// origin of the last unless [`VmBuilder::pc_start`] says otherwise.
+    pub fn with_image(mut self, program: Program) -> Self {
+        self.images.push(program);
+        self
+    }
+
+    pub fn build(self) -> Vm {
+        let mut vm = Vm::new();
+        for (start, words) in &self.regions {
+            for (i, word) in words.iter().enumerate() {
+                vm.memory[start.wrapping_add(i as u16) as usize] = *word;
+            }
+        }
+        for program in &self.images {
+            vm.load_program(program);
+        }
+        if let Some(pc) = self.pc_start {
+            vm.registers[REGISTER::PC as usize] = pc;
+        }
+        if let Some(flag) = self.condition {
+            vm.registers[REGISTER::COND as usize] = flag as u16;
+        }
+        vm.mark_loaded();
+        vm
+    }
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Vm {
+            memory: [0; MEMORY_SIZE],
+            registers: initialize_registers(PC_START),
+            instruction_count: 0,
+            halted: false,
+            break_key: None,
+            monitor_key: None,
+            monitor_requested: false,
+            break_requested: false,
+            at_breakpoint: false,
+            key_ready: false,
+            key_value: 0,
+            in_prompt_shown: false,
+            console_error: None,
+            fetched: (0, 0),
+            breakpoints: BTreeSet::new(),
+            trap_breakpoints: BTreeSet::new(),
+            range_breakpoints: Vec::new(),
+            break_on_traps: false,
+            watchpoints: BTreeMap::new(),
+            watch_hit: None,
+            register_watches: Vec::new(),
+            register_hit: None,
+            console: Box::new(TerminalConsole),
+            profiler: None,
+            timeline: None,
+            watchdog: None,
+            limits: None,
+            throttle: None,
+            history: None,
+            checkpoints: None,
+            recent: None,
+            call_stack: None,
+            recording: None,
+            replay: None,
+            symbols: SymbolTable::default(),
+            trace_stderr: false,
+            echo_getc: false,
+            on_illegal: IllegalPolicy::Halt,
+            trace_output: None,
+            psr: PSR_USER_MODE,
+            saved_ssp: INITIAL_SUPERVISOR_STACK,
+            saved_usp: 0,
+            pending_interrupts: Vec::new(),
+            scheduled: BTreeMap::new(),
+            next_injection: 0,
+            loaded_memory: None,
+            loaded_pc: PC_START,
+            loaded_cond: ConditionFlags::ZRO as u16,
+            bus: Bus::new(),
+            #[cfg(feature = "devices-extra")]
+            devices: Devices::default(),
+            shared: Vec::new(),
+            mmio_hook: None,
+            reserved_handler: None,
+            trap_handlers: BTreeMap::new(),
+            events: EventBus::new(),
+            observers: Vec::new(),
+        }
+    }
+
+    /// Copy a program into memory at its origin and point the PC at it.
+    pub fn load_program(&mut self, program: &Program) {
+        debug!(origin = program.origin, words = program.words.len(), "loading program");
+        for (i, word) in program.words.iter().enumerate() {
+            self.memory[program.origin.wrapping_add(i as u16) as usize] = *word;
+        }
+        self.symbols.extend(&program.symbols);
+        self.registers[REGISTER::PC as usize] = program.origin;
+        self.mark_loaded();
+    }
+
+    /// Start execution at `pc` instead of the loaded image's origin, e.g.
+    /// for a data-only or multi-part image. [`Vm::reset`] returns here too.
+    pub fn set_entry(&mut self, pc: u16) {
+        debug!(pc, "entry point set");
+        self.registers[REGISTER::PC as usize] = pc;
+        self.loaded_pc = pc;
+    }
+
+    /// Pass command-line arguments to the guest, like `argc`/`argv`. The
+    /// page at [`ARGS_START`] (xFD00-xFDFF) holds the count, then one
+    /// pointer per argument, then the arguments as zero-terminated strings
+    /// of one character per word, ready for PUTS. [`Vm::reset`] keeps them.
+    pub fn set_args<S: AsRef<str>>(&mut self, args: &[S]) -> Result<(), VmError> {
+        /* the count, the pointers, and each string with its terminator */
+        let size = 1 + args.len() + args.iter().map(|arg| arg.as_ref().len() + 1).sum::<usize>();
+        if size > (DEVICE_REGION_START - ARGS_START) as usize {
+            return Err(VmError::InvalidArguments(format!(
+                "{} words do not fit in the {} words at x{:04X}",
+                size,
+                DEVICE_REGION_START - ARGS_START,
+                ARGS_START
+            )));
+        }
+        let mut words = vec![0; 1 + args.len()];
+        words[0] = args.len() as u16;
+        for (index, arg) in args.iter().enumerate() {
+            words[index + 1] = ARGS_START + words.len() as u16;
+            words.extend(arg.as_ref().bytes().map(u16::from));
+            words.push(0);
+        }
+        let start = ARGS_START as usize;
+        self.memory[start..start + words.len()].copy_from_slice(&words);
+        if let Some(loaded) = self.loaded_memory.as_mut() {
+            Rc::make_mut(loaded)[start..start + words.len()].copy_from_slice(&words);
+        }
+        debug!(count = args.len(), "guest arguments set");
+        Ok(())
+    }
+
+    /* remember the current memory, PC and condition code as the state reset returns to */
+    fn mark_loaded(&mut self) {
+        self.loaded_memory = Some(self.memory.to_vec().into());
+        self.loaded_pc = self.registers[REGISTER::PC as usize];
+        self.loaded_cond = self.registers[REGISTER::COND as usize];
+    }
+
+    /// Return to the state right after the last [`Vm::load_program`]:
+    /// memory, registers, counters and attached devices are restored
+    /// without reloading the image. Breakpoints, hooks, subscribers and the
+    /// break key are kept; a profiler or timeline starts over.
+    pub fn reset(&mut self) {
+        match &self.loaded_memory {
+            Some(loaded) => self.memory.copy_from_slice(loaded),
+            None => self.memory.fill(0),
+        }
+        self.registers = initialize_registers(self.loaded_pc);
+        self.registers[REGISTER::COND as usize] = self.loaded_cond;
+        self.instruction_count = 0;
+        self.halted = false;
+        self.break_requested = false;
+        self.at_breakpoint = false;
+        self.watch_hit = None;
+        self.register_hit = None;
+        self.psr = PSR_USER_MODE;
+        self.saved_ssp = INITIAL_SUPERVISOR_STACK;
+        self.saved_usp = 0;
+        self.pending_interrupts.clear();
+        self.next_injection = 0;
+        if self.profiler.is_some() {
+            self.profiler = Some(Profiler::new(self.loaded_pc));
+        }
+        if let Some(timeline) = self.timeline.as_mut() {
+            *timeline = Timeline::new(timeline.sample_interval);
+        }
+        if let Some(watchdog) = self.watchdog.as_mut() {
+            watchdog.reset();
+        }
+        if let Some(throttle) = self.throttle.as_mut() {
+            throttle.reset();
+        }
+        if let Some(history) = self.history.as_mut() {
+            history.clear();
+        }
+        if let Some(checkpoints) = self.checkpoints.as_mut() {
+            checkpoints.clear();
+        }
+        if let Some(recent) = self.recent.as_mut() {
+            recent.clear();
+        }
+        if let Some(call_stack) = self.call_stack.as_mut() {
+            call_stack.clear();
+        }
+        if let Some(limits) = self.limits.as_mut() {
+            limits.reset();
+        }
+        self.bus.reset();
+        #[cfg(feature = "devices-extra")]
+        self.devices.reset();
+        self.key_ready = false;
+        self.in_prompt_shown = false;
+        debug!(pc = self.loaded_pc, "reset");
+    }
+
+    /// Capture the machine state, to restore later with [`Vm::restore_state`]
+    /// or, with the `serde` feature, to write out.
+    pub fn state(&self) -> VmState {
+        VmState {
+            memory: self.memory.to_vec(),
+            registers: self.registers,
+            psr: self.psr,
+            saved_ssp: self.saved_ssp,
+            saved_usp: self.saved_usp,
+            halted: self.halted,
+            instruction_count: self.instruction_count,
+            key_ready: self.key_ready,
+            key_value: self.key_value,
+            pending_interrupts: self.pending_interrupts.clone(),
+            devices: self.bus.save(),
+        }
+    }
+
+    /// Put the machine back in a state captured by [`Vm::state`], so it
+    /// continues exactly where that one left off. Host-side settings
+    /// (breakpoints, hooks, the console) and the image [`Vm::reset`]
+    /// returns to are kept; devices missing from the bus are skipped.
+    pub fn restore_state(&mut self, state: &VmState) -> Result<(), VmError> {
+        if state.memory.len() != MEMORY_SIZE {
+            return Err(VmError::InvalidState(format!("{} words of memory, expected {}", state.memory.len(), MEMORY_SIZE)));
+        }
+        self.memory.copy_from_slice(&state.memory);
+        self.registers = state.registers;
+        self.psr = state.psr;
+        self.saved_ssp = state.saved_ssp;
+        self.saved_usp = state.saved_usp;
+        self.halted = state.halted;
+        self.instruction_count = state.instruction_count;
+        self.next_injection = state.instruction_count;
+        self.key_ready = state.key_ready;
+        self.key_value = state.key_value;
+        self.in_prompt_shown = false;
+        self.pending_interrupts = state.pending_interrupts.clone();
+        self.bus.restore(&state.devices);
+        self.break_requested = false;
+        self.at_breakpoint = false;
+        self.watch_hit = None;
+        self.register_hit = None;
+        if let Some(history) = self.history.as_mut() {
+            history.clear();
+        }
+        /* the calls in progress are not part of the state */
+        if let Some(call_stack) = self.call_stack.as_mut() {
+            call_stack.clear();
+        }
+        debug!(pc = self.registers[REGISTER::PC as usize], instructions = self.instruction_count, "state restored");
+        Ok(())
+    }
+
+    /// Checkpoint the machine to a snapshot file; see [`VmState::save`].
+    pub fn save_state(&self, path: impl AsRef<Path>) -> Result<(), VmError> {
+        self.state().save(path)
+    }
+
+    /// Continue from a snapshot file written by [`Vm::save_state`].
+    pub fn load_state(&mut self, path: impl AsRef<Path>) -> Result<(), VmError> {
+        self.restore_state(&VmState::load(path)?)
+    }
+
+    /// Load an object file (origin word followed by the program) from disk.
+    pub fn load_image(&mut self, path: impl AsRef<Path>) -> Result<(), VmError> {
+        self.load_program(&Program::from_file(path)?);
+        Ok(())
+    }
+
+    /// Load an object image (origin word followed by the program) from memory.
+    pub fn load_from_bytes(&mut self, bytes: &[u8]) -> Result<(), VmError> {
+        self.load_program(&Program::from_bytes(bytes)?);
+        Ok(())
+    }
+
+    /// Load an object image from any reader.
+    pub fn load_from_reader(&mut self, reader: impl Read) -> Result<(), VmError> {
+        self.load_program(&Program::from_reader(reader)?);
+        Ok(())
+    }
+
+    /// Attach a device between instructions, e.g. while paused in the debugger; see [`Devices::attach`].
+    #[cfg(feature = "devices-extra")]
+    pub fn attach_device(&mut self, spec: &str) -> Result<(), String> {
+        self.devices.attach(spec, &mut self.bus)
+    }
+
+    /// Detach a device by name once it has finished work in progress; see [`Devices::detach`].
+    #[cfg(feature = "devices-extra")]
+    pub fn detach_device(&mut self, name: &str) -> Result<(), String> {
+        self.devices.detach(name, &mut self.bus, &mut self.memory)
+    }
+
+    /// Map a host [`SharedMemory`](crate::shared::SharedMemory) buffer over
+    /// guest memory starting at `window.start`, so the host and the guest
+    /// exchange data without traps or the console. Guest loads and stores
+    /// in the range go to the buffer (the words underneath in `memory` are
+    /// left alone), and the window's write listener hears about each store.
+    /// The window must lie below the device region and not overlap another.
+    pub fn map_shared(&mut self, window: SharedWindow) -> Result<(), String> {
+        let len = window.memory.len();
+        if len == 0 {
+            return Err("shared memory buffer is empty".to_string());
+        }
+        if window.start as usize + len > DEVICE_REGION_START as usize {
+            return Err(format!("shared window at x{:04X} extends into the device region", window.start));
+        }
+        if let Some(other) = self.shared.iter().find(|other| other.offset(window.start).is_some() || window.offset(other.start).is_some()) {
+            return Err(format!("shared window at x{:04X} overlaps the one at x{:04X}", window.start, other.start));
+        }
+        debug!(start = window.start, len, "shared memory mapped");
+        self.shared.push(window);
+        Ok(())
+    }
+
+    /// Remove the window mapped at `start`, uncovering the memory underneath.
+    pub fn unmap_shared(&mut self, start: u16) -> Option<SharedWindow> {
+        let index = self.shared.iter().position(|window| window.start == start)?;
+        Some(self.shared.remove(index))
+    }
+
+    /// Install a hook that sees every read and write in the device region
+    /// (`0xFE00..=0xFFFF`), replacing any previously installed hook.
+    ///
+    /// The hook can log accesses, veto them, or emulate extra registers by
+    /// returning [`MmioAction::Replace`].
+    pub fn set_mmio_hook<F>(&mut self, hook: F)
+    where
+        F: FnMut(&MmioAccess) -> MmioAction + 'static,
+    {
+        self.mmio_hook = Some(Box::new(hook));
+    }
+
+    pub fn clear_mmio_hook(&mut self) {
+        self.mmio_hook = None;
+    }
+
+    /// Give the reserved opcode (`0b1101`) a meaning. The handler runs in
+    /// place of the instruction and receives the instruction word; the PC
+    /// already points past it. Without a handler [`Vm::on_illegal`] decides;
+    /// by default the opcode faults and halts the VM (see [`StepOutcome::Faulted`]).
+    ///
+    /// For example, a course could define `MUL DR, SR1, SR2` as
+    /// `1101 DR SR1 000 SR2` and have the handler store
+    /// `registers[SR1].wrapping_mul(registers[SR2])` in DR.
+    pub fn set_reserved_handler<F>(&mut self, handler: F)
+    where
+        F: FnMut(&mut Vm, u16) + 'static,
+    {
+        self.reserved_handler = Some(Box::new(handler));
+    }
+
+    pub fn clear_reserved_handler(&mut self) {
+        self.reserved_handler = None;
+    }
+
+    /// Implement trap vector `trap_code` in Rust, e.g. `x30` for a host
+    /// service. The handler runs in place of the built-in routine (so it can
+    /// also replace GETC, OUT, ...); R7 already holds the return address and
+    /// the PC points past the TRAP. Registering a vector again replaces its
+    /// handler.
+    pub fn register_trap<F>(&mut self, trap_code: u8, handler: F)
+    where
+        F: FnMut(&mut Vm) + 'static,
+    {
+        self.trap_handlers.insert(trap_code, Box::new(handler));
+    }
+
+    /// Go back to the built-in behaviour of `trap_code`. Returns false if no handler was registered.
+    pub fn unregister_trap(&mut self, trap_code: u8) -> bool {
+        self.trap_handlers.remove(&trap_code).is_some()
+    }
+
+    /// Register a callback that is invoked for every [`VmEvent`].
+    pub fn subscribe<F>(&mut self, listener: F)
+    where
+        F: FnMut(&VmEvent) + 'static,
+    {
+        self.events.subscribe(listener);
+    }
+
+    /// Subscribe to [`VmEvent`]s through a channel, e.g. to consume them on another thread.
+    pub fn event_channel(&mut self) -> Receiver<VmEvent> {
+        self.events.channel()
+    }
+
+    /// Add an [`Observer`] that sees every instruction and memory access
+    /// from now on. Observers are called in the order they were added.
+    pub fn add_observer(&mut self, observer: impl Observer) {
+        self.observers.push(Box::new(observer));
+    }
+
+    /// The first observer of type `T`, e.g. to read what it collected.
+    pub fn observer<T: Observer>(&self) -> Option<&T> {
+        self.observers.iter().find_map(|observer| (observer.as_ref() as &dyn Any).downcast_ref())
+    }
+
+    pub fn observer_mut<T: Observer>(&mut self) -> Option<&mut T> {
+        self.observers.iter_mut().find_map(|observer| (observer.as_mut() as &mut dyn Any).downcast_mut())
+    }
+
+    /// Remove every observer.
+    pub fn clear_observers(&mut self) {
+        self.observers.clear();
+    }
+
+    fn run_mmio_hook(&mut self, address: u16, value: u16, direction: MmioDirection) -> MmioAction {
+        /* any device access, including polling a status register, counts as progress */
+        if let Some(watchdog) = self.watchdog.as_mut()
+            && address >= DEVICE_REGION_START
+        {
+            watchdog.progress();
+        }
+        match self.mmio_hook.as_mut() {
+            Some(hook) if address >= DEVICE_REGION_START => {
+                let action = hook(&MmioAccess { address, value, direction });
+                trace!(address, value, ?direction, ?action, "mmio hook");
+                action
+            }
+            _ => MmioAction::Pass,
+        }
+    }
+
+    fn write_to_memory(&mut self, address: u16, value: u16) {
+        for observer in self.observers.iter_mut() {
+            observer.on_mem_write(address, value);
+        }
+        if !self.watchpoints.is_empty() {
+            self.watch(address, true, self.memory[address as usize], value);
+        }
+        let value = match self.run_mmio_hook(address, value, MmioDirection::Write) {
+            MmioAction::Pass => value,
+            MmioAction::Veto => return,
+            MmioAction::Replace(replacement) => replacement,
+        };
+        if self.bus.write(address, value) {
+            return;
+        }
+        if self.shared.iter_mut().any(|window| window.write(address, value)) {
+            return;
+        }
+        if let Some(history) = self.history.as_mut() {
+            history.write(address, self.memory[address as usize]);
+        }
+        self.memory[address as usize] = value;
+    }
+
+    /* let `change` write memory directly, as trap services and DMA do, noting the
+       words it changed in the history (by comparing copies, so only when one is kept) */
+    fn change_memory<R>(&mut self, change: impl FnOnce(&mut Vm) -> R) -> R {
+        let Some(before) = self.history.is_some().then(|| self.memory.to_vec()) else {
+            return change(self);
+        };
+        let result = change(self);
+        if let Some(history) = self.history.as_mut() {
+            for (address, (&old, &new)) in before.iter().zip(&self.memory).enumerate() {
+                if old != new {
+                    history.write(address as u16, old);
+                }
+            }
+        }
+        result
+    }
+
+    fn read_from_memory(&mut self, address: u16) -> u16 {
+        let value = self.read_unobserved(address);
+        for observer in self.observers.iter_mut() {
+            observer.on_mem_read(address, value);
+        }
+        if !self.watchpoints.is_empty() {
+            self.watch(address, false, value, value);
+        }
+        value
+    }
+
+    /* note the first access of this step to a watched address */
+    fn watch(&mut self, address: u16, write: bool, old: u16, new: u16) {
+        if self.watch_hit.is_some() || !self.watchpoints.get(&address).is_some_and(|kind| kind.matches(write)) {
+            return;
+        }
+        let (pc, instruction) = self.fetched;
+        debug!(address, write, pc, "watchpoint hit");
+        self.watch_hit = Some(WatchHit { address, write, pc, instruction, old, new });
+    }
+
+    /// The watched access made by the last instruction stepped, if any.
+    pub fn watch_hit(&self) -> Option<WatchHit> {
+        self.watch_hit
+    }
+
+    /// The watched register set by the last instruction stepped, if any.
+    pub fn register_hit(&self) -> Option<RegisterHit> {
+        self.register_hit
+    }
+
+    /* note the first watched register the instruction just executed changed to its target */
+    fn check_register_watches(&mut self, before: &[u16; REGISTER::COUNT as usize]) {
+        let (pc, instruction) = self.fetched;
+        self.register_hit = self.register_watches.iter().find_map(|watch| {
+            let (old, new) = (before[watch.register as usize], self.registers[watch.register as usize]);
+            (old != new && new == watch.value).then_some(RegisterHit { register: watch.register, pc, instruction, old, new })
+        });
+        if let Some(hit) = self.register_hit {
+            debug!(register = hit.register, value = hit.new, pc, "register watch hit");
+        }
+    }
+
+    /* a read that observers are not told about: instruction fetches and the
+       status polling inside GETC and IN */
+    fn read_unobserved(&mut self, address: u16) -> u16 {
+        let value = self.read_device_or_memory(address);
+        match self.run_mmio_hook(address, value, MmioDirection::Read) {
+            MmioAction::Pass => value,
+            MmioAction::Veto => 0,
+            MmioAction::Replace(replacement) => replacement,
+        }
+    }
+
+    fn read_device_or_memory(&mut self, address: u16) -> u16 {
+        if address == MemoryMappedRegisters::KBSR as u16 {
+            self.poll_keyboard();
+            return if self.key_ready { 1 << 15 } else { 0 };
+        }
+
+        if address == MemoryMappedRegisters::KBDR as u16 {
+            self.key_ready = false; // clear latch
+            return self.key_value;
+        }
+
+        if let Some(value) = self.bus.read(address) {
+            return value;
+        }
+
+        if let Some(value) = self.shared.iter().find_map(|window| window.read(address)) {
+            return value;
+        }
+
+        self.memory[address as usize]
+    }
+
+    /* latch a pending console key, diverting the break key away from the guest */
+    fn poll_keyboard(&mut self) {
+        if !self.key_ready {
+            let typed = self.console.read_key();
+            if typed.is_some() && typed.map(u16::from) == self.break_key {
+                debug!("break key pressed");
+                self.break_requested = true;
+                return;
+            }
+            if typed.is_some() && typed.map(u16::from) == self.monitor_key {
+                debug!("monitor key pressed");
+                self.break_requested = true;
+                self.monitor_requested = true;
+                return;
+            }
+            if let Some(replay) = self.replay.as_mut() {
+                match replay.keys.front() {
+                    Some(&(count, key)) => {
+                        if count <= self.instruction_count {
+                            replay.keys.pop_front();
+                            self.latch_key(key);
+                        }
+                        return;
+                    }
+                    None => {
+                        debug!(instructions = self.instruction_count, "replay finished");
+                        self.replay = None;
+                    }
+                }
+            }
+            if let Some(key) = typed {
+                self.latch_key(key);
+            }
+        }
+    }
+
+    /* latch a key from the host, noting it in the recording */
+    fn latch_key(&mut self, key: u8) {
+        trace!(key, "keyboard latched");
+        if let Some(recording) = self.recording.as_mut() {
+            recording.keys.push_back((self.instruction_count, key));
+        }
+        self.deliver_key(key);
+    }
+
+    /* spin until a key is latched; false if a break was requested meanwhile,
+       the console cannot block, or its input ran out (which halts the VM) */
+    fn wait_for_key(&mut self) -> bool {
+        while self.read_unobserved(MemoryMappedRegisters::KBSR as u16) == 0 {
+            /* the guest waits for a key the replay has for later: it has gone another way */
+            if let Some((count, key)) = self.replay.as_mut().and_then(|replay| replay.keys.pop_front()) {
+                warn!(recorded = count, instructions = self.instruction_count, "replay out of step with the program");
+                self.latch_key(key);
+                continue;
+            }
+            if self.console.input_closed() {
+                debug!("console input exhausted");
+                self.halted = true;
+                return false;
+            }
+            if !self.console.blocking() {
+                return false;
+            }
+            #[cfg(feature = "terminal")]
+            self.poll_signals();
+            if self.break_requested {
+                return false;
+            }
+            if let Some(limits) = self.limits.as_mut()
+                && limits.check(self.instruction_count, true).is_some()
+            {
+                self.halted = true;
+                return false;
+            }
+        }
+        true
+    }
+
+    /* act on SIGUSR1 / SIGUSR2 received since the last poll */
+    #[cfg(feature = "terminal")]
+    fn poll_signals(&mut self) {
+        if take_dump_request() {
+            let pc = self.registers[REGISTER::PC as usize];
+            let word = self.memory[pc as usize];
+            let dump = format!("{}Next: x{:04X}  {:04X}  {}\n", crate::summary::render(self, &[]), pc, word, disassemble_with(pc, word, &self.symbols));
+            eprint_report(&dump);
+        }
+        if take_trace_toggle_request() {
+            self.trace_stderr = !self.trace_stderr;
+            debug!(enabled = self.trace_stderr, "instruction tracing toggled");
+        }
+    }
+
+    /* `x3041  1261  ADD R1, R1, #1`, followed by `  ; LOOP+2` where a label is near */
+    fn trace_line(&self, pc: u16, instruction: u16) -> String {
+        let text = disassemble_with(pc, instruction, &self.symbols);
+        match self.symbols.name_for(pc) {
+            Some(name) => format!("x{:04X}  {:04X}  {:<20}  ; {}", pc, instruction, text, name),
+            None => format!("x{:04X}  {:04X}  {}", pc, instruction, text),
+        }
+    }
+
+    fn write_console(&mut self, text: &str) {
+        if let Some(watchdog) = self.watchdog.as_mut() {
+            watchdog.progress();
+        }
+        let written = self.console.write(text);
+        self.check_console(written);
+    }
+
+    /* keep the first console failure for the run to report */
+    fn check_console(&mut self, written: io::Result<()>) {
+        if let Err(err) = written
+            && self.console_error.is_none()
+        {
+            self.console_error = Some(err);
+        }
+    }
+
+    /* R0-R7, the PC and the condition code as the execution core holds them */
+    fn cpu(&self) -> Cpu {
+        let mut cpu = Cpu::new(self.registers[REGISTER::PC as usize]);
+        cpu.registers.copy_from_slice(&self.registers[..8]);
+        cpu.cond = self.registers[REGISTER::COND as usize];
+        cpu
+    }
+
+    fn set_cpu(&mut self, cpu: &Cpu) {
+        self.registers[..8].copy_from_slice(&cpu.registers);
+        self.registers[REGISTER::PC as usize] = cpu.pc;
+        self.registers[REGISTER::COND as usize] = cpu.cond;
+    }
+
+    /* run a console TRAP with the core's routine; false if GETC or IN found no key */
+    fn console_trap(&mut self, pc: u16, vector: u8) -> bool {
+        let mut cpu = self.cpu();
+        let step = cpu.trap(pc, vector, self);
+        self.set_cpu(&cpu);
+        step != CpuStep::WaitingForInput
+    }
+
+    /* undo the fetch of the instruction at `pc` so it runs again on resume */
+    fn rewind_to(&mut self, pc: u16) {
+        self.registers[REGISTER::PC as usize] = pc;
+        self.instruction_count -= 1;
+    }
+
+    /// The processor status register: privilege (bit 15), priority (bits
+    /// 10-8) and the condition codes (bits 2-0).
+    pub fn psr(&self) -> u16 {
+        self.psr | self.registers[REGISTER::COND as usize]
+    }
+
+    /// Set the processor status register, e.g. from a debugger. Unlike a
+    /// mode change through RTI or an interrupt, the stack pointers are not
+    /// swapped.
+    pub fn set_psr(&mut self, psr: u16) {
+        self.psr = psr & (PSR_USER_MODE | 0x0700);
+        self.registers[REGISTER::COND as usize] = psr & 0x7;
+    }
+
+    /// Request an interrupt. It is taken before the next instruction whose
+    /// running priority is below the interrupt's; until then it stays pending.
+    pub fn interrupt(&mut self, interrupt: Interrupt) {
+        trace!(vector = interrupt.vector, priority = interrupt.priority, "interrupt requested");
+        self.pending_interrupts.push(interrupt);
+    }
+
+    /// Inject `injection` once `instruction_count` instructions have run,
+    /// i.e. just before the next one executes. Runs are reproducible because
+    /// nothing depends on wall-clock time or real key presses, and
+    /// [`Vm::reset`] replays the same schedule.
+    pub fn schedule(&mut self, instruction_count: u64, injection: Injection) {
+        self.scheduled.entry(instruction_count).or_default().push(injection);
+    }
+
+    /* fire every injection that has come due since the last call */
+    fn fire_scheduled(&mut self) {
+        if self.instruction_count < self.next_injection {
+            return;
+        }
+        let due: Vec<Injection> = self
+            .scheduled
+            .range(self.next_injection..=self.instruction_count)
+            .flat_map(|(_, injections)| injections.iter().copied())
+            .collect();
+        self.next_injection = self.instruction_count + 1;
+        for injection in due {
+            debug!(?injection, instructions = self.instruction_count, "injecting");
+            match injection {
+                Injection::Key(key) => {
+                    self.deliver_key(key);
+                    self.interrupt(KEYBOARD_INTERRUPT);
+                }
+                Injection::Interrupt(interrupt) => self.interrupt(interrupt),
+            }
+        }
+    }
+
+    /* make a key readable from KBDR as if it had been typed */
+    fn deliver_key(&mut self, key: u8) {
+        self.key_value = key as u16;
+        self.key_ready = true;
+    }
+
+    /* highest-priority pending interrupt that may preempt the running priority */
+    fn take_interrupt(&mut self) -> Option<Interrupt> {
+        let running = ((self.psr >> 8) & 0x7) as u8;
+        let (index, _) = self
+            .pending_interrupts
+            .iter()
+            .enumerate()
+            .filter(|(_, interrupt)| interrupt.priority > running)
+            .max_by_key(|(_, interrupt)| interrupt.priority)?;
+        Some(self.pending_interrupts.remove(index))
+    }
+
+    /* save PSR and PC on the supervisor stack and jump to the service routine */
+    fn enter_interrupt(&mut self, interrupt: Interrupt) {
+        let psr = self.psr();
+        let pc = self.registers[REGISTER::PC as usize];
+        if psr & PSR_USER_MODE != 0 {
+            self.saved_usp = self.registers[REGISTER::R6 as usize];
+            self.registers[REGISTER::R6 as usize] = self.saved_ssp;
+        }
+        self.push(psr);
+        self.push(pc);
+        self.psr = ((interrupt.priority as u16 & 0x7) << 8) & !PSR_USER_MODE;
+        let vector_address = INTERRUPT_VECTOR_TABLE + interrupt.vector as u16;
+        self.registers[REGISTER::PC as usize] = self.read_from_memory(vector_address);
+        debug!(vector = interrupt.vector, pc, "interrupt taken");
+        if let Some(timeline) = self.timeline.as_mut() {
+            timeline.interrupt(self.instruction_count, interrupt.vector, pc);
+        }
+        self.events.emit(VmEvent::InterruptTaken { vector: interrupt.vector, pc });
+    }
+
+    /* apply on_illegal to the instruction at pc; Some ends the step */
+    fn illegal(&mut self, pc: u16, instruction: u16, vector: u8) -> Option<StepOutcome> {
+        match self.on_illegal {
+            IllegalPolicy::Halt => {
+                error!(pc, instruction, "illegal instruction");
+                self.rewind_to(pc);
+                self.halted = true;
+                Some(StepOutcome::Faulted { pc, instruction })
+            }
+            IllegalPolicy::Ignore => {
+                warn!(pc, instruction, "illegal instruction; ignored");
+                None
+            }
+            IllegalPolicy::Exception => {
+                let priority = ((self.psr >> 8) & 0x7) as u8;
+                self.enter_interrupt(Interrupt { vector, priority });
+                None
+            }
+            IllegalPolicy::Panic => {
+                panic!("illegal instruction 0x{:04X} at {}", instruction, self.symbols.describe(pc))
+            }
+        }
+    }
+
+    fn push(&mut self, value: u16) {
+        let sp = self.registers[REGISTER::R6 as usize].wrapping_sub(1);
+        self.registers[REGISTER::R6 as usize] = sp;
+        self.write_to_memory(sp, value);
+    }
+
+    fn pop(&mut self) -> u16 {
+        let sp = self.registers[REGISTER::R6 as usize];
+        self.registers[REGISTER::R6 as usize] = sp.wrapping_add(1);
+        self.read_from_memory(sp)
+    }
+
+    /// Hand a key to a guest that stopped with [`RunState::WaitingForInput`]
+    /// (or [`StepOutcome::WaitingForInput`]): it is latched in KBSR/KBDR, so
+    /// the waiting GETC or IN takes it when execution resumes. Like a real
+    /// keyboard the latch holds one key, which a second call replaces; hosts
+    /// feeding a stream of input can queue it in a
+    /// [`QueuedConsole`](crate::console::QueuedConsole) instead.
+    pub fn provide_input(&mut self, key: u8) {
+        trace!(key, "input provided");
+        self.latch_key(key);
+    }
+
+    /// Ask a running [`Vm::run`] to return [`RunState::Paused`] before the next instruction.
+    pub fn request_break(&mut self) {
+        self.break_requested = true;
+    }
+
+    /// Whether the last [`RunState::Paused`] came from [`Vm::monitor_key`];
+    /// asking again returns false until the key is pressed again.
+    pub fn take_monitor_request(&mut self) -> bool {
+        std::mem::take(&mut self.monitor_requested)
+    }
+
+    /// Run until the guest halts, a breakpoint is reached, or a break is
+    /// requested via the break key. A breakpoint at the current PC is stepped
+    /// over so that resuming from it makes progress.
+    /// Fails if an instruction faults; the VM is then halted on it.
+    pub fn run(&mut self) -> Result<RunState, VmError> {
+        self.at_breakpoint = true;
+        Ok(self.run_for(u64::MAX)?.state)
+    }
+
+    /// Like [`Vm::run`], but return [`RunState::Yielded`] after `budget`
+    /// instructions, e.g. to share a frame of a GUI or game loop. Nothing is
+    /// lost between calls (keyboard latch, pending interrupts, a half-done
+    /// DMA transfer), so the next call carries on exactly where this one
+    /// stopped; only a breakpoint the previous call stopped at is stepped over.
+    /// A GETC or IN waiting for a key blocks until one arrives if the
+    /// console blocks, and otherwise returns [`RunState::WaitingForInput`].
+    pub fn run_for(&mut self, budget: u64) -> Result<RunResult, VmError> {
+        self.run_until(budget, |_| false)
+    }
+
+    /// Execute the instruction at the PC; if it is a subroutine call (JSR or
+    /// JSRR), run the whole subroutine, until it returns to the instruction
+    /// after the call. Stops early like [`Vm::run`], e.g. at a breakpoint
+    /// inside the subroutine; otherwise returns [`RunState::Stepped`].
+    pub fn step_over(&mut self) -> Result<RunState, VmError> {
+        self.at_breakpoint = true;
+        Ok(self.run_until(u64::MAX, Self::call_depth_tracker(0))?.state)
+    }
+
+    /// Run until the current subroutine returns (its RET executes), then
+    /// return [`RunState::Stepped`]. Calls it makes on the way are run
+    /// through; outside any subroutine this runs to HALT. Stops early like
+    /// [`Vm::run`].
+    pub fn step_out(&mut self) -> Result<RunState, VmError> {
+        self.at_breakpoint = true;
+        Ok(self.run_until(u64::MAX, Self::call_depth_tracker(1))?.state)
+    }
+
+    /// Undo the last instruction recorded in [`Vm::history`]: the memory it
+    /// wrote, the registers, processor status and keyboard latch go back to
+    /// what they were before it ran (a HALT is taken back too). Output it
+    /// printed stays printed. Returns false once the history runs out, or
+    /// when none is kept.
+    pub fn step_back(&mut self) -> bool {
+        self.undo().is_some()
+    }
+
+    /// Step backwards until the PC is at a breakpoint, the instruction just
+    /// undone wrote an address watched for writes (see [`Vm::watchpoints`]),
+    /// or it set a register as a [`RegisterWatch`] describes: the last
+    /// instruction, going forwards, to have done so. Returns the
+    /// [`RunState`] [`Vm::run`] would have stopped with, or
+    /// [`RunState::Paused`] at the start of the history.
+    pub fn run_back(&mut self) -> RunState {
+        loop {
+            let registers = self.registers;
+            let Some(writes) = self.undo() else {
+                return RunState::Paused;
+            };
+            let pc = self.registers[REGISTER::PC as usize];
+            let instruction = self.memory[pc as usize];
+            let watched = writes.iter().find(|(address, _, _)| self.watchpoints.get(address).is_some_and(|kind| kind.matches(true)));
+            if let Some(&(address, old, new)) = watched {
+                return RunState::Watchpoint(WatchHit { address, write: true, pc, instruction, old, new });
+            }
+            let set = self.register_watches.iter().find(|watch| {
+                let register = watch.register as usize;
+                registers[register] == watch.value && self.registers[register] != watch.value
+            });
+            if let Some(watch) = set {
+                let register = watch.register;
+                let old = self.registers[register as usize];
+                return RunState::RegisterWatch(RegisterHit { register, pc, instruction, old, new: watch.value });
+            }
+            if self.breakpoints.contains(&pc) {
+                return RunState::Breakpoint(pc);
+            }
+        }
+    }
+
+    /* undo the last recorded instruction, returning the (address, old, new) of each
+       memory write it made; a step that did not finish is undone along with it */
+    fn undo(&mut self) -> Option<Vec<(u16, u16, u16)>> {
+        let mut history = self.history.take()?;
+        let mut undone = Vec::new();
+        let mut finished = false;
+        while !finished && let Some(step) = history.pop() {
+            finished = step.before.instruction_count < self.instruction_count;
+            for &(address, old) in step.writes.iter().rev() {
+                undone.push((address, old, self.memory[address as usize]));
+                self.memory[address as usize] = old;
+            }
+            let before = step.before;
+            self.registers = before.registers;
+            self.psr = before.psr;
+            self.saved_ssp = before.saved_ssp;
+            self.saved_usp = before.saved_usp;
+            self.halted = before.halted;
+            self.instruction_count = before.instruction_count;
+            self.next_injection = before.instruction_count;
+            self.key_ready = before.key_ready;
+            self.key_value = before.key_value;
+            self.in_prompt_shown = false;
+            self.pending_interrupts = before.pending_interrupts;
+        }
+        /* at a snapshot, put back what the log does not cover, e.g. device registers */
+        if finished && let Some(snapshot) = history.snapshot_at(self.instruction_count) {
+            let _ = self.restore_state(snapshot);
+        }
+        let undone = finished.then_some(undone);
+        self.history = Some(history);
+        self.at_breakpoint = false;
+        self.watch_hit = None;
+        self.register_hit = None;
+        undone
+    }
+
+    /// Go to where `instruction_count` instructions had run: back to the
+    /// latest of [`Vm::checkpoints`] at or before it, then forward one
+    /// instruction at a time, past breakpoints and watches. With a
+    /// [`Vm::recording`], the keys received since that checkpoint are
+    /// replayed so the guest goes the same way again. A count ahead of the
+    /// present just executes up to it. Stops early at a HALT or a wait for
+    /// a key; returns the instruction count reached.
+    pub fn travel_to(&mut self, instruction_count: u64) -> Result<u64, VmError> {
+        if instruction_count < self.instruction_count {
+            let checkpoint = self
+                .checkpoints
+                .as_ref()
+                .and_then(|checkpoints| checkpoints.at_or_before(instruction_count))
+                .cloned()
+                .ok_or_else(|| VmError::InvalidState(format!("no checkpoint at or before instruction {}", instruction_count)))?;
+            if let Some(recording) = self.recording.as_mut() {
+                let since = recording.keys.partition_point(|(count, _)| *count <= checkpoint.instruction_count);
+                let keys = recording.keys.split_off(since);
+                /* ahead of whatever was still to be replayed */
+                let replay = self.replay.get_or_insert_with(InputRecording::default);
+                for key in keys.into_iter().rev() {
+                    replay.keys.push_front(key);
+                }
+            }
+            self.restore_state(&checkpoint)?;
+        }
+        while self.instruction_count < instruction_count {
+            match self.step() {
+                StepOutcome::Continued | StepOutcome::Trapped(_) => {}
+                _ => break,
+            }
+        }
+        debug!(instructions = self.instruction_count, "travelled");
+        Ok(self.instruction_count)
+    }
+
+    /// Whether the PC, about to execute `pc`, has just entered one of the
+    /// [`Vm::range_breakpoints`]: the instruction before ran outside it, or
+    /// none has run yet.
+    pub fn range_breakpoint_at(&self, pc: u16) -> bool {
+        let previous = self.fetched.0;
+        self.range_breakpoints.iter().any(|range| range.contains(&pc) && (self.instruction_count == 0 || !range.contains(&previous)))
+    }
+
+    /// The vector of the TRAP at `pc`, when it is one [`Vm::run`] stops
+    /// at: in [`Vm::trap_breakpoints`], or any with [`Vm::break_on_traps`].
+    pub fn trap_breakpoint_at(&self, pc: u16) -> Option<u8> {
+        if !self.break_on_traps && self.trap_breakpoints.is_empty() {
+            return None;
+        }
+        match decode(self.memory[pc as usize]) {
+            Instruction::Trap { vector } if self.break_on_traps || self.trap_breakpoints.contains(&vector) => Some(vector),
+            _ => None,
+        }
+    }
+
+    /* the registers and status an instruction starts from, for the history */
+    fn registers_state(&self) -> VmState {
+        VmState {
+            memory: Vec::new(),
+            registers: self.registers,
+            psr: self.psr,
+            saved_ssp: self.saved_ssp,
+            saved_usp: self.saved_usp,
+            halted: self.halted,
+            instruction_count: self.instruction_count,
+            key_ready: self.key_ready,
+            key_value: self.key_value,
+            pending_interrupts: self.pending_interrupts.clone(),
+            devices: BTreeMap::new(),
+        }
+    }
+
+    /* done once the executed JSR/JSRRs and RETs bring `depth` subroutine levels back to 0;
+       interrupts are left out, as service routines return with RTI */
+    fn call_depth_tracker(mut depth: i32) -> impl FnMut(&Vm) -> bool {
+        move |vm| {
+            match decode(vm.fetched.1) {
+                Instruction::Jsr { .. } | Instruction::Jsrr { .. } => depth += 1,
+                Instruction::Jmp { base: 7 } => depth -= 1,
+                _ => {}
+            }
+            depth <= 0
+        }
+    }
+
+    /* run_for, also stopping once `done` holds after an executed instruction */
+    fn run_until(&mut self, budget: u64, mut done: impl FnMut(&Vm) -> bool) -> Result<RunResult, VmError> {
+        let _span = debug_span!("run", pc = self.registers[REGISTER::PC as usize]).entered();
+        let start = self.instruction_count;
+        let state = loop {
+            if self.halted {
+                break RunState::Halted;
+            }
+            if (self.break_key.is_some() || self.monitor_key.is_some()) && self.instruction_count.is_multiple_of(KEY_POLL_INTERVAL) {
+                self.poll_keyboard();
+            }
+            if self.break_requested {
+                self.break_requested = false;
+                debug!(instructions = self.instruction_count, "paused");
+                break RunState::Paused;
+            }
+            let pc = self.registers[REGISTER::PC as usize];
+            if !self.at_breakpoint && (self.breakpoints.contains(&pc) || self.range_breakpoint_at(pc) || self.trap_breakpoint_at(pc).is_some()) {
+                debug!(pc, "breakpoint hit");
+                self.events.emit(VmEvent::BreakpointHit { pc });
+                self.at_breakpoint = true;
+                break RunState::Breakpoint(pc);
+            }
+            if self.instruction_count - start >= budget {
+                break RunState::Yielded;
+            }
+            let outcome = self.step();
+            match outcome {
+                StepOutcome::Faulted { pc, instruction } => return Err(VmError::IllegalOpcode { pc, instruction }),
+                /* a requested break is reported as Paused at the top of the loop */
+                StepOutcome::WaitingForInput if !self.break_requested => break RunState::WaitingForInput,
+                _ => {}
+            }
+            if let Some(err) = self.console_error.take() {
+                return Err(VmError::Io(err));
+            }
+            if let Some(hit) = self.watch_hit {
+                self.events.emit(VmEvent::WatchpointHit { pc: hit.pc, address: hit.address });
+                break RunState::Watchpoint(hit);
+            }
+            if let Some(hit) = self.register_hit {
+                break RunState::RegisterWatch(hit);
+            }
+            if !self.halted && outcome != StepOutcome::WaitingForInput && done(self) {
+                break RunState::Stepped;
+            }
+        };
+        Ok(RunResult {
+            state,
+            executed: self.instruction_count - start,
+        })
+    }
+
+    /// Execute instructions lazily, one per item, e.g.
+    /// `vm.steps().take(100).filter(|(_, instruction, _)| matches!(instruction, Instruction::Trap { .. }))`.
+    /// The iterator ends once an instruction cannot run: the guest halted
+    /// (after yielding the HALT), faulted, or is waiting for input.
+    /// Breakpoints are not checked; interrupts are taken as in [`Vm::step`],
+    /// so an item's address may be a service routine's.
+    pub fn steps(&mut self) -> Steps<'_> {
+        Steps { vm: self }
+    }
+
+    /// Execute a single instruction and report what happened. Does nothing
+    /// once the guest has halted.
+    pub fn step(&mut self) -> StepOutcome {
+        if self.halted {
+            return StepOutcome::Halted;
+        }
+        if let Some(limits) = self.limits.as_mut()
+            && let Some(exceeded) = limits.check(self.instruction_count, false)
+        {
+            debug!(pc = self.registers[REGISTER::PC as usize], %exceeded, "guest stopped");
+            self.halted = true;
+            return StepOutcome::Halted;
+        }
+        /* a throttled guest is slow enough to look at the host before every instruction */
+        if let Some(throttle) = self.throttle.as_mut()
+            && throttle.pace(self.instruction_count)
+        {
+            self.poll_keyboard();
+            #[cfg(feature = "terminal")]
+            self.poll_signals();
+        }
+        self.at_breakpoint = false;
+        self.watch_hit = None;
+        self.register_hit = None;
+        if let Some(mut history) = self.history.take() {
+            history.begin(self.registers_state(), || self.state());
+            self.history = Some(history);
+        }
+        /* after the throttle polls: keys stamped with a checkpoint's count are all in its past */
+        if self.checkpoints.as_ref().is_some_and(|checkpoints| checkpoints.due(self.instruction_count)) {
+            let state = self.state();
+            self.checkpoints.as_mut().unwrap().push(state);
+        }
+
+        if !self.scheduled.is_empty() {
+            self.fire_scheduled();
+        }
+        if let Some(interrupt) = self.take_interrupt() {
+            self.enter_interrupt(interrupt);
+        }
+
+        #[cfg(feature = "terminal")]
+        if self.instruction_count.is_multiple_of(KEY_POLL_INTERVAL) {
+            self.poll_signals();
+        }
+
+        let pc = self.registers[REGISTER::PC as usize];
+        let instruction = self.read_unobserved(pc);
+        for observer in self.observers.iter_mut() {
+            observer.on_instruction(pc, instruction, decode(instruction));
+        }
+        if self.trace_stderr {
+            eprint!("{}\r\n", self.trace_line(pc, instruction));
+        }
+        if self.trace_output.is_some()
+            && let line = self.trace_line(pc, instruction)
+            && let Some(output) = self.trace_output.as_mut()
+            && let Err(err) = writeln!(output, "{}", line)
+        {
+            warn!(%err, "trace output failed; tracing stopped");
+            self.trace_output = None;
+        }
+        if let Some(recent) = self.recent.as_mut() {
+            recent.fetched(self.instruction_count, pc, instruction, self.registers);
+        }
+        self.registers[REGISTER::PC as usize] = pc.wrapping_add(1);
+        self.instruction_count += 1;
+        self.fetched = (pc, instruction);
+        let registers_before = self.registers;
+
+        let mut outcome = StepOutcome::Continued;
+        let cond = self.registers[REGISTER::COND as usize];
+        /* the core runs everything but TRAP, RTI and the reserved opcode, which need the rest of the VM */
+        let mut cpu = self.cpu();
+        let deferred = cpu.execute(pc, instruction, self);
+        self.set_cpu(&cpu);
+        match deferred {
+            /* a BR, whose condition bits sit where the condition code's do */
+            None => {
+                if instruction >> 12 == 0 && (instruction >> 9) & cond != 0 {
+                    self.events.emit(VmEvent::BranchTaken { from: pc, to: self.registers[REGISTER::PC as usize] });
+                }
+            }
+            Some(Instruction::Trap { vector }) => {
+                self.registers[REGISTER::R7 as usize] = self.registers[REGISTER::PC as usize];
+                let trap_code = vector as u16;
+                trace!(pc, trap_code, "trap");
+                self.events.emit(VmEvent::TrapExecuted { pc, trap_code });
+                outcome = StepOutcome::Trapped(trap_code);
+                match trap_code {
+                    _ if self.run_trap_handler(trap_code as u8) => {}
+                    x if x == TrapCodes::GETC as u16 => {
+                        if !self.console_trap(pc, vector) {
+                            self.rewind_to(pc);
+                            return self.waiting_outcome();
+                        }
+                        /* straight to the console: the echo is not the guest's output */
+                        if self.echo_getc {
+                            let written = self.console.write(&(self.registers[REGISTER::R0 as usize] as u8 as char).to_string());
+                            self.check_console(written);
+                        }
+                    }
+                    x if x == TrapCodes::HALT as u16 => {
+                        self.write_console("HALT");
+                        self.halted = true;
+                        debug!(pc, instructions = self.instruction_count, "halted");
+                        self.events.emit(VmEvent::Halted { pc });
+                    }
+                    x if x == TrapCodes::IN as u16 => {
+                        /* a retried IN has already shown its prompt */
+                        if !self.in_prompt_shown {
+                            self.write_console("Enter a character: ");
+                            self.in_prompt_shown = true;
+                        }
+
+                        /* the core echoes the key */
+                        if !self.console_trap(pc, vector) {
+                            self.rewind_to(pc);
+                            return self.waiting_outcome();
+                        }
+                        self.in_prompt_shown = false;
+                        self.write_console("\n");
+                    }
+                    x if x == TrapCodes::OUT as u16 || x == TrapCodes::PUTS as u16 || x == TrapCodes::PUTSP as u16 => {
+                        self.console_trap(pc, vector);
+                    }
+                    #[cfg(feature = "devices-extra")]
+                    _ if self.change_memory(|vm| vm.devices.trap(trap_code, &mut vm.registers, &mut vm.memory)) => {
+                        update_flags(REGISTER::R0 as u16, &mut self.registers);
+                    }
+                    _ => {
+                        warn!(pc, trap_code, "ignoring unknown trap vector");
+                    }
+                }
+            }
+            Some(Instruction::Rti) => {
+                if self.psr & PSR_USER_MODE != 0 {
+                    if let Some(outcome) = self.illegal(pc, instruction, PRIVILEGE_EXCEPTION) {
+                        return outcome;
+                    }
+                } else {
+                    self.registers[REGISTER::PC as usize] = self.pop();
+                    let psr = self.pop();
+                    self.psr = psr & (PSR_USER_MODE | 0x0700);
+                    self.registers[REGISTER::COND as usize] = psr & 0x7;
+                    if psr & PSR_USER_MODE != 0 {
+                        self.saved_ssp = self.registers[REGISTER::R6 as usize];
+                        self.registers[REGISTER::R6 as usize] = self.saved_usp;
+                    }
+                    debug!(pc = self.registers[REGISTER::PC as usize], "returned from interrupt");
+                }
+            }
+            Some(_) => {
+                /* the handler gets the whole VM, so it is taken out while it runs */
+                if let Some(mut handler) = self.reserved_handler.take() {
+                    handler(self, instruction);
+                    if self.reserved_handler.is_none() {
+                        self.reserved_handler = Some(handler);
+                    }
+                } else if let Some(outcome) = self.illegal(pc, instruction, ILLEGAL_OPCODE_EXCEPTION) {
+                    return outcome;
+                }
+            }
+        }
+
+        if !self.bus.is_empty() {
+            let raised = self.change_memory(|vm| {
+                let mut raised = Vec::new();
+                vm.bus.tick(&mut vm.memory, |interrupt| raised.push(interrupt));
+                raised
+            });
+            for interrupt in raised {
+                self.interrupt(interrupt);
+            }
+        }
+
+        if !self.register_watches.is_empty() {
+            self.check_register_watches(&registers_before);
+        }
+
+        if let Some(recent) = self.recent.as_mut() {
+            recent.finished(self.registers);
+        }
+        if let Some(call_stack) = self.call_stack.as_mut() {
+            call_stack.record(pc, instruction, self.registers[REGISTER::PC as usize]);
+        }
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.record(pc, instruction, self.registers[REGISTER::PC as usize]);
+        }
+        if let Some(timeline) = self.timeline.as_mut() {
+            let next_pc = self.registers[REGISTER::PC as usize];
+            timeline.record(self.instruction_count, pc, instruction, next_pc, self.registers[REGISTER::R0 as usize]);
+        }
+        if let Some(watchdog) = self.watchdog.as_mut()
+            && watchdog.record(self.instruction_count, pc, instruction)
+        {
+            self.watchdog_fired();
+        }
+
+        if self.halted { StepOutcome::Halted } else { outcome }
+    }
+
+    /* run the registered handler for `trap_code`, if any; like the reserved
+       handler, it is taken out of the VM while it runs */
+    fn run_trap_handler(&mut self, trap_code: u8) -> bool {
+        let Some(mut handler) = self.trap_handlers.remove(&trap_code) else {
+            return false;
+        };
+        handler(self);
+        self.trap_handlers.entry(trap_code).or_insert(handler);
+        true
+    }
+
+    /* a GETC or IN that found no key: halted if the console's input ran out */
+    fn waiting_outcome(&self) -> StepOutcome {
+        if self.halted { StepOutcome::Halted } else { StepOutcome::WaitingForInput }
+    }
+
+    /* dump the watchdog report and apply its policy */
+    fn watchdog_fired(&mut self) {
+        let Some(watchdog) = self.watchdog.as_ref() else {
+            return;
+        };
+        warn!(instructions = self.instruction_count, policy = ?watchdog.policy, "watchdog fired");
+        eprint_report(&watchdog.report(self));
+        if watchdog.policy == WatchdogPolicy::Abort {
+            self.halted = true;
+        }
+    }
+}
+
+/* the core's memory accesses go through the devices, hooks, observers and watchpoints */
+impl Memory for Vm {
+    fn read(&mut self, address: u16) -> u16 {
+        self.read_from_memory(address)
+    }
+
+    fn write(&mut self, address: u16, value: u16) {
+        self.write_to_memory(address, value);
+    }
+}
+
+/* the core's console traps wait for keys and print like the rest of the VM */
+impl Io for Vm {
+    fn read_key(&mut self) -> Option<u8> {
+        if !self.wait_for_key() {
+            return None;
+        }
+        Some(self.read_from_memory(MemoryMappedRegisters::KBDR as u16) as u8)
+    }
+
+    fn write_char(&mut self, character: u8) {
+        self.write_console(&(character as char).to_string());
+    }
+}
+
+#[cfg(all(test, feature = "assembler"))]
+mod tests {
+    use super::*;
+    use crate::console::CapturedConsole;
+
+    /* count R1 down from 3, storing each value, then halt */
+    const COUNTDOWN: &str = "
+                .ORIG x3000
+                LD R1, START
+        LOOP    ST R1, LAST
+                ADD R1, R1, #-1
+                BRp LOOP
+                HALT
+        START   .FILL #3
+        LAST    .FILL #0
+                .END
+    ";
+
+    const LAST: usize = 0x3006;
+
+    fn countdown() -> Vm {
+        let mut vm = Vm::new();
+        vm.console = Box::new(CapturedConsole::default());
+        vm.history = Some(History::new(100));
+        vm.load_program(&Program::from_assembly(COUNTDOWN).unwrap());
+        vm
+    }
+
+    #[test]
+    fn step_back_undoes_registers_and_memory() {
+        let mut vm = countdown();
+        for _ in 0..3 {
+            vm.step();
+        }
+        assert_eq!(vm.memory[LAST], 3);
+        assert_eq!(vm.registers[1], 2);
+
+        assert!(vm.step_back());
+        assert_eq!(vm.registers[1], 3);
+        assert_eq!(vm.registers[REGISTER::PC as usize], 0x3002);
+        assert!(vm.step_back());
+        assert_eq!(vm.memory[LAST], 0);
+        assert_eq!(vm.instruction_count, 1);
+    }
+
+    #[test]
+    fn step_back_takes_back_a_halt() {
+        let mut vm = countdown();
+        assert_eq!(vm.run().unwrap(), RunState::Halted);
+        assert_eq!(vm.memory[LAST], 1);
+        assert!(vm.step_back());
+        assert!(!vm.halted);
+        assert_eq!(vm.registers[REGISTER::PC as usize], 0x3004);
+        assert_eq!(vm.run().unwrap(), RunState::Halted);
+    }
+
+    #[test]
+    fn step_back_stops_at_the_start_of_the_history() {
+        let mut vm = countdown();
+        vm.step();
+        assert!(vm.step_back());
+        assert!(!vm.step_back());
+        assert_eq!(vm.instruction_count, 0);
+    }
+
+    #[test]
+    fn run_back_stops_at_a_breakpoint() {
+        let mut vm = countdown();
+        vm.run().unwrap();
+        vm.breakpoints.insert(0x3001);
+        assert_eq!(vm.run_back(), RunState::Breakpoint(0x3001));
+        /* the last ST is about to run again */
+        assert_eq!(vm.registers[1], 1);
+        assert_eq!(vm.memory[LAST], 2);
+    }
+
+    #[test]
+    fn run_back_stops_where_a_watched_address_was_written() {
+        let mut vm = countdown();
+        vm.run().unwrap();
+        vm.watchpoints.insert(LAST as u16, WatchKind::Write);
+        let RunState::Watchpoint(hit) = vm.run_back() else {
+            panic!("expected a watchpoint");
+        };
+        assert_eq!((hit.address, hit.old, hit.new), (LAST as u16, 2, 1));
+        assert_eq!(vm.memory[LAST], 2);
+    }
+
+    #[test]
+    fn run_back_without_a_stop_returns_to_the_start() {
+        let mut vm = countdown();
+        vm.run().unwrap();
+        assert_eq!(vm.run_back(), RunState::Paused);
+        assert_eq!(vm.instruction_count, 0);
+        assert_eq!(vm.memory[LAST], 0);
+    }
+
+    #[test]
+    fn set_args_writes_the_count_pointers_and_strings() {
+        let mut vm = Vm::new();
+        vm.set_args(&["ab", "c"]).unwrap();
+        let start = ARGS_START as usize;
+        assert_eq!(vm.memory[start..start + 8], [2, 0xFD03, 0xFD06, b'a' as u16, b'b' as u16, 0, b'c' as u16, 0]);
+    }
+
+    #[test]
+    fn set_args_refuses_arguments_past_the_page() {
+        let mut vm = Vm::new();
+        assert!(matches!(vm.set_args(&["x".repeat(255)]), Err(VmError::InvalidArguments(_))));
+        /* more pointers than there are addresses */
+        assert!(matches!(vm.set_args(&vec![""; 70_000]), Err(VmError::InvalidArguments(_))));
+        assert_eq!(vm.memory[ARGS_START as usize], 0);
+        /* the count, one pointer and a 253-character string with its terminator fill the page */
+        vm.set_args(&["x".repeat(253)]).unwrap();
+        assert_eq!(vm.memory[DEVICE_REGION_START as usize - 1], 0);
+    }
+
+    /* a DMA-like device that bumps a word of memory on every tick */
+    struct Ticker;
+
+    impl crate::bus::Device for Ticker {
+        fn name(&self) -> &str {
+            "ticker"
+        }
+
+        fn range(&self) -> RangeInclusive<u16> {
+            0xFE40..=0xFE40
+        }
+
+        fn read(&mut self, _address: u16) -> u16 {
+            0
+        }
+
+        fn write(&mut self, _address: u16, _value: u16) {}
+
+        fn tick(&mut self, memory: &mut [u16]) -> Option<Interrupt> {
+            memory[0x4000] += 1;
+            None
+        }
+    }
+
+    #[test]
+    fn step_back_undoes_what_a_device_wrote() {
+        let mut vm = countdown();
+        vm.bus.attach(Box::new(Ticker)).unwrap();
+        vm.step();
+        vm.step();
+        assert_eq!(vm.memory[0x4000], 2);
+        assert!(vm.step_back());
+        assert_eq!(vm.memory[0x4000], 1);
+        assert!(vm.step_back());
+        assert_eq!(vm.memory[0x4000], 0);
+    }
+
+    #[cfg(feature = "devices-extra")]
+    #[test]
+    fn step_back_undoes_what_a_trap_service_wrote() {
+        let mut vm = Vm::new();
+        vm.console = Box::new(CapturedConsole::default());
+        vm.history = Some(History::new(100));
+        vm.devices.host = Some(crate::devices::host::HostEnvironment::new(vec!["hi".to_string()]));
+        let source = "
+                .ORIG x3000
+                AND R0, R0, #0
+                LEA R1, BUFFER
+                ADD R2, R0, #8
+                TRAP x36
+                HALT
+        BUFFER  .BLKW 8
+                .END
+        ";
+        vm.load_program(&Program::from_assembly(source).unwrap());
+        for _ in 0..4 {
+            vm.step();
+        }
+        assert_eq!(vm.memory[0x3005..0x3008], [b'h' as u16, b'i' as u16, 0]);
+        assert!(vm.step_back());
+        assert_eq!(vm.memory[0x3005..0x3007], [0, 0]);
+        assert_eq!(vm.registers[0], 0);
+    }
+}
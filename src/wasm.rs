@@ -0,0 +1,97 @@
+//! Browser bindings, built with the `wasm` feature for
+//! `wasm32-unknown-unknown` and exported through `wasm-bindgen`. The page
+//! owns the event loop: it calls [`WebVm::run`] from an animation frame or
+//! timer, forwards key presses with [`WebVm::key_pressed`] and shows what
+//! [`WebVm::take_output`] returns. Nothing touches a terminal or blocks.
+
+use wasm_bindgen::prelude::*;
+
+use crate::console::QueuedConsole;
+use crate::vm::{REGISTER, StepOutcome, Vm};
+
+/// An LC-3 machine for a web page.
+#[wasm_bindgen]
+pub struct WebVm {
+    vm: Vm,
+}
+
+impl Default for WebVm {
+    fn default() -> Self {
+        let mut vm = Vm::new();
+        vm.console = Box::new(QueuedConsole::default());
+        WebVm { vm }
+    }
+}
+
+#[wasm_bindgen]
+impl WebVm {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load an object image (origin word followed by the program).
+    pub fn load(&mut self, bytes: &[u8]) -> Result<(), JsError> {
+        self.vm.load_from_bytes(bytes)?;
+        Ok(())
+    }
+
+    /// Execute one instruction. Returns false once the program has halted,
+    /// faulted, or is waiting for a key.
+    pub fn step(&mut self) -> bool {
+        matches!(self.vm.step(), StepOutcome::Continued | StepOutcome::Trapped(_))
+    }
+
+    /// Execute up to `budget` instructions, stopping early like [`WebVm::step`].
+    /// Returns the number executed.
+    pub fn run(&mut self, budget: u32) -> u32 {
+        let start = self.vm.instruction_count;
+        for _ in 0..budget {
+            if !self.step() {
+                break;
+            }
+        }
+        (self.vm.instruction_count - start) as u32
+    }
+
+    /// Queue a key for the keyboard; a waiting GETC or IN takes it on the next step.
+    pub fn key_pressed(&mut self, key: u8) {
+        self.page_console().input.push_back(key);
+    }
+
+    /// Everything the program printed since the last call.
+    pub fn take_output(&mut self) -> String {
+        let output = std::mem::take(&mut self.page_console().output);
+        String::from_utf8_lossy(&output).into_owned()
+    }
+
+    pub fn halted(&self) -> bool {
+        self.vm.halted
+    }
+
+    /// Start the loaded image over.
+    pub fn reset(&mut self) {
+        self.vm.reset();
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.vm.registers[REGISTER::PC as usize]
+    }
+
+    /// R0-R7 by number.
+    pub fn register(&self, index: u8) -> u16 {
+        self.vm.registers[(index & 0x7) as usize]
+    }
+
+    pub fn read_memory(&self, address: u16) -> u16 {
+        self.vm.memory[address as usize]
+    }
+
+    pub fn write_memory(&mut self, address: u16, value: u16) {
+        self.vm.memory[address as usize] = value;
+    }
+
+    fn page_console(&mut self) -> &mut QueuedConsole {
+        self.vm.console.downcast_mut().expect("WebVm keeps its page console")
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use crate::disasm::disassemble_with;
+use crate::summary;
+use crate::vm::Vm;
+
+/* how many executed instructions the report shows */
+const RECENT_LEN: usize = 32;
+/* the clock is read this often rather than on every instruction */
+const CHECK_INTERVAL: u64 = 4096;
+/* a loop spanning more addresses than this is listed by its distinct PCs only */
+const MAX_LOOP_SPAN: u16 = 32;
+
+/// What the VM does once the watchdog has fired and dumped its report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchdogPolicy {
+    /// Keep running; the report is repeated after each further stalled interval.
+    Continue,
+    /// Halt the VM.
+    Abort,
+}
+
+/// Notices a guest that has stopped making observable progress — no device
+/// or console I/O and no instruction at a PC it had not executed before —
+/// for `timeout` of wall-clock time, so a hang can be told apart from a long
+/// computation and diagnosed without a debugger.
+pub struct Watchdog {
+    pub timeout: Duration,
+    pub policy: WatchdogPolicy,
+    /// How many times the watchdog has fired.
+    pub fired: u32,
+    /* one bit per address executed so far */
+    visited: Box<[u64]>,
+    /* (pc, instruction) of the most recent instructions, oldest first */
+    recent: VecDeque<(u16, u16)>,
+    last_progress: Instant,
+}
+
+impl Watchdog {
+    pub fn new(timeout: Duration, policy: WatchdogPolicy) -> Self {
+        Watchdog {
+            timeout,
+            policy,
+            fired: 0,
+            visited: vec![0; 1 << 10].into_boxed_slice(),
+            recent: VecDeque::with_capacity(RECENT_LEN),
+            last_progress: Instant::now(),
+        }
+    }
+
+    /// Note that the guest did something observable, restarting the interval.
+    pub fn progress(&mut self) {
+        self.last_progress = Instant::now();
+    }
+
+    /// Start over as if the program had just been loaded.
+    pub fn reset(&mut self) {
+        self.fired = 0;
+        self.visited.fill(0);
+        self.recent.clear();
+        self.progress();
+    }
+
+    /// Record one executed instruction; `count` is the instruction count
+    /// after it ran. Returns true when the watchdog fires.
+    pub fn record(&mut self, count: u64, pc: u16, instruction: u16) -> bool {
+        if self.recent.len() == RECENT_LEN {
+            self.recent.pop_front();
+        }
+        self.recent.push_back((pc, instruction));
+
+        let (word, bit) = (pc as usize / 64, pc % 64);
+        if self.visited[word] & (1 << bit) == 0 {
+            self.visited[word] |= 1 << bit;
+            self.progress();
+        }
+
+        if !count.is_multiple_of(CHECK_INTERVAL) || self.last_progress.elapsed() < self.timeout {
+            return false;
+        }
+        self.fired += 1;
+        self.progress();
+        true
+    }
+
+    /// Describe the stall: the registers, the most recent instructions, and
+    /// a disassembly of the code they were looping over.
+    pub fn report(&self, vm: &Vm) -> String {
+        let mut out = String::new();
+        writeln!(out, "watchdog: no progress for {:.1?}", self.timeout).unwrap();
+        out.push_str(&summary::render(vm, &[]));
+
+        writeln!(out, "Recent instructions:").unwrap();
+        for &(pc, instruction) in &self.recent {
+            writeln!(out, "  x{:04X}  {:04X}  {}", pc, instruction, disassemble_with(pc, instruction, &vm.symbols)).unwrap();
+        }
+
+        let mut pcs: Vec<u16> = self.recent.iter().map(|&(pc, _)| pc).collect();
+        pcs.sort_unstable();
+        pcs.dedup();
+        let (Some(&first), Some(&last)) = (pcs.first(), pcs.last()) else {
+            return out;
+        };
+        if last - first < MAX_LOOP_SPAN {
+            pcs = (first..=last).collect();
+        }
+        writeln!(out, "Suspected loop:").unwrap();
+        for pc in pcs {
+            let word = vm.memory[pc as usize];
+            writeln!(out, "  x{:04X}  {:04X}  {}", pc, word, disassemble_with(pc, word, &vm.symbols)).unwrap();
+        }
+        out
+    }
+}